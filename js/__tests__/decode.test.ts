@@ -1,9 +1,49 @@
 /**
- * Decode tests for decodeUltraHdr and extractSdrBase.
+ * Decode tests for decodeUltraHdr, decodeUltraHdrHandle, decodeUltraHdrStrict, checkCompatibility,
+ * decodeToPixels, locateComponents, repairUltraHdr, extractSdrBase, extractGainMapStandalone,
+ * gainMapDimensionsMatch, listAuxiliaryImages, extractAuxiliaryImage, getQuantizationTables,
+ * splitUltraHdr and encodeFromComponents.
  */
 import { describe, it, expect } from 'vitest';
-import { decodeUltraHdr, extractSdrBase, getMetadata } from '../src/index';
-import { base64ToArrayBuffer, REGULAR_JPEG_BASE64, PNG_HEADER_BASE64 } from './fixtures/test-data';
+import {
+	checkCompatibility,
+	CompatProfile,
+	decodeToPixels,
+	decodeUltraHdr,
+	decodeUltraHdrHandle,
+	decodeUltraHdrStrict,
+	encodeFromComponents,
+	extractAuxiliaryImage,
+	extractGainMapStandalone,
+	extractSdrBase,
+	gainMapDimensionsMatch,
+	GainMapXmpMode,
+	getMetadata,
+	getQuantizationTables,
+	listAuxiliaryImages,
+	locateComponents,
+	metadataApproxEqual,
+	metadataFromJson,
+	parseGainMapXmp,
+	repairUltraHdr,
+	splitUltraHdr,
+	XmpFloatArrayStyle,
+} from '../src/index';
+import {
+	base64ToArrayBuffer,
+	REGULAR_JPEG_BASE64,
+	PNG_HEADER_BASE64,
+	ODD_420_JPEG_BASE64,
+	ODD_444_JPEG_BASE64,
+	PROGRESSIVE_JPEG_BASE64,
+	WIDE_JPEG_BASE64,
+	WIDE_JPEG_WIDTH,
+	WIDE_JPEG_HEIGHT,
+	encodeFixture,
+	patchText,
+	withExifOrientation,
+	withoutGainMapMetadata,
+} from './fixtures/test-data';
 
 describe('decodeUltraHdr', () => {
 	it('throws error for empty buffer', async () => {
@@ -16,6 +56,13 @@ describe('decodeUltraHdr', () => {
 		await expect(decodeUltraHdr('png', pngBuffer)).rejects.toThrow();
 	});
 
+	it('decodes the gain map the GContainer directory locates', async () => {
+		const { bytes, gainMap } = await withDepthBeforeGainMap();
+
+		const result = await decodeUltraHdr('container-depth', bytes.buffer as ArrayBuffer);
+		expect(Array.from(result.gainMap)).toEqual(Array.from(gainMap));
+	});
+
 	it('throws error for regular JPEG without gain map', async () => {
 		const jpegBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
 		await expect(decodeUltraHdr('regular', jpegBuffer)).rejects.toThrow();
@@ -29,6 +76,421 @@ describe('decodeUltraHdr', () => {
 		}
 		await expect(decodeUltraHdr('random', randomBuffer)).rejects.toThrow();
 	});
+
+	it('rejects a file cut off within the primary as truncated', async () => {
+		const bytes = new Uint8Array(await encodeFixture());
+		const { primary } = await locateComponents(bytes.buffer as ArrayBuffer);
+		for (const end of [4, 200, primary.length - 1]) {
+			await expect(
+				decodeUltraHdr('truncated', bytes.slice(0, end).buffer as ArrayBuffer)
+			).rejects.toThrow(/truncated: no EOI found/);
+		}
+	});
+
+	it('returns the SDR with a warning when the gain map is cut off', async () => {
+		const bytes = new Uint8Array(await encodeFixture());
+		const { primary, gainMap } = await locateComponents(bytes.buffer as ArrayBuffer);
+		const ends = [primary.length, gainMap.offset + 2, gainMap.offset + gainMap.length - 1];
+		for (const end of ends) {
+			const decoded = await decodeUltraHdr('cut', bytes.slice(0, end).buffer as ArrayBuffer);
+			const sdr = bytes.subarray(0, primary.length);
+			expect(Array.from(decoded.sdrImage)).toEqual(Array.from(sdr));
+			expect(decoded.gainMap.length).toBe(0);
+			expect(decoded.width).toBe(16);
+			expect(decoded.height).toBe(16);
+			expect(decoded.metadata.gainMapMax).toEqual([0, 0, 0]);
+			expect(decoded.warnings.join('\n')).toMatch(/gain map missing/);
+			await expect(
+				decodeUltraHdrStrict('cut-strict', bytes.slice(0, end).buffer as ArrayBuffer)
+			).rejects.toThrow(/Non-conformant UltraHDR/);
+		}
+	});
+
+	it('returns the SDR with a warning when the gain map has no metadata', async () => {
+		const encoded = await encodeFixture();
+		const { primary } = await locateComponents(encoded);
+		const stripped = await withoutGainMapMetadata(encoded);
+		const decoded = await decodeUltraHdr('no-metadata', stripped);
+		const sdr = new Uint8Array(stripped, 0, primary.length);
+		expect(Array.from(decoded.sdrImage)).toEqual(Array.from(sdr));
+		expect(decoded.gainMap.length).toBe(0);
+		expect(decoded.metadata.gainMapMax).toEqual([0, 0, 0]);
+		expect(decoded.warnings.join('\n')).toMatch(/metadata missing/);
+	});
+
+	it('warns when the ISO 21496-1 and XMP metadata disagree, keeping ISO', async () => {
+		const bytes = new Uint8Array(await encodeFixture());
+		const expected = await decodeUltraHdr('conflict-ref', bytes.buffer as ArrayBuffer);
+		expect(expected.warnings.join('\n')).not.toMatch(/metadata conflict/);
+
+		// Bump the leading digit of the gain map's XMP GainMapMax; same length,
+		// so no offsets move.
+		const { offset, length } = (await locateComponents(bytes.buffer as ArrayBuffer)).gainMap;
+		const text = new TextDecoder('latin1').decode(bytes.subarray(offset, offset + length));
+		const attr = 'hdrgm:GainMapMax="';
+		expect(text.indexOf(attr)).toBeGreaterThan(-1);
+		const digit = offset + text.indexOf(attr) + attr.length;
+		bytes[digit] = bytes[digit] === 0x39 ? 0x31 : bytes[digit] + 1;
+
+		const decoded = await decodeUltraHdr('conflict', bytes.buffer as ArrayBuffer);
+		expect(decoded.warnings.join('\n')).toMatch(/metadata conflict/);
+		expect(decoded.metadata).toEqual(expected.metadata);
+	});
+
+	it('reads the layers by role when the gain map is stored first', async () => {
+		const bytes = new Uint8Array(await encodeFixture());
+		const expected = await decodeUltraHdr('inverted-ref', bytes.buffer as ArrayBuffer);
+		const layout = await locateComponents(bytes.buffer as ArrayBuffer);
+		const primary = bytes.subarray(0, layout.primary.length);
+		const { offset, length } = layout.gainMap;
+		const gainMap = bytes.subarray(offset, offset + length);
+
+		// Gain map first, carrying an MPF index that flags the second image
+		// (the SDR) as the primary.
+		const mpf = bytes.subarray(layout.mpf!.offset - 8, layout.mpf!.offset + layout.mpf!.length);
+		const inverted = concat(gainMap.subarray(0, 2), mpf, gainMap.subarray(2), primary);
+		const first = mpEntry(inverted, 0);
+		const second = mpEntry(inverted, 1);
+		const attributes = inverted.slice(first, first + 4);
+		inverted.copyWithin(first, second, second + 4);
+		inverted.set(attributes, second);
+
+		const decoded = await decodeUltraHdr('inverted', inverted.buffer as ArrayBuffer);
+		expect(Array.from(decoded.sdrImage)).toEqual(Array.from(expected.sdrImage));
+		expect(Array.from(decoded.gainMap)).toEqual(Array.from(expected.gainMap));
+		expect(decoded.metadata).toEqual(expected.metadata);
+		expect([decoded.width, decoded.height]).toEqual([expected.width, expected.height]);
+		expect(decoded.warnings.join('\n')).toMatch(/stored before the SDR base/);
+	});
+});
+
+describe('decodeUltraHdrHandle', () => {
+	it('views the same bytes that decodeUltraHdr copies out', async () => {
+		const bytes = new Uint8Array(await encodeFixture());
+		const expected = await decodeUltraHdr('handle', bytes.buffer as ArrayBuffer);
+		const handle = await decodeUltraHdrHandle(bytes.buffer as ArrayBuffer);
+		try {
+			expect(Array.from(handle.getSdrView())).toEqual(Array.from(expected.sdrImage));
+			expect(Array.from(handle.getGainMapView())).toEqual(Array.from(expected.gainMap));
+			expect(handle.copyOut()).toEqual(expected);
+		} finally {
+			handle.delete();
+		}
+	});
+
+	it('cannot be read after delete, while copies stay valid', async () => {
+		const bytes = new Uint8Array(await encodeFixture());
+		const handle = await decodeUltraHdrHandle(bytes.buffer as ArrayBuffer);
+		const copy = handle.copyOut();
+		const sdr = Array.from(copy.sdrImage);
+		handle.delete();
+
+		expect(() => handle.getSdrView()).toThrow();
+		expect(() => handle.getGainMapView()).toThrow();
+		expect(Array.from(copy.sdrImage)).toEqual(sdr);
+	});
+
+	it('throws error for regular JPEG without gain map', async () => {
+		const jpegBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		await expect(decodeUltraHdrHandle(jpegBuffer)).rejects.toThrow();
+	});
+});
+
+function decodeStrict(bytes: Uint8Array) {
+	return decodeUltraHdrStrict('strict', bytes.buffer as ArrayBuffer);
+}
+
+/**
+ * Byte offset of MP Entry `index` in the primary's MPF segment. Each 16-byte
+ * entry holds attributes, size, then offset, as 32-bit values.
+ */
+function mpEntry(bytes: Uint8Array, index: number): number {
+	const text = new TextDecoder('latin1').decode(bytes);
+	const tiff = text.indexOf('MPF\0') + 4;
+	const view = new DataView(bytes.buffer, bytes.byteOffset + tiff);
+	const littleEndian = text.slice(tiff, tiff + 2) === 'II';
+	const ifd = view.getUint32(4, littleEndian);
+	for (let e = 0; e < view.getUint16(ifd, littleEndian); e++) {
+		const entry = ifd + 2 + e * 12;
+		if (view.getUint16(entry, littleEndian) === 0xb002) {
+			return tiff + view.getUint32(entry + 8, littleEndian) + index * 16;
+		}
+	}
+	throw new Error('No MP Entry table');
+}
+
+describe('decodeUltraHdrStrict', () => {
+	it('accepts a conformant UltraHDR image', async () => {
+		const result = await decodeStrict(new Uint8Array(await encodeFixture()));
+		expect(result.width).toBe(16);
+		expect(result.warnings).toEqual([]);
+	});
+
+	it('rejects a regular JPEG without MPF', async () => {
+		const jpegBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		await expect(decodeUltraHdrStrict('strict-plain', jpegBuffer)).rejects.toThrow(
+			/no MPF segment/
+		);
+	});
+
+	it('rejects missing required metadata fields', async () => {
+		const bytes = new Uint8Array(await encodeFixture());
+		patchText(bytes, /hdrgm:HDRCapacityMax="[^"]*"/g, '');
+		await expect(decodeStrict(bytes)).rejects.toThrow(/missing hdrgm:HDRCapacityMax/);
+	});
+
+	it('rejects an unsupported metadata version', async () => {
+		const bytes = new Uint8Array(await encodeFixture());
+		patchText(bytes, /hdrgm:Version="1\.0"/g, 'hdrgm:Version="2.0"');
+		await expect(decodeStrict(bytes)).rejects.toThrow(/hdrgm:Version is "2.0", expected "1.0"/);
+	});
+
+	it('rejects an out-of-range gamma', async () => {
+		const bytes = new Uint8Array(await encodeFixture());
+		patchText(bytes, /hdrgm:Gamma="[^"]*"/g, 'hdrgm:Gamma="0"');
+		await expect(decodeStrict(bytes)).rejects.toThrow(/gamma must be positive/);
+	});
+
+	it('rejects a gain map downscale ratio other than 1, 2 or 4', async () => {
+		const bytes = new Uint8Array(await encodeFixture({ gainMapScale: 8 }));
+		expect((await decodeUltraHdr('lenient-ratio', bytes.buffer as ArrayBuffer)).width).toBe(16);
+		await expect(decodeStrict(bytes)).rejects.toThrow(/not a 1x, 2x or 4x downscale/);
+	});
+
+	it('rejects an MPF index whose primary flag is not on the primary image', async () => {
+		const bytes = new Uint8Array(await encodeFixture());
+		const primary = mpEntry(bytes, 0);
+		const gainMap = mpEntry(bytes, 1);
+		// Swap the attribute words so the MP primary type code moves to the gain map.
+		const attributes = bytes.slice(primary, primary + 4);
+		bytes.copyWithin(primary, gainMap, gainMap + 4);
+		bytes.set(attributes, gainMap);
+
+		await expect(decodeStrict(bytes)).rejects.toThrow(/first image as the primary/);
+		await expect(decodeStrict(bytes)).rejects.toThrow(/image 2 as a second primary/);
+	});
+
+	it('rejects an MPF offset that does not match the gain map', async () => {
+		const bytes = new Uint8Array(await encodeFixture());
+		bytes[mpEntry(bytes, 1) + 8 + 3] += 1;
+		await expect(decodeStrict(bytes)).rejects.toThrow(/MPF gain map offset/);
+	});
+
+	it('rejects an MPF index claiming 65535 IFD entries', async () => {
+		const original = new Uint8Array(await encodeFixture());
+		const bytes = original.slice();
+		const tiff = new TextDecoder('latin1').decode(bytes).indexOf('MPF\0') + 4;
+		const view = new DataView(bytes.buffer, bytes.byteOffset + tiff);
+		const littleEndian = bytes[tiff] === 0x49;
+		view.setUint16(view.getUint32(4, littleEndian), 0xffff, littleEndian);
+
+		await expect(decodeStrict(bytes)).rejects.toThrow(/MPF segment is malformed/);
+		// Without a usable index the gain map is still found as the next codestream.
+		const layout = await locateComponents(bytes.buffer as ArrayBuffer);
+		expect(layout.gainMap).toEqual(
+			(await locateComponents(original.buffer as ArrayBuffer)).gainMap
+		);
+	});
+});
+
+describe('repairUltraHdr', () => {
+	it('restores MPF offsets so the MPF path decodes again', async () => {
+		const original = new Uint8Array(await encodeFixture());
+		const bytes = original.slice();
+		bytes[mpEntry(bytes, 1) + 8 + 3] += 7;
+		await expect(decodeStrict(bytes)).rejects.toThrow(/MPF gain map offset/);
+
+		const repaired = new Uint8Array(await repairUltraHdr(bytes.buffer as ArrayBuffer));
+		expect(repaired.length).toBe(original.length);
+		expect(await locateComponents(repaired.buffer as ArrayBuffer)).toEqual(
+			await locateComponents(original.buffer as ArrayBuffer)
+		);
+		await expect(decodeStrict(repaired)).resolves.toBeDefined();
+	});
+
+	it('throws for a JPEG without an MPF index', async () => {
+		const jpegBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		await expect(repairUltraHdr(jpegBuffer)).rejects.toThrow();
+	});
+});
+
+describe('checkCompatibility', () => {
+	function status(report: { items: { rule: string; status: string }[] }, rule: string) {
+		return report.items.find((item) => item.rule === rule)?.status;
+	}
+
+	it('passes Android but fails ISO strict without ISO 21496-1 metadata', async () => {
+		const bytes = new Uint8Array(await encodeFixture());
+		patchText(bytes, /urn:iso:std:iso:ts:21496:-1/g, 'urn:example:not-iso-21496');
+
+		const android = await checkCompatibility(bytes.buffer as ArrayBuffer, CompatProfile.Android);
+		expect(android.compatible).toBe(true);
+		expect(status(android, 'xmp')).toBe('pass');
+		expect(status(android, 'even-dimensions')).toBe('pass');
+		expect(status(android, 'gain-map-channels')).toBe('pass');
+		expect(status(android, 'iso-metadata')).toBe('warn');
+
+		const iso = await checkCompatibility(bytes.buffer as ArrayBuffer, CompatProfile.IsoStrict);
+		expect(iso.compatible).toBe(false);
+		expect(status(iso, 'iso-metadata')).toBe('fail');
+		expect(status(iso, 'xmp')).toBeUndefined();
+	});
+
+	it('passes ISO strict but fails Android with an unsupported XMP version', async () => {
+		const bytes = new Uint8Array(await encodeFixture());
+		patchText(bytes, /hdrgm:Version="1\.0"/g, 'hdrgm:Version="2.0"');
+
+		const iso = await checkCompatibility(bytes.buffer as ArrayBuffer, CompatProfile.IsoStrict);
+		expect(iso.compatible).toBe(true);
+		expect(iso.items.every((item) => item.status === 'pass')).toBe(true);
+
+		const android = await checkCompatibility(bytes.buffer as ArrayBuffer, CompatProfile.Android);
+		expect(android.compatible).toBe(false);
+		expect(android.items.find((item) => item.rule === 'xmp')?.message).toMatch(
+			/hdrgm:Version is "2.0"/
+		);
+	});
+
+	it('requires even dimensions only for subsampled chroma', async () => {
+		const encoded = new Uint8Array(await encodeFixture());
+		const { gainMap: range } = await locateComponents(encoded.buffer as ArrayBuffer);
+		const gainMap = encoded.slice(range.offset, range.offset + range.length);
+		const withPrimary = (base64: string) =>
+			concat(
+				withContainerXmp(base64ToArrayBuffer(base64), [
+					'Item:Semantic="Primary" Item:Mime="image/jpeg"',
+					`Item:Semantic="GainMap" Item:Mime="image/jpeg" Item:Length="${gainMap.length}"`,
+				]),
+				gainMap
+			);
+
+		// 15 pixels wide: fine for 4:4:4, half a chroma sample short for 4:2:0.
+		const full = withPrimary(ODD_444_JPEG_BASE64);
+		const report444 = await checkCompatibility(full.buffer as ArrayBuffer, CompatProfile.Android);
+		expect(status(report444, 'even-dimensions')).toBe('pass');
+
+		const subsampled = withPrimary(ODD_420_JPEG_BASE64);
+		const report420 = await checkCompatibility(
+			subsampled.buffer as ArrayBuffer,
+			CompatProfile.Android
+		);
+		expect(status(report420, 'even-dimensions')).toBe('fail');
+	});
+
+	it('fails every profile for a regular JPEG', async () => {
+		const jpegBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const profiles = Object.values(CompatProfile).filter(
+			(v): v is CompatProfile => typeof v === 'number'
+		);
+		expect(profiles).toHaveLength(4);
+		for (const profile of profiles) {
+			const report = await checkCompatibility(jpegBuffer, profile);
+			expect(report.compatible, CompatProfile[profile]).toBe(false);
+		}
+	});
+
+	it('rejects an unknown profile', async () => {
+		const bytes = new Uint8Array(await encodeFixture());
+		await expect(
+			checkCompatibility(bytes.buffer as ArrayBuffer, 7 as CompatProfile)
+		).rejects.toThrow(/Invalid compatibility profile/);
+	});
+});
+
+describe('locateComponents', () => {
+	it('returns ranges that slice out what decodeUltraHdr returns', async () => {
+		const bytes = new Uint8Array(await encodeFixture());
+		const layout = await locateComponents(bytes.buffer as ArrayBuffer);
+		const decoded = await decodeUltraHdr('layout', bytes.buffer as ArrayBuffer);
+		const slice = ({ offset, length }: { offset: number; length: number }) =>
+			Array.from(bytes.subarray(offset, offset + length));
+
+		expect(layout.primary.offset).toBe(0);
+		expect(slice(layout.primary)).toEqual(Array.from(decoded.sdrImage));
+		expect(slice(layout.gainMap)).toEqual(Array.from(decoded.gainMap));
+		expect(layout.xmp).not.toBeNull();
+		expect(layout.mpf).not.toBeNull();
+		const xmp = new TextDecoder().decode(new Uint8Array(slice(layout.xmp!)));
+		expect(xmp).toContain('hdrgm:');
+		const mpf = layout.mpf!;
+		expect(mpf.offset + mpf.length).toBeLessThanOrEqual(layout.primary.length);
+	});
+
+	it('reports XMP and MPF at the offsets a full segment walk finds', async () => {
+		const encoded = new Uint8Array(await encodeFixture());
+		// A 60 KB APP2 ahead of everything, as a large ICC profile would be.
+		const app2 = new Uint8Array(60000 + 4);
+		app2.set([0xff, 0xe2, (60002 >> 8) & 0xff, 60002 & 0xff]);
+		const bytes = concat(encoded.subarray(0, 2), app2, encoded.subarray(2));
+
+		const segments: { marker: number; payload: number; text: string }[] = [];
+		for (let at = 2; bytes[at] === 0xff && bytes[at + 1] !== 0xda; ) {
+			const length = (bytes[at + 2] << 8) | bytes[at + 3];
+			const text = new TextDecoder('latin1').decode(bytes.subarray(at + 4, at + 2 + length));
+			segments.push({ marker: bytes[at + 1], payload: at + 4, text });
+			at += 2 + length;
+		}
+		const xmpSignature = 'http://ns.adobe.com/xap/1.0/\0';
+		const xmp = segments.find((s) => s.text.startsWith(xmpSignature) && s.text.includes('hdrgm'));
+		const mpf = segments.find((s) => s.marker === 0xe2 && s.text.startsWith('MPF\0'));
+
+		const layout = await locateComponents(bytes.buffer as ArrayBuffer);
+		expect(layout.xmp?.offset).toBe(xmp!.payload + xmpSignature.length);
+		expect(layout.mpf?.offset).toBe(mpf!.payload + 4);
+	});
+
+	it('skips a gain map candidate that is really the primary scan after a stray FF D9', async () => {
+		const encoded = new Uint8Array(await encodeFixture());
+		const original = await locateComponents(encoded.buffer as ArrayBuffer);
+		const bytes = encoded.slice();
+		let sos = 2;
+		while (bytes[sos + 1] !== 0xda) sos += 2 + ((bytes[sos + 2] << 8) | bytes[sos + 3]);
+		// Corrupt scan data: an unstuffed EOI right before an SOI, where the
+		// GContainer directory expects the gain map to start.
+		const scan = sos + 2 + ((bytes[sos + 2] << 8) | bytes[sos + 3]);
+		bytes.set([0xff, 0xd9, 0xff, 0xd8, 0x00, 0x00], scan + 4);
+
+		const layout = await locateComponents(bytes.buffer as ArrayBuffer);
+		expect(layout.primary.length).toBe(scan + 6);
+		expect(layout.gainMap).toEqual(original.gainMap);
+	});
+
+	it('follows the GContainer directory past items before the gain map', async () => {
+		const { bytes, primary, depth, gainMap } = await withDepthBeforeGainMap();
+
+		const layout = await locateComponents(bytes.buffer as ArrayBuffer);
+		expect(layout.primary).toEqual({ offset: 0, length: primary.length });
+		expect(layout.gainMap).toEqual({
+			offset: primary.length + depth.length,
+			length: gainMap.length,
+		});
+	});
+
+	it('walks every scan of a multi-scan primary to its EOI', async () => {
+		const encoded = new Uint8Array(await encodeFixture());
+		const { gainMap: range } = await locateComponents(encoded.buffer as ArrayBuffer);
+		const gainMap = encoded.slice(range.offset, range.offset + range.length);
+
+		// Progressive: several SOS segments, with DHTs between the scans.
+		const primary = withContainerXmp(base64ToArrayBuffer(PROGRESSIVE_JPEG_BASE64), [
+			'Item:Semantic="Primary" Item:Mime="image/jpeg"',
+			`Item:Semantic="GainMap" Item:Mime="image/jpeg" Item:Length="${gainMap.length}"`,
+		]);
+		let scans = 0;
+		for (let i = 0; i + 1 < primary.length; i++) {
+			if (primary[i] === 0xff && primary[i + 1] === 0xda) scans++;
+		}
+		expect(scans).toBeGreaterThan(1);
+
+		const layout = await locateComponents(concat(primary, gainMap).buffer as ArrayBuffer);
+		expect(layout.primary).toEqual({ offset: 0, length: primary.length });
+		expect(layout.gainMap).toEqual({ offset: primary.length, length: gainMap.length });
+	});
+
+	it('throws error for regular JPEG without gain map', async () => {
+		const jpegBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		await expect(locateComponents(jpegBuffer)).rejects.toThrow();
+	});
 });
 
 describe('extractSdrBase', () => {
@@ -58,6 +520,397 @@ describe('extractSdrBase', () => {
 	});
 });
 
+describe('listAuxiliaryImages', () => {
+	it('lists and extracts an Alternate item after the gain map', async () => {
+		const encoded = new Uint8Array(await encodeFixture());
+		const { gainMap: range } = await locateComponents(encoded.buffer as ArrayBuffer);
+		const gainMap = encoded.slice(range.offset, range.offset + range.length);
+		const alternate = new Uint8Array(base64ToArrayBuffer(WIDE_JPEG_BASE64));
+		const primary = withContainerXmp(base64ToArrayBuffer(REGULAR_JPEG_BASE64), [
+			'Item:Semantic="Primary" Item:Mime="image/jpeg"',
+			`Item:Semantic="GainMap" Item:Mime="image/jpeg" Item:Length="${gainMap.length}"`,
+			`Item:Semantic="Alternate" Item:Mime="image/jpeg" Item:Length="${alternate.length}"`,
+		]);
+		const bytes = concat(primary, gainMap, alternate).buffer as ArrayBuffer;
+
+		expect(await listAuxiliaryImages(bytes)).toEqual([
+			{ semantic: 'Primary', mime: 'image/jpeg', offset: 0, length: primary.length },
+			{
+				semantic: 'GainMap',
+				mime: 'image/jpeg',
+				offset: primary.length,
+				length: gainMap.length,
+			},
+			{
+				semantic: 'Alternate',
+				mime: 'image/jpeg',
+				offset: primary.length + gainMap.length,
+				length: alternate.length,
+			},
+		]);
+		const extract = async (semantic: string) =>
+			Array.from(new Uint8Array(await extractAuxiliaryImage(bytes, semantic)));
+		expect(await extract('Primary')).toEqual(Array.from(primary));
+		expect(await extract('GainMap')).toEqual(Array.from(gainMap));
+		expect(await extract('Alternate')).toEqual(Array.from(alternate));
+		await expect(extractAuxiliaryImage(bytes, 'Depth')).rejects.toThrow(/semantic "Depth"/);
+	});
+
+	it('is empty without a GContainer directory', async () => {
+		const jpeg = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		expect(await listAuxiliaryImages(jpeg)).toEqual([]);
+	});
+});
+
+describe('getQuantizationTables', () => {
+	it('reads the 8-bit tables of a baseline JPEG', async () => {
+		const tables = await getQuantizationTables(base64ToArrayBuffer(REGULAR_JPEG_BASE64));
+
+		expect(tables.length).toBeGreaterThan(0);
+		for (const table of tables) {
+			expect(table.precision).toBe(8);
+			expect(table.values).toHaveLength(64);
+			expect(Math.min(...table.values)).toBeGreaterThan(0);
+		}
+	});
+
+	it('reads 16-bit tables sharing a DQT segment with an 8-bit one', async () => {
+		const jpeg = new Uint8Array(base64ToArrayBuffer(REGULAR_JPEG_BASE64));
+		const eight = Array.from({ length: 64 }, (_, i) => i + 1);
+		const sixteen = Array.from({ length: 64 }, (_, i) => 300 + i * 500);
+		const payload = [0x02, ...eight, 0x13, ...sixteen.flatMap((v) => [v >> 8, v & 0xff])];
+		const length = payload.length + 2;
+		const dqt = new Uint8Array([0xff, 0xdb, length >> 8, length & 0xff, ...payload]);
+		const patched = concat(jpeg.subarray(0, 2), dqt, jpeg.subarray(2));
+
+		const tables = await getQuantizationTables(patched.buffer as ArrayBuffer);
+
+		expect(tables.slice(0, 2)).toEqual([
+			{ id: 2, precision: 8, values: eight },
+			{ id: 3, precision: 16, values: sixteen },
+		]);
+	});
+});
+
+describe('gainMapDimensionsMatch', () => {
+	it('accepts the dimensions a decode reports', async () => {
+		const decoded = await decodeUltraHdr('dims', await encodeFixture({ gainMapScale: 2 }));
+		expect(decoded.gainMapWidth).toBe(8);
+		expect(await gainMapDimensionsMatch(decoded)).toBe(true);
+	});
+
+	it('rejects declared dimensions that differ from the SOF', async () => {
+		const decoded = await decodeUltraHdr('dims', await encodeFixture({ gainMapScale: 2 }));
+		expect(await gainMapDimensionsMatch({ ...decoded, gainMapWidth: 16 })).toBe(false);
+		expect(await gainMapDimensionsMatch({ ...decoded, gainMapHeight: 4 })).toBe(false);
+		expect(await gainMapDimensionsMatch({ ...decoded, gainMap: new Uint8Array(0) })).toBe(false);
+	});
+});
+
+describe('extractGainMapStandalone', () => {
+	it('throws for a JPEG without a gain map', async () => {
+		const jpegBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		await expect(extractGainMapStandalone(jpegBuffer)).rejects.toThrow();
+	});
+
+	it("carries the parent's metadata in its own XMP", async () => {
+		const parent = new Uint8Array(await encodeFixture());
+		const standalone = new Uint8Array(
+			await extractGainMapStandalone(parent.buffer as ArrayBuffer)
+		);
+
+		expect(Array.from(standalone.subarray(0, 4))).toEqual([0xff, 0xd8, 0xff, 0xe1]);
+		const text = new TextDecoder('latin1').decode(standalone);
+		expect(text).not.toContain('MPF\0');
+		expect(text.split('<x:xmpmeta').length).toBe(2);
+		const xmp = text.slice(text.indexOf('<x:xmpmeta'), text.indexOf('</x:xmpmeta>') + 12);
+
+		const parsed = await parseGainMapXmp(xmp);
+		const original = await getMetadata(parent.buffer as ArrayBuffer);
+		expect(await metadataApproxEqual(parsed, original, 1e-3)).toBe(true);
+	});
+
+	it('writes per-channel metadata as an rdf:Seq when asked', async () => {
+		const parts = await splitUltraHdr(await encodeFixture());
+		const metadata = {
+			...(await metadataFromJson(parts.metadataJson)),
+			gamma: [1, 1.5, 2] as [number, number, number],
+		};
+		const parent = await encodeFromComponents(
+			parts.primaryJpeg.buffer as ArrayBuffer,
+			parts.gainMapJpeg.buffer as ArrayBuffer,
+			metadata
+		);
+		const standalone = new Uint8Array(
+			await extractGainMapStandalone(parent, XmpFloatArrayStyle.RdfSeq)
+		);
+
+		const text = new TextDecoder('latin1').decode(standalone);
+		const xmp = text.slice(text.indexOf('<x:xmpmeta'), text.indexOf('</x:xmpmeta>') + 12);
+		const gamma = xmp.slice(xmp.indexOf('<hdrgm:Gamma>'), xmp.indexOf('</hdrgm:Gamma>'));
+		expect(gamma).toContain('<rdf:Seq>');
+		expect(gamma.match(/<rdf:li>/g)).toHaveLength(3);
+		expect(xmp).not.toContain('hdrgm:Gamma=');
+		const parsed = await parseGainMapXmp(xmp);
+		expect(await metadataApproxEqual(parsed, await getMetadata(parent), 1e-3)).toBe(true);
+	});
+
+	it('writes its XMP after a large Exif segment with a thumbnail', async () => {
+		const exif = exifWithThumbnail(new Uint8Array(base64ToArrayBuffer(REGULAR_JPEG_BASE64)), 40000);
+		const parent = await withGainMapSegment(new Uint8Array(await encodeFixture()), exif);
+		const standalone = new Uint8Array(
+			await extractGainMapStandalone(parent.buffer as ArrayBuffer)
+		);
+
+		// Exif stays first and whole; the XMP gets a segment of its own after it.
+		expect(Array.from(standalone.subarray(2, 2 + exif.length))).toEqual(Array.from(exif));
+		const next = 2 + exif.length;
+		const header = new TextDecoder('latin1').decode(standalone.subarray(next + 4, next + 33));
+		expect(Array.from(standalone.subarray(next, next + 2))).toEqual([0xff, 0xe1]);
+		expect(header).toBe('http://ns.adobe.com/xap/1.0/\0');
+
+		// Still a well-formed JPEG: the segments chain to SOS and it ends at EOI.
+		let at = 2;
+		while (standalone[at] === 0xff && standalone[at + 1] !== 0xda) {
+			at += 2 + ((standalone[at + 2] << 8) | standalone[at + 3]);
+		}
+		expect(standalone[at + 1]).toBe(0xda);
+		expect(Array.from(standalone.subarray(-2))).toEqual([0xff, 0xd9]);
+
+		const text = new TextDecoder('latin1').decode(standalone);
+		const xmp = text.slice(text.indexOf('<x:xmpmeta'), text.indexOf('</x:xmpmeta>') + 12);
+		const original = await getMetadata(parent.buffer as ArrayBuffer);
+		expect(await metadataApproxEqual(await parseGainMapXmp(xmp), original, 1e-3)).toBe(true);
+	});
+});
+
+describe('splitUltraHdr', () => {
+	it('throws for a JPEG without a gain map', async () => {
+		const jpegBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		await expect(splitUltraHdr(jpegBuffer)).rejects.toThrow(/No gain map/);
+	});
+
+	it('reassembles with encodeFromComponents into an equivalent file', async () => {
+		const original = await encodeFixture({ gainMapScale: 2 });
+		const parts = await splitUltraHdr(original);
+		expect(Array.from(parts.primaryJpeg.subarray(0, 2))).toEqual([0xff, 0xd8]);
+		expect(Array.from(parts.gainMapJpeg.subarray(0, 2))).toEqual([0xff, 0xd8]);
+		expect(new TextDecoder('latin1').decode(parts.primaryJpeg)).not.toContain('hdrgm');
+
+		const metadata = await metadataFromJson(parts.metadataJson);
+		const rebuilt = await encodeFromComponents(
+			parts.primaryJpeg.buffer as ArrayBuffer,
+			parts.gainMapJpeg.buffer as ArrayBuffer,
+			metadata
+		);
+
+		const before = await decodeToPixels(original);
+		const after = await decodeToPixels(rebuilt);
+		expect(after.width).toBe(before.width);
+		expect(after.gainMapWidth).toBe(before.gainMapWidth);
+		expect(Array.from(after.sdr)).toEqual(Array.from(before.sdr));
+		expect(Array.from(after.gainMap)).toEqual(Array.from(before.gainMap));
+		const roundTripped = await getMetadata(rebuilt);
+		expect(await metadataApproxEqual(roundTripped, await getMetadata(original), 1e-3)).toBe(true);
+	});
+
+	it('replaces the stale hdrgm XMP a gain map carries when asked to sync', async () => {
+		const original = new Uint8Array(await encodeFixture());
+		const parts = await splitUltraHdr(original);
+		const metadata = await metadataFromJson(parts.metadataJson);
+		const updated = { ...metadata, hdrCapacityMax: metadata.hdrCapacityMax + 1 };
+
+		// A standalone gain map describes the original file in its own XMP; give
+		// it an unrelated editor packet too.
+		const standalone = new Uint8Array(await extractGainMapStandalone(original));
+		const packet = new TextEncoder().encode(
+			'http://ns.adobe.com/xap/1.0/\0<x:xmpmeta>editor-note</x:xmpmeta>'
+		);
+		const stale = new Uint8Array(standalone.length + 4 + packet.length);
+		stale.set(standalone.subarray(0, 2));
+		stale.set([0xff, 0xe1, (packet.length + 2) >> 8, (packet.length + 2) & 0xff], 2);
+		stale.set(packet, 6);
+		stale.set(standalone.subarray(2), 6 + packet.length);
+
+		const gainMapText = async (mode?: GainMapXmpMode) => {
+			const primary = parts.primaryJpeg.buffer as ArrayBuffer;
+			const file = await encodeFromComponents(primary, stale.buffer as ArrayBuffer, updated, mode);
+			const { offset, length } = (await locateComponents(file)).gainMap;
+			return new TextDecoder('latin1').decode(new Uint8Array(file, offset, length));
+		};
+		const capacities = (text: string) =>
+			[...text.matchAll(/hdrgm:HDRCapacityMax="([^"]*)"/g)].map((m) => Number(m[1]));
+
+		const kept = await gainMapText();
+		expect(capacities(kept)).toHaveLength(2);
+		expect(capacities(kept)).toContainEqual(expect.closeTo(metadata.hdrCapacityMax, 3));
+
+		const synced = await gainMapText(GainMapXmpMode.Sync);
+		expect(capacities(synced)).toEqual([expect.closeTo(updated.hdrCapacityMax, 3)]);
+		expect(synced).toContain('editor-note');
+
+		const stripped = await gainMapText(GainMapXmpMode.Strip);
+		expect(capacities(stripped)).toEqual([expect.closeTo(updated.hdrCapacityMax, 3)]);
+		expect(stripped).not.toContain('editor-note');
+	});
+
+	it('refuses metadata that cannot be written', async () => {
+		const parts = await splitUltraHdr(await encodeFixture());
+		const metadata = await metadataFromJson(parts.metadataJson);
+		const primary = parts.primaryJpeg.buffer as ArrayBuffer;
+		const gainMap = parts.gainMapJpeg.buffer as ArrayBuffer;
+
+		await expect(
+			encodeFromComponents(primary, gainMap, { ...metadata, baseRenditionIsHdr: true })
+		).rejects.toThrow(/HDR-base/);
+		await expect(
+			encodeFromComponents(primary, gainMap, { ...metadata, gamma: [0, 1, 1] })
+		).rejects.toThrow(/Invalid/);
+	});
+});
+
+/**
+ * An APP1 Exif segment of `size` bytes whose IFD1 points at `thumbnail`, as
+ * cameras write it; the rest of the segment is padding.
+ */
+function exifWithThumbnail(thumbnail: Uint8Array, size: number): Uint8Array {
+	const segment = new Uint8Array(size);
+	const view = new DataView(segment.buffer);
+	segment.set([0xff, 0xe1]);
+	view.setUint16(2, size - 2);
+	segment.set(new TextEncoder().encode('Exif\0\0'), 4);
+	const tiff = 10;
+	segment.set(new TextEncoder().encode('MM'), tiff);
+	view.setUint16(tiff + 2, 42);
+	view.setUint32(tiff + 4, 8);
+	// IFD0 with no entries, linking to IFD1 at 14.
+	view.setUint16(tiff + 8, 0);
+	view.setUint32(tiff + 10, 14);
+	// IFD1: JPEGInterchangeFormat and JPEGInterchangeFormatLength (LONG).
+	view.setUint16(tiff + 14, 2);
+	const entries: [number, number][] = [
+		[0x0201, 44],
+		[0x0202, thumbnail.length],
+	];
+	entries.forEach(([tag, value], i) => {
+		const entry = tiff + 16 + i * 12;
+		view.setUint16(entry, tag);
+		view.setUint16(entry + 2, 4);
+		view.setUint32(entry + 4, 1);
+		view.setUint32(entry + 8, value);
+	});
+	view.setUint32(tiff + 40, 0);
+	segment.set(thumbnail, tiff + 44);
+	return segment;
+}
+
+/**
+ * Rebuilds an encoded file with `segment` straight after the gain map's SOI,
+ * updating the GContainer Item:Length and MPF index to match.
+ */
+async function withGainMapSegment(encoded: Uint8Array, segment: Uint8Array): Promise<Uint8Array> {
+	const { primary, gainMap } = await locateComponents(encoded.buffer as ArrayBuffer);
+	const oldGainMap = encoded.subarray(gainMap.offset, gainMap.offset + gainMap.length);
+	const newGainMap = concat(oldGainMap.subarray(0, 2), segment, oldGainMap.subarray(2));
+
+	const head = encoded.slice(0, primary.length);
+	const from = `Item:Length="${gainMap.length}"`;
+	const to = new TextEncoder().encode(`Item:Length="${newGainMap.length}"`);
+	const at = new TextDecoder('latin1').decode(head).lastIndexOf(from);
+	// Grow the APP1 segment holding the directory by the change in length.
+	let seg = 2;
+	const segLength = (offset: number) => (head[offset + 2] << 8) | head[offset + 3];
+	while (seg + 2 + segLength(seg) <= at) seg += 2 + segLength(seg);
+	const patched = concat(head.subarray(0, at), to, head.subarray(at + from.length));
+	new DataView(patched.buffer).setUint16(seg + 2, segLength(seg) + to.length - from.length);
+
+	const rebuilt = concat(patched, newGainMap);
+	return new Uint8Array(await repairUltraHdr(rebuilt.buffer as ArrayBuffer));
+}
+
+/**
+ * A primary, a depth map and a gain map, described only by a GContainer
+ * directory: there is no MPF, and the codestream straight after the primary
+ * is the depth map.
+ */
+async function withDepthBeforeGainMap() {
+	const encoded = new Uint8Array(await encodeFixture());
+	const { gainMap: range } = await locateComponents(encoded.buffer as ArrayBuffer);
+	const gainMap = encoded.slice(range.offset, range.offset + range.length);
+	const depth = new Uint8Array(base64ToArrayBuffer(REGULAR_JPEG_BASE64));
+	const primary = withContainerXmp(base64ToArrayBuffer(REGULAR_JPEG_BASE64), [
+		'Item:Semantic="Primary" Item:Mime="image/jpeg"',
+		`Item:Semantic="Depth" Item:Mime="image/jpeg" Item:Length="${depth.length}"`,
+		`Item:Semantic="GainMap" Item:Mime="image/jpeg" Item:Length="${gainMap.length}"`,
+	]);
+	return { bytes: concat(primary, depth, gainMap), primary, depth, gainMap };
+}
+
+/** Inserts an XMP packet whose GContainer directory lists `items` (attribute text). */
+function withContainerXmp(jpeg: ArrayBuffer, items: string[]): Uint8Array {
+	const xmp =
+		'http://ns.adobe.com/xap/1.0/\0' +
+		'<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF ' +
+		'xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">' +
+		'<rdf:Description xmlns:hdrgm="http://ns.adobe.com/hdr-gain-map/1.0/" ' +
+		'xmlns:Container="http://ns.google.com/photos/1.0/container/" ' +
+		'xmlns:Item="http://ns.google.com/photos/1.0/container/item/" hdrgm:Version="1.0">' +
+		'<Container:Directory><rdf:Seq>' +
+		items.map((attrs) => `<rdf:li><Container:Item ${attrs}/></rdf:li>`).join('') +
+		'</rdf:Seq></Container:Directory></rdf:Description></rdf:RDF></x:xmpmeta>';
+	const payload = new TextEncoder().encode(xmp);
+	const segment = concat(new Uint8Array([0xff, 0xe1, 0, 0]), payload);
+	new DataView(segment.buffer).setUint16(2, payload.length + 2);
+
+	const bytes = new Uint8Array(jpeg);
+	return concat(bytes.subarray(0, 2), segment, bytes.subarray(2));
+}
+
+/** Builds an ISOBMFF-style box: 32-bit length, 4-char type, payload. */
+function box(type: string, payload: Uint8Array): Uint8Array {
+	const out = new Uint8Array(8 + payload.length);
+	new DataView(out.buffer).setUint32(0, out.length);
+	out.set(new TextEncoder().encode(type), 4);
+	out.set(payload, 8);
+	return out;
+}
+
+function concat(...parts: Uint8Array[]): Uint8Array {
+	const out = new Uint8Array(parts.reduce((n, p) => n + p.length, 0));
+	let offset = 0;
+	for (const part of parts) {
+		out.set(part, offset);
+		offset += part.length;
+	}
+	return out;
+}
+
+/**
+ * Inserts an APP11 JUMBF superbox labelled with the ISO 21496-1 URN whose
+ * content box carries single-channel binary metadata with a common
+ * denominator of 1000. With `backward`, the base is the HDR rendition: its
+ * headroom comes first and the backward direction flag is set.
+ */
+function withJumbfMetadata(jpeg: ArrayBuffer, backward = false): ArrayBuffer {
+	const iso = new Uint8Array(37);
+	const view = new DataView(iso.buffer);
+	view.setUint8(4, backward ? 0x0c : 0x08); // Common denominator, single channel.
+	const headrooms = backward ? [2500, 0] : [0, 2500];
+	[1000, ...headrooms, 0, 3000, 1000, 15, 15].forEach((v, i) => view.setUint32(5 + i * 4, v));
+
+	const label = new TextEncoder().encode('urn:iso:std:iso:ts:21496:-1\0');
+	const jumd = box('jumd', concat(new Uint8Array(16), new Uint8Array([0x03]), label));
+	const jumbf = box('jumb', concat(jumd, box('bidb', iso)));
+
+	// CI "JP", box instance 1, packet sequence 1.
+	const payload = concat(new Uint8Array([0x4a, 0x50, 0, 1, 0, 0, 0, 1]), jumbf);
+	const segment = concat(new Uint8Array([0xff, 0xeb, 0, 0]), payload);
+	new DataView(segment.buffer).setUint16(2, payload.length + 2);
+
+	const bytes = new Uint8Array(jpeg);
+	return concat(bytes.subarray(0, 2), segment, bytes.subarray(2)).buffer as ArrayBuffer;
+}
+
 describe('getMetadata', () => {
 	it('throws error for empty buffer', async () => {
 		const emptyBuffer = new ArrayBuffer(0);
@@ -73,4 +926,130 @@ describe('getMetadata', () => {
 		const pngBuffer = base64ToArrayBuffer(PNG_HEADER_BASE64);
 		await expect(getMetadata(pngBuffer)).rejects.toThrow();
 	});
+
+	it('reads ISO 21496-1 metadata from an APP11 JUMBF box', async () => {
+		const metadata = await getMetadata(withJumbfMetadata(base64ToArrayBuffer(REGULAR_JPEG_BASE64)));
+
+		expect(metadata.baseRenditionIsHdr).toBe(false);
+		expect(metadata.hdrCapacityMin).toBeCloseTo(0, 5);
+		expect(metadata.hdrCapacityMax).toBeCloseTo(2.5, 5);
+		for (let i = 0; i < 3; i++) {
+			expect(metadata.gainMapMin[i]).toBeCloseTo(0, 5);
+			expect(metadata.gainMapMax[i]).toBeCloseTo(3, 5);
+			expect(metadata.gamma[i]).toBeCloseTo(1, 5);
+			expect(metadata.offsetSdr[i]).toBeCloseTo(0.015, 5);
+			expect(metadata.offsetHdr[i]).toBeCloseTo(0.015, 5);
+		}
+	});
+
+	it('reads the capacity range of an HDR-base JUMBF box low to high', async () => {
+		const jpeg = withJumbfMetadata(base64ToArrayBuffer(REGULAR_JPEG_BASE64), true);
+		const metadata = await getMetadata(jpeg);
+
+		expect(metadata.baseRenditionIsHdr).toBe(true);
+		expect(metadata.hdrCapacityMin).toBeCloseTo(0, 5);
+		expect(metadata.hdrCapacityMax).toBeCloseTo(2.5, 5);
+	});
+});
+
+/** Maps a display-space pixel back to its stored position for orientations 1, 6 and 8. */
+function storedPosition(
+	orientation: number,
+	x: number,
+	y: number,
+	width: number,
+	height: number
+): [number, number] {
+	if (orientation === 6) return [y, height - 1 - x];
+	if (orientation === 8) return [width - 1 - y, x];
+	return [x, y];
+}
+
+function expectOriented(
+	oriented: Uint8Array,
+	stored: Uint8Array,
+	orientation: number,
+	width: number,
+	height: number,
+	channels: number
+) {
+	const swap = orientation >= 5;
+	const outWidth = swap ? height : width;
+	const outHeight = swap ? width : height;
+	for (let y = 0; y < outHeight; y++) {
+		for (let x = 0; x < outWidth; x++) {
+			const [sx, sy] = storedPosition(orientation, x, y, width, height);
+			for (let c = 0; c < channels; c++) {
+				expect(oriented[(y * outWidth + x) * channels + c]).toBe(
+					stored[(sy * width + sx) * channels + c]
+				);
+			}
+		}
+	}
+}
+
+describe('decodeToPixels', () => {
+	const encodeWide = () => encodeFixture({ gainMapScale: 2 }, WIDE_JPEG_BASE64);
+
+	it('reports the Exif orientation without applying it by default', async () => {
+		const tagged = withExifOrientation(await encodeWide(), 6);
+		const pixels = await decodeToPixels(tagged);
+
+		expect(pixels.orientation).toBe(6);
+		expect(pixels.width).toBe(WIDE_JPEG_WIDTH);
+		expect(pixels.height).toBe(WIDE_JPEG_HEIGHT);
+		expect(pixels.sdr.length).toBe(WIDE_JPEG_WIDTH * WIDE_JPEG_HEIGHT * 4);
+		expect(pixels.gainMap.length).toBe(
+			pixels.gainMapWidth * pixels.gainMapHeight * pixels.gainMapChannels
+		);
+	});
+
+	for (const orientation of [1, 6, 8]) {
+		it(`keeps SDR and gain map aligned for orientation ${orientation}`, async () => {
+			const tagged = withExifOrientation(await encodeWide(), orientation);
+			const stored = await decodeToPixels(tagged, false);
+			const oriented = await decodeToPixels(tagged, true);
+
+			const swap = orientation >= 5;
+			expect(oriented.orientation).toBe(orientation);
+			expect(oriented.width).toBe(swap ? stored.height : stored.width);
+			expect(oriented.height).toBe(swap ? stored.width : stored.height);
+			expect(oriented.gainMapWidth).toBe(swap ? stored.gainMapHeight : stored.gainMapWidth);
+			expect(oriented.gainMapHeight).toBe(swap ? stored.gainMapWidth : stored.gainMapHeight);
+			expect(oriented.gainMapChannels).toBe(stored.gainMapChannels);
+
+			expectOriented(oriented.sdr, stored.sdr, orientation, stored.width, stored.height, 4);
+			expectOriented(
+				oriented.gainMap,
+				stored.gainMap,
+				orientation,
+				stored.gainMapWidth,
+				stored.gainMapHeight,
+				stored.gainMapChannels
+			);
+		});
+	}
+
+	it('reports a valid JPEG the decoder cannot handle as backend-unsupported', async () => {
+		const bytes = new Uint8Array(await encodeFixture());
+		// The primary's first SOFn precision byte, patched to 12 bits.
+		let sof = 2;
+		while (bytes[sof + 1] < 0xc0 || bytes[sof + 1] > 0xc2) {
+			sof += 2 + ((bytes[sof + 2] << 8) | bytes[sof + 3]);
+		}
+		bytes[sof + 4] = 12;
+
+		await expect(decodeToPixels(bytes.buffer as ArrayBuffer)).rejects.toMatchObject({
+			name: 'DecodeBackendUnsupportedError',
+			message: expect.stringMatching(/structurally valid but uses 12-bit samples/),
+		});
+	});
+
+	it('reports a JPEG without a frame header as invalid, not unsupported', async () => {
+		const bytes = new Uint8Array([0xff, 0xd8, 0xff, 0xd9]);
+		await expect(decodeToPixels(bytes.buffer)).rejects.toMatchObject({
+			name: 'Error',
+			message: expect.stringMatching(/not a valid JPEG/),
+		});
+	});
 });
@@ -2,9 +2,52 @@
  * Detection tests for isUltraHdr function.
  */
 import { describe, it, expect } from 'vitest';
-import { isUltraHdr } from '../src/index';
+import { isUltraHdr, probeUltraHdr, UltraHdrLayout } from '../src/index';
 import { base64ToArrayBuffer, REGULAR_JPEG_BASE64, PNG_HEADER_BASE64 } from './fixtures/test-data';
 
+/**
+ * Packs two copies of `jpeg` as an MPF stereo pair: the primary gains an APP2
+ * MPF index typing both images as disparity images (MP type 0x020002), and
+ * the second copy follows it. There is no gain map XMP or ISO metadata.
+ */
+function mpfStereoPair(jpeg: Uint8Array): ArrayBuffer {
+	const segment = new Uint8Array(90);
+	const view = new DataView(segment.buffer);
+	view.setUint16(0, 0xffe2);
+	view.setUint16(2, segment.length - 2);
+	segment.set([0x4d, 0x50, 0x46, 0x00], 4); // "MPF\0"
+	const tiff = 8;
+	segment.set([0x4d, 0x4d, 0x00, 0x2a, 0x00, 0x00, 0x00, 0x08], tiff); // "MM", 42, IFD at 8
+	const ifd = tiff + 8;
+	view.setUint16(ifd, 3);
+	const entries: [number, number, number, number][] = [
+		[0xb000, 7, 4, 0x30313030], // MPFVersion "0100"
+		[0xb001, 4, 1, 2], // NumberOfImages
+		[0xb002, 7, 32, 50], // MPEntry, table at TIFF offset 50
+	];
+	entries.forEach(([tag, type, count, value], i) => {
+		const at = ifd + 2 + i * 12;
+		view.setUint16(at, tag);
+		view.setUint16(at + 2, type);
+		view.setUint32(at + 4, count);
+		view.setUint32(at + 8, value);
+	});
+	const table = tiff + 50;
+	const primarySize = jpeg.length + segment.length;
+	view.setUint32(table, 0x20020002); // Primary flag, disparity image
+	view.setUint32(table + 4, primarySize);
+	view.setUint32(table + 16, 0x00020002);
+	view.setUint32(table + 20, jpeg.length);
+	// MP Entry offsets are relative to the TIFF header, which follows the SOI.
+	view.setUint32(table + 24, primarySize - (2 + tiff));
+	const out = new Uint8Array(primarySize + jpeg.length);
+	out.set(jpeg.subarray(0, 2));
+	out.set(segment, 2);
+	out.set(jpeg.subarray(2), 2 + segment.length);
+	out.set(jpeg, primarySize);
+	return out.buffer;
+}
+
 describe('isUltraHdr', () => {
 	it('returns false for empty buffer', async () => {
 		const emptyBuffer = new ArrayBuffer(0);
@@ -39,4 +82,13 @@ describe('isUltraHdr', () => {
 		const result = await isUltraHdr(tinyBuffer);
 		expect(result).toBe(false);
 	});
+
+	it('returns false for an MPF stereo pair without gain map metadata', async () => {
+		const jpeg = new Uint8Array(base64ToArrayBuffer(REGULAR_JPEG_BASE64));
+		const pair = mpfStereoPair(jpeg);
+		expect(await isUltraHdr(pair)).toBe(false);
+		const probe = await probeUltraHdr(pair);
+		expect(probe.layout).toBe(UltraHdrLayout.Unknown);
+		expect(probe.hasMetadata).toBe(false);
+	});
 });
@@ -1,14 +1,89 @@
 /**
- * Encode tests for encodeUltraHdr.
+ * Encode tests for encodeUltraHdr, gain map recompression, and the build's JPEG encoder backend
+ * and capabilities.
  */
 import { describe, it, expect } from 'vitest';
-import { encodeUltraHdr, defaultEncodeOptions } from '../src/index';
+import {
+	capabilities,
+	encodeUltraHdr,
+	encodeUltraHdrWithStats,
+	encodeUltraHdrStrips,
+	encodeUltraHdrToTargetSize,
+	extractSdrBase,
+	getJfifDensity,
+	decodeGainValues,
+	decodeToPixels,
+	decodeUltraHdr,
+	defaultEncodeOptions,
+	encodeFromComponents,
+	isUltraHdr,
+	jpegEncoderBackend,
+	locateComponents,
+	metadataFromJson,
+	applyGainMap,
+	ColorGamut,
+	GainMapDownsample,
+	OddDimensionPolicy,
+	recompressGainMap,
+	recomputeGainMapForSdr,
+	rotateUltraHdr,
+	splitUltraHdr,
+} from '../src/index';
 import {
 	base64ToArrayBuffer,
 	REGULAR_JPEG_BASE64,
+	REGULAR_JPEG_WIDTH,
+	REGULAR_JPEG_HEIGHT,
+	CONTRAST_JPEG_BASE64,
 	PNG_HEADER_BASE64,
 	createSyntheticHdrData,
+	BLOCK_EDGE_JPEG_BASE64,
+	BLOCK_EDGE_JPEG_WIDTH,
+	BLOCK_EDGE_JPEG_HEIGHT,
+	PROGRESSIVE_JPEG_BASE64,
+	WIDE_JPEG_BASE64,
+	WIDE_JPEG_WIDTH,
+	WIDE_JPEG_HEIGHT,
+	ODD_420_JPEG_BASE64,
+	ODD_444_JPEG_BASE64,
+	ODD_JPEG_WIDTH,
+	ODD_JPEG_HEIGHT,
+	UNALIGNED_JPEG_BASE64,
+	encodeFixture,
+	encodeWithGain,
+	hdrWithGain,
+	linearSdr,
+	withExifOrientation,
+	withIccChunks,
+	withJfifDensity,
 } from './fixtures/test-data';
+import { dctBlocks } from './fixtures/jpeg-coefficients';
+
+/** Marker of the first SOFn segment of a JPEG, or -1 if there is none. */
+function sofMarker(jpeg: Uint8Array): number {
+	let at = 2;
+	while (at + 4 <= jpeg.length && jpeg[at] === 0xff) {
+		const marker = jpeg[at + 1];
+		if (marker >= 0xc0 && marker <= 0xcf && ![0xc4, 0xc8, 0xcc].includes(marker)) return marker;
+		at += 2 + ((jpeg[at + 2] << 8) | jpeg[at + 3]);
+	}
+	return -1;
+}
+
+/** Labels the primary's APP2 segments in order: "ICC n" for ICC chunks, "MPF" for MPF. */
+function app2Segments(jpeg: ArrayBuffer): string[] {
+	const bytes = new Uint8Array(jpeg);
+	const labels: string[] = [];
+	let at = 2;
+	while (at + 4 <= bytes.length && bytes[at] === 0xff && bytes[at + 1] !== 0xda) {
+		const length = (bytes[at + 2] << 8) | bytes[at + 3];
+		const id = new TextDecoder('latin1').decode(bytes.subarray(at + 4, at + 16));
+		if (bytes[at + 1] === 0xe2 && id === 'ICC_PROFILE\0') labels.push(`ICC ${bytes[at + 16]}`);
+		if (bytes[at + 1] === 0xe2 && id.startsWith('MPF\0')) labels.push('MPF');
+		at += 2 + length;
+	}
+	return labels;
+}
 
 describe('encodeUltraHdr', () => {
 	it('produces valid JPEG output', async () => {
@@ -108,4 +183,977 @@ describe('encodeUltraHdr', () => {
 		});
 		expect(highCap.byteLength).toBeGreaterThan(0);
 	});
+
+	it('rejects an SDR JPEG with more than 8 bits per sample', async () => {
+		const sdr = new Uint8Array(base64ToArrayBuffer(REGULAR_JPEG_BASE64));
+		// Patch the SOF precision byte (after marker and length) to 12 bits.
+		let sof = 2;
+		while (sdr[sof + 1] < 0xc0 || sdr[sof + 1] > 0xc2) {
+			sof += 2 + ((sdr[sof + 2] << 8) | sdr[sof + 3]);
+		}
+		sdr[sof + 4] = 12;
+		const hdrData = createSyntheticHdrData(16, 16);
+
+		await expect(
+			encodeUltraHdr('encode-12bit', sdr.buffer, hdrData.buffer, defaultEncodeOptions)
+		).rejects.toThrow(/12-bit/);
+	});
+
+	it('points 16-bit SDR JPEGs at the float HDR input', async () => {
+		const sdr = new Uint8Array(base64ToArrayBuffer(REGULAR_JPEG_BASE64));
+		let sof = 2;
+		while (sdr[sof + 1] < 0xc0 || sdr[sof + 1] > 0xc2) {
+			sof += 2 + ((sdr[sof + 2] << 8) | sdr[sof + 3]);
+		}
+		sdr[sof + 4] = 16;
+		const hdrData = createSyntheticHdrData(16, 16);
+
+		// Rejected before libjpeg sees it, with a message naming the precision
+		// and where high bit-depth content belongs instead.
+		await expect(
+			encodeUltraHdr('encode-16bit', sdr.buffer, hdrData.buffer, defaultEncodeOptions)
+		).rejects.toThrow(/16-bit samples.*linear float HDR/);
+	});
+
+	it('rejects HDR capacity targets outside (0, 10] stops', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const hdrData = createSyntheticHdrData(16, 16);
+		const encodeWith = (targetHdrCapacity: number) =>
+			encodeUltraHdr('cap-range', sdrBuffer, hdrData.buffer, {
+				...defaultEncodeOptions,
+				targetHdrCapacity,
+			});
+
+		await expect(encodeWith(0)).rejects.toThrow(/Invalid HDR capacity/);
+		await expect(encodeWith(50)).rejects.toThrow(/Invalid HDR capacity/);
+		expect((await encodeWith(4)).byteLength).toBeGreaterThan(0);
+	});
+
+	it('interprets HDR input according to hdrGamut', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		// Saturated red highlight: a gamut mismatch shows up most on saturated colors.
+		const hdrData = new Float32Array(16 * 16 * 3);
+		for (let i = 0; i < 16 * 16; i++) {
+			hdrData[i * 3] = 3.0;
+			hdrData[i * 3 + 1] = 0.05;
+			hdrData[i * 3 + 2] = 0.05;
+		}
+
+		const asSrgb = await encodeUltraHdr('gamut-srgb', sdrBuffer, hdrData.buffer, {
+			...defaultEncodeOptions,
+			hdrGamut: ColorGamut.Srgb,
+		});
+		const asBt2100 = await encodeUltraHdr('gamut-bt2100', sdrBuffer, hdrData.buffer, {
+			...defaultEncodeOptions,
+			hdrGamut: ColorGamut.Bt2100,
+		});
+
+		const srgbGainMap = (await decodeUltraHdr('gamut-srgb-decode', asSrgb)).gainMap;
+		const bt2100GainMap = (await decodeUltraHdr('gamut-bt2100-decode', asBt2100)).gainMap;
+		expect(Array.from(srgbGainMap)).not.toEqual(Array.from(bt2100GainMap));
+	});
+
+	it('throws error for an unknown hdrGamut', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const hdrData = createSyntheticHdrData(16, 16);
+
+		await expect(
+			encodeUltraHdr('gamut-bad', sdrBuffer, hdrData.buffer, {
+				...defaultEncodeOptions,
+				hdrGamut: 7 as ColorGamut,
+			})
+		).rejects.toThrow();
+	});
+
+	it('writes a gamma of exactly 1.0 on both encode paths', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const hdrData = createSyntheticHdrData(16, 16);
+
+		for (const gainMapDownsample of [GainMapDownsample.Encoder, GainMapDownsample.BoxAverage]) {
+			const encoded = await encodeUltraHdr('linear-gamma', sdrBuffer, hdrData.buffer, {
+				...defaultEncodeOptions,
+				gainMapDownsample,
+			});
+
+			const { metadata } = await decodeUltraHdr('linear-gamma-decode', encoded);
+			expect(metadata.gamma).toEqual([1, 1, 1]);
+		}
+	});
+
+	it('never writes a gainMapMax above absoluteMaxGain', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const hdrData = createSyntheticHdrData(16, 16);
+		// A highlight 64x brighter than SDR white, well past the cap.
+		hdrData.fill(64, 0, 3 * 16 * 4);
+
+		const encoded = await encodeUltraHdr('max-gain', sdrBuffer, hdrData.buffer, {
+			...defaultEncodeOptions,
+			targetHdrCapacity: 4,
+			absoluteMaxGain: 2,
+		});
+
+		const { metadata } = await decodeUltraHdr('max-gain-decode', encoded);
+		for (const max of metadata.gainMapMax) {
+			expect(max).toBeLessThanOrEqual(2 + 1e-3);
+		}
+	});
+
+	it('sets gainMapMax at metadataMaxPercentile, ignoring a hot pixel', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		// Every sample 2 stops above the SDR, except one pixel (0.4%) at 8 stops.
+		const hdr = await hdrWithGain(REGULAR_JPEG_BASE64, (x, y) => (x === 0 && y === 0 ? 256 : 4));
+
+		const gainMapMaxAt = async (metadataMaxPercentile: number) => {
+			const encoded = await encodeUltraHdr('percentile', sdrBuffer, hdr.buffer, {
+				...defaultEncodeOptions,
+				targetHdrCapacity: 10,
+				metadataMaxPercentile,
+			});
+			return Math.max(...(await decodeUltraHdr('percentile-decode', encoded)).metadata.gainMapMax);
+		};
+		const clipped = await gainMapMaxAt(99.5);
+		expect(clipped).toBeGreaterThan(1.9);
+		expect(clipped).toBeLessThan(2.1);
+		expect(await gainMapMaxAt(100)).toBeGreaterThan(7.9);
+	});
+
+	it('rejects a metadataMaxPercentile outside [0, 100]', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const hdrData = createSyntheticHdrData(16, 16);
+
+		await expect(
+			encodeUltraHdr('percentile-bad', sdrBuffer, hdrData.buffer, {
+				...defaultEncodeOptions,
+				metadataMaxPercentile: 101,
+			})
+		).rejects.toThrow(/percentile/);
+	});
+
+	it('keeps a progressive SDR base unless forceBaselineBase is set', async () => {
+		const sdrBuffer = base64ToArrayBuffer(PROGRESSIVE_JPEG_BASE64);
+		const hdrData = createSyntheticHdrData(16, 16);
+
+		const kept = await encodeUltraHdr(
+			'progressive',
+			sdrBuffer,
+			hdrData.buffer,
+			defaultEncodeOptions
+		);
+		const keptDecoded = await decodeUltraHdr('progressive-decode', kept);
+		expect(keptDecoded.primaryIsProgressive).toBe(true);
+		expect(sofMarker(keptDecoded.sdrImage)).toBe(0xc2);
+
+		const forced = await encodeUltraHdr('baseline', sdrBuffer, hdrData.buffer, {
+			...defaultEncodeOptions,
+			forceBaselineBase: true,
+		});
+		const forcedDecoded = await decodeUltraHdr('baseline-decode', forced);
+		expect(forcedDecoded.primaryIsProgressive).toBe(false);
+		expect(sofMarker(forcedDecoded.sdrImage)).toBe(0xc0);
+		expect(await isUltraHdr(forced)).toBe(true);
+	});
+
+	it('writes blackPointOffset into the metadata and reconstructs shadows with it', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const sdr = (await linearSdr(REGULAR_JPEG_BASE64)).data;
+		// A flat 1-stop gain under a 1/128 offset on both sides.
+		const offset = 1 / 128;
+		const hdr = await hdrWithGain(REGULAR_JPEG_BASE64, () => 2, offset);
+
+		const encoded = await encodeUltraHdr('offset', sdrBuffer, hdr.buffer, {
+			...defaultEncodeOptions,
+			blackPointOffset: offset,
+		});
+		const { metadata } = await decodeUltraHdr('offset-decode', encoded);
+		for (let c = 0; c < 3; c++) {
+			expect(metadata.offsetSdr[c]).toBeCloseTo(offset, 6);
+			expect(metadata.offsetHdr[c]).toBeCloseTo(offset, 6);
+		}
+
+		// The darkest tenth of samples, where a mismatched offset shows most.
+		const reconstructed = (await applyGainMap(encoded, metadata.hdrCapacityMax)).data;
+		const shadowLimit = Array.from(sdr).sort((a, b) => a - b)[Math.floor(sdr.length / 10)];
+		for (let i = 0; i < sdr.length; i++) {
+			if (sdr[i] > shadowLimit) continue;
+			expect(Math.abs(reconstructed[i] - hdr[i])).toBeLessThan(0.005);
+		}
+	});
+
+	it('records the exact offsets every computed-gain-map route encodes with', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const offset = 1 / 32;
+		const hdr = await hdrWithGain(REGULAR_JPEG_BASE64, () => 2, offset);
+
+		const options = { ...defaultEncodeOptions, blackPointOffset: offset };
+		const routes = [
+			await encodeUltraHdr('offset-plain', sdrBuffer, hdr.buffer, options),
+			await encodeUltraHdr('offset-percentile', sdrBuffer, hdr.buffer, {
+				...options,
+				metadataMaxPercentile: 100,
+			}),
+			await encodeUltraHdrStrips(
+				'offset-strips',
+				sdrBuffer,
+				(y, rows) => hdr.subarray(y * 16 * 3, (y + rows) * 16 * 3),
+				4,
+				options
+			),
+		];
+		for (const encoded of routes) {
+			const { metadata } = await decodeUltraHdr('offset-routes', encoded);
+			expect(metadata.offsetSdr).toEqual([offset, offset, offset]);
+			expect(metadata.offsetHdr).toEqual([offset, offset, offset]);
+
+			// Decoding with those offsets inverts the encode: a flat 1-stop gain.
+			const full = (await applyGainMap(encoded, metadata.hdrCapacityMax)).data;
+			for (let i = 0; i < sdr.length; i++) {
+				expect((full[i] + offset) / (sdr[i] + offset)).toBeCloseTo(2, 1);
+			}
+		}
+	});
+
+	it('throws error for a blackPointOffset outside (0, 1)', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const hdrData = createSyntheticHdrData(16, 16);
+		for (const blackPointOffset of [0, 1, Number.NaN]) {
+			await expect(
+				encodeUltraHdr('offset-bad', sdrBuffer, hdrData.buffer, {
+					...defaultEncodeOptions,
+					blackPointOffset,
+				})
+			).rejects.toThrow(/black point offset/);
+		}
+	});
+
+	it('smooths the gain map with gainMapBlurSigma while keeping its mean', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		// Per-pixel gains of 0 to 2 stops from a fixed LCG, for a speckled gain map.
+		let seed = 1;
+		const gains = Array.from({ length: 16 * 16 }, () => {
+			seed = (seed * 1103515245 + 12345) % 2 ** 31;
+			return 2 ** ((2 * seed) / 2 ** 31);
+		});
+		const hdr = await hdrWithGain(REGULAR_JPEG_BASE64, (x, y) => gains[y * 16 + x]);
+
+		const stats = async (gainMapBlurSigma: number) => {
+			const encoded = await encodeUltraHdr('blur', sdrBuffer, hdr.buffer, {
+				...defaultEncodeOptions,
+				gainMapBlurSigma,
+			});
+			const { gainMap } = await decodeToPixels(encoded);
+			const mean = gainMap.reduce((sum, v) => sum + v, 0) / gainMap.length;
+			const variance = gainMap.reduce((sum, v) => sum + (v - mean) ** 2, 0) / gainMap.length;
+			return { mean, variance };
+		};
+		const plain = await stats(0);
+		const blurred = await stats(1.5);
+		expect(blurred.variance).toBeLessThan(plain.variance / 2);
+		expect(Math.abs(blurred.mean - plain.mean)).toBeLessThan(3);
+	});
+
+	it('keeps a checkerboard steady with BoxAverage where CenterSample aliases', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		// 3-pixel squares alternating 2 stops and 0 stops of gain, against 4x4
+		// gain map blocks, so block centers land on either square unevenly.
+		const hdr = await hdrWithGain(REGULAR_JPEG_BASE64, (x, y) =>
+			(Math.floor(x / 3) + Math.floor(y / 3)) % 2 === 0 ? 4 : 1
+		);
+
+		const stats = async (gainMapDownsample: GainMapDownsample) => {
+			const encoded = await encodeUltraHdr('downsample', sdrBuffer, hdr.buffer, {
+				...defaultEncodeOptions,
+				gainMapScale: 4,
+				gainMapQuality: 100,
+				gainMapDownsample,
+			});
+			const { gainMap } = await decodeToPixels(encoded);
+			const mean = gainMap.reduce((sum, v) => sum + v, 0) / gainMap.length;
+			const variance = gainMap.reduce((sum, v) => sum + (v - mean) ** 2, 0) / gainMap.length;
+			return { mean, variance };
+		};
+		const center = await stats(GainMapDownsample.CenterSample);
+		const box = await stats(GainMapDownsample.BoxAverage);
+		const max = await stats(GainMapDownsample.MaxGain);
+		expect(box.variance).toBeLessThan(center.variance / 4);
+		expect(max.mean).toBeGreaterThan(box.mean);
+	});
+
+	it('caps gainMapScale at the smaller image dimension', async () => {
+		const cases = [
+			{ base64: REGULAR_JPEG_BASE64, width: 16, height: 16, scale: 32 },
+			{ base64: WIDE_JPEG_BASE64, width: WIDE_JPEG_WIDTH, height: WIDE_JPEG_HEIGHT, scale: 64 },
+		];
+		for (const { base64, width, height, scale } of cases) {
+			for (const gainMapDownsample of [GainMapDownsample.Encoder, GainMapDownsample.BoxAverage]) {
+				const encoded = await encodeUltraHdr(
+					'tiny-gain-map',
+					base64ToArrayBuffer(base64),
+					createSyntheticHdrData(width, height).buffer,
+					{ ...defaultEncodeOptions, gainMapScale: scale, gainMapDownsample }
+				);
+				// Both axes share one scale, the smaller dimension.
+				const effective = Math.min(width, height);
+				const decoded = await decodeUltraHdr('tiny-gain-map', encoded);
+				expect(decoded.gainMapWidth).toBe(Math.ceil(width / effective));
+				expect(decoded.gainMapHeight).toBe(Math.ceil(height / effective));
+
+				const hdr = await applyGainMap(encoded);
+				expect(hdr.data.length).toBe(width * height * 3);
+				for (const v of hdr.data) expect(Number.isFinite(v)).toBe(true);
+			}
+		}
+	});
+
+	it('throws error for an unknown gainMapDownsample', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const hdrData = createSyntheticHdrData(16, 16);
+		await expect(
+			encodeUltraHdr('downsample-bad', sdrBuffer, hdrData.buffer, {
+				...defaultEncodeOptions,
+				gainMapDownsample: 7 as GainMapDownsample,
+			})
+		).rejects.toThrow(/downsample mode/);
+	});
+
+	it('throws error for a negative or non-finite gainMapBlurSigma', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const hdrData = createSyntheticHdrData(16, 16);
+		for (const gainMapBlurSigma of [-1, Number.NaN, Number.POSITIVE_INFINITY]) {
+			await expect(
+				encodeUltraHdr('blur-bad', sdrBuffer, hdrData.buffer, {
+					...defaultEncodeOptions,
+					gainMapBlurSigma,
+				})
+			).rejects.toThrow(/blur sigma/);
+		}
+	});
+
+	it('does not let one dark-SDR, bright-HDR pixel widen the gain range', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const options = { ...defaultEncodeOptions, targetHdrCapacity: 3 };
+		const plain = createSyntheticHdrData(16, 16);
+		const outlier = plain.slice();
+		// A ratio of thousands against any SDR value, the case that would
+		// dominate a content-measured maximum.
+		outlier.fill(5000, 0, 3);
+
+		const a = await encodeUltraHdr('outlier-a', sdrBuffer, plain.buffer, options);
+		const b = await encodeUltraHdr('outlier-b', sdrBuffer, outlier.buffer, options);
+
+		const plainMax = (await decodeUltraHdr('outlier-a-decode', a)).metadata.gainMapMax;
+		const outlierMax = (await decodeUltraHdr('outlier-b-decode', b)).metadata.gainMapMax;
+		expect(outlierMax).toEqual(plainMax);
+		for (const max of outlierMax) {
+			expect(max).toBeLessThanOrEqual(3 + 1e-3);
+		}
+	});
+
+	it('describes the gain map length in the GContainer XMP', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const hdrData = createSyntheticHdrData(16, 16);
+
+		const encoded = await encodeUltraHdr(
+			'gcontainer',
+			sdrBuffer,
+			hdrData.buffer,
+			defaultEncodeOptions
+		);
+
+		// Readers that ignore MPF find the gain map from Item:Length alone.
+		const { primary, gainMap, xmp } = await locateComponents(encoded);
+		expect(xmp).not.toBeNull();
+		const text = new TextDecoder('latin1').decode(new Uint8Array(encoded, 0, primary.length));
+		const item = text.match(/<Container:Item[^>]*Item:Semantic="GainMap"[^>]*>/);
+		expect(item).not.toBeNull();
+		expect(item![0]).toContain(`Item:Length="${gainMap.length}"`);
+		expect(gainMap.offset).toBe(primary.length);
+	});
+
+	it('computes a single-channel gain map from custom lumaCoefficients', async () => {
+		// Two stops on red only, so the luminance ratio depends on the weights.
+		const channelGain = [4, 1, 1];
+		const hdr = await hdrWithGain(REGULAR_JPEG_BASE64, (x, y, c) => channelGain[c]);
+		const sdr = (await linearSdr(REGULAR_JPEG_BASE64)).data;
+		const offset = defaultEncodeOptions.blackPointOffset;
+		const ratio = (weights: number[], pixel: number) => {
+			const luma = (v: Float32Array) =>
+				weights[0] * v[pixel * 3] + weights[1] * v[pixel * 3 + 1] + weights[2] * v[pixel * 3 + 2];
+			return (luma(hdr) + offset) / (luma(sdr) + offset);
+		};
+
+		const third = 1 / 3;
+		const encoded = await encodeUltraHdr(
+			'luma',
+			base64ToArrayBuffer(REGULAR_JPEG_BASE64),
+			hdr.buffer,
+			{ ...defaultEncodeOptions, gainMapQuality: 100, lumaCoefficients: [third, third, third] }
+		);
+		const gains = await decodeGainValues(encoded);
+		expect(gains.channels).toBe(1);
+		expect(gains.data.length).toBe(REGULAR_JPEG_WIDTH * REGULAR_JPEG_HEIGHT);
+
+		let bt709Gap = 0;
+		for (let p = 0; p < gains.data.length; p++) {
+			expect(gains.data[p]).toBeCloseTo(ratio([third, third, third], p), 1);
+			bt709Gap = Math.max(bt709Gap, Math.abs(gains.data[p] - ratio([0.2126, 0.7152, 0.0722], p)));
+		}
+		expect(bt709Gap).toBeGreaterThan(0.2);
+	});
+
+	it('writes a single-channel gain map full range, not 16-235', async () => {
+		// No gain quantizes to 0 and a gain far past the range to 255.
+		const gainMapFor = async (gain: number) => {
+			const encoded = await encodeWithGain(REGULAR_JPEG_BASE64, () => gain, {
+				gainMapQuality: 100,
+				lumaCoefficients: [1 / 3, 1 / 3, 1 / 3],
+			});
+			return (await decodeToPixels(encoded)).gainMap;
+		};
+		expect(Math.max(...(await gainMapFor(1)))).toBeLessThanOrEqual(1);
+		expect(Math.min(...(await gainMapFor(256)))).toBeGreaterThanOrEqual(254);
+	});
+
+	it('throws error for lumaCoefficients with wide-gamut HDR', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const hdrData = createSyntheticHdrData(16, 16);
+		await expect(
+			encodeUltraHdr('luma-p3', sdrBuffer, hdrData.buffer, {
+				...defaultEncodeOptions,
+				hdrGamut: ColorGamut.DisplayP3,
+				lumaCoefficients: [0.25, 0.5, 0.25],
+			})
+		).rejects.toThrow(/BT.709/);
+	});
+
+	it('rounds each side of a computed gain map up to whole blocks', async () => {
+		const encoded = await encodeUltraHdr(
+			'gain-map-ceil',
+			base64ToArrayBuffer(WIDE_JPEG_BASE64),
+			createSyntheticHdrData(WIDE_JPEG_WIDTH, WIDE_JPEG_HEIGHT).buffer,
+			{ ...defaultEncodeOptions, gainMapScale: 5, gainMapDownsample: GainMapDownsample.BoxAverage }
+		);
+		// 32x16 in 5x5 blocks: the partial blocks on the right and bottom count.
+		const decoded = await decodeUltraHdr('gain-map-ceil', encoded);
+		expect(decoded.gainMapWidth).toBe(7);
+		expect(decoded.gainMapHeight).toBe(4);
+	});
+
+	it('never places MPF between the chunks of a multi-chunk ICC profile', async () => {
+		// A stand-in profile: a header and opaque tag data, split over 3 APP2 segments.
+		const profile = new Uint8Array(3000);
+		new DataView(profile.buffer).setUint32(0, profile.length);
+		profile.set(new TextEncoder().encode('mntrRGB XYZ '), 12);
+		profile.set(new TextEncoder().encode('acsp'), 36);
+		for (let i = 128; i < profile.length; i++) profile[i] = i & 0xff;
+		const sdrBuffer = withIccChunks(base64ToArrayBuffer(REGULAR_JPEG_BASE64), profile, 3);
+		const hdrData = createSyntheticHdrData(16, 16);
+
+		// The default options let libultrahdr encode the whole file; BoxAverage
+		// computes the gain map here and only assembles through libultrahdr.
+		for (const gainMapDownsample of [GainMapDownsample.Encoder, GainMapDownsample.BoxAverage]) {
+			const encoded = await encodeUltraHdr('icc-chunks', sdrBuffer, hdrData.buffer, {
+				...defaultEncodeOptions,
+				gainMapDownsample,
+			});
+
+			const labels = app2Segments(encoded);
+			expect(labels.filter((label) => label !== 'MPF')).toEqual(['ICC 1', 'ICC 2', 'ICC 3']);
+			expect(labels.filter((label) => label === 'MPF')).toHaveLength(1);
+			expect([0, 3]).toContain(labels.indexOf('MPF'));
+		}
+	});
+});
+
+describe('encodeUltraHdrWithStats', () => {
+	it('returns the encoded JPEG with per-channel clipping fractions', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const hdrData = createSyntheticHdrData(16, 16);
+
+		const { data, stats } = await encodeUltraHdrWithStats(
+			'stats-1',
+			sdrBuffer,
+			hdrData.buffer,
+			defaultEncodeOptions
+		);
+
+		const view = new Uint8Array(data);
+		expect(view[0]).toBe(0xff);
+		expect(view[1]).toBe(0xd8);
+		expect(stats.clippedLow).toHaveLength(3);
+		expect(stats.clippedHigh).toHaveLength(3);
+		for (const fraction of [...stats.clippedLow, ...stats.clippedHigh]) {
+			expect(fraction).toBeGreaterThanOrEqual(0);
+			expect(fraction).toBeLessThanOrEqual(1);
+		}
+	});
+
+	it('reports more high clipping for a too-narrow gain range', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const hdrData = createSyntheticHdrData(16, 16);
+
+		const narrow = await encodeUltraHdrWithStats('stats-narrow', sdrBuffer, hdrData.buffer, {
+			...defaultEncodeOptions,
+			targetHdrCapacity: 0.1,
+		});
+		const wide = await encodeUltraHdrWithStats('stats-wide', sdrBuffer, hdrData.buffer, {
+			...defaultEncodeOptions,
+			targetHdrCapacity: 6.0,
+		});
+
+		const narrowMax = Math.max(...narrow.stats.clippedHigh);
+		expect(narrowMax).toBeGreaterThan(0);
+		expect(narrowMax).toBeGreaterThan(Math.max(...wide.stats.clippedHigh));
+	});
+
+	it('omits a near-neutral gain map when omitTrivialGainMap is set', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		// HDR identical to the linearized SDR base.
+		const hdrData = (await linearSdr(REGULAR_JPEG_BASE64)).data;
+
+		const options = { ...defaultEncodeOptions, omitTrivialGainMap: true };
+		const result = await encodeUltraHdrWithStats('trivial', sdrBuffer, hdrData.buffer, options);
+
+		expect(result.gainMapEmbedded).toBe(false);
+		expect(await isUltraHdr(result.data)).toBe(false);
+		expect(new Uint8Array(result.data)).toEqual(new Uint8Array(sdrBuffer));
+	});
+
+	it('keeps a meaningful gain map when omitTrivialGainMap is set', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const hdrData = createSyntheticHdrData(16, 16);
+
+		const options = { ...defaultEncodeOptions, omitTrivialGainMap: true };
+		const result = await encodeUltraHdrWithStats('kept', sdrBuffer, hdrData.buffer, options);
+
+		expect(result.gainMapEmbedded).toBe(true);
+		expect(await isUltraHdr(result.data)).toBe(true);
+	});
+});
+
+describe('oddDimensionPolicy', () => {
+	const hdrData = createSyntheticHdrData(ODD_JPEG_WIDTH, ODD_JPEG_HEIGHT);
+
+	it('rejects odd 4:2:0 dimensions by default', async () => {
+		const sdrBuffer = base64ToArrayBuffer(ODD_420_JPEG_BASE64);
+		await expect(
+			encodeUltraHdr('odd-reject', sdrBuffer, hdrData.buffer, defaultEncodeOptions)
+		).rejects.toThrow(/odd dimensions 15x8/);
+	});
+
+	it('encodes an odd-sized 4:4:4 SDR as it is by default', async () => {
+		const sdrBuffer = base64ToArrayBuffer(ODD_444_JPEG_BASE64);
+		const result = await encodeUltraHdrWithStats(
+			'odd-444',
+			sdrBuffer,
+			hdrData.buffer,
+			defaultEncodeOptions
+		);
+
+		expect(result.width).toBe(ODD_JPEG_WIDTH);
+		expect(result.height).toBe(ODD_JPEG_HEIGHT);
+		expect(await isUltraHdr(result.data)).toBe(true);
+		const decoded = await decodeUltraHdr('odd-444', result.data);
+		expect(decoded.width).toBe(ODD_JPEG_WIDTH);
+		expect(decoded.height).toBe(ODD_JPEG_HEIGHT);
+	});
+
+	/**
+	 * Encodes the 15x8 fixture with 2 stops of gain on its left 7 columns and
+	 * none on the rest, then checks the reconstructed gain per column. The HDR
+	 * input is built from the linear SDR a first encode with the same policy
+	 * produces, which the second encode reproduces exactly.
+	 */
+	async function expectAlignedGain(policy: OddDimensionPolicy, width: number) {
+		const options = { ...defaultEncodeOptions, gainMapQuality: 100, oddDimensionPolicy: policy };
+		const sdr = (await linearSdr(ODD_420_JPEG_BASE64, options)).data;
+
+		const hdr = new Float32Array(ODD_JPEG_WIDTH * ODD_JPEG_HEIGHT * 3);
+		for (let y = 0; y < ODD_JPEG_HEIGHT; y++) {
+			for (let x = 0; x < ODD_JPEG_WIDTH; x++) {
+				for (let c = 0; c < 3; c++) {
+					// A cropped last column gets a wild value that must not show up.
+					const value = x < width ? sdr[(y * width + x) * 3 + c] * (x < 7 ? 4 : 1) : 100;
+					hdr[(y * ODD_JPEG_WIDTH + x) * 3 + c] = value;
+				}
+			}
+		}
+
+		const result = await encodeUltraHdrWithStats(
+			'odd',
+			base64ToArrayBuffer(ODD_420_JPEG_BASE64),
+			hdr.buffer,
+			options
+		);
+		expect(result.width).toBe(width);
+		expect(result.height).toBe(ODD_JPEG_HEIGHT);
+		const decoded = await decodeUltraHdr('odd', result.data);
+		expect(decoded.width).toBe(width);
+		expect(decoded.height).toBe(ODD_JPEG_HEIGHT);
+
+		const base = (await applyGainMap(result.data, 0)).data;
+		const boosted = (await applyGainMap(result.data)).data;
+		expect(boosted.length).toBe(width * ODD_JPEG_HEIGHT * 3);
+		for (let y = 0; y < ODD_JPEG_HEIGHT; y++) {
+			// Skip the columns either side of the step, where JPEG rings.
+			for (const x of [0, 1, 2, 3, 4, 5, 8, 9, 10, 11, 12, 13, width - 1]) {
+				const i = (y * width + x) * 3 + 1;
+				if (base[i] < 0.05) continue;
+				expect(Math.log2(boosted[i] / base[i])).toBeCloseTo(x < 7 ? 2 : 0, 0);
+			}
+		}
+	}
+
+	it('crops the last column with CropToEven', async () => {
+		await expectAlignedGain(OddDimensionPolicy.CropToEven, ODD_JPEG_WIDTH - 1);
+	});
+
+	it('pads by repeating the last column with PadToEven', async () => {
+		await expectAlignedGain(OddDimensionPolicy.PadToEven, ODD_JPEG_WIDTH + 1);
+	});
+
+	it('keeps the JFIF density of the SDR it re-encodes', async () => {
+		const sdrBuffer = withJfifDensity(base64ToArrayBuffer(ODD_420_JPEG_BASE64), 1, 300, 300);
+		const density = { units: 1, xDensity: 300, yDensity: 300 };
+		expect(await getJfifDensity(sdrBuffer)).toEqual(density);
+
+		for (const policy of [OddDimensionPolicy.CropToEven, OddDimensionPolicy.PadToEven]) {
+			const encoded = await encodeUltraHdr('odd-density', sdrBuffer, hdrData.buffer, {
+				...defaultEncodeOptions,
+				oddDimensionPolicy: policy,
+			});
+			expect(await getJfifDensity(encoded)).toEqual(density);
+		}
+	});
+
+	it("keeps the SDR's Exif orientation through a crop or pad", async () => {
+		const sdrBuffer = withExifOrientation(base64ToArrayBuffer(ODD_420_JPEG_BASE64), 6);
+		for (const policy of [OddDimensionPolicy.CropToEven, OddDimensionPolicy.PadToEven]) {
+			const encoded = await encodeUltraHdr('odd-exif', sdrBuffer, hdrData.buffer, {
+				...defaultEncodeOptions,
+				oddDimensionPolicy: policy,
+			});
+			expect((await decodeToPixels(encoded)).orientation).toBe(6);
+		}
+	});
+
+	it('crops the SDR in the DCT domain instead of re-encoding it', async () => {
+		const width = BLOCK_EDGE_JPEG_WIDTH;
+		const height = BLOCK_EDGE_JPEG_HEIGHT;
+		const source = new Uint8Array(base64ToArrayBuffer(BLOCK_EDGE_JPEG_BASE64));
+		const edgeHdr = createSyntheticHdrData(width, height);
+		const encodeCropped = (baseQuality: number) =>
+			encodeUltraHdr('odd-crop', source.slice().buffer, edgeHdr.buffer, {
+				...defaultEncodeOptions,
+				baseQuality,
+				oddDimensionPolicy: OddDimensionPolicy.CropToEven,
+			});
+
+		const encoded = await encodeCropped(10);
+		expect((await decodeUltraHdr('odd-crop', encoded)).width).toBe(width - 1);
+		// The 17th column takes a block column of its own in every component.
+		// Each kept block holds the source's coefficients, whatever baseQuality.
+		const cropped = new Uint8Array(await extractSdrBase(encoded));
+		const before = dctBlocks(source);
+		const after = dctBlocks(cropped);
+		expect(after.map((rows) => rows[0].length)).toEqual([2, 1, 1]);
+		expect(before.map((rows) => rows[0].length)).toEqual([3, 2, 2]);
+		after.forEach((rows, c) => {
+			expect(rows).toEqual(before[c].map((row) => row.slice(0, rows[0].length)));
+		});
+		const again = new Uint8Array(await extractSdrBase(await encodeCropped(100)));
+		expect(Array.from(again)).toEqual(Array.from(cropped));
+
+		// Decode the source through the same path by pairing it with the gain map.
+		const parts = await splitUltraHdr(encoded);
+		const uncropped = await encodeFromComponents(
+			source.slice().buffer,
+			parts.gainMapJpeg.buffer as ArrayBuffer,
+			await metadataFromJson(parts.metadataJson)
+		);
+		const sourcePixels = (await decodeToPixels(uncropped)).sdr;
+		const croppedPixels = (await decodeToPixels(encoded)).sdr;
+		for (let y = 0; y < height; y++) {
+			// The last kept column is left out: a decoder that upsamples chroma
+			// between neighbouring samples blends in the one the crop dropped.
+			for (let x = 0; x < width - 2; x++) {
+				const at = (y * width + x) * 4;
+				const kept = (y * (width - 1) + x) * 4;
+				expect(Array.from(croppedPixels.subarray(kept, kept + 4))).toEqual(
+					Array.from(sourcePixels.subarray(at, at + 4))
+				);
+			}
+		}
+	});
+
+	it('throws error for an unknown oddDimensionPolicy', async () => {
+		const sdrBuffer = base64ToArrayBuffer(ODD_420_JPEG_BASE64);
+		await expect(
+			encodeUltraHdr('odd-bad', sdrBuffer, hdrData.buffer, {
+				...defaultEncodeOptions,
+				oddDimensionPolicy: 7 as OddDimensionPolicy,
+			})
+		).rejects.toThrow(/odd dimension policy/);
+	});
+});
+
+describe('recomputeGainMapForSdr', () => {
+	/** Linear HDR target 2 stops above REGULAR_JPEG, brighter than either grade everywhere. */
+	const hdrTarget = () => hdrWithGain(REGULAR_JPEG_BASE64, () => 4);
+
+	function spread(values: Float32Array): number {
+		const green = values.filter((_, i) => i % 3 === 1);
+		const mean = green.reduce((sum, v) => sum + v, 0) / green.length;
+		return Math.sqrt(green.reduce((sum, v) => sum + (v - mean) ** 2, 0) / green.length);
+	}
+
+	it('reconstructs the HDR target from a contrast-boosted SDR', async () => {
+		const target = await hdrTarget();
+		const encoded = await recomputeGainMapForSdr(
+			base64ToArrayBuffer(CONTRAST_JPEG_BASE64),
+			target.buffer as ArrayBuffer,
+			16,
+			16,
+			{ ...defaultEncodeOptions, gainMapQuality: 100, targetHdrCapacity: 6 }
+		);
+		expect(await isUltraHdr(encoded)).toBe(true);
+
+		// The graded SDR is kept as the base rendition.
+		const base = (await applyGainMap(encoded, 0)).data;
+		const original = (await linearSdr(REGULAR_JPEG_BASE64)).data;
+		expect(spread(base)).toBeGreaterThan(spread(original) * 1.2);
+
+		const hdr = (await applyGainMap(encoded)).data;
+		expect(hdr.length).toBe(target.length);
+		for (let i = 0; i < hdr.length; i++) {
+			const error = Math.log2((hdr[i] + 1 / 64) / (target[i] + 1 / 64));
+			expect(Math.abs(error)).toBeLessThan(0.25);
+		}
+	});
+
+	it('throws when the HDR target and SDR sizes differ', async () => {
+		const target = await hdrTarget();
+		await expect(
+			recomputeGainMapForSdr(
+				base64ToArrayBuffer(CONTRAST_JPEG_BASE64),
+				target.buffer as ArrayBuffer,
+				32,
+				8
+			)
+		).rejects.toThrow(/32x8 but the SDR is 16x16/);
+	});
+
+	it('explains an HDR target given in the display orientation of a rotated SDR', async () => {
+		const sdr = withExifOrientation(base64ToArrayBuffer(WIDE_JPEG_BASE64), 6);
+		const target = new Float32Array(WIDE_JPEG_WIDTH * WIDE_JPEG_HEIGHT * 3).fill(1);
+		await expect(
+			recomputeGainMapForSdr(sdr, target.buffer as ArrayBuffer, WIDE_JPEG_HEIGHT, WIDE_JPEG_WIDTH)
+		).rejects.toThrow(/Exif orientation 6.*stored/);
+	});
+});
+
+describe('orientation', () => {
+	const hdrData = createSyntheticHdrData(16, 16);
+	const encodeWith = (sdr: ArrayBuffer, orientation: number) =>
+		encodeUltraHdr('orientation', sdr, hdrData.buffer, { ...defaultEncodeOptions, orientation });
+
+	it('tags an SDR without Exif with the requested orientation', async () => {
+		const sdr = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		for (const orientation of [1, 6, 8]) {
+			const encoded = await encodeWith(sdr, orientation);
+			const pixels = await decodeToPixels(encoded);
+			expect(pixels.orientation).toBe(orientation);
+			expect(pixels.width).toBe(16);
+			expect(await isUltraHdr(encoded)).toBe(true);
+		}
+	});
+
+	it("rewrites the SDR's orientation, or keeps it at 0", async () => {
+		const sdr = withExifOrientation(base64ToArrayBuffer(REGULAR_JPEG_BASE64), 3);
+		expect((await decodeToPixels(await encodeWith(sdr, 8))).orientation).toBe(8);
+		expect((await decodeToPixels(await encodeWith(sdr, 0))).orientation).toBe(3);
+	});
+
+	it('throws error for an orientation outside 0-8', async () => {
+		const sdr = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		await expect(encodeWith(sdr, 9)).rejects.toThrow(/orientation/);
+	});
+});
+
+describe('encodeUltraHdrStrips', () => {
+	const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+	const hdrData = createSyntheticHdrData(16, 16);
+	const strip = (y: number, rows: number) => hdrData.subarray(y * 16 * 3, (y + rows) * 16 * 3);
+
+	it('matches the bulk encode byte for byte', async () => {
+		const modes = [
+			GainMapDownsample.CenterSample,
+			GainMapDownsample.BoxAverage,
+			GainMapDownsample.MaxGain,
+		];
+		for (const gainMapDownsample of modes) {
+			// Scale 3 puts gain map blocks across strip boundaries.
+			const options = { ...defaultEncodeOptions, gainMapScale: 3, gainMapDownsample };
+			const bulk = await encodeUltraHdr('bulk', sdrBuffer, hdrData.buffer, options);
+			for (const stripRows of [1, 5, 16]) {
+				const calls: number[] = [];
+				const read = (y: number, rows: number) => {
+					calls.push(y);
+					return strip(y, rows);
+				};
+				const strips = await encodeUltraHdrStrips('strips', sdrBuffer, read, stripRows, options);
+				expect(new Uint8Array(strips)).toEqual(new Uint8Array(bulk));
+				expect(calls).toHaveLength(Math.ceil(16 / stripRows));
+			}
+		}
+	});
+
+	it('throws for a strip of the wrong size', async () => {
+		await expect(
+			encodeUltraHdrStrips('strips-bad', sdrBuffer, (y, rows) => strip(y, rows - 1), 4)
+		).rejects.toThrow(/HDR strip at row 0/);
+	});
+
+	it('throws for wide-gamut HDR', async () => {
+		await expect(
+			encodeUltraHdrStrips('strips-p3', sdrBuffer, strip, 4, {
+				...defaultEncodeOptions,
+				hdrGamut: ColorGamut.DisplayP3,
+			})
+		).rejects.toThrow(/BT.709/);
+	});
+});
+
+describe('encodeUltraHdrToTargetSize', () => {
+	const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+	const hdrData = createSyntheticHdrData(16, 16);
+	const options = { ...defaultEncodeOptions, gainMapQuality: 100 };
+	const sizeAt = async (gainMapQuality: number) =>
+		(await encodeUltraHdr('size', sdrBuffer, hdrData.buffer, { ...options, gainMapQuality }))
+			.byteLength;
+
+	it('keeps the requested quality when it already fits', async () => {
+		const full = await encodeUltraHdr('full', sdrBuffer, hdrData.buffer, options);
+		const fitted = await encodeUltraHdrToTargetSize(
+			'generous',
+			sdrBuffer,
+			hdrData.buffer,
+			full.byteLength * 2,
+			options
+		);
+		expect(new Uint8Array(fitted)).toEqual(new Uint8Array(full));
+	});
+
+	it('lowers the gain map quality to fit a tight budget', async () => {
+		const smallest = await sizeAt(1);
+		const largest = await sizeAt(100);
+		expect(largest).toBeGreaterThan(smallest);
+		const budget = Math.floor((smallest + largest) / 2);
+
+		const fitted = await encodeUltraHdrToTargetSize(
+			'tight',
+			sdrBuffer,
+			hdrData.buffer,
+			budget,
+			options
+		);
+		expect(fitted.byteLength).toBeLessThanOrEqual(budget);
+		expect(fitted.byteLength).toBeGreaterThanOrEqual(smallest);
+		expect(await isUltraHdr(fitted)).toBe(true);
+	});
+
+	it('throws when even the lowest quality is too large', async () => {
+		const smallest = await sizeAt(1);
+		await expect(
+			encodeUltraHdrToTargetSize('impossible', sdrBuffer, hdrData.buffer, smallest - 1, options)
+		).rejects.toThrow(/over the target/);
+	});
+});
+
+describe('recompressGainMap', () => {
+	it('shrinks the gain map and leaves the SDR base alone', async () => {
+		const encoded = await encodeFixture({ gainMapQuality: 100 }, WIDE_JPEG_BASE64);
+		const recompressed = await recompressGainMap(encoded, 30);
+		expect(recompressed.byteLength).toBeLessThan(encoded.byteLength);
+
+		const before = await decodeToPixels(encoded);
+		const after = await decodeToPixels(recompressed);
+		expect(Array.from(after.sdr)).toEqual(Array.from(before.sdr));
+		expect(after.gainMapWidth).toBe(before.gainMapWidth);
+		expect(after.gainMapHeight).toBe(before.gainMapHeight);
+		expect(after.gainMapChannels).toBe(before.gainMapChannels);
+		for (let i = 0; i < before.gainMap.length; i++) {
+			expect(Math.abs(after.gainMap[i] - before.gainMap[i])).toBeLessThanOrEqual(24);
+		}
+		const source = await decodeUltraHdr('recompress-src', encoded);
+		const result = await decodeUltraHdr('recompress-out', recompressed);
+		expect(result.metadata).toEqual(source.metadata);
+	});
+
+	it('throws error for a quality outside 1-100', async () => {
+		const encoded = await encodeFixture({}, WIDE_JPEG_BASE64);
+		await expect(recompressGainMap(encoded, 0)).rejects.toThrow(/between 1 and 100/);
+		await expect(recompressGainMap(encoded, 101)).rejects.toThrow(/between 1 and 100/);
+	});
+
+	it('refuses to write a primary whose scan has no entropy-coded data', async () => {
+		const encoded = await encodeFixture({}, WIDE_JPEG_BASE64);
+		const bytes = new Uint8Array(encoded);
+		const view = new DataView(encoded);
+		let sosEnd = 2;
+		while (bytes[sosEnd + 1] !== 0xda) sosEnd += 2 + view.getUint16(sosEnd + 2);
+		sosEnd += 2 + view.getUint16(sosEnd + 2);
+		let eoi = sosEnd;
+		while (!(bytes[eoi] === 0xff && bytes[eoi + 1] === 0xd9)) eoi++;
+
+		// The SOS header runs straight into EOI; the gain map still follows it.
+		const headerOnly = new Uint8Array(bytes.length - (eoi - sosEnd));
+		headerOnly.set(bytes.subarray(0, sosEnd));
+		headerOnly.set(bytes.subarray(eoi), sosEnd);
+		await expect(recompressGainMap(headerOnly.buffer, 75)).rejects.toThrow(/base image/);
+	});
+});
+
+describe('jpegEncoderBackend', () => {
+	it('re-encodes layers with mozjpeg that decode close to the source', async ({ skip }) => {
+		skip((await jpegEncoderBackend()) !== 'mozjpeg', 'built without OPEN_ULTRAHDR_MOZJPEG');
+
+		const encoded = await encodeFixture({}, UNALIGNED_JPEG_BASE64);
+		// Both turns re-encode the primary and the gain map.
+		const roundTrip = await rotateUltraHdr(await rotateUltraHdr(encoded, 180), 180);
+		const layout = await decodeUltraHdr('mozjpeg-round-trip', roundTrip);
+		expect(Array.from(layout.gainMap.subarray(0, 2))).toEqual([0xff, 0xd8]);
+
+		const before = await decodeToPixels(encoded);
+		const after = await decodeToPixels(roundTrip);
+		const meanError = (a: Uint8Array, b: Uint8Array) =>
+			a.reduce((sum, v, i) => sum + Math.abs(v - b[i]), 0) / a.length;
+		expect(after.sdr.length).toBe(before.sdr.length);
+		expect(after.gainMap.length).toBe(before.gainMap.length);
+		expect(meanError(after.sdr, before.sdr)).toBeLessThan(8);
+		expect(meanError(after.gainMap, before.gainMap)).toBeLessThan(8);
+	});
+});
+
+describe('capabilities', () => {
+	it('reports every feature key as a boolean', async () => {
+		const caps = await capabilities();
+		expect(Object.keys(caps).sort()).toEqual([
+			'binaryMetadata',
+			'mozjpeg',
+			'perChannelGainMap',
+			'pqOutput',
+			'progressiveJpeg',
+		]);
+		for (const value of Object.values(caps)) {
+			expect(typeof value).toBe('boolean');
+		}
+	});
+
+	it('matches the default build and the JPEG encoder backend', async () => {
+		const caps = await capabilities();
+		expect(caps.mozjpeg).toBe((await jpegEncoderBackend()) === 'mozjpeg');
+		if (!caps.mozjpeg) expect(caps.progressiveJpeg).toBe(false);
+		expect(caps.perChannelGainMap).toBe(true);
+		expect(caps.binaryMetadata).toBe(true);
+	});
 });
@@ -0,0 +1,114 @@
+/**
+ * Test helper that reads quantized DCT coefficients straight from a JPEG, for
+ * tests that must show blocks were carried over without re-encoding.
+ */
+
+/**
+ * Entropy-decodes a baseline JPEG with one interleaved scan and no restart
+ * intervals into each component's quantized DCT blocks, as rows of blocks
+ * covering the image (MCU padding excluded), each in zigzag order.
+ */
+export function dctBlocks(jpeg: Uint8Array): number[][][][] {
+	const view = new DataView(jpeg.buffer, jpeg.byteOffset);
+	const tables = new Map<number, Map<string, number>>();
+	let width = 0;
+	let height = 0;
+	let frame: { id: number; h: number; v: number }[] = [];
+	let scan: { comp: number; dc: number; ac: number }[] = [];
+	let at = 2;
+	for (;;) {
+		const marker = jpeg[at + 1];
+		const length = view.getUint16(at + 2);
+		const body = at + 4;
+		if (marker === 0xc4) {
+			for (let p = body; p < at + 2 + length; ) {
+				const codes = new Map<string, number>();
+				let code = 0;
+				let symbol = p + 17;
+				for (let bits = 1; bits <= 16; bits++, code <<= 1) {
+					for (let n = 0; n < jpeg[p + bits]; n++) codes.set(`${bits}:${code++}`, jpeg[symbol++]);
+				}
+				tables.set(jpeg[p], codes);
+				p = symbol;
+			}
+		} else if (marker === 0xc0) {
+			height = view.getUint16(body + 1);
+			width = view.getUint16(body + 3);
+			frame = Array.from({ length: jpeg[body + 5] }, (_, i) => ({
+				id: jpeg[body + 6 + i * 3],
+				h: jpeg[body + 7 + i * 3] >> 4,
+				v: jpeg[body + 7 + i * 3] & 15,
+			}));
+		} else if (marker === 0xda) {
+			scan = Array.from({ length: jpeg[body] }, (_, i) => ({
+				comp: frame.findIndex((c) => c.id === jpeg[body + 1 + i * 2]),
+				dc: jpeg[body + 2 + i * 2] >> 4,
+				ac: 0x10 | (jpeg[body + 2 + i * 2] & 15),
+			}));
+			at += 2 + length;
+			break;
+		}
+		at += 2 + length;
+	}
+
+	let bit = 0;
+	const readBit = () => {
+		const byte = jpeg[at];
+		const value = (byte >> (7 - bit)) & 1;
+		if (++bit === 8) {
+			bit = 0;
+			at += byte === 0xff ? 2 : 1; // Skip the stuffed zero byte.
+		}
+		return value;
+	};
+	const decode = (codes: Map<string, number>) => {
+		let code = 0;
+		for (let bits = 1; bits <= 16; bits++) {
+			code = (code << 1) | readBit();
+			const symbol = codes.get(`${bits}:${code}`);
+			if (symbol !== undefined) return symbol;
+		}
+		throw new Error('Bad Huffman code');
+	};
+	const receive = (size: number) => {
+		let value = 0;
+		for (let i = 0; i < size; i++) value = (value << 1) | readBit();
+		return size > 0 && value < 1 << (size - 1) ? value - (1 << size) + 1 : value;
+	};
+
+	const hMax = Math.max(...frame.map((c) => c.h));
+	const vMax = Math.max(...frame.map((c) => c.v));
+	const out = frame.map((c) =>
+		Array.from({ length: Math.ceil((height * c.v) / vMax / 8) }, () =>
+			new Array<number[]>(Math.ceil((width * c.h) / hMax / 8))
+		)
+	);
+	const predictors = frame.map(() => 0);
+	for (let my = 0; my < Math.ceil(height / (8 * vMax)); my++) {
+		for (let mx = 0; mx < Math.ceil(width / (8 * hMax)); mx++) {
+			for (const { comp, dc, ac } of scan) {
+				const { h, v } = frame[comp];
+				for (let by = 0; by < v; by++) {
+					for (let bx = 0; bx < h; bx++) {
+						const block = new Array<number>(64).fill(0);
+						predictors[comp] += receive(decode(tables.get(dc)!));
+						block[0] = predictors[comp];
+						for (let k = 1; k < 64; k++) {
+							const rs = decode(tables.get(ac)!);
+							if ((rs & 15) === 0) {
+								if (rs !== 0xf0) break;
+								k += 15;
+								continue;
+							}
+							k += rs >> 4;
+							block[k] = receive(rs & 15);
+						}
+						const row = out[comp][my * v + by];
+						if (row && mx * h + bx < row.length) row[mx * h + bx] = block;
+					}
+				}
+			}
+		}
+	}
+	return out;
+}
@@ -3,6 +3,14 @@
  *
  * Contains base64-encoded test images and helper functions.
  */
+import {
+	applyGainMap,
+	defaultEncodeOptions,
+	encodeUltraHdr,
+	locateComponents,
+	probeUltraHdr,
+} from '../../src/index';
+import type { ReconstructedHdrImage, UltraHdrEncodeOptions } from '../../src/index';
 
 /**
  * 16x16 pixel JPEG image (no gain map). Width is libultrahdr's minimum
@@ -16,6 +24,117 @@ export const REGULAR_JPEG_BASE64 =
 	'2wBDAQUFBQcGBw4ICA4eFBEUHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh7/' +
 	'wAARCAAQABADASIAAhEBAxEB/8QAHwAAAQUBAQEBAQEAAAAAAAAAAAECAwQFBgcICQoL/8QAtRAAAgEDAwIEAwUFBAQAAAF9AQIDAAQRBRIhMUEGE1FhByJxFDKBkaEII0KxwRVS0fAkM2JyggkKFhcYGRolJicoKSo0NTY3ODk6Q0RFRkdISUpTVFVWV1hZWmNkZWZnaGlqc3R1dnd4eXqDhIWGh4iJipKTlJWWl5iZmqKjpKWmp6ipqrKztLW2t7i5usLDxMXGx8jJytLT1NXW19jZ2uHi4+Tl5ufo6erx8vP09fb3+Pn6/8QAHwEAAwEBAQEBAQEBAQAAAAAAAAECAwQFBgcICQoL/8QAtREAAgECBAQDBAcFBAQAAQJ3AAECAxEEBSExBhJBUQdhcRMiMoEIFEKRobHBCSMzUvAVYnLRChYkNOEl8RcYGRomJygpKjU2Nzg5OkNERUZHSElKU1RVVldYWVpjZGVmZ2hpanN0dXZ3eHl6goOEhYaHiImKkpOUlZaXmJmaoqOkpaanqKmqsrO0tba3uLm6wsPExcbHyMnK0tPU1dbX2Nna4uPk5ebn6Onq8vP09fb3+Pn6/9oADAMBAAIRAxEAPwDwzw74Q+7+6/SvSfDnhD7v7r9K7vw54Q+7+6/SvSfDvhD7v7r9KMNiQ4K41+H3j//Z';
 
+/**
+ * REGULAR_JPEG's coefficients rewritten as a progressive (SOF2) JPEG with
+ * libjpeg's simple progression script.
+ */
+export const PROGRESSIVE_JPEG_BASE64 =
+	'/9j/4AAQSkZJRgABAQAAAQABAAD//gANcHJvZ3Jlc3NpdmX/2wBDAAUDBAQEAwUEBAQFBQUGBwwIBwcHBw8LCwkMEQ8SEhEPERETFhwXExQaFRERGCEYGh0d' +
+	'Hx8fExciJCIeJBweHx7/2wBDAQUFBQcGBw4ICA4eFBEUHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh4eHh7/wgARCAAQ' +
+	'ABADASIAAhEBAxEB/8QAFQABAQAAAAAAAAAAAAAAAAAABgX/xAAUAQEAAAAAAAAAAAAAAAAAAAAA/9oADAMBAAIQAxAAAAEKlupT/8QAFRABAQAAAAAAAAAA' +
+	'AAAAAAAABAD/2gAIAQEAAQUCOSOSOSOS/8QAFxEBAAMAAAAAAAAAAAAAAAAABQAhMf/aAAgBAwEBPwEVrLn/xAAVEQEBAAAAAAAAAAAAAAAAAAADAP/aAAgB' +
+	'AgEBPwE0v//EABUQAQEAAAAAAAAAAAAAAAAAAAAx/9oACAEBAAY/AoiI/8QAFRABAQAAAAAAAAAAAAAAAAAAADH/2gAIAQEAAT8hmTJkz//aAAwDAQACAAMA' +
+	'AAAQo//EABQRAQAAAAAAAAAAAAAAAAAAAAD/2gAIAQMBAT8QZ//EABQRAQAAAAAAAAAAAAAAAAAAAAD/2gAIAQIBAT8Qf//EABUQAQEAAAAAAAAAAAAAAAAA' +
+	'AADx/9oACAEBAAE/EIqamor/2Q==';
+
+/**
+ * REGULAR_JPEG re-graded with a strong contrast curve (sample values pushed
+ * 1.6x away from mid-grey) and re-encoded at quality 90, standing in for an
+ * SDR rendition an editor produced by hand rather than by tone mapping.
+ */
+export const CONTRAST_JPEG_BASE64 =
+	'/9j/4AAQSkZJRgABAQAAAQABAAD/2wBDAAMCAgMCAgMDAwMEAwMEBQgFBQQEBQoHBwYIDAoMDAsKCwsNDhIQDQ4RDgsLEBYQERMUFRUVDA8XGBYUGBIUFRT/' +
+	'2wBDAQMEBAUEBQkFBQkUDQsNFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBT/wAARCAAQABADASIAAhEBAxEB/8QA' +
+	'HwAAAQUBAQEBAQEAAAAAAAAAAAECAwQFBgcICQoL/8QAtRAAAgEDAwIEAwUFBAQAAAF9AQIDAAQRBRIhMUEGE1FhByJxFDKBkaEII0KxwRVS0fAkM2JyggkK' +
+	'FhcYGRolJicoKSo0NTY3ODk6Q0RFRkdISUpTVFVWV1hZWmNkZWZnaGlqc3R1dnd4eXqDhIWGh4iJipKTlJWWl5iZmqKjpKWmp6ipqrKztLW2t7i5usLDxMXG' +
+	'x8jJytLT1NXW19jZ2uHi4+Tl5ufo6erx8vP09fb3+Pn6/8QAHwEAAwEBAQEBAQEBAQAAAAAAAAECAwQFBgcICQoL/8QAtREAAgECBAQDBAcFBAQAAQJ3AAEC' +
+	'AxEEBSExBhJBUQdhcRMiMoEIFEKRobHBCSMzUvAVYnLRChYkNOEl8RcYGRomJygpKjU2Nzg5OkNERUZHSElKU1RVVldYWVpjZGVmZ2hpanN0dXZ3eHl6goOE' +
+	'hYaHiImKkpOUlZaXmJmaoqOkpaanqKmqsrO0tba3uLm6wsPExcbHyMnK0tPU1dbX2Nna4uPk5ebn6Onq8vP09fb3+Pn6/9oADAMBAAIRAxEAPwD8/PBvwk1a' +
+	'XUVZkyD/ALFfVHw4+DeqG0AEeOP7lfRHgT4B2H29RhD0/hr6q+H3wI0+OzDMEAA7rWeWYpTtUqQ+dh+FHixT9o7Lv0P/2Q==';
+
+/**
+ * 15x8 pixel JPEGs (no gain map) with an odd width: one with 4:4:4 sampling,
+ * which needs no even dimensions, and one with 4:2:0, which does.
+ */
+export const ODD_JPEG_WIDTH = 15;
+export const ODD_JPEG_HEIGHT = 8;
+export const ODD_444_JPEG_BASE64 =
+	'/9j/4AAQSkZJRgABAQAAAQABAAD/2wBDAAMCAgMCAgMDAwMEAwMEBQgFBQQEBQoHBwYIDAoMDAsKCwsNDhIQDQ4RDgsLEBYQERMUFRUVDA8XGBYUGBIUFRT/' +
+	'2wBDAQMEBAUEBQkFBQkUDQsNFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBT/wAARCAAIAA8DAREAAhEBAxEB/8QA' +
+	'FAABAAAAAAAAAAAAAAAAAAAAB//EAB0QAAAFBQAAAAAAAAAAAAAAAAABBggkIjJBUqL/xAAWAQEBAQAAAAAAAAAAAAAAAAAGBwj/xAAbEQACAgMBAAAAAAAA' +
+	'AAAAAAAABgQiASEyMf/aAAwDAQACEQMRAD8AIkm1+yJyGsxt9sTlbeebjIl2v0FExqA0tt30aoXXmuLn/9k=';
+export const ODD_420_JPEG_BASE64 =
+	'/9j/4AAQSkZJRgABAQAAAQABAAD/2wBDAAMCAgMCAgMDAwMEAwMEBQgFBQQEBQoHBwYIDAoMDAsKCwsNDhIQDQ4RDgsLEBYQERMUFRUVDA8XGBYUGBIUFRT/' +
+	'2wBDAQMEBAUEBQkFBQkUDQsNFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBT/wAARCAAIAA8DASIAAhEBAxEB/8QA' +
+	'FQABAQAAAAAAAAAAAAAAAAAAAAf/xAAdEAABAwUBAAAAAAAAAAAAAAAABggkASIyQVKi/8QAFQEBAQAAAAAAAAAAAAAAAAAABQb/xAAdEQAABQUAAAAAAAAA' +
+	'AAAAAAAAAgUGIwcRIZHw/9oADAMBAAIRAxEAPwCRJNr+ETyWRLtfspE1yAHrDkUsyCapu7laxJO2P//Z';
+
+/**
+ * 17x8 pixel JPEG (4:2:0, no gain map, standard Huffman tables). Cropping it
+ * to 16 columns drops a whole block column from every component: 3 luma
+ * blocks per row become 2, and 2 chroma blocks become 1.
+ */
+export const BLOCK_EDGE_JPEG_WIDTH = 17;
+export const BLOCK_EDGE_JPEG_HEIGHT = 8;
+export const BLOCK_EDGE_JPEG_BASE64 =
+	'/9j/4AAQSkZJRgABAQAAAQABAAD/2wBDAAMCAgMCAgMDAwMEAwMEBQgFBQQEBQoHBwYIDAoMDAsKCwsNDhIQDQ4RDgsLEBYQERMUFRUVDA8XGBYUGBIUFRT/' +
+	'2wBDAQMEBAUEBQkFBQkUDQsNFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBT/wAARCAAIABEDASIAAhEBAxEB/8QA' +
+	'HwAAAQUBAQEBAQEAAAAAAAAAAAECAwQFBgcICQoL/8QAtRAAAgEDAwIEAwUFBAQAAAF9AQIDAAQRBRIhMUEGE1FhByJxFDKBkaEII0KxwRVS0fAkM2JyggkK' +
+	'FhcYGRolJicoKSo0NTY3ODk6Q0RFRkdISUpTVFVWV1hZWmNkZWZnaGlqc3R1dnd4eXqDhIWGh4iJipKTlJWWl5iZmqKjpKWmp6ipqrKztLW2t7i5usLDxMXG' +
+	'x8jJytLT1NXW19jZ2uHi4+Tl5ufo6erx8vP09fb3+Pn6/8QAHwEAAwEBAQEBAQEBAQAAAAAAAAECAwQFBgcICQoL/8QAtREAAgECBAQDBAcFBAQAAQJ3AAEC' +
+	'AxEEBSExBhJBUQdhcRMiMoEIFEKRobHBCSMzUvAVYnLRChYkNOEl8RcYGRomJygpKjU2Nzg5OkNERUZHSElKU1RVVldYWVpjZGVmZ2hpanN0dXZ3eHl6goOE' +
+	'hYaHiImKkpOUlZaXmJmaoqOkpaanqKmqsrO0tba3uLm6wsPExcbHyMnK0tPU1dbX2Nna4uPk5ebn6Onq8vP09fb3+Pn6/9oADAMBAAIRAxEAPwDzDQv2Z/u/' +
+	'6J/47Xoeh/sz/d/0T/x2iivO8TuM85/efvf6+8+T8MuK81/d/vO39bnS/wDDM/8A06f+O0UUV/Hv+uec/wDP3+vvP7A/1rzX/n5/X3n/2Q==';
+
+/**
+ * 32x16 pixel JPEG (4:2:0, no gain map). Both dimensions are whole 16x16
+ * MCUs, so its DCT blocks can be rotated losslessly.
+ */
+export const WIDE_JPEG_WIDTH = 32;
+export const WIDE_JPEG_HEIGHT = 16;
+export const WIDE_JPEG_BASE64 =
+	'/9j/4AAQSkZJRgABAQAAAQABAAD/2wBDAAgGBgcGBQgHBwcJCQgKDBQNDAsLDBkSEw8UHRofHh0aHBwgJC4nICIsIxwcKDcpLDAxNDQ0Hyc5PTgyPC4zNDL/' +
+	'2wBDAQkJCQwLDBgNDRgyIRwhMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjL/wAARCAAQACADASIAAhEBAxEB/8QA' +
+	'HwAAAQUBAQEBAQEAAAAAAAAAAAECAwQFBgcICQoL/8QAtRAAAgEDAwIEAwUFBAQAAAF9AQIDAAQRBRIhMUEGE1FhByJxFDKBkaEII0KxwRVS0fAkM2JyggkK' +
+	'FhcYGRolJicoKSo0NTY3ODk6Q0RFRkdISUpTVFVWV1hZWmNkZWZnaGlqc3R1dnd4eXqDhIWGh4iJipKTlJWWl5iZmqKjpKWmp6ipqrKztLW2t7i5usLDxMXG' +
+	'x8jJytLT1NXW19jZ2uHi4+Tl5ufo6erx8vP09fb3+Pn6/8QAHwEAAwEBAQEBAQEBAQAAAAAAAAECAwQFBgcICQoL/8QAtREAAgECBAQDBAcFBAQAAQJ3AAEC' +
+	'AxEEBSExBhJBUQdhcRMiMoEIFEKRobHBCSMzUvAVYnLRChYkNOEl8RcYGRomJygpKjU2Nzg5OkNERUZHSElKU1RVVldYWVpjZGVmZ2hpanN0dXZ3eHl6goOE' +
+	'hYaHiImKkpOUlZaXmJmaoqOkpaanqKmqsrO0tba3uLm6wsPExcbHyMnK0tPU1dbX2Nna4uPk5ebn6Onq8vP09fb3+Pn6/9oADAMBAAIRAxEAPwDzmDQenyVq' +
+	'QaD0+Su8g0H/AGK1INB6fJXVWzfzPCy7PdtTgoNB6fJWpb6D0+Su8g0Hp8lalvoPT5K8utm/mfdZdnu2p//Z';
+
+/**
+ * 32x32 pixel JPEG (4:2:0, no gain map): twice the regular fixture on both
+ * axes, a smooth gradient kept clear of black and white.
+ */
+export const LARGE_JPEG_WIDTH = 32;
+export const LARGE_JPEG_HEIGHT = 32;
+export const LARGE_JPEG_BASE64 =
+	'/9j/4AAQSkZJRgABAQAAAQABAAD/2wBDAAMCAgMCAgMDAwMEAwMEBQgFBQQEBQoHBwYIDAoMDAsKCwsNDhIQDQ4RDgsLEBYQERMUFRUVDA8XGBYUGBIUFRT/2wBD' +
+	'AQMEBAUEBQkFBQkUDQsNFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBQUFBT/wAARCAAgACADASIAAhEBAxEB/8QAHwAAAQUB' +
+	'AQEBAQEAAAAAAAAAAAECAwQFBgcICQoL/8QAtRAAAgEDAwIEAwUFBAQAAAF9AQIDAAQRBRIhMUEGE1FhByJxFDKBkaEII0KxwRVS0fAkM2JyggkKFhcYGRolJico' +
+	'KSo0NTY3ODk6Q0RFRkdISUpTVFVWV1hZWmNkZWZnaGlqc3R1dnd4eXqDhIWGh4iJipKTlJWWl5iZmqKjpKWmp6ipqrKztLW2t7i5usLDxMXGx8jJytLT1NXW19jZ' +
+	'2uHi4+Tl5ufo6erx8vP09fb3+Pn6/8QAHwEAAwEBAQEBAQEBAQAAAAAAAAECAwQFBgcICQoL/8QAtREAAgECBAQDBAcFBAQAAQJ3AAECAxEEBSExBhJBUQdhcRMi' +
+	'MoEIFEKRobHBCSMzUvAVYnLRChYkNOEl8RcYGRomJygpKjU2Nzg5OkNERUZHSElKU1RVVldYWVpjZGVmZ2hpanN0dXZ3eHl6goOEhYaHiImKkpOUlZaXmJmaoqOk' +
+	'paanqKmqsrO0tba3uLm6wsPExcbHyMnK0tPU1dbX2Nna4uPk5ebn6Onq8vP09fb3+Pn6/9oADAMBAAIRAxEAPwDyS30/pxWnb6f04rSt9P6cVqW+n9OK9eriTPA4' +
+	'vbUzLfT+nFadvp/TitO30/pxWnb6f04ryauJP0DA4vbUy7fT+nFadvp/TitO30/pxWnb6f04ry6mJPwrA4vbUzLfT+nFalvp/TitK30/pxWnb6f04ryauJP0DA4v' +
+	'bU//2Q==';
+
+/**
+ * 24x16 pixel JPEG (4:2:0, no gain map). The width is not a multiple of the
+ * 16-pixel MCU, so lossless rotation is not possible.
+ */
+export const UNALIGNED_JPEG_WIDTH = 24;
+export const UNALIGNED_JPEG_HEIGHT = 16;
+export const UNALIGNED_JPEG_BASE64 =
+	'/9j/4AAQSkZJRgABAQAAAQABAAD/2wBDAAgGBgcGBQgHBwcJCQgKDBQNDAsLDBkSEw8UHRofHh0aHBwgJC4nICIsIxwcKDcpLDAxNDQ0Hyc5PTgyPC4zNDL/' +
+	'2wBDAQkJCQwLDBgNDRgyIRwhMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjL/wAARCAAQABgDASIAAhEBAxEB/8QA' +
+	'HwAAAQUBAQEBAQEAAAAAAAAAAAECAwQFBgcICQoL/8QAtRAAAgEDAwIEAwUFBAQAAAF9AQIDAAQRBRIhMUEGE1FhByJxFDKBkaEII0KxwRVS0fAkM2JyggkK' +
+	'FhcYGRolJicoKSo0NTY3ODk6Q0RFRkdISUpTVFVWV1hZWmNkZWZnaGlqc3R1dnd4eXqDhIWGh4iJipKTlJWWl5iZmqKjpKWmp6ipqrKztLW2t7i5usLDxMXG' +
+	'x8jJytLT1NXW19jZ2uHi4+Tl5ufo6erx8vP09fb3+Pn6/8QAHwEAAwEBAQEBAQEBAQAAAAAAAAECAwQFBgcICQoL/8QAtREAAgECBAQDBAcFBAQAAQJ3AAEC' +
+	'AxEEBSExBhJBUQdhcRMiMoEIFEKRobHBCSMzUvAVYnLRChYkNOEl8RcYGRomJygpKjU2Nzg5OkNERUZHSElKU1RVVldYWVpjZGVmZ2hpanN0dXZ3eHl6goOE' +
+	'hYaHiImKkpOUlZaXmJmaoqOkpaanqKmqsrO0tba3uLm6wsPExcbHyMnK0tPU1dbX2Nna4uPk5ebn6Onq8vP09fb3+Pn6/9oADAMBAAIRAxEAPwDzy10Hp8lb' +
+	'VroPT5K7a10Hp8lbVroPT5K3xGb+Z5eU57tqcVa6D0+SivULXQenyUV5E8313P0TC57+7Wp//9k=';
+
 /**
  * PNG file header (should not be detected as JPEG or UltraHDR).
  */
@@ -76,3 +195,169 @@ export function createMinimalJpegBuffer(_width: number, _height: number): ArrayB
 	// In a real scenario, we'd use a library like sharp or canvas
 	return base64ToArrayBuffer(REGULAR_JPEG_BASE64);
 }
+
+/**
+ * Encodes an SDR fixture, REGULAR_JPEG by default, against synthetic HDR data
+ * of its size, with `options` overriding defaultEncodeOptions.
+ */
+export async function encodeFixture(
+	options: Partial<UltraHdrEncodeOptions> = {},
+	sdrBase64: string = REGULAR_JPEG_BASE64
+): Promise<ArrayBuffer> {
+	const sdrBuffer = base64ToArrayBuffer(sdrBase64);
+	const { width, height } = await probeUltraHdr(sdrBuffer);
+	const hdrData = createSyntheticHdrData(width, height);
+	return encodeUltraHdr('fixture', sdrBuffer, hdrData.buffer, options);
+}
+
+/**
+ * Linear SDR of a fixture as a decoder reconstructs it: an encode of it with
+ * `options`, applied at display capacity 0. An odd-dimension policy in
+ * `options` can make it smaller or larger than the fixture.
+ */
+export async function linearSdr(
+	sdrBase64: string,
+	options: Partial<UltraHdrEncodeOptions> = {}
+): Promise<ReconstructedHdrImage> {
+	return applyGainMap(await encodeFixture(options, sdrBase64), 0);
+}
+
+/**
+ * Linear HDR for an SDR fixture whose gain over the fixture's linear SDR is
+ * `gainAt(x, y, c)` at each pixel and channel. The ratio includes `offset` on
+ * both sides, as the encoder's does with its blackPointOffset.
+ */
+export async function hdrWithGain(
+	sdrBase64: string,
+	gainAt: (x: number, y: number, c: number) => number,
+	offset: number = defaultEncodeOptions.blackPointOffset
+): Promise<Float32Array> {
+	const { data, width } = await linearSdr(sdrBase64);
+	const hdr = new Float32Array(data.length);
+	for (let i = 0; i < data.length; i++) {
+		const pixel = Math.floor(i / 3);
+		const gain = gainAt(pixel % width, Math.floor(pixel / width), i % 3);
+		hdr[i] = gain * (data[i] + offset) - offset;
+	}
+	return hdr;
+}
+
+/**
+ * Encodes an SDR fixture against the HDR `hdrWithGain` builds for `gainAt`,
+ * with the blackPointOffset of `options`.
+ */
+export async function encodeWithGain(
+	sdrBase64: string,
+	gainAt: (x: number, y: number, c: number) => number,
+	options: Partial<UltraHdrEncodeOptions> = {}
+): Promise<ArrayBuffer> {
+	const offset = options.blackPointOffset ?? defaultEncodeOptions.blackPointOffset;
+	const hdr = await hdrWithGain(sdrBase64, gainAt, offset);
+	return encodeUltraHdr('with-gain', base64ToArrayBuffer(sdrBase64), hdr.buffer, options);
+}
+
+/**
+ * Rewrites every match of `pattern` in place. Shorter replacements are padded
+ * with spaces so segment lengths stay valid; longer ones throw.
+ */
+export function patchText(bytes: Uint8Array, pattern: RegExp, replacement: string): void {
+	const text = new TextDecoder('latin1').decode(bytes);
+	for (const match of text.matchAll(pattern)) {
+		if (replacement.length > match[0].length) {
+			throw new Error(`"${replacement}" does not fit in place of "${match[0]}"`);
+		}
+		const start = match.index ?? 0;
+		bytes.fill(0x20, start, start + match[0].length);
+		bytes.set(new TextEncoder().encode(replacement), start);
+	}
+}
+
+/**
+ * Inserts a big-endian Exif APP1 holding only an Orientation tag after the
+ * SOI. MPF offsets are relative to the MPF segment, so they survive.
+ */
+export function withExifOrientation(jpeg: ArrayBuffer, orientation: number): ArrayBuffer {
+	const segment = new Uint8Array(4 + 6 + 26);
+	const view = new DataView(segment.buffer);
+	view.setUint16(0, 0xffe1);
+	view.setUint16(2, segment.length - 2);
+	segment.set(new TextEncoder().encode('Exif\0\0'), 4);
+	view.setUint16(10, 0x4d4d); // "MM"
+	view.setUint16(12, 42);
+	view.setUint32(14, 8); // IFD0 offset.
+	view.setUint16(18, 1); // One entry.
+	view.setUint16(20, 0x0112); // Orientation, SHORT, count 1.
+	view.setUint16(22, 3);
+	view.setUint32(24, 1);
+	view.setUint16(28, orientation);
+
+	const bytes = new Uint8Array(jpeg);
+	const out = new Uint8Array(bytes.length + segment.length);
+	out.set(bytes.subarray(0, 2));
+	out.set(segment, 2);
+	out.set(bytes.subarray(2), 2 + segment.length);
+	return out.buffer;
+}
+
+/** Copy of `jpeg` with the density of its first JFIF header replaced. */
+export function withJfifDensity(
+	jpeg: ArrayBuffer,
+	units: number,
+	x: number,
+	y: number
+): ArrayBuffer {
+	const bytes = new Uint8Array(jpeg.slice(0));
+	const view = new DataView(bytes.buffer);
+	let at = 2;
+	while (bytes[at] === 0xff && bytes[at + 1] !== 0xda) {
+		const id = new TextDecoder('latin1').decode(bytes.subarray(at + 4, at + 9));
+		if (bytes[at + 1] === 0xe0 && id === 'JFIF\0') {
+			bytes[at + 11] = units;
+			view.setUint16(at + 12, x);
+			view.setUint16(at + 14, y);
+			return bytes.buffer;
+		}
+		at += 2 + view.getUint16(at + 2);
+	}
+	throw new Error('No JFIF header');
+}
+
+/** Inserts `profile` after the SOI as `chunks` APP2 ICC chunks of near-equal size. */
+export function withIccChunks(jpeg: ArrayBuffer, profile: Uint8Array, chunks = 1): ArrayBuffer {
+	const signature = new TextEncoder().encode('ICC_PROFILE\0');
+	const bytes = new Uint8Array(jpeg);
+	const parts: Uint8Array[] = [bytes.subarray(0, 2)];
+	const size = Math.ceil(profile.length / chunks);
+	for (let i = 0; i < chunks; i++) {
+		const data = profile.subarray(i * size, (i + 1) * size);
+		const segment = new Uint8Array(4 + signature.length + 2 + data.length);
+		const length = segment.length - 2;
+		segment.set([0xff, 0xe2, length >> 8, length & 0xff]);
+		segment.set(signature, 4);
+		segment.set([i + 1, chunks], 4 + signature.length);
+		segment.set(data, 6 + signature.length);
+		parts.push(segment);
+	}
+	parts.push(bytes.subarray(2));
+	const out = new Uint8Array(parts.reduce((n, p) => n + p.length, 0));
+	let at = 0;
+	for (const part of parts) {
+		out.set(part, at);
+		at += part.length;
+	}
+	return out.buffer;
+}
+
+/**
+ * `encoded` with the gain map's hdrgm XMP and ISO 21496-1 segments renamed so
+ * no reader recognises them. The primary still signals the gain map and its
+ * MPF index still lists it: a gain map codestream without metadata.
+ */
+export async function withoutGainMapMetadata(encoded: ArrayBuffer): Promise<ArrayBuffer> {
+	const bytes = new Uint8Array(encoded.slice(0));
+	const { gainMap } = await locateComponents(bytes.buffer);
+	const segments = bytes.subarray(gainMap.offset, gainMap.offset + gainMap.length);
+	patchText(segments, /urn:iso:std:iso:ts:21496:-1/g, 'urn:example:not-iso-21496');
+	patchText(segments, /hdrgm/g, 'nohdr');
+	return bytes.buffer;
+}
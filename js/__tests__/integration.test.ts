@@ -64,6 +64,24 @@ describe('encode-decode roundtrip', () => {
 		// Verify dimensions
 		expect(decoded.width).toBe(16);
 		expect(decoded.height).toBe(16);
+
+		// A cleanly encoded file decodes without warnings
+		expect(decoded.warnings).toEqual([]);
+	});
+
+	it('warns when the gain map is not an integer downscale of the primary', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const hdrData = createSyntheticHdrData(16, 16);
+
+		// 16 is not divisible by the resulting gain map size for scale 3.
+		const encoded = await encodeUltraHdr('test-warn', sdrBuffer, hdrData.buffer, {
+			...defaultEncodeOptions,
+			gainMapScale: 3,
+		});
+		const decoded = await decodeUltraHdr('test-warn-decode', encoded);
+
+		expect(decoded.warnings.length).toBeGreaterThan(0);
+		expect(decoded.warnings.join('\n')).toContain('not a uniform integer downscale');
 	});
 
 	it('preserves metadata through encode-decode', async () => {
@@ -164,3 +182,43 @@ describe('encoding options', () => {
 		expect(isUltra).toBe(true);
 	});
 });
+
+describe('double-encoded input', () => {
+	it('rejects an UltraHDR JPEG supplied as the SDR base', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const hdrData = createSyntheticHdrData(16, 16);
+		const encoded = await encodeUltraHdr(
+			'nested-1',
+			sdrBuffer,
+			hdrData.buffer,
+			defaultEncodeOptions
+		);
+
+		await expect(
+			encodeUltraHdr('nested-2', encoded, hdrData.buffer, defaultEncodeOptions)
+		).rejects.toThrow('SDR base already contains a gain map');
+	});
+
+	it('flattens an UltraHDR SDR base when flattenExisting is set', async () => {
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const hdrData = createSyntheticHdrData(16, 16);
+		const encoded = await encodeUltraHdr(
+			'flatten-1',
+			sdrBuffer,
+			hdrData.buffer,
+			defaultEncodeOptions
+		);
+
+		const reencoded = await encodeUltraHdr('flatten-2', encoded, hdrData.buffer, {
+			...defaultEncodeOptions,
+			flattenExisting: true,
+		});
+
+		expect(await isUltraHdr(reencoded)).toBe(true);
+		const decoded = await decodeUltraHdr('flatten-decode', reencoded);
+		expect(decoded.width).toBe(16);
+		expect(decoded.height).toBe(16);
+		// The new primary must not itself be an UltraHDR image.
+		expect(await isUltraHdr(decoded.sdrImage.slice().buffer as ArrayBuffer)).toBe(false);
+	});
+});
@@ -2,9 +2,55 @@
  * Metadata function tests.
  */
 import { describe, it, expect } from 'vitest';
-import { validateMetadata, estimateHdrHeadroom, isMeaningfulHdr } from '../src/index';
+import {
+	validateMetadata,
+	estimateHdrHeadroom,
+	forPeakNits,
+	isMeaningfulHdr,
+	hdrStrengthScore,
+	estimateCapacityFromGainMap,
+	metadataWarnings,
+	supportedVersions,
+	isoDefaultMetadata,
+	metadataApproxEqual,
+	gainMapXmp,
+	parseGainMapXmp,
+	metadataToJson,
+	metadataFromJson,
+	encodeFromComponents,
+	getMetadata,
+	splitUltraHdr,
+} from '../src/index';
+import {
+	base64ToArrayBuffer,
+	REGULAR_JPEG_BASE64,
+	encodeFixture,
+	encodeWithGain,
+	patchText,
+} from './fixtures/test-data';
+import { ColorGamut, GainMapXmpMode, XmpFloatArrayStyle } from '../src/types';
 import type { GainMapMetadata } from '../src/types';
 
+describe('forPeakNits', () => {
+	it('gives 2 stops of capacity for 812 nits over 203-nit white', async () => {
+		const metadata = await forPeakNits(812, 203);
+		expect(metadata.hdrCapacityMax).toBeCloseTo(2.0, 5);
+		for (const max of metadata.gainMapMax) {
+			expect(max).toBeCloseTo(2.0, 5);
+		}
+		expect(await validateMetadata(metadata)).toBe(true);
+	});
+
+	it('defaults SDR white to 203 nits', async () => {
+		expect((await forPeakNits(1624)).hdrCapacityMax).toBeCloseTo(3.0, 5);
+	});
+
+	it('throws error for a peak at or below SDR white', async () => {
+		await expect(forPeakNits(203, 203)).rejects.toThrow(/must exceed SDR white/);
+		await expect(forPeakNits(1000, 0)).rejects.toThrow(/SDR white/);
+	});
+});
+
 describe('validateMetadata', () => {
 	it('returns true for valid default metadata', async () => {
 		const validMetadata: GainMapMetadata = {
@@ -56,6 +102,41 @@ describe('validateMetadata', () => {
 		const result = await validateMetadata(invalidMetadata);
 		expect(result).toBe(false);
 	});
+
+	it('returns false for an HDR capacity beyond 10 stops', async () => {
+		const invalidMetadata: GainMapMetadata = {
+			version: '1.0',
+			baseRenditionIsHdr: false,
+			gainMapMin: [0.0, 0.0, 0.0],
+			gainMapMax: [1.0, 1.0, 1.0],
+			gamma: [1.0, 1.0, 1.0],
+			offsetSdr: [0.0, 0.0, 0.0],
+			offsetHdr: [0.0, 0.0, 0.0],
+			hdrCapacityMin: 0.0,
+			hdrCapacityMax: 50.0,
+		};
+
+		const result = await validateMetadata(invalidMetadata);
+		expect(result).toBe(false);
+	});
+
+	it('checks applyColorSpace is a ColorGamut when present', async () => {
+		const metadata: GainMapMetadata = {
+			version: '1.0',
+			baseRenditionIsHdr: false,
+			gainMapMin: [0.0, 0.0, 0.0],
+			gainMapMax: [1.0, 1.0, 1.0],
+			gamma: [1.0, 1.0, 1.0],
+			offsetSdr: [0.0, 0.0, 0.0],
+			offsetHdr: [0.0, 0.0, 0.0],
+			hdrCapacityMin: 0.0,
+			hdrCapacityMax: 3.0,
+			applyColorSpace: ColorGamut.Bt2100,
+		};
+
+		expect(await validateMetadata(metadata)).toBe(true);
+		expect(await validateMetadata({ ...metadata, applyColorSpace: 7 as ColorGamut })).toBe(false);
+	});
 });
 
 describe('estimateHdrHeadroom', () => {
@@ -95,6 +176,47 @@ describe('estimateHdrHeadroom', () => {
 	});
 });
 
+describe('hdrStrengthScore', () => {
+	/**
+	 * Encodes the 16x16 fixture against an HDR that is its linear SDR with
+	 * pixels inside `boosted` brightened 2 stops.
+	 */
+	const encodeBoosted = (boosted: (x: number, y: number) => boolean) =>
+		encodeWithGain(REGULAR_JPEG_BASE64, (x, y) => (boosted(x, y) ? 4 : 1));
+
+	it('scores broadly boosted HDR above a single highlight', async () => {
+		const broad = await hdrStrengthScore(await encodeBoosted(() => true));
+		const highlight = await hdrStrengthScore(await encodeBoosted((x, y) => x < 4 && y < 4));
+
+		expect(broad).toBeLessThanOrEqual(100);
+		expect(highlight).toBeGreaterThan(0);
+		expect(broad).toBeGreaterThan(highlight);
+	});
+
+	it('scores a plain JPEG 0', async () => {
+		expect(await hdrStrengthScore(base64ToArrayBuffer(REGULAR_JPEG_BASE64))).toBe(0);
+	});
+});
+
+describe('estimateCapacityFromGainMap', () => {
+	/** Encodes the 16x16 fixture against an HDR exactly 2x its linear SDR. */
+	const encodeFlatOneStop = () => encodeWithGain(REGULAR_JPEG_BASE64, () => 2);
+
+	it('reads about 1 stop from a flat 2x gain map', async () => {
+		const file = await encodeFlatOneStop();
+		const capacity = await estimateCapacityFromGainMap(file);
+		expect(capacity).toBeGreaterThan(0.9);
+		expect(capacity).toBeLessThan(1.1);
+	});
+
+	it('decodes with the given metadata instead of the file\'s', async () => {
+		const file = await encodeFlatOneStop();
+		const metadata = await getMetadata(file);
+		const flat = { ...metadata, gainMapMin: [0, 0, 0], gainMapMax: [0, 0, 0] };
+		expect(await estimateCapacityFromGainMap(file, flat)).toBe(0);
+	});
+});
+
 describe('isMeaningfulHdr', () => {
 	it('returns true for metadata with significant HDR capacity', async () => {
 		const metadata: GainMapMetadata = {
@@ -130,3 +252,267 @@ describe('isMeaningfulHdr', () => {
 		expect(result).toBe(false);
 	});
 });
+
+describe('metadataApproxEqual', () => {
+	const reference: GainMapMetadata = {
+		version: '1.0',
+		baseRenditionIsHdr: false,
+		gainMapMin: [0.0, 0.0, 0.0],
+		gainMapMax: [3.0, 3.0, 3.0],
+		gamma: [1.0, 1.0, 1.0],
+		offsetSdr: [1 / 64, 1 / 64, 1 / 64],
+		offsetHdr: [1 / 64, 1 / 64, 1 / 64],
+		hdrCapacityMin: 0.0,
+		hdrCapacityMax: 3.0,
+	};
+
+	it('tolerates tiny float differences', async () => {
+		const reencoded: GainMapMetadata = {
+			...reference,
+			gainMapMax: [3.0004, 2.9997, 3.0],
+			gamma: [1.0002, 1.0, 0.9999],
+			hdrCapacityMax: 3.0003,
+		};
+
+		expect(await metadataApproxEqual(reference, reencoded, 1e-3)).toBe(true);
+	});
+
+	it('rejects a changed version string', async () => {
+		const other: GainMapMetadata = { ...reference, version: '1.1' };
+		expect(await metadataApproxEqual(reference, other, 1e-3)).toBe(false);
+	});
+
+	it('rejects a different apply color space', async () => {
+		const other: GainMapMetadata = { ...reference, applyColorSpace: ColorGamut.Bt2100 };
+		expect(await metadataApproxEqual(reference, other, 1e-3)).toBe(false);
+		expect(await metadataApproxEqual(other, { ...other }, 1e-3)).toBe(true);
+	});
+
+	it('rejects a gain range outside the tolerance', async () => {
+		const other: GainMapMetadata = { ...reference, gainMapMax: [3.0, 3.1, 3.0] };
+		expect(await metadataApproxEqual(reference, other, 1e-3)).toBe(false);
+	});
+});
+
+describe('isoDefaultMetadata', () => {
+	it('matches the ISO 21496-1 omission defaults', () => {
+		expect(isoDefaultMetadata.gainMapMin).toEqual([0, 0, 0]);
+		expect(isoDefaultMetadata.gamma).toEqual([1, 1, 1]);
+		expect(isoDefaultMetadata.offsetSdr).toEqual([1 / 64, 1 / 64, 1 / 64]);
+		expect(isoDefaultMetadata.offsetHdr).toEqual([1 / 64, 1 / 64, 1 / 64]);
+		expect(isoDefaultMetadata.hdrCapacityMin).toBe(0);
+	});
+
+	it('is applied when a packet omits Gamma and offsets', async () => {
+		// A packet whose Gamma and offsets differ from the defaults.
+		const parts = await splitUltraHdr(await encodeFixture());
+		const metadata: GainMapMetadata = {
+			...(await metadataFromJson(parts.metadataJson)),
+			gamma: [2, 2, 2],
+			offsetSdr: [1 / 16, 1 / 16, 1 / 16],
+			offsetHdr: [1 / 16, 1 / 16, 1 / 16],
+		};
+		const encoded = await encodeFromComponents(
+			parts.primaryJpeg.buffer as ArrayBuffer,
+			parts.gainMapJpeg.buffer as ArrayBuffer,
+			metadata,
+			GainMapXmpMode.Sync
+		);
+
+		// Hide the ISO 21496-1 packets so the XMP is the metadata read.
+		const bytes = new Uint8Array(encoded);
+		patchText(bytes, /urn:iso:std:iso:ts:21496:-1/g, 'urn:example:not-iso-21496');
+		const written = await getMetadata(bytes.buffer);
+		expect(written.gamma[0]).toBeCloseTo(2, 5);
+		expect(written.offsetSdr[0]).toBeCloseTo(1 / 16, 5);
+
+		patchText(bytes, /hdrgm:(Gamma|OffsetSDR|OffsetHDR)="[^"]*"/g, '');
+		const omitted = await getMetadata(bytes.buffer);
+		for (let i = 0; i < 3; i++) {
+			expect(omitted.gamma[i]).toBeCloseTo(isoDefaultMetadata.gamma[i], 5);
+			expect(omitted.offsetSdr[i]).toBeCloseTo(isoDefaultMetadata.offsetSdr[i], 5);
+			expect(omitted.offsetHdr[i]).toBeCloseTo(isoDefaultMetadata.offsetHdr[i], 5);
+		}
+	});
+});
+
+describe('gainMapXmp', () => {
+	const perChannel: GainMapMetadata = {
+		version: '1.0',
+		baseRenditionIsHdr: false,
+		gainMapMin: [0.0, 0.0, 0.0],
+		gainMapMax: [2.0, 2.5, 3.0],
+		gamma: [1.0, 1.25, 1.0],
+		offsetSdr: [1 / 64, 1 / 64, 1 / 64],
+		offsetHdr: [1 / 64, 1 / 64, 1 / 64],
+		hdrCapacityMin: 0.0,
+		hdrCapacityMax: 3.0,
+	};
+
+	it('writes per-channel values as an rdf:Seq', async () => {
+		const xmp = await gainMapXmp(perChannel, XmpFloatArrayStyle.RdfSeq);
+
+		expect(xmp).toContain('<hdrgm:GainMapMax>');
+		expect(xmp).toContain('<rdf:Seq>');
+		expect(xmp).not.toContain('hdrgm:GainMapMax=');
+		expect(xmp).toContain('hdrgm:GainMapMin="0"');
+		const block = xmp.slice(xmp.indexOf('<hdrgm:GainMapMax>'), xmp.indexOf('</hdrgm:GainMapMax>'));
+		expect(block.match(/<rdf:li>/g)).toHaveLength(3);
+
+		expect(await parseGainMapXmp(xmp)).toEqual(perChannel);
+	});
+
+	it('writes per-channel values as comma-separated attributes', async () => {
+		const xmp = await gainMapXmp(perChannel);
+
+		expect(xmp).toContain('hdrgm:GainMapMax="2, 2.5, 3"');
+		expect(await parseGainMapXmp(xmp)).toEqual(perChannel);
+	});
+
+	it('reads exponents and decimal commas', async () => {
+		const xmp = (await gainMapXmp(perChannel))
+			.replace(/hdrgm:GainMapMin="[^"]*"/, 'hdrgm:GainMapMin="1e-2"')
+			.replace(/hdrgm:GainMapMax="[^"]*"/, 'hdrgm:GainMapMax="2,5, 3,5, 4,5"')
+			.replace(/hdrgm:HDRCapacityMax="[^"]*"/, 'hdrgm:HDRCapacityMax="0,5"');
+		const parsed = await parseGainMapXmp(xmp);
+
+		for (const min of parsed.gainMapMin) expect(min).toBeCloseTo(0.01, 6);
+		expect(parsed.gainMapMax).toEqual([2.5, 3.5, 4.5]);
+		expect(parsed.hdrCapacityMax).toBe(0.5);
+	});
+
+	it('reads an ambiguous comma list as separate values', async () => {
+		const xmp = (await gainMapXmp(perChannel)).replace(
+			/hdrgm:Gamma="[^"]*"/,
+			'hdrgm:Gamma="1,5,2"'
+		);
+
+		expect((await parseGainMapXmp(xmp)).gamma).toEqual([1, 5, 2]);
+	});
+
+	it('reads BaseRenditionIsHDR leniently', async () => {
+		const xmp = await gainMapXmp(perChannel);
+		const cases: [string, boolean][] = [
+			[' True ', true],
+			['1', true],
+			['yes', true],
+			['FALSE', false],
+			['0', false],
+			['', false],
+		];
+		for (const [value, expected] of cases) {
+			const variant = xmp.replace(
+				/hdrgm:BaseRenditionIsHDR="[^"]*"/,
+				`hdrgm:BaseRenditionIsHDR="${value}"`
+			);
+			expect((await parseGainMapXmp(variant)).baseRenditionIsHdr).toBe(expected);
+		}
+	});
+
+	it('rejects XMP without a GainMapMax', async () => {
+		const xmp = (await gainMapXmp(perChannel)).replace(/hdrgm:GainMapMax="[^"]*"/, '');
+		await expect(parseGainMapXmp(xmp)).rejects.toThrow();
+	});
+});
+
+describe('metadataToJson', () => {
+	const perChannel: GainMapMetadata = {
+		version: '1.0',
+		baseRenditionIsHdr: false,
+		gainMapMin: [0.0, 0.125, 0.25],
+		gainMapMax: [2.0, 2.5, 3.0],
+		gamma: [1.0, 1.25, 1.0],
+		offsetSdr: [1 / 64, 1 / 32, 1 / 64],
+		offsetHdr: [1 / 64, 1 / 64, 1 / 128],
+		hdrCapacityMin: 0.0,
+		hdrCapacityMax: 3.0,
+	};
+
+	it('round-trips per-channel metadata', async () => {
+		const json = await metadataToJson(perChannel);
+
+		expect(JSON.parse(json).gainMapMax).toEqual([2.0, 2.5, 3.0]);
+		expect(await metadataFromJson(json)).toEqual(perChannel);
+	});
+
+	it('keeps applyColorSpace when set', async () => {
+		const metadata = { ...perChannel, applyColorSpace: ColorGamut.DisplayP3 };
+		expect(await metadataFromJson(await metadataToJson(metadata))).toEqual(metadata);
+	});
+
+	it('rejects malformed or invalid JSON metadata', async () => {
+		const json = await metadataToJson(perChannel);
+
+		await expect(metadataFromJson(json.slice(0, -1))).rejects.toThrow();
+		await expect(metadataFromJson('[]')).rejects.toThrow(/must be an object/);
+		await expect(
+			metadataFromJson(JSON.stringify({ ...perChannel, gamma: [1, 1] }))
+		).rejects.toThrow(/gamma must hold 3 numbers/);
+		await expect(
+			metadataFromJson(JSON.stringify({ ...perChannel, hdrCapacityMax: '3' }))
+		).rejects.toThrow(/hdrCapacityMax must be a number/);
+		await expect(
+			metadataFromJson(JSON.stringify({ ...perChannel, gamma: [0, 1, 1] }))
+		).rejects.toThrow(/not valid metadata/);
+	});
+});
+
+describe('metadataWarnings', () => {
+	const metadata: GainMapMetadata = {
+		version: '1.0',
+		baseRenditionIsHdr: false,
+		gainMapMin: [0.0, 0.0, 0.0],
+		gainMapMax: [3.0, 3.0, 3.0],
+		gamma: [1.0, 1.0, 1.0],
+		offsetSdr: [1 / 64, 1 / 64, 1 / 64],
+		offsetHdr: [1 / 64, 1 / 64, 1 / 64],
+		hdrCapacityMin: 0.0,
+		hdrCapacityMax: 3.0,
+	};
+
+	it('lists 1.0 as a supported version', async () => {
+		expect(await supportedVersions()).toEqual(['1.0']);
+	});
+
+	it('is clean for version 1.0', async () => {
+		expect(await metadataWarnings(metadata)).toEqual([]);
+	});
+
+	it('flags an unknown version that validateMetadata still accepts', async () => {
+		for (const version of ['2.0', '1.O']) {
+			const other: GainMapMetadata = { ...metadata, version };
+			expect(await validateMetadata(other)).toBe(true);
+			const warnings = await metadataWarnings(other);
+			expect(warnings).toHaveLength(1);
+			expect(warnings[0]).toContain(`Unknown metadata version "${version}"`);
+		}
+	});
+
+	it('accepts an HDR capacity within a stop of the peak gain', async () => {
+		const other: GainMapMetadata = {
+			...metadata,
+			gainMapMax: [3.0, 2.5, 2.0],
+			hdrCapacityMax: 2.5,
+		};
+		expect(await metadataWarnings(other)).toEqual([]);
+	});
+
+	it('flags an HDR capacity far below the peak gain', async () => {
+		const other: GainMapMetadata = { ...metadata, hdrCapacityMax: 0.5 };
+		expect(await validateMetadata(other)).toBe(true);
+		const warnings = await metadataWarnings(other);
+		expect(warnings).toHaveLength(1);
+		expect(warnings[0]).toMatch(/hdrCapacityMax 0\.5.* peak gain of 3/);
+	});
+
+	it('measures an HDR base against its deepest gainMapMin', async () => {
+		const hdrBase: GainMapMetadata = {
+			...metadata,
+			baseRenditionIsHdr: true,
+			gainMapMin: [-3.0, -3.0, -3.0],
+			gainMapMax: [0.0, 0.0, 0.0],
+		};
+		expect(await metadataWarnings(hdrBase)).toEqual([]);
+		expect(await metadataWarnings({ ...hdrBase, hdrCapacityMax: 6.0 })).toHaveLength(1);
+	});
+});
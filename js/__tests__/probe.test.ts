@@ -1,9 +1,25 @@
 /**
- * Probe tests for probeUltraHdr function.
+ * Probe tests for probeUltraHdr and indexUltraHdr.
  */
 import { describe, it, expect } from 'vitest';
-import { probeUltraHdr } from '../src/index';
-import { base64ToArrayBuffer, REGULAR_JPEG_BASE64, PNG_HEADER_BASE64 } from './fixtures/test-data';
+import {
+	defaultEncodeOptions,
+	encodeUltraHdr,
+	hdrStrengthScore,
+	indexUltraHdr,
+	locateComponents,
+	probeUltraHdr,
+	UltraHdrLayout,
+} from '../src/index';
+import {
+	base64ToArrayBuffer,
+	REGULAR_JPEG_BASE64,
+	PNG_HEADER_BASE64,
+	PROGRESSIVE_JPEG_BASE64,
+	createSyntheticHdrData,
+	encodeFixture,
+	withoutGainMapMetadata,
+} from './fixtures/test-data';
 
 describe('probeUltraHdr', () => {
 	it('returns invalid result with all flags false for empty buffer', async () => {
@@ -42,6 +58,15 @@ describe('probeUltraHdr', () => {
 		expect(result.hasMetadata).toBe(false); // And no metadata
 	});
 
+	it('reports whether the primary is progressive', async () => {
+		const progressive = await probeUltraHdr(base64ToArrayBuffer(PROGRESSIVE_JPEG_BASE64));
+		expect(progressive.hasPrimaryImage).toBe(true);
+		expect(progressive.primaryIsProgressive).toBe(true);
+
+		const baseline = await probeUltraHdr(base64ToArrayBuffer(REGULAR_JPEG_BASE64));
+		expect(baseline.primaryIsProgressive).toBe(false);
+	});
+
 	it('provides failure reasons through structured result', async () => {
 		const jpegBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
 		const result = await probeUltraHdr(jpegBuffer);
@@ -142,4 +167,131 @@ describe('probeUltraHdr', () => {
 		expect(result.hasMetadata).toBe(false);
 		expect(result.metadataVersion).toBe('');
 	});
+
+	it('recovers past a corrupt segment length to find later XMP', async () => {
+		const jpeg = new Uint8Array(base64ToArrayBuffer(REGULAR_JPEG_BASE64));
+		// COM segment claiming length 1 (less than its own length field).
+		const badCom = [0xff, 0xfe, 0x00, 0x01, 0x6a, 0x75, 0x6e, 0x6b];
+		const xmpText = new TextEncoder().encode(
+			'http://ns.adobe.com/xap/1.0/\0<x:xmpmeta><rdf:Description hdrgm:Version="1.0"/></x:xmpmeta>'
+		);
+		const xmpLength = xmpText.length + 2;
+		const xmp = [0xff, 0xe1, xmpLength >> 8, xmpLength & 0xff, ...xmpText];
+
+		const corrupt = new Uint8Array([
+			...jpeg.subarray(0, 2),
+			...badCom,
+			...xmp,
+			...jpeg.subarray(2),
+		]);
+		const result = await probeUltraHdr(corrupt.buffer);
+
+		expect(result.isValid).toBe(false);
+		expect(result.hasPrimaryImage).toBe(true);
+		expect(result.width).toBe(16);
+		expect(result.hasMetadata).toBe(true);
+		expect(result.warnings).toHaveLength(1);
+		expect(result.warnings[0]).toContain('invalid length 1');
+	});
+
+	it('warns about a gain map codestream whose metadata is missing', async () => {
+		const result = await probeUltraHdr(await withoutGainMapMetadata(await encodeFixture()));
+
+		expect(result.isValid).toBe(false);
+		expect(result.hasGainMap).toBe(true);
+		expect(result.warnings).toHaveLength(1);
+		expect(result.warnings[0]).toMatch(/metadata missing/);
+	});
+
+	it('reports no warnings for a well-formed JPEG', async () => {
+		const jpegBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const result = await probeUltraHdr(jpegBuffer);
+
+		expect(result.warnings).toEqual([]);
+	});
+});
+
+describe('probeUltraHdr layout', () => {
+	const encode = () =>
+		encodeUltraHdr(
+			'layout',
+			base64ToArrayBuffer(REGULAR_JPEG_BASE64),
+			createSyntheticHdrData(16, 16).buffer,
+			defaultEncodeOptions
+		);
+
+	it('reports an MPF-indexed gain map as DualImageMpf', async () => {
+		expect((await probeUltraHdr(await encode())).layout).toBe(UltraHdrLayout.DualImageMpf);
+	});
+
+	it('reports a gain map appended without MPF as DualImageAppended', async () => {
+		const encoded = await encode();
+		const { mpf } = await locateComponents(encoded);
+		const bytes = new Uint8Array(encoded);
+		// The APP2 marker, length and "MPF\0" identifier precede the MPF data.
+		const stripped = new Uint8Array(bytes.length - mpf!.length - 8);
+		stripped.set(bytes.subarray(0, mpf!.offset - 8));
+		stripped.set(bytes.subarray(mpf!.offset + mpf!.length), mpf!.offset - 8);
+
+		expect((await locateComponents(stripped.buffer)).mpf).toBeNull();
+		expect((await probeUltraHdr(stripped.buffer)).layout).toBe(UltraHdrLayout.DualImageAppended);
+	});
+
+	it('reports a lone primary announcing a gain map as GainMapMissing', async () => {
+		const encoded = await encode();
+		const { primary } = await locateComponents(encoded);
+		const lone = encoded.slice(primary.offset, primary.offset + primary.length);
+
+		const result = await probeUltraHdr(lone);
+		expect(result.layout).toBe(UltraHdrLayout.GainMapMissing);
+		expect(result.warnings.some((w) => w.includes('none follows it'))).toBe(true);
+	});
+
+	it('reports two concatenated plain JPEGs as Unknown', async () => {
+		const jpeg = new Uint8Array(base64ToArrayBuffer(REGULAR_JPEG_BASE64));
+		const pair = new Uint8Array(jpeg.length * 2);
+		pair.set(jpeg);
+		pair.set(jpeg, jpeg.length);
+
+		const result = await probeUltraHdr(pair.buffer);
+		expect(result.layout).toBe(UltraHdrLayout.Unknown);
+		expect(result.isValid).toBe(false);
+		expect(result.hasGainMap).toBe(false);
+	});
+
+	it('reports a plain JPEG as Unknown', async () => {
+		const result = await probeUltraHdr(base64ToArrayBuffer(REGULAR_JPEG_BASE64));
+		expect(result.layout).toBe(UltraHdrLayout.Unknown);
+	});
+});
+
+describe('indexUltraHdr', () => {
+	it('summarizes a known fixture', async () => {
+		const encoded = await encodeUltraHdr(
+			'index',
+			base64ToArrayBuffer(REGULAR_JPEG_BASE64),
+			createSyntheticHdrData(16, 16).buffer,
+			{ ...defaultEncodeOptions, gainMapScale: 2 }
+		);
+		const record = await indexUltraHdr(encoded);
+
+		expect(record.width).toBe(16);
+		expect(record.height).toBe(16);
+		expect(record.gainMapWidth).toBe(8);
+		expect(record.gainMapHeight).toBe(8);
+		expect(record.hdrCapacityMin).toBeCloseTo(0, 3);
+		expect(record.hdrCapacityMax).toBeCloseTo(defaultEncodeOptions.targetHdrCapacity, 3);
+		expect(record.version).toBe('1.0');
+		expect(record.layout).toBe(UltraHdrLayout.DualImageMpf);
+		expect(record.hdrStrength).toBe(await hdrStrengthScore(encoded));
+		for (const value of Object.values(record)) {
+			expect(['number', 'string']).toContain(typeof value);
+		}
+	});
+
+	it('throws for a JPEG without a gain map', async () => {
+		await expect(indexUltraHdr(base64ToArrayBuffer(REGULAR_JPEG_BASE64))).rejects.toThrow(
+			/Not an UltraHDR/
+		);
+	});
 });
@@ -0,0 +1,678 @@
+/**
+ * HDR reconstruction tests for applyGainMap, applyGainMapMulti, applyGainMapRows and the derived
+ * helpers, including sdrReconstructionSimilarity.
+ */
+import { describe, it, expect } from 'vitest';
+import {
+	applyGainMap,
+	applyGainMapMulti,
+	applyGainMapRegion,
+	applyGainMapRows,
+	ColorGamut,
+	computeMaxCllMaxFall,
+	decodeGainValues,
+	decodeUltraHdr,
+	decodeUltraHdrThumbnail,
+	encodeUltraHdr,
+	defaultEncodeOptions,
+	encodeFromComponents,
+	estimateGainMapGamma,
+	exportGainDecodeLut,
+	GainMapDownsample,
+	GainMapEdgeMode,
+	getMetadata,
+	isoDefaultMetadata,
+	isUltraHdr,
+	locateComponents,
+	metadataFromJson,
+	probeUltraHdr,
+	reconstructNits,
+	reconstructionError,
+	sdrReconstructionSimilarity,
+	splitUltraHdr,
+} from '../src/index';
+import {
+	base64ToArrayBuffer,
+	REGULAR_JPEG_BASE64,
+	REGULAR_JPEG_WIDTH,
+	REGULAR_JPEG_HEIGHT,
+	LARGE_JPEG_BASE64,
+	LARGE_JPEG_WIDTH,
+	LARGE_JPEG_HEIGHT,
+	WIDE_JPEG_BASE64,
+	encodeFixture,
+	encodeWithGain,
+	hdrWithGain,
+	linearSdr,
+	patchText,
+} from './fixtures/test-data';
+
+describe('applyGainMap', () => {
+	it('returns width * height * 3 linear floats', async () => {
+		const encoded = await encodeFixture();
+		const hdr = await applyGainMap(encoded);
+
+		expect(hdr.width).toBe(REGULAR_JPEG_WIDTH);
+		expect(hdr.height).toBe(REGULAR_JPEG_HEIGHT);
+		expect(hdr.data).toBeInstanceOf(Float32Array);
+		expect(hdr.data.length).toBe(REGULAR_JPEG_WIDTH * REGULAR_JPEG_HEIGHT * 3);
+		for (const v of hdr.data) {
+			expect(Number.isFinite(v)).toBe(true);
+			expect(v).toBeGreaterThanOrEqual(0);
+		}
+	});
+
+	it('throws error for regular JPEG without gain map', async () => {
+		const jpegBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		await expect(applyGainMap(jpegBuffer)).rejects.toThrow();
+	});
+
+	it('reproduces the SDR at weight 0 regardless of the gain range', async () => {
+		// Both files share the same SDR base but carry different gain ranges.
+		const narrow = await applyGainMap(await encodeFixture({ targetHdrCapacity: 1 }), 0);
+		const wide = await applyGainMap(await encodeFixture({ targetHdrCapacity: 6 }), 0);
+
+		expect(Array.from(narrow.data)).toEqual(Array.from(wide.data));
+		// A gain of exactly 1.0 keeps every value within the SDR range.
+		for (const v of narrow.data) {
+			expect(v).toBeLessThanOrEqual(1.0);
+		}
+	});
+
+	it('brightens highlights at full weight', async () => {
+		const encoded = await encodeFixture({ targetHdrCapacity: 3 });
+		const sdr = await applyGainMap(encoded, 0);
+		const hdr = await applyGainMap(encoded);
+
+		const peak = (data: Float32Array) => data.reduce((m, v) => Math.max(m, v), 0);
+		expect(peak(hdr.data)).toBeGreaterThan(peak(sdr.data));
+	});
+
+	it('samples a gain map larger than the primary at each pixel center', async () => {
+		// A 32x32 gain map whose odd columns and odd rows each add a stop, so
+		// every 2x2 block spans 0 to 2 stops and averages 1. Sampling at pixel
+		// centers lands in the middle of a block; top-left or corner-aligned
+		// sampling reads 0 stops at the origin and 2 at the far corner.
+		const gainAt = (x: number, y: number) => 2 ** ((x % 2) + (y % 2));
+		const options = { gainMapScale: 1, gainMapQuality: 100 };
+		const large = await encodeWithGain(LARGE_JPEG_BASE64, gainAt, options);
+		const parts = await splitUltraHdr(large);
+
+		// Pair it with the 16x16 regular image as the primary.
+		const combined = await encodeFromComponents(
+			base64ToArrayBuffer(REGULAR_JPEG_BASE64),
+			parts.gainMapJpeg.buffer as ArrayBuffer,
+			await metadataFromJson(parts.metadataJson)
+		);
+		const decoded = await decodeUltraHdr('oversized', combined);
+		expect(decoded.gainMapWidth).toBe(LARGE_JPEG_WIDTH);
+		expect(decoded.gainMapHeight).toBe(LARGE_JPEG_HEIGHT);
+		expect(decoded.width).toBe(REGULAR_JPEG_WIDTH);
+		expect(decoded.warnings.some((w) => w.includes('larger than'))).toBe(true);
+
+		const base = await applyGainMap(combined, 0);
+		const full = await applyGainMap(combined);
+		expect(full.width).toBe(REGULAR_JPEG_WIDTH);
+		expect(full.height).toBe(REGULAR_JPEG_HEIGHT);
+		const { offsetSdr: os, offsetHdr: oh } = decoded.metadata;
+		const ratio = (x: number, y: number, c: number) => {
+			const i = (y * full.width + x) * 3 + c;
+			return (full.data[i] + oh[c]) / (base.data[i] + os[c]);
+		};
+		const last = REGULAR_JPEG_WIDTH - 1;
+		for (const [x, y] of [
+			[0, 0],
+			[last, 0],
+			[0, last],
+			[last, last],
+		]) {
+			for (let c = 0; c < 3; c++) expect(ratio(x, y, c)).toBeCloseTo(2, 1);
+		}
+		for (let y = 0; y < full.height; y++) {
+			for (let x = 0; x < full.width; x++) {
+				for (let c = 0; c < 3; c++) {
+					expect(ratio(x, y, c)).toBeGreaterThan(1.8);
+					expect(ratio(x, y, c)).toBeLessThan(2.2);
+				}
+			}
+		}
+	});
+
+	it('clamps to maxLinear when set and leaves values unbounded otherwise', async () => {
+		const encoded = await encodeFixture({ targetHdrCapacity: 3 });
+		const unbounded = await applyGainMap(encoded);
+		const ceiling = 1.5;
+		expect(Math.max(...unbounded.data)).toBeGreaterThan(ceiling);
+
+		const clamped = await applyGainMap(encoded, undefined, undefined, ceiling);
+		for (let i = 0; i < clamped.data.length; i++) {
+			expect(clamped.data[i]).toBe(Math.min(unbounded.data[i], ceiling));
+		}
+		const [multi] = await applyGainMapMulti(
+			encoded,
+			[Number.POSITIVE_INFINITY],
+			GainMapEdgeMode.Clamp,
+			ceiling
+		);
+		expect(Array.from(multi.data)).toEqual(Array.from(clamped.data));
+		await expect(applyGainMap(encoded, undefined, undefined, 0)).rejects.toThrow(/maxLinear/);
+	});
+
+	it('reconstructs an XMP-only gain map the same as its ISO 21496-1 original', async () => {
+		const encoded = await encodeFixture({ targetHdrCapacity: 3 });
+		// Hide the ISO packets so only the Adobe hdrgm XMP describes the gain map.
+		const bytes = new Uint8Array(encoded.slice(0));
+		patchText(bytes, /urn:iso:std:iso:ts:21496:-1/g, 'urn:example:not-iso-21496');
+
+		const iso = await applyGainMap(encoded);
+		const xmp = await applyGainMap(bytes.buffer);
+		expect(xmp.data.length).toBe(iso.data.length);
+		for (let i = 0; i < iso.data.length; i++) {
+			expect(xmp.data[i]).toBeCloseTo(iso.data[i], 2);
+		}
+	});
+});
+
+describe('applyGainMapMulti', () => {
+	it('matches applyGainMap at each display headroom', async () => {
+		const encoded = await encodeFixture({ targetHdrCapacity: 3 });
+		const capacities = [0, 0.5, 1.5, 3, Number.POSITIVE_INFINITY];
+		const multi = await applyGainMapMulti(encoded, capacities, GainMapEdgeMode.Mirror);
+
+		expect(multi).toHaveLength(capacities.length);
+		for (let i = 0; i < capacities.length; i++) {
+			const single = await applyGainMap(encoded, capacities[i], GainMapEdgeMode.Mirror);
+			expect(multi[i].width).toBe(single.width);
+			expect(multi[i].height).toBe(single.height);
+			expect(Array.from(multi[i].data)).toEqual(Array.from(single.data));
+		}
+	});
+
+	it('returns no images for no headrooms', async () => {
+		expect(await applyGainMapMulti(await encodeFixture(), [])).toEqual([]);
+	});
+});
+
+describe('gain map edge modes', () => {
+	// Dark left, bright right HDR with a 4x downscaled gain map, so the last
+	// gain map column holds a sharp step against its neighbour.
+	async function encodeEdgeFixture() {
+		const width = REGULAR_JPEG_WIDTH;
+		const height = REGULAR_JPEG_HEIGHT;
+		const hdrData = new Float32Array(width * height * 3);
+		for (let i = 0; i < width * height; i++) {
+			hdrData.fill(i % width >= width - 4 ? 4.0 : 0.05, i * 3, i * 3 + 3);
+		}
+		return encodeUltraHdr('edge', base64ToArrayBuffer(REGULAR_JPEG_BASE64), hdrData.buffer, {
+			...defaultEncodeOptions,
+			gainMapScale: 4,
+		});
+	}
+
+	const column = (data: Float32Array, x: number) =>
+		Array.from({ length: REGULAR_JPEG_HEIGHT }, (_, y) => data[(y * REGULAR_JPEG_WIDTH + x) * 3]);
+
+	it('differs only where sampling leaves the gain map', async () => {
+		const encoded = await encodeEdgeFixture();
+		const clamp = await applyGainMap(encoded, undefined, GainMapEdgeMode.Clamp);
+		const mirror = await applyGainMap(encoded, undefined, GainMapEdgeMode.Mirror);
+
+		const last = REGULAR_JPEG_WIDTH - 1;
+		expect(column(mirror.data, last)).not.toEqual(column(clamp.data, last));
+		// Column 8 samples between gain map texel centers in both modes.
+		expect(column(mirror.data, 8)).toEqual(column(clamp.data, 8));
+	});
+
+	it('defaults to clamping', async () => {
+		const encoded = await encodeEdgeFixture();
+		const implicit = await applyGainMap(encoded);
+		const clamp = await applyGainMap(encoded, undefined, GainMapEdgeMode.Clamp);
+		expect(Array.from(implicit.data)).toEqual(Array.from(clamp.data));
+	});
+
+	it('throws error for an unknown edge mode', async () => {
+		const encoded = await encodeEdgeFixture();
+		await expect(applyGainMap(encoded, undefined, 5 as GainMapEdgeMode)).rejects.toThrow();
+	});
+});
+
+describe('applyGainMapRegion', () => {
+	const encodeScaledFixture = () => encodeFixture({ gainMapScale: 4 });
+
+	it('matches the same slice of a full reconstruction', async () => {
+		const encoded = await encodeScaledFixture();
+		const full = await applyGainMap(encoded, 2.0);
+		const region = { x: 5, y: 3, width: 7, height: 9 };
+		const tile = await applyGainMapRegion(encoded, region, 2.0);
+
+		expect(tile.width).toBe(region.width);
+		expect(tile.height).toBe(region.height);
+		expect(tile.data.length).toBe(region.width * region.height * 3);
+		for (let y = 0; y < region.height; y++) {
+			const start = ((region.y + y) * REGULAR_JPEG_WIDTH + region.x) * 3;
+			const expected = full.data.subarray(start, start + region.width * 3);
+			const actual = tile.data.subarray(y * region.width * 3, (y + 1) * region.width * 3);
+			expect(Array.from(actual)).toEqual(Array.from(expected));
+		}
+	});
+
+	it('throws error for a region outside the image', async () => {
+		const encoded = await encodeScaledFixture();
+		const region = { x: 10, y: 0, width: 8, height: 4 };
+		await expect(applyGainMapRegion(encoded, region)).rejects.toThrow();
+	});
+});
+
+describe('applyGainMapRows', () => {
+	it('streams rows that concatenate to the bulk output', async () => {
+		const encoded = await encodeFixture();
+		const bulk = await applyGainMap(encoded, 2.0);
+
+		const rows: Float32Array[] = [];
+		const ys: number[] = [];
+		await applyGainMapRows(
+			encoded,
+			(y, row) => {
+				ys.push(y);
+				rows.push(row);
+			},
+			2.0
+		);
+
+		expect(ys).toEqual(Array.from({ length: REGULAR_JPEG_HEIGHT }, (_, i) => i));
+		const streamed = new Float32Array(bulk.data.length);
+		rows.forEach((row, y) => {
+			expect(row.length).toBe(REGULAR_JPEG_WIDTH * 3);
+			streamed.set(row, y * row.length);
+		});
+		expect(Array.from(streamed)).toEqual(Array.from(bulk.data));
+	});
+});
+
+/** A matrix/TRC ICC profile reduced to the rXYZ and gXYZ colorants of BT.2020 (D50). */
+function createBt2020Profile(): Uint8Array {
+	const profile = new Uint8Array(196);
+	const view = new DataView(profile.buffer);
+	view.setUint32(0, profile.length);
+	profile.set(new TextEncoder().encode('mntrRGB XYZ '), 12);
+	profile.set(new TextEncoder().encode('acsp'), 36);
+	view.setUint32(128, 2);
+	const colorants: [string, number, number][] = [
+		['rXYZ', 0.6734, 0.279],
+		['gXYZ', 0.1656, 0.6753],
+	];
+	colorants.forEach(([tag, x, y], i) => {
+		const data = 156 + i * 20;
+		profile.set(new TextEncoder().encode(tag), 132 + i * 12);
+		view.setUint32(136 + i * 12, data);
+		view.setUint32(140 + i * 12, 20);
+		profile.set(new TextEncoder().encode('XYZ '), data);
+		view.setInt32(data + 8, Math.round(x * 65536));
+		view.setInt32(data + 12, Math.round(y * 65536));
+	});
+	return profile;
+}
+
+describe('applyColorSpace', () => {
+	it('is absent when the gain applies in the base color space', async () => {
+		const metadata = await getMetadata(await encodeFixture());
+		expect(metadata.applyColorSpace).toBeUndefined();
+	});
+
+	it('applies the gain in the gain map ICC color space when ISO metadata asks', async () => {
+		const encoded = await encodeFixture({ gainMapDownsample: GainMapDownsample.BoxAverage });
+		const source = new Uint8Array(encoded);
+		const sdr = (await applyGainMap(encoded, 0)).data;
+		const ratios = (await decodeGainValues(encoded)).data;
+
+		// Give the gain map a BT.2020 profile right after its SOI, and clear
+		// use_base_colour_space in its ISO 21496-1 metadata.
+		const icc = createBt2020Profile();
+		const chunk = new Uint8Array(4 + 14 + icc.length);
+		chunk.set([0xff, 0xe2, (chunk.length - 2) >> 8, (chunk.length - 2) & 0xff]);
+		chunk.set(new TextEncoder().encode('ICC_PROFILE\0'), 4);
+		chunk.set([1, 1], 16);
+		chunk.set(icc, 18);
+		const { gainMap } = await locateComponents(encoded);
+		const bytes = new Uint8Array(source.length + chunk.length);
+		bytes.set(source.subarray(0, gainMap.offset + 2));
+		bytes.set(chunk, gainMap.offset + 2);
+		bytes.set(source.subarray(gainMap.offset + 2), gainMap.offset + 2 + chunk.length);
+		const text = new TextDecoder('latin1').decode(bytes);
+		// The segment payload is the NUL-terminated URN, minimum_version and
+		// writer_version (16 bits each), then the flags byte.
+		const urnText = 'urn:iso:std:iso:ts:21496:-1\0';
+		const urn = text.lastIndexOf(urnText);
+		expect(urn).toBeGreaterThan(gainMap.offset);
+		const flags = urn + urnText.length + 2 + 2;
+		const useBaseColourSpace = 0x40;
+		bytes[flags] &= ~useBaseColourSpace;
+		const length = `Item:Length="${gainMap.length}"`;
+		const patched = `Item:Length="${gainMap.length + chunk.length}"`;
+		expect(patched.length).toBe(length.length);
+		bytes.set(new TextEncoder().encode(patched), text.indexOf(length));
+
+		const metadata = await getMetadata(bytes.buffer);
+		expect(metadata.applyColorSpace).toBe(ColorGamut.Bt2100);
+		const { offsetSdr, offsetHdr } = metadata;
+
+		// BT.709 <-> BT.2020 linear RGB (D65).
+		const toBt2020 = [0.6275, 0.3293, 0.0433, 0.0691, 0.9195, 0.0114, 0.0164, 0.088, 0.8954];
+		const toBt709 = [1.6602, -0.5875, -0.0728, -0.1246, 1.1329, -0.0083, -0.0182, -0.1006, 1.119];
+		const mul = (m: number[], v: number[]) =>
+			[0, 1, 2].map((r) => m[r * 3] * v[0] + m[r * 3 + 1] * v[1] + m[r * 3 + 2] * v[2]);
+
+		const hdr = (await applyGainMap(bytes.buffer)).data;
+		const plain = (await applyGainMap(encoded)).data;
+		let differs = false;
+		for (let i = 0; i < hdr.length; i += 3) {
+			const s = mul(toBt2020, [sdr[i], sdr[i + 1], sdr[i + 2]]);
+			const applied = s.map((v, c) =>
+				Math.max((v + offsetSdr[c]) * ratios[i + c] - offsetHdr[c], 0)
+			);
+			const expected = mul(toBt709, applied).map((v) => Math.max(v, 0));
+			for (let c = 0; c < 3; c++) {
+				const tolerance = 2e-3 * Math.max(1, expected[c]);
+				expect(Math.abs(hdr[i + c] - expected[c])).toBeLessThan(tolerance);
+				differs ||= Math.abs(hdr[i + c] - plain[i + c]) > 1e-3;
+			}
+		}
+		expect(differs).toBe(true);
+	});
+});
+
+describe('decodeGainValues', () => {
+	it('decodes a flat gain map to a constant linear ratio', async () => {
+		// HDR at exactly twice the SDR once offsets are included: a flat 1-stop gain.
+		const encoded = await encodeWithGain(REGULAR_JPEG_BASE64, () => 2);
+
+		const gains = await decodeGainValues(encoded);
+		expect(gains.width).toBeGreaterThan(0);
+		expect(gains.height).toBeGreaterThan(0);
+		expect([1, 3]).toContain(gains.channels);
+		expect(gains.data.length).toBe(gains.width * gains.height * gains.channels);
+		for (const ratio of gains.data) {
+			expect(ratio).toBeGreaterThan(1.8);
+			expect(ratio).toBeLessThan(2.2);
+		}
+	});
+
+	it('applies a three-value XMP gamma to each channel separately', async () => {
+		// A computed gain map is RGB, so every channel is decoded with its own gamma.
+		const encoded = await encodeWithGain(REGULAR_JPEG_BASE64, () => 2, {
+			gainMapDownsample: GainMapDownsample.BoxAverage,
+		});
+
+		// Hide the ISO 21496-1 packets so the XMP is the metadata read, then give
+		// the green channel gamma 2, dropping the offsets (their default is 1/64).
+		const bytes = new Uint8Array(encoded);
+		patchText(bytes, /urn:iso:std:iso:ts:21496:-1/g, 'urn:example:not-iso-21496');
+		const gammaAndOffsets = /hdrgm:Gamma="[^"]*"(\s+hdrgm:Offset(SDR|HDR)="[^"]*")*/g;
+		patchText(bytes, gammaAndOffsets, 'hdrgm:Gamma="1, 2, 1"');
+
+		const metadata = await getMetadata(bytes.buffer);
+		expect(metadata.gamma[0]).toBeCloseTo(1, 5);
+		expect(metadata.gamma[1]).toBeCloseTo(2, 5);
+		expect(metadata.gamma[2]).toBeCloseTo(1, 5);
+
+		// Gain map min is 0, so a sample v decodes to max * v^(1 / gamma) stops.
+		const gains = await decodeGainValues(bytes.buffer);
+		expect(gains.channels).toBe(3);
+		const [maxR, maxG] = metadata.gainMapMax;
+		for (let i = 0; i < gains.data.length; i += 3) {
+			const [r, g, b] = [0, 1, 2].map((c) => Math.log2(gains.data[i + c]));
+			expect(r).toBeCloseTo(1, 1);
+			expect(b).toBeCloseTo(r, 1);
+			expect(g).toBeCloseTo(maxG * Math.sqrt(r / maxR), 1);
+		}
+	});
+
+	it('throws error for regular JPEG without gain map', async () => {
+		const jpegBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		await expect(decodeGainValues(jpegBuffer)).rejects.toThrow();
+	});
+});
+
+describe('exportGainDecodeLut', () => {
+	it('matches the decoded gain at encoded 0 and 1', async () => {
+		const metadata = await getMetadata(await encodeFixture());
+		const lut = await exportGainDecodeLut(metadata, 0, 17);
+		expect(lut).toBeInstanceOf(Float32Array);
+		expect(lut.length).toBe(17);
+		expect(lut[0]).toBeCloseTo(2 ** metadata.gainMapMin[0], 5);
+		expect(lut[16]).toBeCloseTo(2 ** metadata.gainMapMax[0], 5);
+		for (let i = 1; i < lut.length; i++) expect(lut[i]).toBeGreaterThanOrEqual(lut[i - 1]);
+	});
+
+	it('follows each channel with its gamma', async () => {
+		const metadata = {
+			...isoDefaultMetadata,
+			gainMapMin: [-1, 0, 0],
+			gainMapMax: [1, 2, 2],
+			gamma: [1, 1, 2],
+		};
+		const red = await exportGainDecodeLut(metadata, 0, 3);
+		expect(Array.from(red).map((v) => Math.round(v * 1000) / 1000)).toEqual([0.5, 1, 2]);
+		// Encoded 0.5 with gamma 2 decodes 0.5^(1/2) of the way to gainMapMax.
+		const blue = await exportGainDecodeLut(metadata, 2, 3);
+		expect(blue[0]).toBeCloseTo(1, 5);
+		expect(blue[1]).toBeCloseTo(2 ** (2 * Math.SQRT1_2), 4);
+		expect(blue[2]).toBeCloseTo(4, 5);
+	});
+
+	it('rejects a table smaller than two entries or an out-of-range channel', async () => {
+		await expect(exportGainDecodeLut(isoDefaultMetadata, 0, 1)).rejects.toThrow(/at least 2/);
+		await expect(exportGainDecodeLut(isoDefaultMetadata, 3, 16)).rejects.toThrow(/Channel/);
+	});
+});
+
+describe('decodeUltraHdrThumbnail', () => {
+	it('returns a plain JPEG whose largest dimension is maxDim', async () => {
+		const encoded = await encodeFixture({}, WIDE_JPEG_BASE64);
+
+		const thumbnail = await decodeUltraHdrThumbnail(encoded, 8);
+		expect(await isUltraHdr(thumbnail)).toBe(false);
+		const probe = await probeUltraHdr(thumbnail);
+		expect(probe.hasPrimaryImage).toBe(true);
+		expect(Math.max(probe.width, probe.height)).toBe(8);
+		expect(probe.height).toBe(4);
+	});
+
+	it('decimates past the scaled IDCT to sizes it cannot produce', async () => {
+		// 32x32 decodes at 1/4 to 8x8, which is then decimated to 5x5.
+		const encoded = await encodeFixture({}, LARGE_JPEG_BASE64);
+
+		const probe = await probeUltraHdr(await decodeUltraHdrThumbnail(encoded, 5));
+		expect(probe.width).toBe(5);
+		expect(probe.height).toBe(5);
+	});
+
+	it('throws error for a non-positive size', async () => {
+		await expect(decodeUltraHdrThumbnail(await encodeFixture(), 0)).rejects.toThrow();
+	});
+});
+
+describe('reconstructNits', () => {
+	it('scales a flat 1-stop gain to twice the SDR luminance in nits', async () => {
+		const hdr = await hdrWithGain(REGULAR_JPEG_BASE64, () => 2);
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const encoded = await encodeUltraHdr('nits', sdrBuffer, hdr.buffer, defaultEncodeOptions);
+
+		// Peak of 203 * Y(2 * (sdr + offset) - offset) over the reference SDR.
+		let expectedPeak = 0;
+		for (let i = 0; i < hdr.length; i += 3) {
+			const luma = 0.2126 * hdr[i] + 0.7152 * hdr[i + 1] + 0.0722 * hdr[i + 2];
+			expectedPeak = Math.max(expectedPeak, luma * 203);
+		}
+
+		const nits = await reconstructNits(encoded);
+		expect(nits.width).toBe(REGULAR_JPEG_WIDTH);
+		expect(nits.height).toBe(REGULAR_JPEG_HEIGHT);
+		expect(nits.data.length).toBe(REGULAR_JPEG_WIDTH * REGULAR_JPEG_HEIGHT);
+		const { maxCll } = await computeMaxCllMaxFall(nits.data, nits.width, nits.height);
+		expect(Math.abs(maxCll - expectedPeak) / expectedPeak).toBeLessThan(0.05);
+	});
+
+	it('averages the channels with equal-weight luminance coefficients', async () => {
+		const encoded = await encodeFixture();
+		const hdr = (await applyGainMap(encoded)).data;
+		const third = 1 / 3;
+		const nits = await reconstructNits(
+			encoded,
+			100,
+			Number.POSITIVE_INFINITY,
+			GainMapEdgeMode.Clamp,
+			[third, third, third]
+		);
+		for (let i = 0; i < nits.data.length; i++) {
+			const mean = (hdr[i * 3] + hdr[i * 3 + 1] + hdr[i * 3 + 2]) / 3;
+			expect(nits.data[i]).toBeCloseTo(mean * 100, 2);
+		}
+	});
+
+	it('throws error for luminance coefficients that are not 3 non-negative numbers', async () => {
+		const encoded = await encodeFixture();
+		for (const luma of [[1, 1], [1, -1, 1], [0, 0, 0]]) {
+			await expect(
+				reconstructNits(
+					encoded,
+					203,
+					Number.POSITIVE_INFINITY,
+					GainMapEdgeMode.Clamp,
+					luma as [number, number, number]
+				)
+			).rejects.toThrow(/uminance coefficient/);
+		}
+	});
+
+	it('scales linearly with sdrWhiteNits', async () => {
+		const encoded = await encodeFixture();
+		const at100 = await reconstructNits(encoded, 100);
+		const at400 = await reconstructNits(encoded, 400);
+		for (let i = 0; i < at100.data.length; i++) {
+			expect(at400.data[i]).toBeCloseTo(at100.data[i] * 4, 2);
+		}
+	});
+});
+
+describe('computeMaxCllMaxFall', () => {
+	it('returns the brightest pixel and the frame average', async () => {
+		const nits = new Float32Array([100, 300, 200, 400]);
+		const level = await computeMaxCllMaxFall(nits, 2, 2);
+		expect(level.maxCll).toBeCloseTo(400, 3);
+		expect(level.maxFall).toBeCloseTo(250, 3);
+	});
+
+	it('throws error when the buffer does not match the dimensions', async () => {
+		await expect(computeMaxCllMaxFall(new Float32Array(3), 2, 2)).rejects.toThrow();
+	});
+
+	it('throws error instead of wrapping when the pixel count overflows', async () => {
+		// 65536 * 65536 wraps to 0 in 32-bit wasm, which an empty buffer would match.
+		await expect(computeMaxCllMaxFall(new Float32Array(0), 65536, 65536)).rejects.toThrow(
+			/too large/
+		);
+	});
+});
+
+describe('reconstructionError', () => {
+	it('is small when a file reconstructs the HDR it was encoded from', async () => {
+		const hdr = await hdrWithGain(REGULAR_JPEG_BASE64, () => 2);
+		const sdrBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		const encoded = await encodeUltraHdr('error', sdrBuffer, hdr.buffer, defaultEncodeOptions);
+
+		const error = await reconstructionError(encoded, hdr);
+		for (let c = 0; c < 3; c++) {
+			expect(error.rmse[c]).toBeLessThan(0.02);
+			expect(error.maxError[c]).toBeLessThan(0.1);
+			expect(error.psnr[c]).toBeGreaterThan(30);
+		}
+	});
+
+	it('reports zero error and infinite PSNR against its own reconstruction', async () => {
+		const encoded = await encodeFixture();
+		const error = await reconstructionError(encoded, (await applyGainMap(encoded, 1)).data, 1);
+		expect(error.rmse).toEqual([0, 0, 0]);
+		expect(error.maxError).toEqual([0, 0, 0]);
+		expect(error.psnr).toEqual([Infinity, Infinity, Infinity]);
+	});
+
+	it('throws error when the reference does not match the image size', async () => {
+		const encoded = await encodeFixture();
+		await expect(reconstructionError(encoded, new Float32Array(3))).rejects.toThrow(
+			/does not match/
+		);
+	});
+});
+
+describe('estimateGainMapGamma', () => {
+	it('recovers the gamma used to encode the samples', async () => {
+		const gamma = 2.2;
+		const min = -1;
+		const max = 3;
+		const count = 64;
+		const encoded = new Uint8Array(count);
+		const ratios = new Float32Array(count);
+		for (let i = 0; i < count; i++) {
+			const normalized = (i + 0.5) / count;
+			ratios[i] = Math.pow(2, min + (max - min) * normalized);
+			encoded[i] = Math.round(Math.pow(normalized, gamma) * 255);
+		}
+		const estimate = await estimateGainMapGamma(encoded, ratios, min, max);
+		expect(Math.abs(estimate - gamma)).toBeLessThan(0.05);
+	});
+
+	it('returns 1 when no sample lies inside the gain range', async () => {
+		const estimate = await estimateGainMapGamma(
+			new Uint8Array([0, 255]),
+			new Float32Array([0.5, 8]),
+			-1,
+			3
+		);
+		expect(estimate).toBe(1);
+	});
+
+	it('throws error when the sample counts differ', async () => {
+		await expect(
+			estimateGainMapGamma(new Uint8Array(2), new Float32Array(3), 0, 1)
+		).rejects.toThrow();
+	});
+});
+
+describe('sdrReconstructionSimilarity', () => {
+	// HDR computed per pixel from `sdrPixelAt`, encoded over the regular fixture.
+	async function encodeAgainst(sdrPixelAt: (x: number, y: number) => number, scale: number) {
+		const sdr = (await linearSdr(REGULAR_JPEG_BASE64)).data;
+		const offset = defaultEncodeOptions.blackPointOffset;
+		const hdr = new Float32Array(sdr.length);
+		for (let y = 0; y < REGULAR_JPEG_HEIGHT; y++) {
+			for (let x = 0; x < REGULAR_JPEG_WIDTH; x++) {
+				const at = sdrPixelAt(x, y) * 3;
+				for (let c = 0; c < 3; c++) {
+					hdr[(y * REGULAR_JPEG_WIDTH + x) * 3 + c] = scale * (sdr[at + c] + offset) - offset;
+				}
+			}
+		}
+		return encodeUltraHdr('similarity', base64ToArrayBuffer(REGULAR_JPEG_BASE64), hdr.buffer, {
+			...defaultEncodeOptions,
+			gainMapDownsample: GainMapDownsample.BoxAverage,
+		});
+	}
+
+	it('scores a gain map computed against its own base close to 1', async () => {
+		const encoded = await encodeAgainst((x, y) => y * REGULAR_JPEG_WIDTH + x, 2);
+		expect(await sdrReconstructionSimilarity(encoded)).toBeGreaterThan(0.9);
+	});
+
+	it('scores a gain map computed against another image low', async () => {
+		// The HDR is the base rotated by 180 degrees, eight times brighter.
+		const last = REGULAR_JPEG_WIDTH * REGULAR_JPEG_HEIGHT - 1;
+		const encoded = await encodeAgainst((x, y) => last - (y * REGULAR_JPEG_WIDTH + x), 8);
+		expect(await sdrReconstructionSimilarity(encoded)).toBeLessThan(0.5);
+	});
+
+	it('throws for a JPEG without a gain map', async () => {
+		const jpegBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		await expect(sdrReconstructionSimilarity(jpegBuffer)).rejects.toThrow();
+	});
+});
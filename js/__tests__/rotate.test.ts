@@ -0,0 +1,130 @@
+/**
+ * Rotation tests for rotateUltraHdr.
+ */
+import { describe, it, expect } from 'vitest';
+import {
+	rotateUltraHdr,
+	decodeUltraHdr,
+	isUltraHdr,
+	applyGainMap,
+	getJfifDensity,
+} from '../src/index';
+import {
+	base64ToArrayBuffer,
+	REGULAR_JPEG_BASE64,
+	WIDE_JPEG_BASE64,
+	WIDE_JPEG_WIDTH,
+	WIDE_JPEG_HEIGHT,
+	UNALIGNED_JPEG_BASE64,
+	UNALIGNED_JPEG_WIDTH,
+	UNALIGNED_JPEG_HEIGHT,
+	encodeFixture,
+	withIccChunks,
+	withJfifDensity,
+} from './fixtures/test-data';
+
+const ICC_SIGNATURE = 'ICC_PROFILE\0';
+
+/** A stand-in profile: an ICC header naming Display P3 in its description. */
+function createP3Profile(): Uint8Array {
+	const profile = new Uint8Array(160);
+	new DataView(profile.buffer).setUint32(0, profile.length);
+	profile.set(new TextEncoder().encode('mntrRGB XYZ '), 12);
+	profile.set(new TextEncoder().encode('acsp'), 36);
+	profile.set(new TextEncoder().encode('descDisplay P3'), 128);
+	return profile;
+}
+
+/** Concatenates the ICC chunks found before the primary's first SOS. */
+function readIccProfile(jpeg: ArrayBuffer): Uint8Array {
+	const bytes = new Uint8Array(jpeg);
+	const chunks: Uint8Array[] = [];
+	let offset = 2;
+	while (offset + 4 <= bytes.length && bytes[offset] === 0xff && bytes[offset + 1] !== 0xda) {
+		const length = (bytes[offset + 2] << 8) | bytes[offset + 3];
+		const payload = bytes.subarray(offset + 4, offset + 2 + length);
+		const id = new TextDecoder('latin1').decode(payload.subarray(0, ICC_SIGNATURE.length));
+		if (bytes[offset + 1] === 0xe2 && id === ICC_SIGNATURE) {
+			chunks.push(payload.subarray(ICC_SIGNATURE.length + 2));
+		}
+		offset += 2 + length;
+	}
+	const out = new Uint8Array(chunks.reduce((n, c) => n + c.length, 0));
+	let at = 0;
+	for (const chunk of chunks) {
+		out.set(chunk, at);
+		at += chunk.length;
+	}
+	return out;
+}
+
+describe('rotateUltraHdr', () => {
+	for (const degrees of [90, 180, 270] as const) {
+		it(`rotates both layers by ${degrees} degrees`, async () => {
+			const encoded = await encodeFixture({}, WIDE_JPEG_BASE64);
+			const source = await decodeUltraHdr('rotate-src', encoded);
+
+			const rotated = await rotateUltraHdr(encoded, degrees);
+			expect(await isUltraHdr(rotated)).toBe(true);
+			const result = await decodeUltraHdr('rotate-out', rotated);
+
+			const swap = degrees !== 180;
+			expect(result.width).toBe(swap ? WIDE_JPEG_HEIGHT : WIDE_JPEG_WIDTH);
+			expect(result.height).toBe(swap ? WIDE_JPEG_WIDTH : WIDE_JPEG_HEIGHT);
+			expect(result.gainMapWidth).toBe(swap ? source.gainMapHeight : source.gainMapWidth);
+			expect(result.gainMapHeight).toBe(swap ? source.gainMapWidth : source.gainMapHeight);
+			expect(result.metadata.gainMapMax).toEqual(source.metadata.gainMapMax);
+		});
+	}
+
+	it('is lossless for MCU-aligned images', async () => {
+		const encoded = await encodeFixture({}, WIDE_JPEG_BASE64);
+		const roundTrip = await rotateUltraHdr(await rotateUltraHdr(encoded, 180), 180);
+
+		const before = await applyGainMap(encoded);
+		const after = await applyGainMap(roundTrip);
+		expect(Array.from(after.data)).toEqual(Array.from(before.data));
+	});
+
+	it('falls back to re-encoding when the image is not MCU aligned', async () => {
+		const encoded = await encodeFixture({}, UNALIGNED_JPEG_BASE64);
+		const result = await decodeUltraHdr('rotate-unaligned', await rotateUltraHdr(encoded, 90));
+
+		expect(result.width).toBe(UNALIGNED_JPEG_HEIGHT);
+		expect(result.height).toBe(UNALIGNED_JPEG_WIDTH);
+	});
+
+	it('keeps the JFIF density when re-encoding, swapped for a quarter turn', async () => {
+		const encoded = await encodeFixture({}, UNALIGNED_JPEG_BASE64);
+		const source = withJfifDensity(encoded, 1, 300, 150);
+
+		expect(await getJfifDensity(await rotateUltraHdr(source, 180))).toEqual({
+			units: 1,
+			xDensity: 300,
+			yDensity: 150,
+		});
+		expect(await getJfifDensity(await rotateUltraHdr(source, 90))).toEqual({
+			units: 1,
+			xDensity: 150,
+			yDensity: 300,
+		});
+	});
+
+	it('keeps the ICC profile of a P3 base when re-encoding', async () => {
+		const encoded = await encodeFixture({}, UNALIGNED_JPEG_BASE64);
+		const profile = createP3Profile();
+		const rotated = await rotateUltraHdr(withIccChunks(encoded, profile), 90);
+
+		expect(Array.from(readIccProfile(rotated))).toEqual(Array.from(profile));
+	});
+
+	it('throws error for an unsupported angle', async () => {
+		const encoded = await encodeFixture({}, WIDE_JPEG_BASE64);
+		await expect(rotateUltraHdr(encoded, 45 as 90)).rejects.toThrow();
+	});
+
+	it('throws error for regular JPEG without gain map', async () => {
+		const jpegBuffer = base64ToArrayBuffer(REGULAR_JPEG_BASE64);
+		await expect(rotateUltraHdr(jpegBuffer, 90)).rejects.toThrow();
+	});
+});
@@ -0,0 +1,65 @@
+/**
+ * Transfer function tests (sRGB, PQ, HLG).
+ */
+import { describe, it, expect } from 'vitest';
+import {
+	srgbToLinear,
+	linearToSrgb,
+	pqToNits,
+	nitsToPq,
+	hlgToLinear,
+	linearToHlg,
+	pqToNitsInPlace,
+	nitsToPqInPlace,
+	srgbToLinearInPlace,
+} from '../src/index';
+
+describe('PQ', () => {
+	it('round-trips 1000 nits', async () => {
+		const nits = await pqToNits(await nitsToPq(1000));
+		expect(nits).toBeCloseTo(1000, 0);
+	});
+
+	it('maps code value endpoints to 0 and 10000 nits', async () => {
+		expect(await pqToNits(0)).toBeCloseTo(0, 5);
+		expect(await pqToNits(1)).toBeCloseTo(10000, 0);
+	});
+
+	it('batch conversion matches the scalar conversion', async () => {
+		const values = new Float32Array([0, 100, 203, 1000, 4000]);
+		await nitsToPqInPlace(values);
+		expect(values[3]).toBeCloseTo(await nitsToPq(1000), 5);
+		await pqToNitsInPlace(values);
+		expect(values[1]).toBeCloseTo(100, 1);
+		expect(values[4]).toBeCloseTo(4000, 0);
+	});
+});
+
+describe('HLG', () => {
+	it('round-trips across both curve segments', async () => {
+		for (const linear of [0.01, 1 / 12, 0.5, 1.0]) {
+			const back = await hlgToLinear(await linearToHlg(linear));
+			expect(back).toBeCloseTo(linear, 5);
+		}
+	});
+
+	it('maps reference points to their specified signal values', async () => {
+		expect(await linearToHlg(1 / 12)).toBeCloseTo(0.5, 5);
+		expect(await linearToHlg(1)).toBeCloseTo(1, 4);
+	});
+});
+
+describe('sRGB', () => {
+	it('round-trips mid grey', async () => {
+		const back = await linearToSrgb(await srgbToLinear(0.5));
+		expect(back).toBeCloseTo(0.5, 5);
+	});
+
+	it('converts a buffer in place', async () => {
+		const values = new Float32Array([0, 0.5, 1]);
+		await srgbToLinearInPlace(values);
+		expect(values[0]).toBeCloseTo(0, 6);
+		expect(values[1]).toBeCloseTo(0.214, 3);
+		expect(values[2]).toBeCloseTo(1, 6);
+	});
+});
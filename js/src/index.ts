@@ -23,29 +23,84 @@
 
 export type {
 	ItemId,
+	AuxiliaryImage,
+	ByteRange,
+	Capabilities,
+	CompatItem,
+	CompatReport,
+	ContentLightLevel,
 	GainMapMetadata,
+	GainMapValues,
+	HdrLuminanceImage,
+	ImageRegion,
+	JfifDensity,
+	QuantizationTable,
+	ReconstructedHdrImage,
+	ReconstructionErrorMetrics,
+	UltraHdrComponentLayout,
+	UltraHdrComponents,
+	UltraHdrDecodeHandle,
 	UltraHdrDecodeResult,
 	UltraHdrEncodeOptions,
+	UltraHdrEncodeResult,
+	UltraHdrEncodeStats,
+	UltraHdrIndexRecord,
+	UltraHdrPixels,
 	UltraHdrProbeResult,
 } from './types';
 
 export {
 	ColorGamut,
+	CompatProfile,
+	GainMapDownsample,
+	GainMapEdgeMode,
+	GainMapXmpMode,
+	OddDimensionPolicy,
 	TransferFunction,
+	UltraHdrLayout,
+	XmpFloatArrayStyle,
 	defaultEncodeOptions,
 	highQualityEncodeOptions,
 	smallSizeEncodeOptions,
+	isoDefaultMetadata,
 } from './types';
 
 import type {
 	ItemId,
+	AuxiliaryImage,
+	ByteRange,
+	Capabilities,
+	CompatItem,
+	CompatReport,
+	CompatProfile,
+	ContentLightLevel,
 	GainMapMetadata,
+	GainMapValues,
+	HdrLuminanceImage,
+	ImageRegion,
+	JfifDensity,
+	QuantizationTable,
+	ReconstructedHdrImage,
+	ReconstructionErrorMetrics,
+	UltraHdrComponentLayout,
+	UltraHdrComponents,
+	UltraHdrDecodeHandle,
 	UltraHdrDecodeResult,
 	UltraHdrEncodeOptions,
+	UltraHdrEncodeResult,
+	UltraHdrEncodeStats,
+	UltraHdrIndexRecord,
+	UltraHdrPixels,
 	UltraHdrProbeResult,
 } from './types';
 
-import { defaultEncodeOptions } from './types';
+import {
+	defaultEncodeOptions,
+	GainMapEdgeMode,
+	GainMapXmpMode,
+	UltraHdrLayout,
+	XmpFloatArrayStyle,
+} from './types';
 
 import type { OpenUltraHdrModule } from 'open-ultrahdr-wasm';
 
@@ -177,12 +232,37 @@ export async function probeUltraHdr(buffer: ArrayBuffer): Promise<UltraHdrProbeR
 			gainMapHeight: 0,
 			hdrCapacity: 0,
 			metadataVersion: '',
+			primaryIsProgressive: false,
+			warnings: [],
+			layout: UltraHdrLayout.Unknown,
 		};
 	}
 }
 
+/**
+ * Summarizes an UltraHDR file as scalars only, for a media index: dimensions,
+ * gain map dimensions, the HDR capacity range, metadata version, layout and
+ * `hdrStrengthScore`. Unlike `probeUltraHdr` it reads the full metadata, and
+ * it decodes the gain map to score it.
+ *
+ * @throws If the file is not UltraHDR
+ */
+export async function indexUltraHdr(buffer: ArrayBuffer): Promise<UltraHdrIndexRecord> {
+	const wasm = await getWasm();
+	return wasm.indexUltraHdr(new Uint8Array(buffer));
+}
+
 /**
  * Decodes an UltraHDR image, extracting all components.
+ *
+ * `sdrImage` and `gainMap` are assigned by role: files that store the gain
+ * map first and flag the SDR as the MPF primary (or list the gain map first
+ * in their container XMP) are read correctly and noted in `warnings`.
+ *
+ * A file cut off before the primary's EOI is rejected as truncated. One cut
+ * off after the primary, within or before the gain map, still yields the SDR
+ * image, with an empty `gainMap`, neutral metadata and a `gain map missing`
+ * warning.
  */
 export async function decodeUltraHdr(
 	_id: ItemId,
@@ -192,8 +272,79 @@ export async function decodeUltraHdr(
 	return wasm.decodeUltraHdr(new Uint8Array(buffer));
 }
 
+/**
+ * Decodes an UltraHDR image without copying its JPEG layers out of WASM
+ * memory, for large images where decodeUltraHdr's copies matter.
+ *
+ * The views returned by the handle are invalidated by `delete()` and by any
+ * later call into the module, so read them straight away or use `copyOut()`.
+ * The handle must be freed with `delete()`.
+ */
+export async function decodeUltraHdrHandle(buffer: ArrayBuffer): Promise<UltraHdrDecodeHandle> {
+	const wasm = await getWasm();
+	return wasm.decodeUltraHdrHandle(new Uint8Array(buffer));
+}
+
+/**
+ * Decodes an UltraHDR image, rejecting any file that is not spec-conformant.
+ *
+ * Where decodeUltraHdr recovers leniently, this throws instead: a missing or
+ * inconsistent MPF index, missing required metadata fields, a metadata
+ * version other than "1.0", out-of-range gamma or offsets, or a gain map that
+ * is not a 1x, 2x or 4x downscale of the primary.
+ */
+export async function decodeUltraHdrStrict(
+	_id: ItemId,
+	buffer: ArrayBuffer
+): Promise<UltraHdrDecodeResult> {
+	const wasm = await getWasm();
+	return wasm.decodeUltraHdrStrict(new Uint8Array(buffer));
+}
+
+/**
+ * Whether a decode result's `gainMapWidth` and `gainMapHeight` agree with the
+ * SOF of its `gainMap` JPEG, so inconsistencies the decode only noted in
+ * `warnings` can be checked in one place. False when the gain map has no
+ * readable SOF, as for the empty gain map of a truncated file.
+ */
+export async function gainMapDimensionsMatch(
+	result: Pick<UltraHdrDecodeResult, 'gainMap' | 'gainMapWidth' | 'gainMapHeight'>
+): Promise<boolean> {
+	const wasm = await getWasm();
+	return wasm.gainMapDimensionsMatch(result.gainMap, result.gainMapWidth, result.gainMapHeight);
+}
+
+/**
+ * Checks an UltraHDR file against the structural rules one ecosystem's
+ * readers depend on, without decoding it.
+ *
+ * Each rule the profile checks yields a `pass`, `warn` or `fail` item; rules
+ * a profile only recommends never fail. For example, Android requires hdrgm
+ * v1 XMP, MPF, even dimensions and a single-channel gain map, while
+ * ISO-strict readers ignore XMP and require ISO 21496-1 metadata.
+ *
+ * @param buffer - UltraHDR JPEG file contents
+ * @param profile - Ecosystem to check against
+ */
+export async function checkCompatibility(
+	buffer: ArrayBuffer,
+	profile: CompatProfile
+): Promise<CompatReport> {
+	const wasm = await getWasm();
+	return wasm.checkCompatibility(new Uint8Array(buffer), profile) as CompatReport;
+}
+
 /**
  * Encodes an UltraHDR JPEG from SDR and HDR inputs.
+ *
+ * The SDR base must be an 8-bit JPEG. HDR content from 12- or 16-bit sources
+ * should be converted to linear floats (SDR white = 1.0) and passed as
+ * `hdrBuffer`, which is used at full float precision.
+ *
+ * `hdrBuffer` must follow the SDR's stored pixel layout, not its display
+ * orientation: an SDR tagged with Exif orientation 5-8 needs HDR rows in the
+ * rotated order the JPEG stores them. The output keeps the SDR's Exif, so
+ * viewers turn both layers together.
  */
 export async function encodeUltraHdr(
 	_id: ItemId,
@@ -214,6 +365,211 @@ export async function encodeUltraHdr(
 	) as ArrayBuffer;
 }
 
+/**
+ * Encode an UltraHDR image and report how much of the gain map clipped.
+ *
+ * A large clipped fraction means the gain range (targetHdrCapacity) was too
+ * narrow or too wide for the content.
+ *
+ * @param _id - Item identifier (for tracking)
+ * @param sdrBuffer - SDR JPEG image as ArrayBuffer
+ * @param hdrBuffer - Linear HDR data as Float32Array buffer
+ * @param options - Encoding options (uses defaults if not specified)
+ * @returns Encoded UltraHDR JPEG and its gain map clipping statistics
+ */
+export async function encodeUltraHdrWithStats(
+	_id: ItemId,
+	sdrBuffer: ArrayBuffer,
+	hdrBuffer: ArrayBuffer,
+	options?: Partial<UltraHdrEncodeOptions>
+): Promise<UltraHdrEncodeResult> {
+	const wasm = await getWasm();
+	const merged: UltraHdrEncodeOptions = { ...defaultEncodeOptions, ...options };
+	const result = wasm.encodeUltraHdrWithStats(
+		new Uint8Array(sdrBuffer),
+		new Float32Array(hdrBuffer),
+		merged
+	);
+	const data = result.data.buffer.slice(
+		result.data.byteOffset,
+		result.data.byteOffset + result.data.byteLength
+	) as ArrayBuffer;
+	return {
+		data,
+		stats: result.stats,
+		gainMapEmbedded: result.gainMapEmbedded,
+		width: result.width,
+		height: result.height,
+	};
+}
+
+/**
+ * Encodes an UltraHDR JPEG from HDR supplied in horizontal strips, for images
+ * too large to hold as one float buffer.
+ *
+ * Each strip is folded into the gain map as it arrives, so neither the whole
+ * float HDR image nor libultrahdr's half-float copy of it is ever held. The
+ * gain map is always computed by this library, as encodeUltraHdr does for a
+ * custom `blackPointOffset` or `gainMapDownsample` (BoxAverage when none is
+ * set): the output is byte-identical to encodeUltraHdr with such options, and
+ * `hdrGamut` must be Srgb. The SDR is used as given, so `flattenExisting`,
+ * `forceBaselineBase`, `oddDimensionPolicy`, `gainMapBlurSigma` and
+ * `omitTrivialGainMap` do not apply; nor does `metadataMaxPercentile`, which
+ * would need the HDR read twice.
+ *
+ * @param _id - Item identifier (for tracking)
+ * @param sdrBuffer - SDR JPEG image as ArrayBuffer
+ * @param readHdrRows - Returns `rows` rows of linear RGB HDR starting at row
+ *   `y` (rows * width * 3 floats); called top to bottom
+ * @param stripRows - Rows requested per call
+ * @param options - Encoding options (uses defaults if not specified)
+ */
+export async function encodeUltraHdrStrips(
+	_id: ItemId,
+	sdrBuffer: ArrayBuffer,
+	readHdrRows: (y: number, rows: number) => Float32Array,
+	stripRows = 64,
+	options?: Partial<UltraHdrEncodeOptions>
+): Promise<ArrayBuffer> {
+	const wasm = await getWasm();
+	const merged: UltraHdrEncodeOptions = { ...defaultEncodeOptions, ...options };
+	const result = wasm.encodeUltraHdrStrips(
+		new Uint8Array(sdrBuffer),
+		readHdrRows,
+		stripRows,
+		merged
+	);
+	return result.buffer.slice(
+		result.byteOffset,
+		result.byteOffset + result.byteLength
+	) as ArrayBuffer;
+}
+
+/**
+ * Encodes an UltraHDR JPEG no larger than `maxBytes`, for size-limited uploads.
+ *
+ * The gain map quality is searched downwards from `gainMapQuality` and the
+ * highest quality found to fit is used; the search takes a handful of encodes,
+ * so it may settle a few steps below the exact best. The SDR JPEG and all
+ * other options are kept as given.
+ *
+ * @param _id - Item identifier (for tracking)
+ * @param sdrBuffer - SDR JPEG image as ArrayBuffer
+ * @param hdrBuffer - Linear HDR data as Float32Array buffer
+ * @param maxBytes - Largest acceptable output size in bytes
+ * @param options - Encoding options (uses defaults if not specified)
+ * @throws If the output exceeds `maxBytes` even at gain map quality 1
+ */
+export async function encodeUltraHdrToTargetSize(
+	_id: ItemId,
+	sdrBuffer: ArrayBuffer,
+	hdrBuffer: ArrayBuffer,
+	maxBytes: number,
+	options?: Partial<UltraHdrEncodeOptions>
+): Promise<ArrayBuffer> {
+	const wasm = await getWasm();
+	const merged: UltraHdrEncodeOptions = { ...defaultEncodeOptions, ...options };
+	const result = wasm.encodeUltraHdrToTargetSize(
+		new Uint8Array(sdrBuffer),
+		new Float32Array(hdrBuffer),
+		maxBytes,
+		merged
+	);
+	return result.buffer.slice(
+		result.byteOffset,
+		result.byteOffset + result.byteLength
+	) as ArrayBuffer;
+}
+
+/**
+ * Pairs an externally graded SDR JPEG with a gain map computed afresh
+ * against the original HDR, e.g. after an editor re-grades the SDR by hand.
+ *
+ * The SDR is authoritative and need not be a tone map of the HDR: the gain
+ * map records whatever per-pixel ratio takes it to the HDR target, so
+ * contrast or color grades reconstruct the same HDR. Where the SDR is
+ * brighter than the target the gain stops at 1 and the SDR value is kept.
+ *
+ * @param sdrBuffer - Graded SDR JPEG; it is embedded as it is
+ * @param hdrBuffer - Linear HDR target as a Float32Array buffer (RGB)
+ * @param width - Width of the HDR target, which must match the SDR's stored
+ *   pixels (before any Exif orientation is applied)
+ * @param height - Height of the HDR target, which must match the SDR's stored pixels
+ * @param options - Encoding options (uses defaults if not specified)
+ */
+export async function recomputeGainMapForSdr(
+	sdrBuffer: ArrayBuffer,
+	hdrBuffer: ArrayBuffer,
+	width: number,
+	height: number,
+	options?: Partial<UltraHdrEncodeOptions>
+): Promise<ArrayBuffer> {
+	const wasm = await getWasm();
+	const merged: UltraHdrEncodeOptions = { ...defaultEncodeOptions, ...options };
+	const result = wasm.recomputeGainMapForSdr(
+		new Uint8Array(sdrBuffer),
+		new Float32Array(hdrBuffer),
+		width,
+		height,
+		merged
+	);
+	return result.buffer.slice(
+		result.byteOffset,
+		result.byteOffset + result.byteLength
+	) as ArrayBuffer;
+}
+
+/**
+ * Reads the pixel density from the first JFIF (APP0) header of a JPEG, for
+ * DPI and physical-size handling. Returns null when there is no JFIF header.
+ *
+ * Re-encodes made by this library (odd dimension crops and pads, rotation)
+ * keep the source density.
+ */
+export async function getJfifDensity(buffer: ArrayBuffer): Promise<JfifDensity | null> {
+	const wasm = await getWasm();
+	return wasm.getJfifDensity(new Uint8Array(buffer)) as JfifDensity | null;
+}
+
+/**
+ * Reads the quantization tables of a JPEG, in file order, reading 16-bit
+ * precision tables as well as 8-bit ones. For an UltraHDR file these are the
+ * primary's; pass the output of `extractGainMapStandalone` for the gain map's.
+ */
+export async function getQuantizationTables(buffer: ArrayBuffer): Promise<QuantizationTable[]> {
+	const wasm = await getWasm();
+	return wasm.getQuantizationTables(new Uint8Array(buffer));
+}
+
+/**
+ * Lists the items of the primary's GContainer directory in order, with their
+ * byte ranges: the primary, the gain map and any others, such as the
+ * `Alternate` (fully rendered HDR) image of ISO 21496-1, which can be shown
+ * directly instead of reconstructing the HDR. Empty when the file has no
+ * directory; items after one without an `Item:Length` are not listed.
+ */
+export async function listAuxiliaryImages(buffer: ArrayBuffer): Promise<AuxiliaryImage[]> {
+	const wasm = await getWasm();
+	return wasm.listAuxiliaryImages(new Uint8Array(buffer)) as AuxiliaryImage[];
+}
+
+/**
+ * Copies out the first GContainer item with the given `Item:Semantic`.
+ *
+ * @throws If listAuxiliaryImages finds no such item
+ */
+export async function extractAuxiliaryImage(
+	buffer: ArrayBuffer,
+	semantic: string
+): Promise<ArrayBuffer> {
+	const wasm = await getWasm();
+	const result = wasm.extractAuxiliaryImage(new Uint8Array(buffer), semantic);
+	return result.buffer.slice(
+		result.byteOffset,
+		result.byteOffset + result.byteLength
+	) as ArrayBuffer;
+}
+
 /**
  * Extracts the SDR base image from an UltraHDR JPEG.
  */
@@ -226,6 +582,166 @@ export async function extractSdrBase(buffer: ArrayBuffer): Promise<ArrayBuffer>
 	) as ArrayBuffer;
 }
 
+/**
+ * Extracts the gain map of an UltraHDR JPEG as a standalone JPEG.
+ *
+ * The gain map carries the parent's gain map metadata in its own hdrgm XMP
+ * packet, so it opens as a regular JPEG and still describes how to
+ * reconstruct the HDR rendition.
+ *
+ * @param style - How the packet writes per-channel values; see `gainMapXmp`
+ */
+export async function extractGainMapStandalone(
+	buffer: ArrayBuffer,
+	style: XmpFloatArrayStyle = XmpFloatArrayStyle.CommaSeparated
+): Promise<ArrayBuffer> {
+	const wasm = await getWasm();
+	const result = wasm.extractGainMapStandalone(new Uint8Array(buffer), style);
+	return result.buffer.slice(
+		result.byteOffset,
+		result.byteOffset + result.byteLength
+	) as ArrayBuffer;
+}
+
+/**
+ * Splits an UltraHDR JPEG into its primary JPEG, gain map JPEG and metadata
+ * JSON, for storing the parts separately. `encodeFromComponents` with the
+ * parsed metadata joins them again; neither JPEG is re-encoded either way.
+ */
+export async function splitUltraHdr(buffer: ArrayBuffer): Promise<UltraHdrComponents> {
+	const wasm = await getWasm();
+	return wasm.splitUltraHdr(new Uint8Array(buffer));
+}
+
+/**
+ * Joins a primary JPEG and a gain map JPEG into an UltraHDR file described by
+ * `metadata`, e.g. the output of `splitUltraHdr` read back with
+ * `metadataFromJson`. The codestreams are copied, not re-encoded.
+ *
+ * @param gainMapXmp - What to do with hdrgm XMP the gain map JPEG already
+ *   carries, which may be stale. Defaults to keeping the gain map untouched.
+ * @throws If the metadata is invalid, has an HDR base rendition or an
+ * `applyColorSpace`, none of which can be written
+ */
+export async function encodeFromComponents(
+	primaryJpeg: ArrayBuffer,
+	gainMapJpeg: ArrayBuffer,
+	metadata: GainMapMetadata,
+	gainMapXmp: GainMapXmpMode = GainMapXmpMode.Keep
+): Promise<ArrayBuffer> {
+	const wasm = await getWasm();
+	const result = wasm.encodeFromComponents(
+		new Uint8Array(primaryJpeg),
+		new Uint8Array(gainMapJpeg),
+		metadata,
+		gainMapXmp
+	);
+	return result.buffer.slice(
+		result.byteOffset,
+		result.byteOffset + result.byteLength
+	) as ArrayBuffer;
+}
+
+/**
+ * Locates the primary JPEG, gain map JPEG, gain map XMP packet and MPF data
+ * within an UltraHDR file, for slicing the original buffer without copies.
+ *
+ * The gain map is found from the XMP GContainer directory (`Item:Length` and
+ * `Item:Padding` of the items before it) when present, then from the MPF
+ * index, then as the codestream following the primary.
+ *
+ * @param buffer - UltraHDR JPEG file contents
+ * @returns Byte ranges of each component; `xmp` and `mpf` are null when absent
+ */
+export async function locateComponents(buffer: ArrayBuffer): Promise<UltraHdrComponentLayout> {
+	const wasm = await getWasm();
+	return wasm.locateComponents(new Uint8Array(buffer));
+}
+
+/**
+ * Rewrites the MPF index of an UltraHDR JPEG so it points at the gain map.
+ *
+ * Editors that rewrite the primary image without updating MPF leave stale
+ * offsets behind. The gain map is located as the codestream following the
+ * primary's EOI and the primary and gain map MP entries are corrected; all
+ * other bytes are unchanged.
+ *
+ * @param buffer - UltraHDR JPEG bytes with an MPF index.
+ * @returns The corrected file.
+ */
+export async function repairUltraHdr(buffer: ArrayBuffer): Promise<ArrayBuffer> {
+	const wasm = await getWasm();
+	const result = wasm.repairUltraHdr(new Uint8Array(buffer));
+	return result.buffer.slice(
+		result.byteOffset,
+		result.byteOffset + result.byteLength
+	) as ArrayBuffer;
+}
+
+/**
+ * Re-encodes only the gain map of an UltraHDR JPEG at a new quality.
+ *
+ * The gain map keeps its dimensions and metadata and the primary codestream
+ * is carried over as it is, so a file can be shrunk without degrading the SDR
+ * base.
+ *
+ * @param buffer - UltraHDR JPEG bytes.
+ * @param quality - JPEG quality for the gain map (1-100).
+ */
+export async function recompressGainMap(
+	buffer: ArrayBuffer,
+	quality: number
+): Promise<ArrayBuffer> {
+	const wasm = await getWasm();
+	const result = wasm.recompressGainMap(new Uint8Array(buffer), quality);
+	return result.buffer.slice(
+		result.byteOffset,
+		result.byteOffset + result.byteLength
+	) as ArrayBuffer;
+}
+
+/**
+ * Rotates an UltraHDR JPEG clockwise, keeping its gain map aligned.
+ *
+ * When both layers are whole MCUs in size their DCT blocks are rearranged
+ * without re-encoding; otherwise both layers are decoded, rotated and
+ * re-encoded at the default qualities. Metadata, ICC and Exif are preserved.
+ *
+ * @param buffer - UltraHDR JPEG bytes.
+ * @param degrees - 90, 180 or 270.
+ */
+export async function rotateUltraHdr(
+	buffer: ArrayBuffer,
+	degrees: 90 | 180 | 270
+): Promise<ArrayBuffer> {
+	const wasm = await getWasm();
+	const result = wasm.rotateUltraHdr(new Uint8Array(buffer), degrees);
+	return result.buffer.slice(
+		result.byteOffset,
+		result.byteOffset + result.byteLength
+	) as ArrayBuffer;
+}
+
+/**
+ * Reports the optional features compiled into the WASM module, so apps can
+ * hide or replace what the loaded build lacks. Every key is always present.
+ */
+export async function capabilities(): Promise<Capabilities> {
+	const wasm = await getWasm();
+	return wasm.capabilities();
+}
+
+/**
+ * Reports which JPEG encoder the WASM module was built with.
+ *
+ * `'mozjpeg'` builds (CMake option `OPEN_ULTRAHDR_MOZJPEG`) write smaller
+ * gain maps and re-encoded layers at the same quality setting.
+ */
+export async function jpegEncoderBackend(): Promise<'libjpeg' | 'mozjpeg'> {
+	const wasm = await getWasm();
+	return wasm.jpegEncoderBackend();
+}
+
 /**
  * Gets gain map metadata from an UltraHDR JPEG.
  */
@@ -234,6 +750,309 @@ export async function getMetadata(buffer: ArrayBuffer): Promise<GainMapMetadata>
 	return wasm.getMetadata(new Uint8Array(buffer));
 }
 
+/**
+ * Decodes the SDR base and gain map of an UltraHDR JPEG to raw pixels.
+ *
+ * With `applyOrientation`, the primary's Exif orientation is applied to both
+ * layers alike, so the pixels are ready to draw and the gain map stays
+ * spatially aligned with the SDR. The returned dimensions are the oriented
+ * ones.
+ *
+ * @param buffer - UltraHDR JPEG bytes.
+ * @param applyOrientation - Rotate/flip into display orientation. Defaults to false.
+ */
+export async function decodeToPixels(
+	buffer: ArrayBuffer,
+	applyOrientation: boolean = false
+): Promise<UltraHdrPixels> {
+	const wasm = await getWasm();
+	return wasm.decodeToPixels(new Uint8Array(buffer), applyOrientation);
+}
+
+/**
+ * Reconstructs the linear HDR image from an UltraHDR JPEG.
+ *
+ * @param buffer - UltraHDR JPEG bytes.
+ * @param displayHdrCapacity - Display headroom in log2 stops. Defaults to
+ *   full HDR (the gain map is applied at its maximum weight).
+ * @param edgeMode - Gain map border extension. Defaults to clamping.
+ * @param maxLinear - Ceiling for the reconstructed values, e.g. the peak the
+ *   metadata implies, so later PQ or 16-bit conversion cannot overflow.
+ *   Defaults to unbounded (values are only floored at 0).
+ */
+export async function applyGainMap(
+	buffer: ArrayBuffer,
+	displayHdrCapacity: number = Number.POSITIVE_INFINITY,
+	edgeMode: GainMapEdgeMode = GainMapEdgeMode.Clamp,
+	maxLinear: number = Number.POSITIVE_INFINITY
+): Promise<ReconstructedHdrImage> {
+	const wasm = await getWasm();
+	return wasm.applyGainMap(new Uint8Array(buffer), displayHdrCapacity, edgeMode, maxLinear);
+}
+
+/**
+ * Reconstructs the linear HDR image at several display headrooms at once,
+ * e.g. for a UI comparing simulated display brightnesses side by side.
+ *
+ * Faster than calling applyGainMap once per headroom: the file is decoded and
+ * each SDR sample and gain is decoded once, and only the weighted blend is
+ * repeated. Each result equals applyGainMap at the same headroom.
+ *
+ * @param buffer - UltraHDR JPEG bytes.
+ * @param displayHdrCapacities - Display headrooms in log2 stops.
+ * @param edgeMode - Gain map border extension. Defaults to clamping.
+ * @param maxLinear - Ceiling for the reconstructed values. Defaults to unbounded.
+ * @returns One reconstruction per headroom, in the same order.
+ */
+export async function applyGainMapMulti(
+	buffer: ArrayBuffer,
+	displayHdrCapacities: number[],
+	edgeMode: GainMapEdgeMode = GainMapEdgeMode.Clamp,
+	maxLinear: number = Number.POSITIVE_INFINITY
+): Promise<ReconstructedHdrImage[]> {
+	const wasm = await getWasm();
+	return wasm.applyGainMapMulti(new Uint8Array(buffer), displayHdrCapacities, edgeMode, maxLinear);
+}
+
+/**
+ * Reconstructs only one region of the linear HDR image.
+ *
+ * For tiled/deep-zoom viewers that render the visible part of a large image.
+ * The region is given in full-image coordinates and the gain map is sampled
+ * at those global positions, so the result matches the same slice of
+ * applyGainMap exactly and adjacent tiles join without seams.
+ *
+ * @param buffer - UltraHDR JPEG bytes.
+ * @param region - Pixels to reconstruct; must lie within the image.
+ * @param displayHdrCapacity - Display headroom in log2 stops. Defaults to full HDR.
+ * @param edgeMode - Gain map border extension. Defaults to clamping.
+ * @param maxLinear - Ceiling for the reconstructed values. Defaults to unbounded.
+ */
+export async function applyGainMapRegion(
+	buffer: ArrayBuffer,
+	region: ImageRegion,
+	displayHdrCapacity: number = Number.POSITIVE_INFINITY,
+	edgeMode: GainMapEdgeMode = GainMapEdgeMode.Clamp,
+	maxLinear: number = Number.POSITIVE_INFINITY
+): Promise<ReconstructedHdrImage> {
+	const wasm = await getWasm();
+	return wasm.applyGainMapRegion(
+		new Uint8Array(buffer),
+		region.x,
+		region.y,
+		region.width,
+		region.height,
+		displayHdrCapacity,
+		edgeMode,
+		maxLinear
+	);
+}
+
+/**
+ * Reconstructs the linear HDR image one row at a time.
+ *
+ * Avoids holding the full `width * height * 3` float buffer, so very large
+ * images can be streamed to disk or a canvas tile. Each row passed to `onRow`
+ * is a fresh `Float32Array` of `width * 3` values.
+ *
+ * @param buffer - UltraHDR JPEG bytes.
+ * @param onRow - Called once per row, top to bottom.
+ * @param displayHdrCapacity - Display headroom in log2 stops. Defaults to full HDR.
+ * @param edgeMode - Gain map border extension. Defaults to clamping.
+ * @param maxLinear - Ceiling for the reconstructed values. Defaults to unbounded.
+ */
+export async function applyGainMapRows(
+	buffer: ArrayBuffer,
+	onRow: (y: number, row: Float32Array) => void,
+	displayHdrCapacity: number = Number.POSITIVE_INFINITY,
+	edgeMode: GainMapEdgeMode = GainMapEdgeMode.Clamp,
+	maxLinear: number = Number.POSITIVE_INFINITY
+): Promise<void> {
+	const wasm = await getWasm();
+	wasm.applyGainMapRows(new Uint8Array(buffer), displayHdrCapacity, edgeMode, maxLinear, onRow);
+}
+
+/**
+ * Renders a small tone-mapped preview of an UltraHDR JPEG.
+ *
+ * Much cheaper than applyGainMap for gallery thumbnails: the gain map is
+ * applied with nearest sampling at thumbnail resolution only, and highlights
+ * are tone mapped into the SDR range of a plain sRGB JPEG. The SDR base is
+ * decoded at 1/2, 1/4 or 1/8 size when that still covers `maxDim`.
+ *
+ * @param buffer - UltraHDR JPEG bytes.
+ * @param maxDim - Length of the longer side in pixels; smaller images are not upscaled.
+ * @param displayHdrCapacity - Headroom to render for, in log2 stops. Defaults to full HDR.
+ * @returns Thumbnail JPEG bytes.
+ */
+export async function decodeUltraHdrThumbnail(
+	buffer: ArrayBuffer,
+	maxDim: number,
+	displayHdrCapacity: number = Number.POSITIVE_INFINITY
+): Promise<ArrayBuffer> {
+	const wasm = await getWasm();
+	const result = wasm.decodeUltraHdrThumbnail(new Uint8Array(buffer), maxDim, displayHdrCapacity);
+	return result.buffer.slice(
+		result.byteOffset,
+		result.byteOffset + result.byteLength
+	) as ArrayBuffer;
+}
+
+/**
+ * Reconstructs HDR and returns its absolute luminance in nits.
+ *
+ * @param buffer - UltraHDR JPEG as ArrayBuffer
+ * @param sdrWhiteNits - Luminance of SDR diffuse white (203 nits per BT.2408)
+ * @param displayHdrCapacity - Display headroom in log2 stops (full HDR by default)
+ * @param edgeMode - How gain map samples are extended past its edges
+ * @param lumaCoefficients - Luminance weights `[r, g, b]` (BT.709's by default)
+ * @returns Luminance per pixel, in nits
+ */
+export async function reconstructNits(
+	buffer: ArrayBuffer,
+	sdrWhiteNits: number = 203,
+	displayHdrCapacity: number = Number.POSITIVE_INFINITY,
+	edgeMode: GainMapEdgeMode = GainMapEdgeMode.Clamp,
+	lumaCoefficients?: [number, number, number]
+): Promise<HdrLuminanceImage> {
+	const wasm = await getWasm();
+	return wasm.reconstructNits(
+		new Uint8Array(buffer),
+		sdrWhiteNits,
+		displayHdrCapacity,
+		edgeMode,
+		lumaCoefficients ?? null
+	);
+}
+
+/**
+ * Computes HDR10 MaxCLL/MaxFALL from per-pixel luminance in nits, e.g. the
+ * output of reconstructNits.
+ */
+export async function computeMaxCllMaxFall(
+	nits: Float32Array,
+	width: number,
+	height: number
+): Promise<ContentLightLevel> {
+	const wasm = await getWasm();
+	return wasm.computeMaxCllMaxFall(nits, width, height);
+}
+
+/**
+ * Measures how well an UltraHDR file reconstructs a known HDR image, the
+ * regression metric for tuning encoder options.
+ *
+ * @param buffer - UltraHDR JPEG as ArrayBuffer
+ * @param referenceHdr - Linear RGB HDR the file was encoded from, 3 floats per
+ *   pixel at the file's size
+ * @param displayHdrCapacity - Display headroom in log2 stops (full HDR by default)
+ * @param edgeMode - How gain map samples are extended past its edges
+ */
+export async function reconstructionError(
+	buffer: ArrayBuffer,
+	referenceHdr: Float32Array,
+	displayHdrCapacity: number = Number.POSITIVE_INFINITY,
+	edgeMode: GainMapEdgeMode = GainMapEdgeMode.Clamp
+): Promise<ReconstructionErrorMetrics> {
+	const wasm = await getWasm();
+	return wasm.reconstructionError(
+		new Uint8Array(buffer),
+		referenceHdr,
+		displayHdrCapacity,
+		edgeMode
+	);
+}
+
+/**
+ * Scores how well an UltraHDR file's gain map fits its SDR base, a quality
+ * gate that needs no reference image. The HDR is reconstructed at full
+ * headroom, tone mapped back into SDR range and compared with the base by
+ * SSIM on luma.
+ *
+ * @param buffer - UltraHDR JPEG as ArrayBuffer
+ * @returns Mean SSIM, 1.0 for identical structure; well-formed files score
+ *   close to 1, a gain map that belongs to another image far lower
+ */
+export async function sdrReconstructionSimilarity(buffer: ArrayBuffer): Promise<number> {
+	const wasm = await getWasm();
+	return wasm.sdrReconstructionSimilarity(new Uint8Array(buffer));
+}
+
+/**
+ * Estimates the gamma a gain map was encoded with, for importing gain maps
+ * whose metadata has been lost.
+ *
+ * @param encodedValues - Sampled gain map bytes
+ * @param ratios - Linear gain ratio each sample is known to represent
+ * @param gainMapMin - Log2 gain of encoded value 0
+ * @param gainMapMax - Log2 gain of encoded value 255
+ * @returns Least-squares gamma fit, or 1.0 if no sample lies strictly inside the range
+ */
+export async function estimateGainMapGamma(
+	encodedValues: Uint8Array,
+	ratios: Float32Array,
+	gainMapMin: number,
+	gainMapMax: number
+): Promise<number> {
+	const wasm = await getWasm();
+	return wasm.estimateGainMapGamma(encodedValues, ratios, gainMapMin, gainMapMax);
+}
+
+/**
+ * Decodes the gain map to the linear gain ratio each sample applies.
+ *
+ * Unlike the quantized bytes in `UltraHdrDecodeResult.gainMap`, these are the
+ * file's metadata applied to every sample: 2^gain, where gain is interpolated
+ * between gainMapMin and gainMapMax with the gamma and per-channel values.
+ *
+ * @param buffer - UltraHDR JPEG as ArrayBuffer
+ * @returns Gain ratios at gain map resolution
+ */
+export async function decodeGainValues(buffer: ArrayBuffer): Promise<GainMapValues> {
+	const wasm = await getWasm();
+	return wasm.decodeGainValues(new Uint8Array(buffer));
+}
+
+/**
+ * Samples one channel's gain decode curve as a lookup table, for clients that
+ * apply the gain map themselves, e.g. in a shader.
+ *
+ * Entry i is the linear gain ratio for the encoded value i / (size - 1), so
+ * the first entry is 2^gainMapMin and the last 2^gainMapMax for that channel,
+ * with the metadata's gamma in between. Upload it as a 1D texture and sample
+ * it with the gain map byte / 255.
+ *
+ * @param metadata - Gain map metadata
+ * @param channel - 0, 1 or 2 (use 0 for single-channel gain maps)
+ * @param size - Number of entries, at least 2
+ */
+export async function exportGainDecodeLut(
+	metadata: GainMapMetadata,
+	channel: number,
+	size = 256
+): Promise<Float32Array> {
+	const wasm = await getWasm();
+	return wasm.exportGainDecodeLut(metadata, channel, size);
+}
+
+/**
+ * Builds default metadata for highlights up to a peak luminance, for callers
+ * who think in nits rather than stops.
+ *
+ * The HDR capacity and gain map maximum are both `log2(peakNits /
+ * sdrWhiteNits)`; e.g. 812 nits over 203-nit white is 2 stops.
+ *
+ * @param peakNits - Brightest highlight, in nits; must exceed SDR white
+ * @param sdrWhiteNits - Luminance of SDR diffuse white (203 nits per BT.2408)
+ */
+export async function forPeakNits(
+	peakNits: number,
+	sdrWhiteNits: number = 203
+): Promise<GainMapMetadata> {
+	const wasm = await getWasm();
+	return wasm.forPeakNits(peakNits, sdrWhiteNits);
+}
+
 /**
  * Validates gain map metadata.
  */
@@ -257,3 +1076,218 @@ export async function isMeaningfulHdr(metadata: GainMapMetadata): Promise<boolea
 	const wasm = await getWasm();
 	return wasm.isMeaningfulHdr(metadata);
 }
+
+/**
+ * Scores how HDR an image looks, from 0 to 100, for sorting galleries.
+ *
+ * Combines the metadata headroom and HDR capacity range with the fraction of
+ * the gain map that actually brightens the image, so a wide gain range used
+ * only by a small highlight scores below a smaller one applied broadly.
+ * Images without a gain map score 0.
+ */
+export async function hdrStrengthScore(buffer: ArrayBuffer): Promise<number> {
+	const wasm = await getWasm();
+	return wasm.hdrStrengthScore(new Uint8Array(buffer));
+}
+
+/**
+ * Re-derives the HDR capacity, in stops, from the gain map's content: the
+ * largest gain any gain map sample applies, decoded with the file's metadata
+ * or with `metadata` when given.
+ *
+ * Useful for checking synthesized or suspect metadata, whose
+ * `hdrCapacityMax` should be close to this value, and for repairing it.
+ */
+export async function estimateCapacityFromGainMap(
+	buffer: ArrayBuffer,
+	metadata?: GainMapMetadata
+): Promise<number> {
+	const wasm = await getWasm();
+	return wasm.estimateCapacityFromGainMap(new Uint8Array(buffer), metadata ?? null);
+}
+
+/**
+ * Lists the hdrgm metadata versions this library understands.
+ */
+export async function supportedVersions(): Promise<string[]> {
+	const wasm = await getWasm();
+	return wasm.supportedVersions();
+}
+
+/**
+ * Reports problems that `validateMetadata` accepts, such as an unknown
+ * `version`. Such metadata is still usable, but may not mean what was intended.
+ *
+ * `hdrCapacityMax` is also checked against the peak gain (the largest
+ * `gainMapMax`, or the deepest `gainMapMin` when `baseRenditionIsHdr`) and
+ * flagged when they are more than a stop apart: a display with
+ * `hdrCapacityMax` of headroom gets the full gain, so the two should agree.
+ */
+export async function metadataWarnings(metadata: GainMapMetadata): Promise<string[]> {
+	const wasm = await getWasm();
+	return wasm.metadataWarnings(metadata);
+}
+
+/**
+ * Compares two metadata blocks, allowing for float rounding.
+ *
+ * Unlike a deep equality check, numeric fields may differ by up to
+ * `tolerance`, which suits asserting that a re-encode reproduced a reference.
+ * The version string, `baseRenditionIsHdr` and `applyColorSpace` must match
+ * exactly.
+ *
+ * @param a - First metadata block
+ * @param b - Second metadata block
+ * @param tolerance - Largest allowed absolute difference per numeric field
+ */
+export async function metadataApproxEqual(
+	a: GainMapMetadata,
+	b: GainMapMetadata,
+	tolerance: number
+): Promise<boolean> {
+	const wasm = await getWasm();
+	return wasm.metadataApproxEqual(a, b, tolerance);
+}
+
+/**
+ * Writes the hdrgm XMP packet that describes a gain map image.
+ *
+ * The packet text is returned without the APP1 namespace signature. A
+ * per-channel field whose channels are all equal is written as one value;
+ * `style` picks how differing channels are written.
+ *
+ * @param metadata - Metadata to describe
+ * @param style - Comma-separated attributes (default) or `rdf:Seq` elements
+ */
+export async function gainMapXmp(
+	metadata: GainMapMetadata,
+	style: XmpFloatArrayStyle = XmpFloatArrayStyle.CommaSeparated
+): Promise<string> {
+	const wasm = await getWasm();
+	return wasm.gainMapXmp(metadata, style);
+}
+
+/**
+ * Reads gain map metadata from hdrgm XMP packet text, in either the attribute
+ * or the `rdf:Seq` form. Throws if a required field is missing or a value is
+ * not a number.
+ */
+export async function parseGainMapXmp(xmp: string): Promise<GainMapMetadata> {
+	const wasm = await getWasm();
+	return wasm.parseGainMapXmp(xmp);
+}
+
+/**
+ * Serializes gain map metadata as JSON, e.g. for a sidecar file. Per-channel
+ * values are kept as arrays.
+ */
+export async function metadataToJson(metadata: GainMapMetadata): Promise<string> {
+	const wasm = await getWasm();
+	return wasm.metadataToJson(metadata);
+}
+
+/**
+ * Reads gain map metadata written by `metadataToJson`. Throws if the JSON is
+ * malformed, a field has the wrong type or the metadata fails
+ * `validateMetadata`.
+ */
+export async function metadataFromJson(json: string): Promise<GainMapMetadata> {
+	const wasm = await getWasm();
+	return wasm.metadataFromJson(json);
+}
+
+/**
+ * Converts an sRGB-encoded value [0, 1] to linear light.
+ */
+export async function srgbToLinear(value: number): Promise<number> {
+	const wasm = await getWasm();
+	return wasm.srgbToLinear(value);
+}
+
+/**
+ * Converts a linear-light value [0, 1] to sRGB encoding.
+ */
+export async function linearToSrgb(value: number): Promise<number> {
+	const wasm = await getWasm();
+	return wasm.linearToSrgb(value);
+}
+
+/**
+ * Converts a PQ (SMPTE ST 2084) code value [0, 1] to absolute luminance in nits.
+ */
+export async function pqToNits(value: number): Promise<number> {
+	const wasm = await getWasm();
+	return wasm.pqToNits(value);
+}
+
+/**
+ * Converts absolute luminance in nits (0-10000) to a PQ code value [0, 1].
+ */
+export async function nitsToPq(nits: number): Promise<number> {
+	const wasm = await getWasm();
+	return wasm.nitsToPq(nits);
+}
+
+/**
+ * Converts an HLG signal value [0, 1] to scene-linear light [0, 1].
+ */
+export async function hlgToLinear(value: number): Promise<number> {
+	const wasm = await getWasm();
+	return wasm.hlgToLinear(value);
+}
+
+/**
+ * Converts scene-linear light [0, 1] to an HLG signal value [0, 1].
+ */
+export async function linearToHlg(value: number): Promise<number> {
+	const wasm = await getWasm();
+	return wasm.linearToHlg(value);
+}
+
+/**
+ * Converts every sRGB-encoded value in `values` to linear light, in place.
+ */
+export async function srgbToLinearInPlace(values: Float32Array): Promise<void> {
+	const wasm = await getWasm();
+	wasm.srgbToLinearInPlace(values);
+}
+
+/**
+ * Converts every linear-light value in `values` to sRGB encoding, in place.
+ */
+export async function linearToSrgbInPlace(values: Float32Array): Promise<void> {
+	const wasm = await getWasm();
+	wasm.linearToSrgbInPlace(values);
+}
+
+/**
+ * Converts every PQ code value in `values` to nits, in place.
+ */
+export async function pqToNitsInPlace(values: Float32Array): Promise<void> {
+	const wasm = await getWasm();
+	wasm.pqToNitsInPlace(values);
+}
+
+/**
+ * Converts every nits value in `values` to a PQ code value, in place.
+ */
+export async function nitsToPqInPlace(values: Float32Array): Promise<void> {
+	const wasm = await getWasm();
+	wasm.nitsToPqInPlace(values);
+}
+
+/**
+ * Converts every HLG signal value in `values` to scene-linear light, in place.
+ */
+export async function hlgToLinearInPlace(values: Float32Array): Promise<void> {
+	const wasm = await getWasm();
+	wasm.hlgToLinearInPlace(values);
+}
+
+/**
+ * Converts every scene-linear value in `values` to an HLG signal value, in place.
+ */
+export async function linearToHlgInPlace(values: Float32Array): Promise<void> {
+	const wasm = await getWasm();
+	wasm.linearToHlgInPlace(values);
+}
@@ -36,6 +36,13 @@ export interface GainMapMetadata {
 
 	/** Maximum HDR capacity (log2 scale) for full HDR output */
 	hdrCapacityMax: number;
+
+	/**
+	 * Color space the gain is applied in, when ISO 21496-1 metadata says it is
+	 * not the base image's (read from the gain map's ICC profile). Absent means
+	 * the base image's color space. hdrgm XMP has no such property.
+	 */
+	applyColorSpace?: ColorGamut;
 }
 
 /**
@@ -74,6 +81,57 @@ export interface UltraHdrProbeResult {
 
 	/** Metadata version string (empty if not found) */
 	metadataVersion: string;
+
+	/** Whether the primary is a progressive JPEG, which some hardware decoders reject */
+	primaryIsProgressive: boolean;
+
+	/**
+	 * Problems found while scanning a file that is not a full UltraHDR image:
+	 * malformed segments skipped, e.g. a bad segment length, and a gain map
+	 * codestream that carries no metadata (`metadata missing: ...`), and a
+	 * primary announcing a gain map that does not follow it. Empty when there
+	 * were none.
+	 *
+	 * Only probing skips malformed segments; `decodeUltraHdr` and
+	 * `locateComponents` still fail on the same file.
+	 */
+	warnings: string[];
+
+	/** How the file packages its gain map, to pick an extraction path */
+	layout: UltraHdrLayout;
+}
+
+/**
+ * Scalar summary of an UltraHDR file, for storing one row per file in a media
+ * index.
+ */
+export interface UltraHdrIndexRecord {
+	/** Image width in pixels */
+	width: number;
+
+	/** Image height in pixels */
+	height: number;
+
+	/** Gain map width in pixels */
+	gainMapWidth: number;
+
+	/** Gain map height in pixels */
+	gainMapHeight: number;
+
+	/** HDR capacity where the gain map starts to apply (log2 stops) */
+	hdrCapacityMin: number;
+
+	/** HDR capacity where the gain map applies fully (log2 stops) */
+	hdrCapacityMax: number;
+
+	/** hdrgm:Version as written, or "1.0" for files with only ISO 21496-1 metadata */
+	version: string;
+
+	/** How the file packages its gain map */
+	layout: UltraHdrLayout;
+
+	/** 0-100, as from `hdrStrengthScore` */
+	hdrStrength: number;
 }
 
 /**
@@ -83,10 +141,10 @@ export interface UltraHdrDecodeResult {
 	/** The SDR base image as JPEG bytes */
 	sdrImage: Uint8Array;
 
-	/** The gain map as JPEG bytes */
+	/** The gain map as JPEG bytes; empty when it was lost or has no metadata (see `warnings`) */
 	gainMap: Uint8Array;
 
-	/** Gain map metadata */
+	/** Gain map metadata; neutral (no boost) when the gain map is missing */
 	metadata: GainMapMetadata;
 
 	/** Image width in pixels */
@@ -100,6 +158,333 @@ export interface UltraHdrDecodeResult {
 
 	/** Gain map height in pixels (may differ from image height) */
 	gainMapHeight: number;
+
+	/** Whether the SDR base is a progressive JPEG, which some hardware decoders reject */
+	primaryIsProgressive: boolean;
+
+	/**
+	 * Notes about lenient-recovery paths taken while decoding, e.g. a gain map
+	 * that is larger than, or not a clean integer downscale of, the primary, or
+	 * ISO 21496-1 and XMP metadata that disagree (reported as
+	 * `metadata conflict: ...`; the ISO values are used), or a gain map without
+	 * metadata (`metadata missing: ...`; the SDR is returned with neutral
+	 * metadata). Empty for a fully conformant file.
+	 */
+	warnings: string[];
+}
+
+/**
+ * A decode whose JPEG layers stay in WASM memory. Views alias that memory
+ * without copying: they are only valid until `delete()` and until the next
+ * call into the module, which may grow (and so detach) the heap. Use
+ * `copyOut()` for buffers that outlive the handle.
+ */
+export interface UltraHdrDecodeHandle {
+	/** The SDR base image as JPEG bytes, viewed in place */
+	getSdrView(): Uint8Array;
+
+	/** The gain map as JPEG bytes, viewed in place */
+	getGainMapView(): Uint8Array;
+
+	/** Copies everything out into an ordinary decode result */
+	copyOut(): UltraHdrDecodeResult;
+
+	/** Frees the WASM-side buffers; the handle is not garbage collected */
+	delete(): void;
+}
+
+/**
+ * Gain map saturation measured after encoding. Each array holds the fraction
+ * [0, 1] of gain map samples per channel (R, G, B) quantized to the limit;
+ * single-channel gain maps repeat the same value.
+ */
+export interface UltraHdrEncodeStats {
+	/** Samples clamped at the bottom of the gain range (gainMapMin) */
+	clippedLow: number[];
+
+	/** Samples clamped at the top of the gain range (gainMapMax) */
+	clippedHigh: number[];
+}
+
+/**
+ * Encoded UltraHDR JPEG together with gain map diagnostics.
+ */
+export interface UltraHdrEncodeResult {
+	/** The encoded UltraHDR JPEG */
+	data: ArrayBuffer;
+
+	/** Clipping statistics for the encoded gain map (zero when none was embedded) */
+	stats: UltraHdrEncodeStats;
+
+	/**
+	 * Whether `data` carries a gain map. False only when `omitTrivialGainMap`
+	 * found the gain map too weak to keep and `data` is the plain SDR JPEG.
+	 */
+	gainMapEmbedded: boolean;
+
+	/** Width of the encoded image, after any `oddDimensionPolicy` crop or pad */
+	width: number;
+
+	/** Height of the encoded image, after any `oddDimensionPolicy` crop or pad */
+	height: number;
+}
+
+/**
+ * SDR base and gain map decoded to 8-bit pixels.
+ */
+export interface UltraHdrPixels {
+	/** sRGB-encoded RGBA, 4 bytes per pixel */
+	sdr: Uint8Array;
+
+	/** Image width in pixels */
+	width: number;
+
+	/** Image height in pixels */
+	height: number;
+
+	/** Encoded gain map samples, `gainMapChannels` bytes per pixel */
+	gainMap: Uint8Array;
+
+	/** Gain map width in pixels */
+	gainMapWidth: number;
+
+	/** Gain map height in pixels */
+	gainMapHeight: number;
+
+	/** 1 for a single-channel gain map, 4 for an RGBA one */
+	gainMapChannels: number;
+
+	/** Exif orientation of the primary (1-8; 1 when untagged) */
+	orientation: number;
+}
+
+/**
+ * Linear HDR image reconstructed by applying a gain map to its SDR base.
+ */
+export interface ReconstructedHdrImage {
+	/** Linear RGB values, 3 per pixel, with SDR diffuse white at 1.0 */
+	data: Float32Array;
+
+	/** Image width in pixels */
+	width: number;
+
+	/** Image height in pixels */
+	height: number;
+}
+
+/**
+ * A rectangle of pixels within an image.
+ */
+export interface ImageRegion {
+	/** Left edge in pixels */
+	x: number;
+
+	/** Top edge in pixels */
+	y: number;
+
+	/** Width in pixels */
+	width: number;
+
+	/** Height in pixels */
+	height: number;
+}
+
+/**
+ * Absolute luminance of a reconstructed HDR image.
+ */
+export interface HdrLuminanceImage {
+	/** BT.709 luminance in nits, 1 value per pixel */
+	data: Float32Array;
+
+	/** Image width in pixels */
+	width: number;
+
+	/** Image height in pixels */
+	height: number;
+}
+
+/**
+ * How closely a reconstructed HDR image matches a reference. Each array holds
+ * one value per channel (R, G, B) in linear units, SDR diffuse white = 1.0.
+ */
+export interface ReconstructionErrorMetrics {
+	/** Root-mean-square error */
+	rmse: number[];
+
+	/** Largest absolute error of any pixel */
+	maxError: number[];
+
+	/**
+	 * PSNR in dB against the brightest reference sample (at least 1.0);
+	 * Infinity when the channel matches exactly
+	 */
+	psnr: number[];
+}
+
+/**
+ * HDR10 content light level metadata (CTA-861.3), in nits.
+ */
+export interface ContentLightLevel {
+	/** Maximum content light level: the brightest pixel */
+	maxCll: number;
+
+	/** Maximum frame-average light level */
+	maxFall: number;
+}
+
+/**
+ * A span of bytes within a file.
+ */
+export interface ByteRange {
+	/** Offset of the first byte */
+	offset: number;
+
+	/** Number of bytes */
+	length: number;
+}
+
+/**
+ * An item of a file's GContainer directory and where it sits within the file.
+ */
+export interface AuxiliaryImage {
+	/** Item:Semantic, e.g. `Primary`, `GainMap`, or `Alternate` for a fully rendered HDR image */
+	semantic: string;
+
+	/** Item:Mime, or an empty string when the item has none */
+	mime: string;
+
+	/** Offset of the item's first byte */
+	offset: number;
+
+	/** Item length in bytes */
+	length: number;
+}
+
+/**
+ * Where each component of an UltraHDR file sits within its bytes.
+ */
+export interface UltraHdrComponentLayout {
+	/** Primary (SDR) JPEG, from its SOI to its EOI */
+	primary: ByteRange;
+
+	/** Gain map JPEG, as listed in the MPF index */
+	gainMap: ByteRange;
+
+	/** The primary's gain map XMP packet, after the namespace identifier */
+	xmp: ByteRange | null;
+
+	/** The primary's MPF data, after the "MPF" identifier */
+	mpf: ByteRange | null;
+}
+
+/**
+ * An UltraHDR file split into parts that `encodeFromComponents` joins again.
+ */
+export interface UltraHdrComponents {
+	/** Primary (SDR) JPEG without its gain map XMP, MPF and ISO 21496-1 segments */
+	primaryJpeg: Uint8Array;
+
+	/** Gain map JPEG, likewise stripped */
+	gainMapJpeg: Uint8Array;
+
+	/** Gain map metadata as written by `metadataToJson` */
+	metadataJson: string;
+}
+
+/**
+ * Pixel density from a JFIF (APP0) header.
+ */
+export interface JfifDensity {
+	/** 0 when the densities only give the aspect ratio, 1 for dots per inch, 2 for dots per cm */
+	units: number;
+
+	/** Horizontal density */
+	xDensity: number;
+
+	/** Vertical density */
+	yDensity: number;
+}
+
+/**
+ * One quantization table from a JPEG DQT segment.
+ */
+export interface QuantizationTable {
+	/** Table slot (0-3) that components refer to */
+	id: number;
+
+	/** Bits per value: 8, or 16 for high-precision tables */
+	precision: number;
+
+	/** The 64 quantizer values, in the zigzag order they are stored in */
+	values: number[];
+}
+
+/**
+ * Outcome of one rule of a compatibility profile.
+ */
+export interface CompatItem {
+	/** Stable rule identifier, e.g. `mpf`, `xmp`, `iso-metadata` or `even-dimensions` */
+	rule: string;
+
+	/** `warn` for rules the profile recommends, `fail` for rules it requires */
+	status: 'pass' | 'warn' | 'fail';
+
+	/** Why the rule warned or failed; empty when it passed */
+	message: string;
+}
+
+/**
+ * Result of checking a file against a compatibility profile.
+ */
+export interface CompatReport {
+	/** Profile the file was checked against */
+	profile: CompatProfile;
+
+	/** False if any rule failed */
+	compatible: boolean;
+
+	/** One entry per rule the profile checks, in a fixed order */
+	items: CompatItem[];
+}
+
+/**
+ * Gain map decoded to linear gain ratios.
+ */
+export interface GainMapValues {
+	/**
+	 * Linear gain (HDR / SDR ratio at full weight) per sample, `channels` per
+	 * pixel in row-major order
+	 */
+	data: Float32Array;
+
+	/** Gain map width in pixels */
+	width: number;
+
+	/** Gain map height in pixels */
+	height: number;
+
+	/** 1 for a single-channel gain map, 3 for a per-channel (RGB) one */
+	channels: number;
+}
+
+/**
+ * Optional features of the loaded WASM build.
+ */
+export interface Capabilities {
+	/** Library-encoded JPEGs are written with mozjpeg rather than libjpeg */
+	mozjpeg: boolean;
+
+	/** Library-encoded JPEGs are progressive (mozjpeg builds by default) */
+	progressiveJpeg: boolean;
+
+	/** RGB gain maps are decoded, applied and can be encoded per channel */
+	perChannelGainMap: boolean;
+
+	/** HDR can be returned PQ-encoded rather than as linear values or nits */
+	pqOutput: boolean;
+
+	/** ISO 21496-1 binary gain map metadata is read */
+	binaryMetadata: boolean;
 }
 
 /**
@@ -112,7 +497,10 @@ export interface UltraHdrEncodeOptions {
 	/** JPEG quality for the gain map (1-100) */
 	gainMapQuality: number;
 
-	/** Target HDR capacity (typically 2.0-4.0) */
+	/**
+	 * Target HDR capacity in log2 stops (typically 2.0-4.0). Must be in
+	 * (0, 10]; encoding rejects anything else.
+	 */
 	targetHdrCapacity: number;
 
 	/**
@@ -135,8 +523,134 @@ export interface UltraHdrEncodeOptions {
 	 */
 	includeUltrahdrV1: boolean;
 
-	/** Downscale factor for the gain map (1 = same size, 2 = half, 4 = quarter) */
+	/**
+	 * Downscale factor for the gain map (1 = same size, 2 = half, 4 = quarter).
+	 * Capped at the image's smaller dimension.
+	 */
 	gainMapScale: number;
+
+	/**
+	 * Color primaries of the linear HDR input.
+	 *
+	 * The HDR data is converted into the SDR base's gamut before gain ratios
+	 * are computed, so the gain map encodes luminance rather than a gamut
+	 * mismatch. Defaults to `ColorGamut.Srgb`.
+	 *
+	 * Earlier versions always read the HDR input as BT.2100; pass
+	 * `ColorGamut.Bt2100` to keep that behaviour.
+	 */
+	hdrGamut: ColorGamut;
+
+	/**
+	 * What to do when the SDR input is already an UltraHDR JPEG.
+	 *
+	 * By default encoding is rejected, since the output would carry a nested
+	 * gain map. When `true`, the existing gain map and its metadata are
+	 * stripped and the clean primary is used as the SDR base.
+	 */
+	flattenExisting: boolean;
+
+	/**
+	 * Skip the gain map when it would be near-neutral.
+	 *
+	 * When the encoded gain map boosts no sample by 0.5 stops or more (for
+	 * example, HDR input identical to the SDR base), the clean SDR JPEG is
+	 * returned instead of an UltraHDR file.
+	 */
+	omitTrivialGainMap: boolean;
+
+	/**
+	 * Hard ceiling on the encoded gainMapMax, in log2 stops.
+	 *
+	 * The gain range normally follows targetHdrCapacity; when this is lower it
+	 * wins, giving a predictable maximum at the cost of clipping highlights that
+	 * need more boost. `Infinity` (the default) applies no extra cap.
+	 */
+	absoluteMaxGain: number;
+
+	/**
+	 * Sets gainMapMax from the content instead: the gain at this percentile,
+	 * in (0, 100], of all pixel gains, still capped by targetHdrCapacity and
+	 * absoluteMaxGain.
+	 *
+	 * At 100 the brightest sample sets the range, so a few hot pixels can
+	 * stretch it for the whole image; 99.9 clips the brightest 0.1% instead and
+	 * keeps the 8-bit gain map's precision for the rest. 0 (the default) keeps
+	 * the range at targetHdrCapacity. Any other value computes a linear-gamma
+	 * gain map in this library, and requires `hdrGamut` Srgb.
+	 */
+	metadataMaxPercentile: number;
+
+	/**
+	 * Offset added to SDR and HDR values before taking gain ratios, written
+	 * verbatim as offsetSdr/offsetHdr. Must be in (0, 1).
+	 *
+	 * The default 1/64 is what libultrahdr uses. Other values (e.g. 1/128 to
+	 * match another decoder's assumption) compute a linear-gamma RGB gain map
+	 * in this library instead, and require `hdrGamut` Srgb.
+	 */
+	blackPointOffset: number;
+
+	/**
+	 * Rewrite a progressive SDR base as a baseline JPEG before encoding, for
+	 * older hardware decoders. The DCT coefficients are copied, so no quality
+	 * is lost; baseline inputs are used as they are.
+	 */
+	forceBaselineBase: boolean;
+
+	/**
+	 * Standard deviation, in gain map pixels, of a Gaussian blur applied to
+	 * the gain map before it is compressed. Smooths the speckle noisy SDR/HDR
+	 * pairs produce, which would otherwise show as luminance noise, and makes
+	 * the gain map compress better. 0 (the default) disables it.
+	 */
+	gainMapBlurSigma: number;
+
+	/**
+	 * How each gainMapScale x gainMapScale block of pixel gains becomes one
+	 * gain map sample.
+	 *
+	 * `Encoder` (the default) keeps libultrahdr's gain map. Any other mode
+	 * computes a linear-gamma RGB gain map in this library instead, and
+	 * requires `hdrGamut` Srgb.
+	 */
+	gainMapDownsample: GainMapDownsample;
+
+	/**
+	 * What to do with an SDR base whose width or height is not a whole number
+	 * of chroma samples, such as an odd width under 4:2:0, which the encoder
+	 * cannot take as it is. Odd-sized 4:4:4 bases are encoded unchanged.
+	 *
+	 * `Reject` (the default) throws. `CropToEven` and `PadToEven` trim or
+	 * repeat the last column and/or row of both the SDR and the HDR input so
+	 * they stay aligned. `CropToEven` crops the SDR losslessly, keeping its DCT
+	 * blocks as they are; `PadToEven` re-encodes it as 4:2:0 at `baseQuality`.
+	 */
+	oddDimensionPolicy: OddDimensionPolicy;
+
+	/**
+	 * Exif orientation (1-8) to tag the output with, e.g. when the SDR was
+	 * rendered rotated. The pixels are not turned: both layers stay in the
+	 * stored layout and viewers rotate them together.
+	 *
+	 * The SDR's own Orientation entry is rewritten, or a minimal Exif segment
+	 * added when it has none (orientation 1 needs none). 0 (the default) keeps
+	 * the SDR's tagging. Throws if the SDR has Exif without an Orientation entry.
+	 */
+	orientation: number;
+
+	/**
+	 * Luminance weights `[r, g, b]` for a single-channel gain map, e.g. equal
+	 * thirds to match a reference encoder that uses the plain mean.
+	 *
+	 * `null` (the default) keeps libultrahdr's gain map, whose luminance uses
+	 * fixed weights for the SDR gamut. Any weights compute a linear-gamma
+	 * single-channel gain map in this library instead (blocks reduced by
+	 * `gainMapDownsample`, BoxAverage when it is Encoder), and require
+	 * `hdrGamut` Srgb. It is written as a full-range (JFIF) grayscale JPEG,
+	 * so the gain range maps onto samples 0 to 255.
+	 */
+	lumaCoefficients: [number, number, number] | null;
 }
 
 /**
@@ -151,6 +665,109 @@ export enum ColorGamut {
 	Bt2100 = 2,
 }
 
+/**
+ * How gain map samples are extended past the outermost texels when the gain
+ * map is smaller than the image.
+ */
+export enum GainMapEdgeMode {
+	/** Repeat the edge texel */
+	Clamp = 0,
+	/** Reflect about the edge texel, for encoders that downsample symmetrically */
+	Mirror = 1,
+}
+
+/**
+ * How a file packages its gain map.
+ */
+export enum UltraHdrLayout {
+	/** No gain map is announced */
+	Unknown = 0,
+	/** A second codestream after the primary, listed in the primary's MPF index */
+	DualImageMpf = 1,
+	/** A second codestream after the primary with no MPF index, found from XMP or by scanning */
+	DualImageAppended = 2,
+	/**
+	 * A single-image legacy file. Not reported yet: from the bytes alone it
+	 * cannot be told apart from a file truncated after the primary, which
+	 * reports `GainMapMissing`
+	 */
+	SingleImageLegacy = 3,
+	/**
+	 * A lone primary whose metadata announces a gain map that does not follow
+	 * it; the probe also returns a warning
+	 */
+	GainMapMissing = 4,
+}
+
+/**
+ * How a block of pixel gains is reduced to one gain map sample when
+ * gainMapScale is above 1.
+ */
+export enum GainMapDownsample {
+	/** libultrahdr's own gain map */
+	Encoder = 0,
+	/** The gain at the block's center pixel; aliases on detail finer than the block */
+	CenterSample = 1,
+	/** The mean log2 gain over the block; the steadiest on fine detail */
+	BoxAverage = 2,
+	/** The largest log2 gain in the block, so small highlights keep their full boost */
+	MaxGain = 3,
+}
+
+/**
+ * How encoding treats an SDR base whose width or height splits a chroma
+ * sample, e.g. an odd width under 4:2:0.
+ */
+export enum OddDimensionPolicy {
+	/** Throw */
+	Reject = 0,
+	/** Drop the partial chroma column and/or row */
+	CropToEven = 1,
+	/** Repeat the last column and/or row up to even dimensions */
+	PadToEven = 2,
+}
+
+/**
+ * How `gainMapXmp` and `extractGainMapStandalone` write a per-channel property
+ * whose channels differ. Properties with one value for every channel are
+ * always a plain attribute. UltraHDR files from the encoders carry
+ * libultrahdr's own packet instead.
+ */
+export enum XmpFloatArrayStyle {
+	/** An attribute listing the channels: `hdrgm:Gamma="1, 1.2, 1"` */
+	CommaSeparated = 0,
+	/** An element holding an `rdf:Seq` of three `rdf:li`, the form strict ISO readers expect */
+	RdfSeq = 1,
+}
+
+/**
+ * What `encodeFromComponents` does with metadata the gain map JPEG carries
+ * itself. The container's metadata is always written into the gain map, so a
+ * kept hdrgm packet from elsewhere can contradict it.
+ */
+export enum GainMapXmpMode {
+	/** Copy the gain map's segments untouched */
+	Keep = 0,
+	/** Replace its hdrgm XMP and ISO 21496-1 segments with the container's metadata */
+	Sync = 1,
+	/** As `Sync`, and also drop any other XMP it carries */
+	Strip = 2,
+}
+
+/**
+ * Ecosystems whose UltraHDR readers differ in what they require.
+ */
+export enum CompatProfile {
+	/** hdrgm v1 XMP, MPF, even dimensions under 4:2:0 chroma and a single-channel gain map */
+	Android = 0,
+	/** hdrgm v1 XMP and MPF */
+	Adobe = 1,
+	/** ISO 21496-1 metadata and MPF */
+	Apple = 2,
+	/** ISO 21496-1 metadata on both images and MPF; XMP is not consulted */
+	IsoStrict = 3,
+}
+
 /**
  * Transfer function for encoding luminance.
  */
@@ -175,6 +792,18 @@ export const defaultEncodeOptions: UltraHdrEncodeOptions = {
 	includeIsoMetadata: true,
 	includeUltrahdrV1: true,
 	gainMapScale: 1,
+	hdrGamut: ColorGamut.Srgb,
+	flattenExisting: false,
+	omitTrivialGainMap: false,
+	absoluteMaxGain: Number.POSITIVE_INFINITY,
+	metadataMaxPercentile: 0,
+	blackPointOffset: 1 / 64,
+	forceBaselineBase: false,
+	gainMapBlurSigma: 0,
+	gainMapDownsample: GainMapDownsample.Encoder,
+	oddDimensionPolicy: OddDimensionPolicy.Reject,
+	orientation: 0,
+	lumaCoefficients: null,
 };
 
 /**
@@ -187,6 +816,18 @@ export const highQualityEncodeOptions: UltraHdrEncodeOptions = {
 	includeIsoMetadata: true,
 	includeUltrahdrV1: true,
 	gainMapScale: 1,
+	hdrGamut: ColorGamut.Srgb,
+	flattenExisting: false,
+	omitTrivialGainMap: false,
+	absoluteMaxGain: Number.POSITIVE_INFINITY,
+	metadataMaxPercentile: 0,
+	blackPointOffset: 1 / 64,
+	forceBaselineBase: false,
+	gainMapBlurSigma: 0,
+	gainMapDownsample: GainMapDownsample.Encoder,
+	oddDimensionPolicy: OddDimensionPolicy.Reject,
+	orientation: 0,
+	lumaCoefficients: null,
 };
 
 /**
@@ -199,4 +840,35 @@ export const smallSizeEncodeOptions: UltraHdrEncodeOptions = {
 	includeIsoMetadata: true,
 	includeUltrahdrV1: true,
 	gainMapScale: 2,
+	hdrGamut: ColorGamut.Srgb,
+	flattenExisting: false,
+	omitTrivialGainMap: false,
+	absoluteMaxGain: Number.POSITIVE_INFINITY,
+	metadataMaxPercentile: 0,
+	blackPointOffset: 1 / 64,
+	forceBaselineBase: false,
+	gainMapBlurSigma: 0,
+	gainMapDownsample: GainMapDownsample.Encoder,
+	oddDimensionPolicy: OddDimensionPolicy.Reject,
+	orientation: 0,
+	lumaCoefficients: null,
+};
+
+/**
+ * Values ISO 21496-1 assigns to metadata fields omitted from a packet.
+ *
+ * Fields the spec requires (gainMapMax, hdrCapacityMax) have no default and are
+ * zero here, describing an identity gain map. Use this to complete partial
+ * metadata; it is not an encoding preset.
+ */
+export const isoDefaultMetadata: GainMapMetadata = {
+	version: '1.0',
+	baseRenditionIsHdr: false,
+	gainMapMin: [0.0, 0.0, 0.0],
+	gainMapMax: [0.0, 0.0, 0.0],
+	gamma: [1.0, 1.0, 1.0],
+	offsetSdr: [1 / 64, 1 / 64, 1 / 64],
+	offsetHdr: [1 / 64, 1 / 64, 1 / 64],
+	hdrCapacityMin: 0.0,
+	hdrCapacityMax: 0.0,
 };
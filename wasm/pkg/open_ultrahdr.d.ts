@@ -18,6 +18,23 @@ export interface UltraHdrEncodeOptions {
 	includeIsoMetadata: boolean;
 	includeUltrahdrV1: boolean;
 	gainMapScale: number;
+	/** Primaries of the HDR input: 0 = BT.709/sRGB, 1 = Display P3, 2 = BT.2100. */
+	hdrGamut: number;
+	flattenExisting: boolean;
+	omitTrivialGainMap: boolean;
+	absoluteMaxGain: number;
+	/** 0 = gainMapMax from targetHdrCapacity, else the percentile of pixel gains. */
+	metadataMaxPercentile: number;
+	blackPointOffset: number;
+	forceBaselineBase: boolean;
+	gainMapBlurSigma: number;
+	gainMapDownsample: number;
+	/** 0 = reject, 1 = crop to even, 2 = pad to even. */
+	oddDimensionPolicy: number;
+	/** Exif orientation 1-8 to tag the output with, or 0 to keep the SDR's. */
+	orientation: number;
+	/** Three luminance weights for a single-channel computed gain map, or null. */
+	lumaCoefficients: number[] | null;
 }
 
 export interface GainMapMetadata {
@@ -30,6 +47,8 @@ export interface GainMapMetadata {
 	offsetHdr: number[];
 	hdrCapacityMin: number;
 	hdrCapacityMax: number;
+	/** ColorGamut value; undefined for the base image's color space. */
+	applyColorSpace?: number;
 }
 
 export interface UltraHdrProbeResult {
@@ -43,6 +62,21 @@ export interface UltraHdrProbeResult {
 	gainMapHeight: number;
 	hdrCapacity: number;
 	metadataVersion: string;
+	primaryIsProgressive: boolean;
+	warnings: string[];
+	layout: number;
+}
+
+export interface UltraHdrIndexRecord {
+	width: number;
+	height: number;
+	gainMapWidth: number;
+	gainMapHeight: number;
+	hdrCapacityMin: number;
+	hdrCapacityMax: number;
+	version: string;
+	layout: number;
+	hdrStrength: number;
 }
 
 export interface UltraHdrDecodeResult {
@@ -53,6 +87,127 @@ export interface UltraHdrDecodeResult {
 	height: number;
 	gainMapWidth: number;
 	gainMapHeight: number;
+	primaryIsProgressive: boolean;
+	warnings: string[];
+}
+
+export interface UltraHdrDecodeHandle {
+	getSdrView(): Uint8Array;
+	getGainMapView(): Uint8Array;
+	copyOut(): UltraHdrDecodeResult;
+	delete(): void;
+}
+
+export interface UltraHdrEncodeStats {
+	clippedLow: number[];
+	clippedHigh: number[];
+}
+
+export interface UltraHdrEncodeResult {
+	data: Uint8Array;
+	stats: UltraHdrEncodeStats;
+	gainMapEmbedded: boolean;
+	width: number;
+	height: number;
+}
+
+export interface UltraHdrPixels {
+	sdr: Uint8Array;
+	width: number;
+	height: number;
+	gainMap: Uint8Array;
+	gainMapWidth: number;
+	gainMapHeight: number;
+	gainMapChannels: number;
+	orientation: number;
+}
+
+export interface ReconstructedHdrImage {
+	data: Float32Array;
+	width: number;
+	height: number;
+}
+
+export interface HdrLuminanceImage {
+	data: Float32Array;
+	width: number;
+	height: number;
+}
+
+export interface ContentLightLevel {
+	maxCll: number;
+	maxFall: number;
+}
+
+export interface ReconstructionErrorMetrics {
+	rmse: number[];
+	maxError: number[];
+	psnr: number[];
+}
+
+export interface ByteRange {
+	offset: number;
+	length: number;
+}
+
+export interface AuxiliaryImage {
+	semantic: string;
+	mime: string;
+	offset: number;
+	length: number;
+}
+
+export interface UltraHdrComponentLayout {
+	primary: ByteRange;
+	gainMap: ByteRange;
+	xmp: ByteRange | null;
+	mpf: ByteRange | null;
+}
+
+export interface UltraHdrComponents {
+	primaryJpeg: Uint8Array;
+	gainMapJpeg: Uint8Array;
+	metadataJson: string;
+}
+
+export interface JfifDensity {
+	units: number;
+	xDensity: number;
+	yDensity: number;
+}
+
+export interface QuantizationTable {
+	id: number;
+	precision: number;
+	values: number[];
+}
+
+export interface CompatItem {
+	rule: string;
+	status: 'pass' | 'warn' | 'fail';
+	message: string;
+}
+
+export interface CompatReport {
+	/** 0 = Android, 1 = Adobe, 2 = Apple, 3 = ISO strict. */
+	profile: number;
+	compatible: boolean;
+	items: CompatItem[];
+}
+
+export interface GainMapValues {
+	data: Float32Array;
+	width: number;
+	height: number;
+	channels: number;
+}
+
+export interface Capabilities {
+	mozjpeg: boolean;
+	progressiveJpeg: boolean;
+	perChannelGainMap: boolean;
+	pqOutput: boolean;
+	binaryMetadata: boolean;
 }
 
 /** Options accepted by the module factory. */
@@ -70,21 +225,150 @@ export interface OpenUltraHdrModuleOptions {
 export interface OpenUltraHdrModule {
 	isUltraHdr(buffer: Uint8Array): boolean;
 	probeUltraHdr(buffer: Uint8Array): UltraHdrProbeResult;
+	indexUltraHdr(buffer: Uint8Array): UltraHdrIndexRecord;
 	decodeUltraHdr(buffer: Uint8Array): UltraHdrDecodeResult;
+	decodeUltraHdrHandle(buffer: Uint8Array): UltraHdrDecodeHandle;
+	decodeUltraHdrStrict(buffer: Uint8Array): UltraHdrDecodeResult;
+	gainMapDimensionsMatch(gainMap: Uint8Array, gainMapWidth: number, gainMapHeight: number): boolean;
+	checkCompatibility(buffer: Uint8Array, profile: number): CompatReport;
 	encodeUltraHdr(
 		sdrBuffer: Uint8Array,
 		hdrBuffer: Float32Array,
 		options: UltraHdrEncodeOptions
 	): Uint8Array;
+	encodeUltraHdrWithStats(
+		sdrBuffer: Uint8Array,
+		hdrBuffer: Float32Array,
+		options: UltraHdrEncodeOptions
+	): UltraHdrEncodeResult;
+	encodeUltraHdrStrips(
+		sdrBuffer: Uint8Array,
+		readHdrRows: (y: number, rows: number) => Float32Array,
+		stripRows: number,
+		options: UltraHdrEncodeOptions
+	): Uint8Array;
+	encodeUltraHdrToTargetSize(
+		sdrBuffer: Uint8Array,
+		hdrBuffer: Float32Array,
+		maxBytes: number,
+		options: UltraHdrEncodeOptions
+	): Uint8Array;
+	recomputeGainMapForSdr(
+		sdrBuffer: Uint8Array,
+		hdrBuffer: Float32Array,
+		width: number,
+		height: number,
+		options: UltraHdrEncodeOptions
+	): Uint8Array;
+	locateComponents(buffer: Uint8Array): UltraHdrComponentLayout;
+	getJfifDensity(buffer: Uint8Array): JfifDensity | null;
+	getQuantizationTables(buffer: Uint8Array): QuantizationTable[];
+	listAuxiliaryImages(buffer: Uint8Array): AuxiliaryImage[];
+	extractAuxiliaryImage(buffer: Uint8Array, semantic: string): Uint8Array;
 	extractSdrBase(buffer: Uint8Array): Uint8Array;
+	/** style: 0 = comma-separated attributes, 1 = rdf:Seq elements. */
+	extractGainMapStandalone(buffer: Uint8Array, style: number): Uint8Array;
+	splitUltraHdr(buffer: Uint8Array): UltraHdrComponents;
+	encodeFromComponents(
+		primary: Uint8Array,
+		gainMap: Uint8Array,
+		metadata: GainMapMetadata,
+		gainMapXmp: number
+	): Uint8Array;
 	getMetadata(buffer: Uint8Array): GainMapMetadata;
+	decodeToPixels(buffer: Uint8Array, applyOrientation: boolean): UltraHdrPixels;
+	applyGainMap(
+		buffer: Uint8Array,
+		displayHdrCapacity: number,
+		edgeMode: number,
+		maxLinear: number
+	): ReconstructedHdrImage;
+	applyGainMapMulti(
+		buffer: Uint8Array,
+		displayHdrCapacities: number[],
+		edgeMode: number,
+		maxLinear: number
+	): ReconstructedHdrImage[];
+	applyGainMapRegion(
+		buffer: Uint8Array,
+		x: number,
+		y: number,
+		width: number,
+		height: number,
+		displayHdrCapacity: number,
+		edgeMode: number,
+		maxLinear: number
+	): ReconstructedHdrImage;
+	applyGainMapRows(
+		buffer: Uint8Array,
+		displayHdrCapacity: number,
+		edgeMode: number,
+		maxLinear: number,
+		onRow: (y: number, row: Float32Array) => void
+	): void;
+	decodeUltraHdrThumbnail(
+		buffer: Uint8Array,
+		maxDim: number,
+		displayHdrCapacity: number
+	): Uint8Array;
+	reconstructNits(
+		buffer: Uint8Array,
+		sdrWhiteNits: number,
+		displayHdrCapacity: number,
+		edgeMode: number,
+		lumaCoefficients: number[] | null
+	): HdrLuminanceImage;
+	computeMaxCllMaxFall(nits: Float32Array, width: number, height: number): ContentLightLevel;
+	reconstructionError(
+		buffer: Uint8Array,
+		referenceHdr: Float32Array,
+		displayHdrCapacity: number,
+		edgeMode: number
+	): ReconstructionErrorMetrics;
+	sdrReconstructionSimilarity(buffer: Uint8Array): number;
+	estimateGainMapGamma(
+		encodedValues: Uint8Array,
+		ratios: Float32Array,
+		gainMapMin: number,
+		gainMapMax: number
+	): number;
+	decodeGainValues(buffer: Uint8Array): GainMapValues;
+	exportGainDecodeLut(metadata: GainMapMetadata, channel: number, size: number): Float32Array;
+	repairUltraHdr(buffer: Uint8Array): Uint8Array;
+	recompressGainMap(buffer: Uint8Array, quality: number): Uint8Array;
+	rotateUltraHdr(buffer: Uint8Array, degrees: number): Uint8Array;
+	jpegEncoderBackend(): 'libjpeg' | 'mozjpeg';
+	capabilities(): Capabilities;
 	createDefaultOptions(): UltraHdrEncodeOptions;
 	createHighQualityOptions(): UltraHdrEncodeOptions;
 	createSmallSizeOptions(): UltraHdrEncodeOptions;
 	createDefaultMetadata(): GainMapMetadata;
+	forPeakNits(peakNits: number, sdrWhiteNits: number): GainMapMetadata;
 	validateMetadata(metadata: GainMapMetadata): boolean;
 	estimateHdrHeadroom(metadata: GainMapMetadata): number;
 	isMeaningfulHdr(metadata: GainMapMetadata): boolean;
+	hdrStrengthScore(buffer: Uint8Array): number;
+	estimateCapacityFromGainMap(buffer: Uint8Array, metadata: GainMapMetadata | null): number;
+	supportedVersions(): string[];
+	metadataWarnings(metadata: GainMapMetadata): string[];
+	metadataApproxEqual(a: GainMapMetadata, b: GainMapMetadata, tolerance: number): boolean;
+	/** style: 0 = comma-separated attributes, 1 = rdf:Seq elements. */
+	gainMapXmp(metadata: GainMapMetadata, style: number): string;
+	parseGainMapXmp(xmp: string): GainMapMetadata;
+	metadataToJson(metadata: GainMapMetadata): string;
+	metadataFromJson(json: string): GainMapMetadata;
+	srgbToLinear(value: number): number;
+	linearToSrgb(value: number): number;
+	pqToNits(value: number): number;
+	nitsToPq(nits: number): number;
+	hlgToLinear(value: number): number;
+	linearToHlg(value: number): number;
+	srgbToLinearInPlace(values: Float32Array): void;
+	linearToSrgbInPlace(values: Float32Array): void;
+	pqToNitsInPlace(values: Float32Array): void;
+	nitsToPqInPlace(values: Float32Array): void;
+	hlgToLinearInPlace(values: Float32Array): void;
+	linearToHlgInPlace(values: Float32Array): void;
 }
 
 declare const createOpenUltraHdrModule: (
@@ -9,6 +9,8 @@
 #include <cmath>
 #include <cstdint>
 #include <cstring>
+#include <functional>
+#include <limits>
 #include <stdexcept>
 #include <string>
 #include <vector>
@@ -18,7 +20,13 @@
 #include <emscripten/val.h>
 
 #include "ultrahdr_api.h"
+#include "color_math.h"
+#include "conformance.h"
+#include "gainmap.h"
+#include "iso_metadata.h"
 #include "jpeg_meta.h"
+#include "jpeg_rotate.h"
+#include "xmp_metadata.h"
 
 // Throws a native JS Error so callers see a human-readable message rather than
 // an opaque embind CppException pointer.
@@ -26,6 +34,12 @@ EM_JS(void, openUltraHdrThrowJsError, (const char* msg), {
   throw new Error(UTF8ToString(msg));
 });
 
+EM_JS(void, openUltraHdrThrowNamedJsError, (const char* name, const char* msg), {
+  const err = new Error(UTF8ToString(msg));
+  err.name = UTF8ToString(name);
+  throw err;
+});
+
 using namespace emscripten;
 
 namespace {
@@ -50,6 +64,25 @@ constexpr uint8_t kSmallGainMapScale = 2;
 // Minimum HDR headroom (in stops) considered meaningful.
 constexpr float kMeaningfulHdrThreshold = 0.5f;
 
+// Largest HDR capacity (in stops) accepted for encoding or in metadata. 10
+// stops is a 1024x boost, far beyond any display; larger values only stretch
+// the gain range until the 8-bit gain map can no longer resolve it.
+constexpr float kMaxHdrCapacity = 10.0f;
+
+// How far (in stops) hdrCapacityMax may sit from the gain map's peak gain
+// before metadataWarnings flags the pair. Encoders set them equal; a display
+// with hdrCapacityMax of headroom is meant to receive the full gain.
+constexpr float kCapacityGainToleranceStops = 1.0f;
+
+// SDR and HDR offset libultrahdr's encoder builds the gain map with. It has no
+// setting for it, so other values take a slower path (see
+// encodeWithComputedGainMap).
+constexpr float kDefaultBlackPointOffset = 1.0f / 64.0f;
+
+// Bisection steps encodeUltraHdrToTargetSize takes between its two bracketing
+// encodes; five narrow a 1-100 quality range to within four.
+constexpr int kMaxTargetSizeSteps = 5;
+
 // =============================================================================
 // JS-facing structs
 // =============================================================================
@@ -66,6 +99,47 @@ struct UltraHdrEncodeOptions {
   bool includeIsoMetadata = true;
   bool includeUltrahdrV1 = true;
   int gainMapScale = 1;
+  // Primaries of the linear HDR input (ColorGamut on the JS side; values match
+  // uhdr_color_gamut_t). libultrahdr converts the HDR input into the SDR
+  // base's gamut before computing gain ratios.
+  int hdrGamut = UHDR_CG_BT_709;
+  // When the SDR input is itself an UltraHDR JPEG, strip its gain map and
+  // signalling and encode onto the clean primary instead of rejecting it.
+  bool flattenExisting = false;
+  // Return the clean SDR JPEG without a gain map when the encoded gain map
+  // would add less than kMeaningfulHdrThreshold stops anywhere.
+  bool omitTrivialGainMap = false;
+  // Hard ceiling on gainMapMax (log2 stops), applied after targetHdrCapacity.
+  // Highlights brighter than this are clipped. Infinity leaves it uncapped.
+  float absoluteMaxGain = std::numeric_limits<float>::infinity();
+  // Percentile (0, 100] of the content's pixel gains to take as gainMapMax
+  // (still capped as above), so a few hot pixels cannot set the range. 0
+  // keeps the range at targetHdrCapacity.
+  float metadataMaxPercentile = 0.0f;
+  // Offset added to SDR and HDR before taking gain ratios, written verbatim as
+  // offsetSdr/offsetHdr. Must be in (0, 1); ecosystems assuming 1/128 need
+  // it matched to avoid shadow error.
+  float blackPointOffset = kDefaultBlackPointOffset;
+  // Rewrite a progressive SDR base as baseline (losslessly, coefficients
+  // unchanged) for hardware decoders that only handle sequential JPEGs.
+  bool forceBaselineBase = false;
+  // Standard deviation, in gain map pixels, of a Gaussian blur applied to the
+  // gain map before it is JPEG-encoded. 0 leaves it as computed.
+  float gainMapBlurSigma = 0.0f;
+  // GainMapDownsample for a gain map computed in this library instead of
+  // libultrahdr's; 0 (the default) keeps libultrahdr's.
+  int gainMapDownsample = 0;
+  // OddDimensionPolicy for an SDR base whose width or height is not a whole
+  // number of chroma samples, e.g. odd under 4:2:0, which libultrahdr cannot
+  // encode. Odd 4:4:4 bases are encoded as they are. The default rejects it.
+  int oddDimensionPolicy = 0;
+  // Exif orientation (1-8) to tag the output with, e.g. for an SDR rendered
+  // rotated. The SDR's own Orientation entry is rewritten, or a minimal Exif
+  // segment added when it has none; 0 (the default) keeps its tagging.
+  int orientation = 0;
+  // Luminance weights (JS array of three numbers) for a single-channel gain
+  // map computed in this library, or null to keep libultrahdr's.
+  val lumaCoefficients = val::null();
 };
 
 // JS-side arrays are exchanged via `val` (JS Array) so callers see and pass
@@ -80,6 +154,8 @@ struct GainMapMetadata {
   val offsetHdr = val::array();
   float hdrCapacityMin = 0.0f;
   float hdrCapacityMax = kDefaultTargetHdrCapacity;
+  // ColorGamut the gain applies in, or undefined for the base image's.
+  val applyColorSpace = val::undefined();
 };
 
 struct UltraHdrProbeResult {
@@ -93,6 +169,26 @@ struct UltraHdrProbeResult {
   int gainMapHeight = 0;
   float hdrCapacity = 0.0f;
   std::string metadataVersion;
+  // Primary uses a progressive SOF, which some hardware decoders reject.
+  bool primaryIsProgressive = false;
+  // Malformed segments skipped while scanning a file libultrahdr rejected, a
+  // gain map codestream found without metadata, and an announced gain map
+  // that is not there.
+  val warnings = val::array();
+  int layout = 0;  // UltraHdrLayout.
+};
+
+// Fixed-size summary of an UltraHDR file for media indexes: scalars only.
+struct UltraHdrIndexRecord {
+  int width = 0;
+  int height = 0;
+  int gainMapWidth = 0;
+  int gainMapHeight = 0;
+  float hdrCapacityMin = 0.0f;
+  float hdrCapacityMax = 0.0f;
+  std::string version;
+  int layout = 0;  // UltraHdrLayout.
+  int hdrStrength = 0;
 };
 
 struct UltraHdrDecodeResult {
@@ -103,6 +199,84 @@ struct UltraHdrDecodeResult {
   int height = 0;
   int gainMapWidth = 0;
   int gainMapHeight = 0;
+  bool primaryIsProgressive = false;
+  // Human-readable notes about lenient-recovery paths taken while decoding
+  // (JS string array; empty for a fully conformant file).
+  val warnings = val::array();
+};
+
+// Fraction [0, 1] of gain map samples per channel (RGB) quantized to 0 and
+// 255. Single-channel gain maps report the same value for every channel.
+struct UltraHdrEncodeStats {
+  val clippedLow = val::array();
+  val clippedHigh = val::array();
+};
+
+struct UltraHdrEncodeResult {
+  val data = val::undefined();
+  UltraHdrEncodeStats stats;
+  bool gainMapEmbedded = true;
+  // Dimensions of the encoded primary, after any oddDimensionPolicy crop or pad.
+  int width = 0;
+  int height = 0;
+};
+
+// Decoded 8-bit layers: `sdr` is a Uint8Array of width * height * 4 (RGBA)
+// and `gainMap` one of gainMapWidth * gainMapHeight * gainMapChannels bytes.
+struct UltraHdrPixels {
+  val sdr = val::undefined();
+  int width = 0;
+  int height = 0;
+  val gainMap = val::undefined();
+  int gainMapWidth = 0;
+  int gainMapHeight = 0;
+  int gainMapChannels = 0;
+  // Exif orientation of the primary (1 when untagged), whether or not it was
+  // applied to the pixels.
+  int orientation = 1;
+};
+
+// Linear HDR reconstruction: `data` is a Float32Array of width * height * 3
+// floats (RGB, SDR diffuse white = 1.0).
+struct ReconstructedHdrImage {
+  val data = val::undefined();
+  int width = 0;
+  int height = 0;
+};
+
+// Absolute luminance: `data` is a Float32Array of width * height nits.
+struct HdrLuminanceImage {
+  val data = val::undefined();
+  int width = 0;
+  int height = 0;
+};
+
+struct ContentLightLevel {
+  float maxCll = 0.0f;
+  float maxFall = 0.0f;
+};
+
+// Per-channel (RGB) error of a reconstruction against a reference HDR image;
+// each field is a JS array of three numbers.
+struct ReconstructionErrorMetrics {
+  val rmse = val::array();
+  val maxError = val::array();
+  val psnr = val::array();
+};
+
+// Linear gain ratios decoded from a gain map: `data` is a Float32Array of
+// width * height * channels floats, where channels is 1 or 3.
+struct GainMapValues {
+  val data = val::undefined();
+  int width = 0;
+  int height = 0;
+  int channels = 0;
+};
+
+struct UltraHdrComponents {
+  val primaryJpeg = val::undefined();
+  val gainMapJpeg = val::undefined();
+  std::string metadataJson;
 };
 
 // =============================================================================
@@ -148,6 +322,8 @@ val makeFloat3Array(const float (&arr)[3]) {
   return makeFloat3Array(arr[0], arr[1], arr[2]);
 }
 
+// libultrahdr fills fields omitted from the XMP/ISO packet with the ISO
+// 21496-1 defaults (isoDefaultMetadata in the JS package) before we see them.
 GainMapMetadata fromLibUhdr(const uhdr_gainmap_metadata_t& m) {
   GainMapMetadata out;
   out.version = "1.0";
@@ -166,6 +342,38 @@ GainMapMetadata fromLibUhdr(const uhdr_gainmap_metadata_t& m) {
   return out;
 }
 
+GainMapMetadata fromParams(const open_ultrahdr::GainMapParams& p, bool baseRenditionIsHdr) {
+  GainMapMetadata out;
+  out.version = "1.0";
+  out.baseRenditionIsHdr = baseRenditionIsHdr;
+  out.gainMapMin = makeFloat3Array(p.gainMapMin);
+  out.gainMapMax = makeFloat3Array(p.gainMapMax);
+  out.gamma = makeFloat3Array(p.gamma);
+  out.offsetSdr = makeFloat3Array(p.offsetSdr);
+  out.offsetHdr = makeFloat3Array(p.offsetHdr);
+  out.hdrCapacityMin = p.hdrCapacityMin;
+  out.hdrCapacityMax = p.hdrCapacityMax;
+  return out;
+}
+
+GainMapMetadata fromIso(const open_ultrahdr::IsoGainMapMetadata& iso) {
+  return fromParams(iso.params, iso.baseRenditionIsHdr);
+}
+
+open_ultrahdr::GainMapParams paramsFromLibUhdr(const uhdr_gainmap_metadata_t& m) {
+  open_ultrahdr::GainMapParams p;
+  for (int i = 0; i < 3; ++i) {
+    p.gainMapMin[i] = linearToLog2(m.min_content_boost[i]);
+    p.gainMapMax[i] = linearToLog2(m.max_content_boost[i]);
+    p.gamma[i] = m.gamma[i];
+    p.offsetSdr[i] = m.offset_sdr[i];
+    p.offsetHdr[i] = m.offset_hdr[i];
+  }
+  p.hdrCapacityMin = linearToLog2(m.hdr_capacity_min);
+  p.hdrCapacityMax = linearToLog2(m.hdr_capacity_max);
+  return p;
+}
+
 // =============================================================================
 // F32 (RGB, 3-ch) -> F16 (RGBA, 4-ch) packing
 // =============================================================================
@@ -249,10 +457,195 @@ val u8VectorToVal(const std::vector<uint8_t>& src) {
   return u8;
 }
 
+val stringVectorToVal(const std::vector<std::string>& src) {
+  val out = val::array();
+  for (size_t i = 0; i < src.size(); ++i) out.set(static_cast<unsigned>(i), src[i]);
+  return out;
+}
+
+val f32ArrayToVal(const float* src, size_t length) {
+  val view{typed_memory_view(length, src)};
+  val f32 = val::global("Float32Array").new_(static_cast<unsigned>(length));
+  f32.call<void>("set", view);
+  return f32;
+}
+
 // =============================================================================
 // Library helpers
 // =============================================================================
 
+uhdr_color_gamut_t toUhdrGamut(int gamut) {
+  switch (gamut) {
+    case UHDR_CG_BT_709:
+      return UHDR_CG_BT_709;
+    case UHDR_CG_DISPLAY_P3:
+      return UHDR_CG_DISPLAY_P3;
+    case UHDR_CG_BT_2100:
+      return UHDR_CG_BT_2100;
+    default:
+      throw std::runtime_error("Invalid color gamut: " + std::to_string(gamut));
+  }
+}
+
+open_ultrahdr::GainMapEdgeMode toEdgeMode(int mode) {
+  switch (mode) {
+    case static_cast<int>(open_ultrahdr::GainMapEdgeMode::Clamp):
+      return open_ultrahdr::GainMapEdgeMode::Clamp;
+    case static_cast<int>(open_ultrahdr::GainMapEdgeMode::Mirror):
+      return open_ultrahdr::GainMapEdgeMode::Mirror;
+    default:
+      throw std::runtime_error("Invalid gain map edge mode: " + std::to_string(mode));
+  }
+}
+
+// Ceiling for reconstructed linear values; Infinity leaves them unbounded.
+void checkMaxLinear(float maxLinear) {
+  if (!(maxLinear > 0.0f)) {
+    throw std::runtime_error("Invalid maxLinear: " + std::to_string(maxLinear) +
+                             " (must be positive)");
+  }
+}
+
+open_ultrahdr::GainMapDownsample toGainMapDownsample(int mode) {
+  switch (mode) {
+    case static_cast<int>(open_ultrahdr::GainMapDownsample::CenterSample):
+      return open_ultrahdr::GainMapDownsample::CenterSample;
+    case static_cast<int>(open_ultrahdr::GainMapDownsample::BoxAverage):
+      return open_ultrahdr::GainMapDownsample::BoxAverage;
+    case static_cast<int>(open_ultrahdr::GainMapDownsample::MaxGain):
+      return open_ultrahdr::GainMapDownsample::MaxGain;
+    default:
+      throw std::runtime_error("Invalid gain map downsample mode: " + std::to_string(mode));
+  }
+}
+
+// Reads luminance weights from a JS array of three finite, non-negative
+// numbers with a positive sum. Returns false for null or undefined.
+bool lumaFromVal(const val& v, float (&out)[3]) {
+  if (v.isNull() || v.isUndefined()) return false;
+  if (!v.isArray() || v["length"].as<unsigned>() != 3) {
+    throw std::runtime_error("Luminance coefficients must be an array of 3 numbers");
+  }
+  for (unsigned c = 0; c < 3; ++c) {
+    out[c] = v[c].as<float>();
+    if (!std::isfinite(out[c]) || out[c] < 0.0f) {
+      throw std::runtime_error("Invalid luminance coefficient: " + std::to_string(out[c]));
+    }
+  }
+  if (!(out[0] + out[1] + out[2] > 0.0f)) {
+    throw std::runtime_error("Luminance coefficients must not all be 0");
+  }
+  return true;
+}
+
+// What encoding does with an SDR base whose width or height is not a multiple
+// of its luma sampling factor, so its last chroma sample is partial. The HDR
+// input is trimmed or padded the same way as the SDR, so both stay pixel
+// aligned. A cropped SDR keeps its DCT blocks; a padded one is re-encoded at
+// baseQuality with 4:2:0 chroma.
+enum class OddDimensionPolicy {
+  Reject = 0,
+  CropToEven = 1,  // Drop the partial chroma column and/or row.
+  PadToEven = 2,   // Repeat the last column and/or row up to even dimensions.
+};
+
+OddDimensionPolicy toOddDimensionPolicy(int policy) {
+  switch (policy) {
+    case static_cast<int>(OddDimensionPolicy::Reject):
+      return OddDimensionPolicy::Reject;
+    case static_cast<int>(OddDimensionPolicy::CropToEven):
+      return OddDimensionPolicy::CropToEven;
+    case static_cast<int>(OddDimensionPolicy::PadToEven):
+      return OddDimensionPolicy::PadToEven;
+    default:
+      throw std::runtime_error("Invalid odd dimension policy: " + std::to_string(policy));
+  }
+}
+
+// What assembling from components does with metadata the gain map JPEG
+// carries itself. Assembling always writes the container's metadata into the
+// gain map, so a stale hdrgm packet kept alongside it contradicts the file.
+enum class GainMapXmpMode {
+  Keep = 0,   // Copy the gain map's segments as they are.
+  Sync = 1,   // Drop its hdrgm XMP and ISO 21496-1 segments for the new ones.
+  Strip = 2,  // Also drop any other XMP it carries.
+};
+
+GainMapXmpMode toGainMapXmpMode(int mode) {
+  switch (mode) {
+    case static_cast<int>(GainMapXmpMode::Keep):
+      return GainMapXmpMode::Keep;
+    case static_cast<int>(GainMapXmpMode::Sync):
+      return GainMapXmpMode::Sync;
+    case static_cast<int>(GainMapXmpMode::Strip):
+      return GainMapXmpMode::Strip;
+    default:
+      throw std::runtime_error("Invalid gain map XMP mode: " + std::to_string(mode));
+  }
+}
+
+open_ultrahdr::CompatProfile toCompatProfile(int profile) {
+  switch (profile) {
+    case static_cast<int>(open_ultrahdr::CompatProfile::Android):
+      return open_ultrahdr::CompatProfile::Android;
+    case static_cast<int>(open_ultrahdr::CompatProfile::Adobe):
+      return open_ultrahdr::CompatProfile::Adobe;
+    case static_cast<int>(open_ultrahdr::CompatProfile::Apple):
+      return open_ultrahdr::CompatProfile::Apple;
+    case static_cast<int>(open_ultrahdr::CompatProfile::IsoStrict):
+      return open_ultrahdr::CompatProfile::IsoStrict;
+    default:
+      throw std::runtime_error("Invalid compatibility profile: " + std::to_string(profile));
+  }
+}
+
+// A JPEG whose marker structure parses but whose pixels the decode path
+// (libjpeg, then libultrahdr) cannot decode. Reaches JS as an Error named DecodeBackendUnsupportedError,
+// so callers can tell a valid but unusual file from a corrupt one.
+class DecodeBackendUnsupported : public std::runtime_error {
+ public:
+  using std::runtime_error::runtime_error;
+};
+
+// The coding feature of `sof` the decode path has no support for and what
+// rejects it, or empty if it can decode it. Coding modes and sample precision
+// are libjpeg limits; the component count is libultrahdr's, which takes
+// grayscale or YCbCr only.
+std::string backendLimitation(const open_ultrahdr::JpegDims& sof) {
+  const char* libjpeg = ", which the libjpeg decoder cannot decode";
+  switch (sof.marker) {
+    case 0xC3:
+    case 0xCB:
+      return std::string("lossless coding") + libjpeg;
+    case 0xC5:
+    case 0xC6:
+    case 0xC7:
+    case 0xCD:
+    case 0xCE:
+    case 0xCF:
+      return std::string("hierarchical coding") + libjpeg;
+  }
+  if (sof.precision != 8) return std::to_string(sof.precision) + "-bit samples" + libjpeg;
+  if (sof.components != 1 && sof.components != 3) {
+    return std::to_string(sof.components) +
+           " colour components, which libultrahdr cannot decode (it takes 1 or 3)";
+  }
+  return {};
+}
+
+// Throws DecodeBackendUnsupported if the JPEG `name` parses but uses coding
+// the decode path cannot handle, naming the feature and what rejects it. JPEGs without a
+// readable SOF are reported as structurally invalid instead.
+void checkPixelDecodable(const uint8_t* data, size_t size, const std::string& name) {
+  open_ultrahdr::JpegDims sof = open_ultrahdr::parseJpegDimensions(data, size);
+  if (!sof.ok) throw std::runtime_error(name + " is not a valid JPEG: no readable SOF marker");
+  std::string limitation = backendLimitation(sof);
+  if (!limitation.empty()) {
+    throw DecodeBackendUnsupported(name + " JPEG is structurally valid but uses " + limitation +
+                                   "; use a decoder that supports it");
+  }
+}
+
 void throwOnError(const uhdr_error_info_t& err, const char* prefix) {
   if (err.error_code == UHDR_CODEC_OK) return;
   std::string msg = prefix;
@@ -297,20 +690,400 @@ class EncoderHandle {
   uhdr_codec_private_t* handle_ = nullptr;
 };
 
+uhdr_compressed_image_t compressedImageFor(std::vector<uint8_t>& data) {
+  uhdr_compressed_image_t img{};
+  img.data = data.data();
+  img.data_sz = data.size();
+  img.capacity = data.size();
+  img.cg = UHDR_CG_UNSPECIFIED;
+  img.ct = UHDR_CT_UNSPECIFIED;
+  img.range = UHDR_CR_UNSPECIFIED;
+  return img;
+}
+
+// Copies an 8-bit raw image (RGBA8888, RGB888 or grayscale) into a tightly
+// packed buffer, reporting its channel count.
+std::vector<uint8_t> copyRawImage8(const uhdr_raw_image_t& raw, uint32_t& channels) {
+  switch (raw.fmt) {
+    case UHDR_IMG_FMT_32bppRGBA8888:
+      channels = 4;
+      break;
+    case UHDR_IMG_FMT_24bppRGB888:
+      channels = 3;
+      break;
+    case UHDR_IMG_FMT_8bppYCbCr400:
+      channels = 1;
+      break;
+    default:
+      throw std::runtime_error("Unsupported decoded image format: " +
+                               std::to_string(static_cast<int>(raw.fmt)));
+  }
+  size_t rowBytes = static_cast<size_t>(raw.w) * channels;
+  std::vector<uint8_t> out(open_ultrahdr::Dimensions{raw.w, raw.h}.samples(channels));
+  const uint8_t* src = static_cast<const uint8_t*>(raw.planes[UHDR_PLANE_PACKED]);
+  size_t srcRowBytes = static_cast<size_t>(raw.stride[UHDR_PLANE_PACKED]) * channels;
+  for (unsigned y = 0; y < raw.h; ++y) {
+    std::memcpy(out.data() + y * rowBytes, src + y * srcRowBytes, rowBytes);
+  }
+  return out;
+}
+
+// SDR base (sRGB RGBA8888) and gain map pixels decoded from an UltraHDR JPEG.
+struct DecodedPixels {
+  std::vector<uint8_t> sdr;
+  uint32_t width = 0;
+  uint32_t height = 0;
+  std::vector<uint8_t> gainMap;
+  uint32_t gainMapWidth = 0;
+  uint32_t gainMapHeight = 0;
+  uint32_t gainMapChannels = 0;
+  open_ultrahdr::GainMapParams params;
+  uint16_t orientation = 1;  // Exif orientation of the primary.
+
+  open_ultrahdr::Dimensions dims() const { return {width, height}; }
+  open_ultrahdr::ImageView8 sdrView() const {
+    return {sdr.data(), width, height, width, 4};
+  }
+  open_ultrahdr::ImageView8 gainMapView() const {
+    return {gainMap.data(), gainMapWidth, gainMapHeight, gainMapWidth, gainMapChannels};
+  }
+};
+
+// libultrahdr's XMP reader takes one value per hdrgm property, so a gain map
+// described only by XMP would lose the per-channel values it lists. Copies
+// them into `meta` from the gain map's own XMP. ISO 21496-1 metadata, which
+// libultrahdr prefers and reads per channel, is left as libultrahdr read it.
+void applyXmpChannels(const std::vector<uint8_t>& data, uhdr_gainmap_metadata_t& meta) {
+  open_ultrahdr::ComponentLayout layout;
+  if (!open_ultrahdr::locateComponents(data.data(), data.size(), layout)) return;
+  const uint8_t* gainMap = data.data() + layout.gainMap.offset;
+  open_ultrahdr::JpegSegmentReader reader(gainMap, layout.gainMap.length);
+  for (open_ultrahdr::JpegSegment seg; reader.next(seg);) {
+    if (open_ultrahdr::isIsoGainMapSegment(gainMap, seg)) return;
+  }
+  std::string xmp = open_ultrahdr::findHdrgmXmp(gainMap, layout.gainMap.length);
+  if (xmp.empty()) return;
+
+  float v[3];
+  if (open_ultrahdr::readXmpChannels(xmp, "hdrgm:GainMapMin", v)) {
+    for (int c = 0; c < 3; ++c) meta.min_content_boost[c] = log2ToLinear(v[c]);
+  }
+  if (open_ultrahdr::readXmpChannels(xmp, "hdrgm:GainMapMax", v)) {
+    for (int c = 0; c < 3; ++c) meta.max_content_boost[c] = log2ToLinear(v[c]);
+  }
+  open_ultrahdr::readXmpChannels(xmp, "hdrgm:Gamma", meta.gamma);
+  open_ultrahdr::readXmpChannels(xmp, "hdrgm:OffsetSDR", meta.offset_sdr);
+  open_ultrahdr::readXmpChannels(xmp, "hdrgm:OffsetHDR", meta.offset_hdr);
+}
+
+// A buggy encoder can give the gain map ISO 21496-1 and hdrgm XMP metadata
+// that disagree, e.g. on the gain range. libultrahdr reads the ISO block and
+// ignores the XMP, so the ISO values win; this only reports the conflict.
+void checkMetadataConflict(const std::vector<uint8_t>& data, std::vector<std::string>& warnings) {
+  open_ultrahdr::ComponentLayout layout;
+  if (!open_ultrahdr::locateComponents(data.data(), data.size(), layout)) return;
+  const uint8_t* gainMap = data.data() + layout.gainMap.offset;
+  open_ultrahdr::IsoGainMapMetadata iso;
+  if (!open_ultrahdr::findIsoSegmentMetadata(gainMap, layout.gainMap.length, iso)) return;
+  open_ultrahdr::XmpGainMapMetadata xmp;
+  std::string text = open_ultrahdr::findHdrgmXmp(gainMap, layout.gainMap.length);
+  if (text.empty() || !open_ultrahdr::decodeGainMapXmp(text, xmp)) return;
+
+  // ISO stores rationals and XMP decimal text, so allow for rounding.
+  constexpr float kTolerance = 1e-3f;
+  auto near = [&](float a, float b) { return std::fabs(a - b) <= kTolerance; };
+  const open_ultrahdr::GainMapParams& a = iso.params;
+  const open_ultrahdr::GainMapParams& b = xmp.params;
+  bool same = iso.baseRenditionIsHdr == xmp.baseRenditionIsHdr &&
+              near(a.hdrCapacityMin, b.hdrCapacityMin) && near(a.hdrCapacityMax, b.hdrCapacityMax);
+  for (int c = 0; c < 3 && same; ++c) {
+    same = near(a.gainMapMin[c], b.gainMapMin[c]) && near(a.gainMapMax[c], b.gainMapMax[c]) &&
+           near(a.gamma[c], b.gamma[c]) && near(a.offsetSdr[c], b.offsetSdr[c]) &&
+           near(a.offsetHdr[c], b.offsetHdr[c]);
+  }
+  if (!same) {
+    warnings.push_back("metadata conflict: ISO 21496-1 and hdrgm XMP gain map metadata differ; "
+                       "the ISO 21496-1 values were used");
+  }
+}
+
+// Identifies a matrix/TRC RGB ICC profile's primaries from its rXYZ and gXYZ
+// colorants. Returns false for other profiles and for primaries that are not
+// BT.709, Display P3 or BT.2020 within a small tolerance.
+bool iccProfileGamut(const std::vector<uint8_t>& profile, open_ultrahdr::Gamut& out) {
+  // Red and green colorants (X, Y) of each gamut, chromatically adapted to the
+  // D50 connection space as ICC requires.
+  struct Colorants {
+    open_ultrahdr::Gamut gamut;
+    double red[2];
+    double green[2];
+  };
+  static constexpr Colorants kKnown[] = {
+      {open_ultrahdr::Gamut::Bt709, {0.4361, 0.2225}, {0.3851, 0.7169}},
+      {open_ultrahdr::Gamut::DisplayP3, {0.5151, 0.2412}, {0.2919, 0.6922}},
+      {open_ultrahdr::Gamut::Bt2100, {0.6734, 0.2790}, {0.1656, 0.6753}},
+  };
+  constexpr double kTolerance = 0.01;
+
+  auto u32 = [&](size_t at) {
+    return (static_cast<uint32_t>(profile[at]) << 24) | (profile[at + 1] << 16) |
+           (profile[at + 2] << 8) | profile[at + 3];
+  };
+  // Reads an XYZType tag's X and Y, stored as s15Fixed16Number.
+  auto readXyz = [&](uint32_t signature, double (&xy)[2]) {
+    if (profile.size() < 132) return false;
+    uint32_t count = u32(128);
+    for (uint32_t i = 0; i < count; ++i) {
+      size_t entry = 132 + static_cast<size_t>(i) * 12;
+      if (entry + 12 > profile.size()) return false;
+      if (u32(entry) != signature) continue;
+      size_t offset = u32(entry + 4);
+      if (offset > profile.size() || profile.size() - offset < 20) return false;
+      for (int k = 0; k < 2; ++k) {
+        xy[k] = static_cast<int32_t>(u32(offset + 8 + k * 4)) / 65536.0;
+      }
+      return true;
+    }
+    return false;
+  };
+
+  double red[2], green[2];
+  if (!readXyz(0x7258595A, red) || !readXyz(0x6758595A, green)) return false;  // rXYZ, gXYZ
+  for (const Colorants& known : kKnown) {
+    bool match = true;
+    for (int k = 0; k < 2; ++k) {
+      match &= std::fabs(red[k] - known.red[k]) <= kTolerance;
+      match &= std::fabs(green[k] - known.green[k]) <= kTolerance;
+    }
+    if (match) {
+      out = known.gamut;
+      return true;
+    }
+  }
+  return false;
+}
+
+// ISO 21496-1 metadata with use_base_colour_space cleared applies the gain in
+// the alternate image's colour space, which the gain map's ICC profile
+// describes. Returns false when the gain applies in the base's colour space:
+// the flag is set, there is no ISO metadata, or the profile is not one of the
+// known gamuts.
+bool gainMapApplyGamut(const std::vector<uint8_t>& data, open_ultrahdr::Gamut& out) {
+  open_ultrahdr::ComponentLayout layout;
+  if (!open_ultrahdr::locateComponents(data.data(), data.size(), layout)) return false;
+  const uint8_t* gainMap = data.data() + layout.gainMap.offset;
+  open_ultrahdr::IsoGainMapMetadata iso;
+  if (!open_ultrahdr::findIsoSegmentMetadata(gainMap, layout.gainMap.length, iso)) return false;
+  if (iso.useBaseColorSpace) return false;
+  return iccProfileGamut(
+      open_ultrahdr::extractIccProfile(gainMap, layout.gainMap.length), out);
+}
+
+void setApplyColorSpace(const std::vector<uint8_t>& data, GainMapMetadata& meta) {
+  open_ultrahdr::Gamut gamut;
+  if (gainMapApplyGamut(data, gamut)) meta.applyColorSpace = val(static_cast<int>(gamut));
+}
+
+open_ultrahdr::Gamut toGamut(uhdr_color_gamut_t cg) {
+  switch (cg) {
+    case UHDR_CG_DISPLAY_P3:
+      return open_ultrahdr::Gamut::DisplayP3;
+    case UHDR_CG_BT_2100:
+      return open_ultrahdr::Gamut::Bt2100;
+    default:
+      // Untagged bases are sRGB.
+      return open_ultrahdr::Gamut::Bt709;
+  }
+}
+
+// libultrahdr takes the codestream after the primary as the gain map. Points
+// it at the one locateComponents finds instead, so decoding agrees with
+// locateComponents when the GContainer directory lists other items first.
+void isolateLocatedGainMap(std::vector<uint8_t>& data) {
+  std::vector<uint8_t> isolated = open_ultrahdr::isolateGainMapLayers(data.data(), data.size());
+  if (!isolated.empty()) data.swap(isolated);
+}
+
+// Reconstruction parameters for `meta` as read from `data`, with the gain
+// applied in the gain map's colour space when that differs from `baseGamut`.
+open_ultrahdr::GainMapParams decodeParams(const std::vector<uint8_t>& data,
+                                          const uhdr_gainmap_metadata_t& meta,
+                                          open_ultrahdr::Gamut baseGamut) {
+  uhdr_gainmap_metadata_t channels = meta;
+  applyXmpChannels(data, channels);
+  open_ultrahdr::GainMapParams params = paramsFromLibUhdr(channels);
+  open_ultrahdr::Gamut applyGamut;
+  if (gainMapApplyGamut(data, applyGamut) && applyGamut != baseGamut) {
+    params.applyInBaseSpace = false;
+    open_ultrahdr::gamutConversion(baseGamut, applyGamut, params.toApplySpace);
+    open_ultrahdr::gamutConversion(applyGamut, baseGamut, params.fromApplySpace);
+  }
+  return params;
+}
+
+DecodedPixels decodePixels(std::vector<uint8_t>& data) {
+  if (data.empty()) throw std::runtime_error("Empty buffer");
+  isolateLocatedGainMap(data);
+  checkPixelDecodable(data.data(), data.size(), "Primary");
+  open_ultrahdr::ComponentLayout layout;
+  if (open_ultrahdr::locateComponents(data.data(), data.size(), layout)) {
+    checkPixelDecodable(data.data() + layout.gainMap.offset, layout.gainMap.length, "Gain map");
+  }
+
+  DecoderHandle dec;
+  uhdr_compressed_image_t img = compressedImageFor(data);
+  throwOnError(uhdr_dec_set_image(dec.get(), &img), "uhdr_dec_set_image");
+  throwOnError(uhdr_dec_set_out_img_format(dec.get(), UHDR_IMG_FMT_32bppRGBA8888),
+               "uhdr_dec_set_out_img_format");
+  throwOnError(uhdr_dec_set_out_color_transfer(dec.get(), UHDR_CT_SRGB),
+               "uhdr_dec_set_out_color_transfer");
+  throwOnError(uhdr_decode(dec.get()), "uhdr_decode");
+
+  uhdr_raw_image_t* base = uhdr_get_decoded_image(dec.get());
+  uhdr_raw_image_t* gm = uhdr_get_decoded_gainmap_image(dec.get());
+  uhdr_gainmap_metadata_t* meta = uhdr_dec_get_gainmap_metadata(dec.get());
+  if (!base) throw std::runtime_error("Failed to decode base image");
+  if (!gm) throw std::runtime_error("Failed to decode gain map image");
+  if (!meta) throw std::runtime_error("Failed to get gain map metadata");
+
+  DecodedPixels out;
+  uint32_t sdrChannels = 0;
+  out.sdr = copyRawImage8(*base, sdrChannels);
+  if (sdrChannels != 4) throw std::runtime_error("Unexpected decoded base image format");
+  out.width = base->w;
+  out.height = base->h;
+  out.gainMap = copyRawImage8(*gm, out.gainMapChannels);
+  out.gainMapWidth = gm->w;
+  out.gainMapHeight = gm->h;
+  out.params = decodeParams(data, *meta, toGamut(base->cg));
+  uhdr_mem_block_t* exif = uhdr_dec_get_exif(dec.get());
+  if (exif && exif->data && exif->data_sz > 0) {
+    out.orientation = open_ultrahdr::readExifOrientation(static_cast<const uint8_t*>(exif->data),
+                                                         exif->data_sz);
+  }
+  return out;
+}
+
+std::string dimsToString(int w, int h) {
+  return std::to_string(w) + "x" + std::to_string(h);
+}
+
+// Flags gain map geometry that decoders tolerate but that is not a clean
+// integer downscale of the primary.
+void checkGainMapGeometry(int width, int height, int gmWidth, int gmHeight,
+                          const std::vector<uint8_t>& gmJpeg, std::vector<std::string>& warnings) {
+  if (gmWidth > 0 && gmHeight > 0) {
+    bool even = width % gmWidth == 0 && height % gmHeight == 0 &&
+                width / gmWidth == height / gmHeight;
+    if (gmWidth > width || gmHeight > height) {
+      warnings.push_back("Gain map " + dimsToString(gmWidth, gmHeight) + " is larger than the " +
+                         dimsToString(width, height) +
+                         " primary; it is sampled at each pixel's center");
+    } else if (!even) {
+      warnings.push_back("Gain map " + dimsToString(gmWidth, gmHeight) +
+                         " is not a uniform integer downscale of the " +
+                         dimsToString(width, height) + " primary");
+    }
+  }
+  open_ultrahdr::JpegDims sof = open_ultrahdr::parseJpegDimensions(gmJpeg.data(), gmJpeg.size());
+  if (!sof.ok) {
+    warnings.push_back("Gain map JPEG has no readable SOF marker");
+  } else if (static_cast<int>(sof.width) != gmWidth || static_cast<int>(sof.height) != gmHeight) {
+    warnings.push_back("Gain map JPEG SOF declares " +
+                       dimsToString(static_cast<int>(sof.width), static_cast<int>(sof.height)) +
+                       " but the decoder reports " + dimsToString(gmWidth, gmHeight));
+  }
+}
+
 // =============================================================================
 // Public API
 // =============================================================================
 
+// True when the primary JPEG, ending at `primaryEnd`, announces a gain map
+// through gain map XMP, ISO 21496-1 metadata or an MPF index. An MPF index
+// only counts when its second image could be a gain map: stereo pairs and
+// bursts carry MPF too.
+bool primarySignalsGainMap(const std::vector<uint8_t>& data, size_t primaryEnd) {
+  open_ultrahdr::JpegSegmentReader reader(data.data(), primaryEnd);
+  for (open_ultrahdr::JpegSegment seg; reader.next(seg);) {
+    if (open_ultrahdr::isGainMapXmpSegment(data.data(), seg) ||
+        open_ultrahdr::isIsoGainMapSegment(data.data(), seg)) {
+      return true;
+    }
+    if (open_ultrahdr::isMpfSegment(data.data(), seg) &&
+        open_ultrahdr::mpfListsPossibleGainMap(data.data(), data.size(), seg)) {
+      return true;
+    }
+  }
+  return false;
+}
+
+constexpr char kGainMapMetadataMissing[] =
+    "metadata missing: the gain map carries no hdrgm XMP or ISO 21496-1 metadata";
+
+// True when the primary ending at `primaryEnd` signals a gain map that does
+// follow it, but neither the gain map's segments nor any JUMBF box hold its
+// metadata, so no decoder can apply it.
+bool gainMapMetadataMissing(const std::vector<uint8_t>& data, size_t primaryEnd) {
+  if (primaryEnd == 0 || !primarySignalsGainMap(data, primaryEnd)) return false;
+  open_ultrahdr::ComponentLayout layout;
+  if (!open_ultrahdr::locateComponents(data.data(), data.size(), layout)) return false;
+  const uint8_t* gainMap = data.data() + layout.gainMap.offset;
+  open_ultrahdr::JpegSegmentReader reader(gainMap, layout.gainMap.length);
+  for (open_ultrahdr::JpegSegment seg; reader.next(seg);) {
+    if (open_ultrahdr::isGainMapXmpSegment(gainMap, seg) ||
+        open_ultrahdr::isIsoGainMapSegment(gainMap, seg)) {
+      return false;
+    }
+  }
+  open_ultrahdr::IsoGainMapMetadata iso;
+  return !open_ultrahdr::findJumbfGainMapMetadata(data.data(), data.size(), iso);
+}
+
 bool isUltraHdr(const val& buffer) {
   std::vector<uint8_t> data = u8FromVal(buffer);
   if (data.empty()) return false;
+  // Screen out plain JPEGs and MPF-only multi-image files before libultrahdr.
+  size_t primaryEnd = open_ultrahdr::findJpegEnd(data.data(), data.size());
+  if (primaryEnd == 0 || !primarySignalsGainMap(data, primaryEnd)) return false;
   return is_uhdr_image(data.data(), static_cast<int>(data.size())) == 1;
 }
 
-UltraHdrProbeResult probeUltraHdr(const val& buffer) {
+// How a file packages its gain map. DualImageMpf and DualImageAppended both
+// have the gain map as a second codestream after the primary, located with
+// and without an MPF index respectively. GainMapMissing is a lone primary
+// announcing a gain map that does not follow it: a file truncated after the
+// primary and a single-image legacy writer look the same from the bytes, so
+// SingleImageLegacy is kept for the day one can be told apart and is not
+// reported yet.
+enum class UltraHdrLayout {
+  Unknown = 0,
+  DualImageMpf = 1,
+  DualImageAppended = 2,
+  SingleImageLegacy = 3,
+  GainMapMissing = 4,
+};
+
+constexpr char kGainMapNotFound[] =
+    "The primary announces a gain map but none follows it; the file may be truncated";
+
+UltraHdrLayout probeLayout(const std::vector<uint8_t>& data) {
+  open_ultrahdr::ComponentLayout layout;
+  size_t primaryEnd = open_ultrahdr::findJpegEnd(data.data(), data.size());
+  bool signalled = primaryEnd != 0 && primarySignalsGainMap(data, primaryEnd);
+  if (open_ultrahdr::locateComponents(data.data(), data.size(), layout)) {
+    // Two codestreams alone, such as an MPF stereo pair or burst or two
+    // concatenated JPEGs, are not a gain map.
+    if (!signalled) return UltraHdrLayout::Unknown;
+    return layout.hasMpf ? UltraHdrLayout::DualImageMpf : UltraHdrLayout::DualImageAppended;
+  }
+  return signalled ? UltraHdrLayout::GainMapMissing : UltraHdrLayout::Unknown;
+}
+
+UltraHdrProbeResult probeBytes(std::vector<uint8_t>& data) {
   UltraHdrProbeResult result;
-  std::vector<uint8_t> data = u8FromVal(buffer);
   if (data.empty()) return result;
+  result.layout = static_cast<int>(probeLayout(data));
 
   // First, attempt a libultrahdr probe to see if it's a full UltraHDR image.
   bool fullProbeOk = false;
@@ -336,6 +1109,8 @@ UltraHdrProbeResult probeUltraHdr(const val& buffer) {
         result.height = uhdr_dec_get_image_height(dec.get());
         result.gainMapWidth = uhdr_dec_get_gainmap_width(dec.get());
         result.gainMapHeight = uhdr_dec_get_gainmap_height(dec.get());
+        result.primaryIsProgressive =
+            open_ultrahdr::parseJpegDimensions(data.data(), data.size()).progressive;
         auto* meta = uhdr_dec_get_gainmap_metadata(dec.get());
         if (meta) {
           float maxLin = std::max({meta->max_content_boost[0],
@@ -353,20 +1128,85 @@ UltraHdrProbeResult probeUltraHdr(const val& buffer) {
   if (fullProbeOk) return result;
 
   // Not a full UltraHDR — try to identify a regular JPEG so callers can tell
-  // apart "not even a JPEG" vs "JPEG without gain map".
-  open_ultrahdr::JpegDims dims =
-      open_ultrahdr::parseJpegDimensions(data.data(), data.size());
+  // apart "not even a JPEG" vs "JPEG without gain map". A bad length in some
+  // unrelated segment should not hide the frame header or metadata after it.
+  std::vector<std::string> warnings;
+  std::vector<open_ultrahdr::JpegSegment> segments =
+      open_ultrahdr::parseJpegSegmentsRecover(data.data(), data.size(), warnings);
+  open_ultrahdr::JpegDims dims = open_ultrahdr::dimensionsFromSegments(data.data(), segments);
   if (dims.ok) {
     result.hasPrimaryImage = true;
     result.width = static_cast<int>(dims.width);
     result.height = static_cast<int>(dims.height);
+    result.primaryIsProgressive = dims.progressive;
+  }
+  for (const open_ultrahdr::JpegSegment& seg : segments) {
+    if (open_ultrahdr::isGainMapXmpSegment(data.data(), seg) ||
+        open_ultrahdr::isIsoGainMapSegment(data.data(), seg)) {
+      result.hasMetadata = true;
+    }
   }
+  if (gainMapMetadataMissing(data, open_ultrahdr::findJpegEnd(data.data(), data.size()))) {
+    result.hasGainMap = true;
+    warnings.push_back(kGainMapMetadataMissing);
+  }
+  if (result.layout == static_cast<int>(UltraHdrLayout::GainMapMissing)) {
+    warnings.push_back(kGainMapNotFound);
+  }
+  result.warnings = stringVectorToVal(warnings);
   return result;
 }
 
-UltraHdrDecodeResult decodeUltraHdr(const val& buffer) {
+UltraHdrProbeResult probeUltraHdr(const val& buffer) {
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  return probeBytes(data);
+}
+
+// Fills everything in `out` except sdrImage and gainMap, whose JPEG bytes are
+// returned through `sdr` and `gainMap` so callers choose how to expose them.
+void decodeCompressedLayers(const val& buffer, UltraHdrDecodeResult& out,
+                            std::vector<uint8_t>& sdr, std::vector<uint8_t>& gainMap) {
   std::vector<uint8_t> data = u8FromVal(buffer);
   if (data.empty()) throw std::runtime_error("Empty buffer");
+  std::vector<std::string> warnings;
+
+  // libultrahdr takes the first codestream as the SDR base, so hand it the
+  // layers in that order when the file signals the gain map came first.
+  std::vector<uint8_t> reordered = open_ultrahdr::reorderInvertedLayers(data.data(), data.size());
+  if (!reordered.empty()) {
+    data.swap(reordered);
+    warnings.push_back("gain map is stored before the SDR base; layers were read by role");
+  }
+  isolateLocatedGainMap(data);
+
+  // libultrahdr reports a short file as whichever read ran out, or not at all
+  // when only entropy-coded data is missing, so check for truncation first. A
+  // complete primary whose gain map was cut off, or whose gain map has lost
+  // its metadata, is still a usable SDR image.
+  if (data.size() >= 2 && data[0] == 0xFF && data[1] == 0xD8) {
+    size_t primaryEnd = open_ultrahdr::findJpegEnd(data.data(), data.size());
+    if (primaryEnd == 0) throw std::runtime_error("Invalid JPEG: truncated, no EOI found");
+    open_ultrahdr::ComponentLayout layout;
+    std::string unusable;
+    if (primarySignalsGainMap(data, primaryEnd) &&
+        !open_ultrahdr::locateComponents(data.data(), data.size(), layout)) {
+      unusable = "gain map missing: file is truncated after the primary image";
+    } else if (gainMapMetadataMissing(data, primaryEnd)) {
+      unusable = kGainMapMetadataMissing;
+    }
+    if (!unusable.empty()) {
+      open_ultrahdr::JpegDims dims = open_ultrahdr::parseJpegDimensions(data.data(), primaryEnd);
+      sdr.assign(data.begin(), data.begin() + primaryEnd);
+      gainMap.clear();
+      out.width = static_cast<int>(dims.width);
+      out.height = static_cast<int>(dims.height);
+      out.primaryIsProgressive = dims.progressive;
+      out.metadata = fromParams(open_ultrahdr::GainMapParams{}, false);
+      warnings.push_back(unusable + "; returning the SDR image with neutral metadata");
+      out.warnings = stringVectorToVal(warnings);
+      return;
+    }
+  }
 
   DecoderHandle dec;
   uhdr_compressed_image_t img{};
@@ -379,7 +1219,6 @@ UltraHdrDecodeResult decodeUltraHdr(const val& buffer) {
   throwOnError(uhdr_dec_set_image(dec.get(), &img), "uhdr_dec_set_image");
   throwOnError(uhdr_dec_probe(dec.get()), "uhdr_dec_probe");
 
-  UltraHdrDecodeResult out;
   out.width = uhdr_dec_get_image_width(dec.get());
   out.height = uhdr_dec_get_image_height(dec.get());
   out.gainMapWidth = uhdr_dec_get_gainmap_width(dec.get());
@@ -389,26 +1228,160 @@ UltraHdrDecodeResult decodeUltraHdr(const val& buffer) {
   if (!base || !base->data || base->data_sz == 0) {
     throw std::runtime_error("Failed to get base image");
   }
-  std::vector<uint8_t> baseBytes(static_cast<const uint8_t*>(base->data),
-                                 static_cast<const uint8_t*>(base->data) + base->data_sz);
-  out.sdrImage = u8VectorToVal(baseBytes);
+  sdr.assign(static_cast<const uint8_t*>(base->data),
+             static_cast<const uint8_t*>(base->data) + base->data_sz);
+  out.primaryIsProgressive = open_ultrahdr::parseJpegDimensions(sdr.data(), sdr.size()).progressive;
 
   uhdr_mem_block_t* gm = uhdr_dec_get_gainmap_image(dec.get());
   if (!gm || !gm->data || gm->data_sz == 0) {
     throw std::runtime_error("Failed to get gain map image");
   }
-  std::vector<uint8_t> gmBytes(static_cast<const uint8_t*>(gm->data),
-                               static_cast<const uint8_t*>(gm->data) + gm->data_sz);
-  out.gainMap = u8VectorToVal(gmBytes);
+  gainMap.assign(static_cast<const uint8_t*>(gm->data),
+                 static_cast<const uint8_t*>(gm->data) + gm->data_sz);
 
   uhdr_gainmap_metadata_t* meta = uhdr_dec_get_gainmap_metadata(dec.get());
   if (!meta) {
     throw std::runtime_error("Failed to get gain map metadata");
   }
-  out.metadata = fromLibUhdr(*meta);
+  uhdr_gainmap_metadata_t channels = *meta;
+  applyXmpChannels(data, channels);
+  out.metadata = fromLibUhdr(channels);
+  setApplyColorSpace(data, out.metadata);
+  checkMetadataConflict(data, warnings);
+
+  checkGainMapGeometry(out.width, out.height, out.gainMapWidth, out.gainMapHeight, gainMap,
+                       warnings);
+  out.warnings = stringVectorToVal(warnings);
+}
+
+UltraHdrDecodeResult decodeUltraHdr(const val& buffer) {
+  UltraHdrDecodeResult out;
+  std::vector<uint8_t> sdr, gainMap;
+  decodeCompressedLayers(buffer, out, sdr, gainMap);
+  out.sdrImage = u8VectorToVal(sdr);
+  out.gainMap = u8VectorToVal(gainMap);
+  return out;
+}
+
+// Whether `gainMap`'s SOF agrees with the dimensions a decode result declares
+// for it. False when the JPEG has no readable SOF, e.g. an empty gain map.
+bool gainMapDimensionsMatch(const val& gainMap, int gainMapWidth, int gainMapHeight) {
+  std::vector<uint8_t> data = u8FromVal(gainMap);
+  open_ultrahdr::JpegDims sof = open_ultrahdr::parseJpegDimensions(data.data(), data.size());
+  return sof.ok && static_cast<int>(sof.width) == gainMapWidth &&
+         static_cast<int>(sof.height) == gainMapHeight;
+}
+
+// A decode whose JPEG layers stay in the WASM heap. The views alias that heap
+// with no copy: they dangle once the handle is deleted and are detached by
+// any heap growth, which any later call into the module may cause. copyOut
+// returns an ordinary UltraHdrDecodeResult.
+class UltraHdrDecodeHandle {
+ public:
+  explicit UltraHdrDecodeHandle(const val& buffer) {
+    decodeCompressedLayers(buffer, info_, sdr_, gainMap_);
+  }
+
+  val getSdrView() const { return val(typed_memory_view(sdr_.size(), sdr_.data())); }
+  val getGainMapView() const { return val(typed_memory_view(gainMap_.size(), gainMap_.data())); }
+
+  UltraHdrDecodeResult copyOut() const {
+    UltraHdrDecodeResult out = info_;
+    out.sdrImage = u8VectorToVal(sdr_);
+    out.gainMap = u8VectorToVal(gainMap_);
+    return out;
+  }
+
+ private:
+  UltraHdrDecodeResult info_;
+  std::vector<uint8_t> sdr_;
+  std::vector<uint8_t> gainMap_;
+};
+
+val makeByteRange(const open_ultrahdr::ByteRange& r) {
+  val out = val::object();
+  out.set("offset", static_cast<double>(r.offset));
+  out.set("length", static_cast<double>(r.length));
+  return out;
+}
+
+// Offsets rather than copies, so zero-copy consumers can slice the input.
+// `xmp` and `mpf` are null when the primary has no such segment.
+val locateComponents(const val& buffer) {
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  if (data.empty()) throw std::runtime_error("Empty buffer");
+  open_ultrahdr::ComponentLayout layout;
+  if (!open_ultrahdr::locateComponents(data.data(), data.size(), layout)) {
+    throw std::runtime_error("Could not locate a primary and gain map JPEG");
+  }
+  val out = val::object();
+  out.set("primary", makeByteRange(layout.primary));
+  out.set("gainMap", makeByteRange(layout.gainMap));
+  out.set("xmp", layout.hasXmp ? makeByteRange(layout.xmp) : val::null());
+  out.set("mpf", layout.hasMpf ? makeByteRange(layout.mpf) : val::null());
+  return out;
+}
+
+// Density of the first JFIF APP0 header, or null when there is none.
+val getJfifDensity(const val& buffer) {
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  open_ultrahdr::JfifDensity density;
+  if (!open_ultrahdr::readJfifDensity(data.data(), data.size(), density)) return val::null();
+  val out = val::object();
+  out.set("units", density.units);
+  out.set("xDensity", density.x);
+  out.set("yDensity", density.y);
+  return out;
+}
+
+// Quantization tables of the first JPEG in the buffer, for either 8- or
+// 16-bit DQT precision.
+val getQuantizationTables(const val& buffer) {
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  std::vector<open_ultrahdr::QuantizationTable> tables =
+      open_ultrahdr::readQuantizationTables(data.data(), data.size());
+  val out = val::array();
+  for (size_t i = 0; i < tables.size(); ++i) {
+    val values = val::array();
+    for (unsigned k = 0; k < 64; ++k) values.set(k, tables[i].values[k]);
+    val table = val::object();
+    table.set("id", tables[i].id);
+    table.set("precision", tables[i].precision);
+    table.set("values", values);
+    out.set(static_cast<unsigned>(i), table);
+  }
+  return out;
+}
+
+// Every GContainer item of the file, e.g. an ISO 21496-1 Alternate (fully
+// rendered HDR) image alongside the primary and gain map.
+val listAuxiliaryImages(const val& buffer) {
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  std::vector<open_ultrahdr::AuxiliaryImage> images =
+      open_ultrahdr::listAuxiliaryImages(data.data(), data.size());
+  val out = val::array();
+  for (size_t i = 0; i < images.size(); ++i) {
+    val item = val::object();
+    item.set("semantic", images[i].semantic);
+    item.set("mime", images[i].mime);
+    item.set("offset", static_cast<double>(images[i].range.offset));
+    item.set("length", static_cast<double>(images[i].range.length));
+    out.set(static_cast<unsigned>(i), item);
+  }
   return out;
 }
 
+val extractAuxiliaryImage(const val& buffer, const std::string& semantic) {
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  for (const open_ultrahdr::AuxiliaryImage& image :
+       open_ultrahdr::listAuxiliaryImages(data.data(), data.size())) {
+    if (image.semantic != semantic) continue;
+    const uint8_t* begin = data.data() + image.range.offset;
+    return u8VectorToVal(std::vector<uint8_t>(begin, begin + image.range.length));
+  }
+  throw std::runtime_error("No GContainer item with semantic \"" + semantic + "\"");
+}
+
 val extractSdrBase(const val& buffer) {
   std::vector<uint8_t> data = u8FromVal(buffer);
   if (data.empty()) throw std::runtime_error("Empty buffer");
@@ -433,9 +1406,9 @@ val extractSdrBase(const val& buffer) {
   return u8VectorToVal(baseBytes);
 }
 
-GainMapMetadata getMetadata(const val& buffer) {
-  std::vector<uint8_t> data = u8FromVal(buffer);
+GainMapMetadata readMetadata(std::vector<uint8_t>& data) {
   if (data.empty()) throw std::runtime_error("Empty buffer");
+  isolateLocatedGainMap(data);
 
   DecoderHandle dec;
   uhdr_compressed_image_t img{};
@@ -446,34 +1419,354 @@ GainMapMetadata getMetadata(const val& buffer) {
   img.ct = UHDR_CT_UNSPECIFIED;
   img.range = UHDR_CR_UNSPECIFIED;
   throwOnError(uhdr_dec_set_image(dec.get(), &img), "uhdr_dec_set_image");
-  throwOnError(uhdr_dec_probe(dec.get()), "uhdr_dec_probe");
+  uhdr_error_info_t probed = uhdr_dec_probe(dec.get());
+  if (probed.error_code != UHDR_CODEC_OK) {
+    // libultrahdr reads XMP and ISO APP2 metadata only; some ISO 21496-1
+    // writers carry it in JUMBF boxes instead.
+    open_ultrahdr::IsoGainMapMetadata iso;
+    if (open_ultrahdr::findJumbfGainMapMetadata(data.data(), data.size(), iso)) {
+      return fromIso(iso);
+    }
+    throwOnError(probed, "uhdr_dec_probe");
+  }
 
   uhdr_gainmap_metadata_t* meta = uhdr_dec_get_gainmap_metadata(dec.get());
   if (!meta) throw std::runtime_error("Failed to get gain map metadata");
-  return fromLibUhdr(*meta);
+  uhdr_gainmap_metadata_t channels = *meta;
+  applyXmpChannels(data, channels);
+  GainMapMetadata out = fromLibUhdr(channels);
+  setApplyColorSpace(data, out);
+  return out;
 }
 
-val encodeUltraHdr(const val& sdrBuffer, const val& hdrBuffer,
-                   const UltraHdrEncodeOptions& options) {
-  std::vector<uint8_t> sdr = u8FromVal(sdrBuffer);
-  if (sdr.empty()) throw std::runtime_error("Empty SDR buffer");
+GainMapMetadata getMetadata(const val& buffer) {
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  return readMetadata(data);
+}
+
+// Joins an SDR JPEG and a gain map JPEG into an UltraHDR file described by
+// `meta`.
+std::vector<uint8_t> assembleUltraHdr(std::vector<uint8_t>& sdr, std::vector<uint8_t>& gainMapJpeg,
+                                      const uhdr_gainmap_metadata_t& meta) {
+  uhdr_gainmap_metadata_t m = meta;
+  EncoderHandle enc;
+  uhdr_compressed_image_t baseImg = compressedImageFor(sdr);
+  uhdr_compressed_image_t gainMapImg = compressedImageFor(gainMapJpeg);
+  throwOnError(uhdr_enc_set_compressed_image(enc.get(), &baseImg, UHDR_BASE_IMG),
+               "uhdr_enc_set_compressed_image");
+  throwOnError(uhdr_enc_set_gainmap_image(enc.get(), &gainMapImg, &m),
+               "uhdr_enc_set_gainmap_image");
+  throwOnError(uhdr_encode(enc.get()), "uhdr_encode");
+  uhdr_compressed_image_t* out = uhdr_get_encoded_stream(enc.get());
+  if (!out || !out->data || out->data_sz == 0) {
+    throw std::runtime_error("uhdr_encode produced no output");
+  }
+  return std::vector<uint8_t>(static_cast<const uint8_t*>(out->data),
+                              static_cast<const uint8_t*>(out->data) + out->data_sz);
+}
+
+// libultrahdr metadata describing `p`; log2 gains and capacities become
+// linear boosts.
+uhdr_gainmap_metadata_t libUhdrFromParams(const open_ultrahdr::GainMapParams& p) {
+  uhdr_gainmap_metadata_t out{};
+  for (int c = 0; c < 3; ++c) {
+    out.min_content_boost[c] = log2ToLinear(p.gainMapMin[c]);
+    out.max_content_boost[c] = log2ToLinear(p.gainMapMax[c]);
+    out.gamma[c] = p.gamma[c];
+    out.offset_sdr[c] = p.offsetSdr[c];
+    out.offset_hdr[c] = p.offsetHdr[c];
+  }
+  out.hdr_capacity_min = log2ToLinear(p.hdrCapacityMin);
+  out.hdr_capacity_max = log2ToLinear(p.hdrCapacityMax);
+  return out;
+}
+
+// Parameters of a gain map built by encodeWithComputedGainMap: linear gamma,
+// gains from 1 up to `maxBoostLinear`, and `offset` on both sides. The same
+// struct drives the gain computation and, through libUhdrFromParams, the
+// metadata written, so the offsets decoders read are the ones encoded with.
+open_ultrahdr::GainMapParams computedGainMapParams(float offset, float maxBoostLinear) {
+  open_ultrahdr::GainMapParams p;
+  float maxBoostLog2 = linearToLog2(maxBoostLinear);
+  for (int c = 0; c < 3; ++c) {
+    p.gainMapMin[c] = 0.0f;
+    p.gainMapMax[c] = maxBoostLog2;
+    p.gamma[c] = 1.0f;
+    p.offsetSdr[c] = offset;
+    p.offsetHdr[c] = offset;
+  }
+  p.hdrCapacityMin = 0.0f;
+  p.hdrCapacityMax = maxBoostLog2;
+  return p;
+}
+
+// Supplies `rows` rows of linear RGB HDR starting at row `y`: rows * width * 3
+// floats, valid until the next call.
+using HdrStripReader = std::function<const float*(uint32_t y, uint32_t rows)>;
+
+// Packages `sdr` with a gain map computed in this library against `offset` on
+// both sides and reduced per block by `downsample`, over the SDR pixels `px`
+// exactly as decoders will see them, with metadata that records the offset so
+// decoding inverts the same math. The HDR is read `stripRows` rows at a time.
+// Always writes a linear (gamma 1) gain map, RGB unless `luma` gives weights
+// for a single-channel one; the HDR must share the SDR's primaries. A
+// positive `blurSigma` smooths the gain map with blurGainMap before it is
+// encoded.
+std::vector<uint8_t> encodeWithComputedGainMap(std::vector<uint8_t>& sdr, const DecodedPixels& px,
+                                               const HdrStripReader& readHdr, uint32_t stripRows,
+                                               float offset,
+                                               open_ultrahdr::GainMapDownsample downsample,
+                                               float maxBoostLinear, int scale, int quality,
+                                               const float* luma, float blurSigma) {
+  open_ultrahdr::GainMapParams p = computedGainMapParams(offset, maxBoostLinear);
+  uint32_t s = static_cast<uint32_t>(scale);
+  uint32_t gmWidth = (px.width + s - 1) / s;
+  uint32_t gmHeight = (px.height + s - 1) / s;
+  open_ultrahdr::GainMapStripBuilder builder(px.width, px.height, p, s, downsample, luma);
+  for (uint32_t y = 0; y < px.height; y += stripRows) {
+    uint32_t rows = std::min(stripRows, px.height - y);
+    const uint8_t* strip = px.sdr.data() + static_cast<size_t>(y) * px.width * 4;
+    builder.addRows({strip, px.width, rows, px.width, 4}, readHdr(y, rows));
+  }
+  std::vector<uint8_t> gainMap = builder.finish();
+  if (blurSigma > 0.0f) {
+    gainMap = open_ultrahdr::blurGainMap(
+        {gainMap.data(), gmWidth, gmHeight, gmWidth, builder.channels()}, blurSigma);
+  }
+  std::vector<uint8_t> gainMapJpeg = open_ultrahdr::encodeJpeg8(
+      {gainMap.data(), gmWidth, gmHeight, gmWidth, builder.channels()}, quality, {});
+
+  return assembleUltraHdr(sdr, gainMapJpeg, libUhdrFromParams(p));
+}
+
+// The gain map JPEG of `data` decoded on its own, without the primary, with
+// as many channels as it was written with.
+open_ultrahdr::Image8 decodeGainMapOnly(const std::vector<uint8_t>& data) {
+  open_ultrahdr::ComponentLayout layout;
+  if (!open_ultrahdr::locateComponents(data.data(), data.size(), layout)) {
+    throw std::runtime_error("No gain map found");
+  }
+  return open_ultrahdr::decodeJpeg8(data.data() + layout.gainMap.offset, layout.gainMap.length,
+                                    /*keepGrayscale=*/true);
+}
+
+// Re-packages `encoded` with its gain map smoothed by blurGainMap, keeping
+// the metadata libultrahdr wrote. libultrahdr encodes the gain map itself, so
+// this decodes just that codestream and re-encodes it at `quality`; gain maps
+// computed here are blurred before their only encode instead.
+std::vector<uint8_t> encodeWithBlurredGainMap(std::vector<uint8_t>& sdr,
+                                              std::vector<uint8_t>& encoded, float sigma,
+                                              int quality) {
+  uhdr_gainmap_metadata_t meta{};
+  {
+    DecoderHandle dec;
+    uhdr_compressed_image_t img = compressedImageFor(encoded);
+    throwOnError(uhdr_dec_set_image(dec.get(), &img), "uhdr_dec_set_image");
+    throwOnError(uhdr_dec_probe(dec.get()), "uhdr_dec_probe");
+    uhdr_gainmap_metadata_t* m = uhdr_dec_get_gainmap_metadata(dec.get());
+    if (!m) throw std::runtime_error("Failed to get gain map metadata");
+    meta = *m;
+  }
+  open_ultrahdr::Image8 gainMap = decodeGainMapOnly(encoded);
+  std::vector<uint8_t> blurred = open_ultrahdr::blurGainMap(gainMap.view(), sigma);
+  std::vector<uint8_t> gainMapJpeg = open_ultrahdr::encodeJpeg8(
+      {blurred.data(), gainMap.width, gainMap.height, gainMap.width, gainMap.channels}, quality,
+      {});
+  return assembleUltraHdr(sdr, gainMapJpeg, meta);
+}
+
+// Gain maps computed in this library read the HDR as BT.709; `what` names the
+// option or path that needs one.
+void requireBt709Hdr(const UltraHdrEncodeOptions& options, const char* what) {
+  if (options.hdrGamut != UHDR_CG_BT_709) {
+    throw std::runtime_error(std::string(what) + " requires BT.709 (sRGB) HDR input");
+  }
+}
+
+void checkGainRangeOptions(const UltraHdrEncodeOptions& options) {
+  if (!(options.targetHdrCapacity > 0.0f) || options.targetHdrCapacity > kMaxHdrCapacity) {
+    throw std::runtime_error("Invalid HDR capacity: " + std::to_string(options.targetHdrCapacity) +
+                             " (must be in (0, " + std::to_string(kMaxHdrCapacity) + "] stops)");
+  }
+  if (!(options.blackPointOffset > 0.0f) || !(options.blackPointOffset < 1.0f)) {
+    throw std::runtime_error("Invalid black point offset: " +
+                             std::to_string(options.blackPointOffset) + " (must be in (0, 1))");
+  }
+  if (!(options.metadataMaxPercentile >= 0.0f) || options.metadataMaxPercentile > 100.0f) {
+    throw std::runtime_error("Invalid metadata max percentile: " +
+                             std::to_string(options.metadataMaxPercentile) +
+                             " (must be in [0, 100])");
+  }
+}
+
+// Tags `sdr` with options.orientation; 0 leaves it as it is.
+void applyOrientationOption(std::vector<uint8_t>& sdr, const UltraHdrEncodeOptions& options) {
+  if (options.orientation < 0 || options.orientation > 8) {
+    throw std::runtime_error("Invalid orientation: " + std::to_string(options.orientation) +
+                             " (must be 1-8, or 0 to keep the SDR's)");
+  }
+  if (options.orientation == 0) return;
+  sdr = open_ultrahdr::setJpegOrientation(sdr, static_cast<uint16_t>(options.orientation));
+  if (sdr.empty()) throw std::runtime_error("SDR Exif has no Orientation entry to rewrite");
+}
+
+// Gain map scaling factor. libultrahdr accepts (0, 128]. A scale beyond the
+// smaller dimension would collapse that axis to one texel while the other
+// keeps several, so decoders, which derive each axis's scale from the two
+// sizes, would see a non-uniform downscale; cap it there.
+int effectiveGainMapScale(const UltraHdrEncodeOptions& options,
+                          const open_ultrahdr::JpegDims& dims) {
+  int gmScale = std::clamp(options.gainMapScale, 1, 128);
+  return std::min<int>(gmScale, std::min(dims.width, dims.height));
+}
+
+// Maps targetHdrCapacity (log2 stops, capped by absoluteMaxGain) to the linear
+// max content boost, 2^stops. The min boost is always 1 (no darkening below
+// SDR).
+float maxContentBoost(const UltraHdrEncodeOptions& options) {
+  float maxBoostLog2 = std::min(options.targetHdrCapacity, options.absoluteMaxGain);
+  float maxBoostLinear = log2ToLinear(std::max(maxBoostLog2, 0.0f));
+  if (!std::isfinite(maxBoostLinear) || maxBoostLinear < 1.0f) maxBoostLinear = 1.0f;
+  return maxBoostLinear;
+}
+
+// `side` cropped down, or padded up, to a multiple of `factor`.
+uint32_t alignedSide(uint32_t side, uint32_t factor, bool pad) {
+  uint32_t rem = side % factor;
+  return rem == 0 ? side : pad ? side + factor - rem : side - rem;
+}
+
+// The Exif, non-gain-map XMP and ICC segments of `sdr`, in file order, for a
+// re-encode to carry over. The Exif keeps the Orientation that
+// applyOrientationOption preserves by default.
+std::vector<open_ultrahdr::JpegMarker> sdrMetadataMarkers(const std::vector<uint8_t>& sdr) {
+  std::vector<open_ultrahdr::JpegMarker> out;
+  open_ultrahdr::JpegSegmentReader reader(sdr.data(), sdr.size());
+  for (open_ultrahdr::JpegSegment seg; reader.next(seg);) {
+    bool keep = open_ultrahdr::isExifSegment(sdr.data(), seg) ||
+                open_ultrahdr::isIccSegment(sdr.data(), seg) ||
+                (open_ultrahdr::isXmpSegment(sdr.data(), seg) &&
+                 !open_ultrahdr::isGainMapXmpSegment(sdr.data(), seg));
+    if (!keep) continue;
+    open_ultrahdr::JpegMarker m;
+    m.marker = seg.marker;
+    m.payload.assign(sdr.begin() + seg.payloadOffset,
+                     sdr.begin() + seg.payloadOffset + seg.payloadLength);
+    out.push_back(std::move(m));
+  }
+  return out;
+}
+
+// Crops or edge-pads a packed width x height image of `channels` samples per
+// pixel to w x h, keeping its top-left corner.
+template <typename T>
+std::vector<T> cropOrPad(const std::vector<T>& src, uint32_t width, uint32_t height,
+                         uint32_t channels, uint32_t w, uint32_t h) {
+  std::vector<T> out(static_cast<size_t>(w) * h * channels);
+  for (uint32_t y = 0; y < h; ++y) {
+    uint32_t sy = std::min(y, height - 1);
+    for (uint32_t x = 0; x < w; ++x) {
+      uint32_t sx = std::min(x, width - 1);
+      const T* in = src.data() + (static_cast<size_t>(sy) * width + sx) * channels;
+      std::copy(in, in + channels, out.data() + (static_cast<size_t>(y) * w + x) * channels);
+    }
+  }
+  return out;
+}
+
+// libultrahdr always writes an SDR-base layout: the SDR primary first, flagged
+// as the MPF primary image, followed by a gain map that derives HDR from it.
+// There is no HDR-base (baseRenditionIsHdr) writer; toLibUhdr rejects such
+// metadata rather than writing it with SDR-base flags. Its
+// XMP is a fixed, single-valued hdrgm packet of well under 1 KB, so it always
+// fits one APP1 segment; there is no Extended XMP path to take.
+//
+// `gainMapEmbedded`, when given, reports whether the output carries a gain map;
+// it is false only when omitTrivialGainMap dropped it.
+std::vector<uint8_t> encodeToBytes(std::vector<uint8_t> sdr, std::vector<float> hdr,
+                                   const UltraHdrEncodeOptions& options,
+                                   bool* gainMapEmbedded = nullptr) {
+  if (gainMapEmbedded) *gainMapEmbedded = true;
+  if (sdr.empty()) throw std::runtime_error("Empty SDR buffer");
+
+  // An UltraHDR base would end up with a nested gain map inside the primary
+  // plus the newly appended one, which confuses every decoder.
+  if (is_uhdr_image(sdr.data(), static_cast<int>(sdr.size())) == 1) {
+    if (!options.flattenExisting) {
+      throw std::runtime_error("SDR base already contains a gain map");
+    }
+    sdr = open_ultrahdr::stripGainMapSignalling(sdr.data(), sdr.size());
+    if (sdr.empty()) throw std::runtime_error("Failed to extract clean SDR from UltraHDR base");
+  }
 
   // Validate JPEG magic + extract dimensions for HDR sanity-check.
   open_ultrahdr::JpegDims dims =
       open_ultrahdr::parseJpegDimensions(sdr.data(), sdr.size());
   if (!dims.ok) throw std::runtime_error("SDR buffer is not a valid JPEG");
+  // The libjpeg build decodes 8-bit samples only. High bit-depth sources
+  // belong in the HDR input, which is taken as floats and never truncated.
+  if (dims.precision != 8) {
+    throw std::runtime_error("SDR JPEG has " + std::to_string(dims.precision) +
+                             "-bit samples; only 8-bit JPEGs can be the SDR base. Pass high "
+                             "bit-depth content as linear float HDR data instead");
+  }
+  checkPixelDecodable(sdr.data(), sdr.size(), "SDR");
+  if (options.forceBaselineBase && dims.progressive) {
+    sdr = open_ultrahdr::transcodeJpegBaseline(sdr.data(), sdr.size());
+  }
 
-  std::vector<float> hdr = f32FromVal(hdrBuffer);
-  size_t expectedFloats = static_cast<size_t>(dims.width) *
-                          static_cast<size_t>(dims.height) * 3u;
+  size_t expectedFloats = open_ultrahdr::Dimensions{dims.width, dims.height}.rgbLength();
   if (hdr.size() != expectedFloats) {
     throw std::runtime_error("HDR buffer size does not match SDR dimensions: expected " +
                              std::to_string(expectedFloats) + " floats, got " +
                              std::to_string(hdr.size()));
   }
 
-  std::vector<uint16_t> hdrHalf =
-      packF32RgbToF16Rgba(hdr.data(), static_cast<size_t>(dims.width) * dims.height);
+  // Only chroma subsampling needs whole chroma samples; 4:4:4 may be odd.
+  uint32_t hSampling = std::max<uint32_t>(dims.hSampling, 1);
+  uint32_t vSampling = std::max<uint32_t>(dims.vSampling, 1);
+  if (dims.width % hSampling != 0 || dims.height % vSampling != 0) {
+    OddDimensionPolicy policy = toOddDimensionPolicy(options.oddDimensionPolicy);
+    if (policy == OddDimensionPolicy::Reject) {
+      throw std::runtime_error("SDR image has odd dimensions " +
+                               dimsToString(dims.width, dims.height) + " for its " +
+                               dimsToString(hSampling, vSampling) +
+                               " chroma subsampling; set oddDimensionPolicy to crop or pad it");
+    }
+    bool pad = policy == OddDimensionPolicy::PadToEven;
+    // The padded SDR is re-encoded as 4:2:0, which needs both sides even.
+    uint32_t width = pad ? alignedSide(dims.width, 2, true)
+                         : alignedSide(dims.width, hSampling, false);
+    uint32_t height = pad ? alignedSide(dims.height, 2, true)
+                          : alignedSide(dims.height, vSampling, false);
+    if (width == 0 || height == 0) {
+      throw std::runtime_error("SDR image " + dimsToString(dims.width, dims.height) +
+                               " is too small to crop to whole chroma samples");
+    }
+    hdr = cropOrPad(hdr, dims.width, dims.height, 3, width, height);
+    if (!pad) {
+      // Dropping the last column or row keeps the top-left corner in place,
+      // so the DCT blocks are kept as they are and nothing is re-quantized.
+      sdr = open_ultrahdr::cropJpegLossless(sdr.data(), sdr.size(), width, height);
+    } else {
+      // The crop keeps every marker of its own accord; a re-encode only has
+      // those it is given.
+      std::vector<open_ultrahdr::JpegMarker> markers = sdrMetadataMarkers(sdr);
+      open_ultrahdr::Image8 rgb = open_ultrahdr::decodeJpeg8(sdr.data(), sdr.size());
+      std::vector<uint8_t> even = cropOrPad(rgb.data, rgb.width, rgb.height, 3, width, height);
+      open_ultrahdr::JfifDensity density;
+      bool hasDensity = open_ultrahdr::readJfifDensity(sdr.data(), sdr.size(), density);
+      sdr = open_ultrahdr::encodeJpeg8({even.data(), width, height, width, 3},
+                                       std::clamp(options.baseQuality, 1, 100), markers,
+                                       hasDensity ? &density : nullptr);
+    }
+    dims.width = width;
+    dims.height = height;
+  }
+  applyOrientationOption(sdr, options);
+
+  std::vector<uint16_t> hdrHalf = packF32RgbToF16Rgba(
+      hdr.data(), open_ultrahdr::Dimensions{dims.width, dims.height}.pixelCount());
 
   EncoderHandle enc;
 
@@ -489,7 +1782,7 @@ val encodeUltraHdr(const val& sdrBuffer, const val& hdrBuffer,
 
   uhdr_raw_image_t hdrRaw{};
   hdrRaw.fmt = UHDR_IMG_FMT_64bppRGBAHalfFloat;
-  hdrRaw.cg = UHDR_CG_BT_2100;
+  hdrRaw.cg = toUhdrGamut(options.hdrGamut);
   hdrRaw.ct = UHDR_CT_LINEAR;
   hdrRaw.range = UHDR_CR_FULL_RANGE;
   hdrRaw.w = dims.width;
@@ -503,6 +1796,28 @@ val encodeUltraHdr(const val& sdrBuffer, const val& hdrBuffer,
   throwOnError(uhdr_enc_set_raw_image(enc.get(), &hdrRaw, UHDR_HDR_IMG),
                "uhdr_enc_set_raw_image");
 
+  checkGainRangeOptions(options);
+  if (!(options.gainMapBlurSigma >= 0.0f) || !std::isfinite(options.gainMapBlurSigma)) {
+    throw std::runtime_error("Invalid gain map blur sigma: " +
+                             std::to_string(options.gainMapBlurSigma) + " (must be >= 0)");
+  }
+  bool customBlackPoint = options.blackPointOffset != kDefaultBlackPointOffset;
+  bool customDownsample = options.gainMapDownsample != 0;
+  open_ultrahdr::GainMapDownsample downsample =
+      customDownsample ? toGainMapDownsample(options.gainMapDownsample)
+                       : open_ultrahdr::GainMapDownsample::BoxAverage;
+  float luma[3];
+  bool customLuma = lumaFromVal(options.lumaCoefficients, luma);
+  bool measuredRange = options.metadataMaxPercentile > 0.0f;
+  // Each of these options has this library compute the gain map.
+  bool computedPath = customBlackPoint || customDownsample || customLuma || measuredRange;
+  if (computedPath) {
+    requireBt709Hdr(options, customBlackPoint   ? "A custom blackPointOffset"
+                             : customDownsample ? "A gainMapDownsample mode"
+                             : customLuma       ? "Custom lumaCoefficients"
+                                                : "A metadataMaxPercentile");
+  }
+
   // Quality: clamp to [0, 100].
   int baseQ = std::clamp(options.baseQuality, 0, 100);
   int gmQ = std::clamp(options.gainMapQuality, 0, 100);
@@ -511,19 +1826,601 @@ val encodeUltraHdr(const val& sdrBuffer, const val& hdrBuffer,
   throwOnError(uhdr_enc_set_quality(enc.get(), gmQ, UHDR_GAIN_MAP_IMG),
                "uhdr_enc_set_quality(gainmap)");
 
-  // Gain map scaling factor. libultrahdr accepts (0, 128].
-  int gmScale = std::clamp(options.gainMapScale, 1, 128);
+  int gmScale = effectiveGainMapScale(options, dims);
   throwOnError(uhdr_enc_set_gainmap_scale_factor(enc.get(), gmScale),
                "uhdr_enc_set_gainmap_scale_factor");
 
-  // Map targetHdrCapacity (log2 stops) → linear max content boost.
-  // min boost = 1.0 (no darkening below SDR), max = 2^stops, with sensible bounds.
-  float maxBoostLinear = log2ToLinear(std::max(options.targetHdrCapacity, 0.0f));
-  if (!std::isfinite(maxBoostLinear) || maxBoostLinear < 1.0f) maxBoostLinear = 1.0f;
+  // Setting the range here stops libultrahdr from measuring it from the
+  // content, so a near-black SDR pixel under a bright HDR one cannot inflate
+  // gainMapMax; that pixel's ratio simply clips at the top of the range.
+  float maxBoostLinear = maxContentBoost(options);
   throwOnError(uhdr_enc_set_min_max_content_boost(enc.get(), 1.0f, maxBoostLinear),
                "uhdr_enc_set_min_max_content_boost");
 
   throwOnError(uhdr_encode(enc.get()), "uhdr_encode");
+  uhdr_compressed_image_t* out = uhdr_get_encoded_stream(enc.get());
+  if (!out || !out->data || out->data_sz == 0) {
+    throw std::runtime_error("uhdr_encode produced no output");
+  }
+  std::vector<uint8_t> encoded(static_cast<const uint8_t*>(out->data),
+                               static_cast<const uint8_t*>(out->data) + out->data_sz);
+  if (computedPath) {
+    DecodedPixels px = decodePixels(encoded);
+    auto readHdr = [&](uint32_t y, uint32_t) {
+      return hdr.data() + static_cast<size_t>(y) * px.width * 3;
+    };
+    if (measuredRange) {
+      float measured = open_ultrahdr::gainPercentileLog2(
+          px.sdrView(), hdr.data(), options.blackPointOffset, linearToLog2(maxBoostLinear),
+          options.metadataMaxPercentile, customLuma ? luma : nullptr);
+      maxBoostLinear = log2ToLinear(measured);
+    }
+    encoded = encodeWithComputedGainMap(sdr, px, readHdr, px.height, options.blackPointOffset,
+                                        downsample, maxBoostLinear, gmScale, gmQ,
+                                        customLuma ? luma : nullptr, options.gainMapBlurSigma);
+  } else if (options.gainMapBlurSigma > 0.0f) {
+    // Noisy inputs give a speckled gain map that reappears as luminance noise
+    // after reconstruction; gain maps are low-frequency, so mild blurring is
+    // invisible and also compresses better.
+    encoded = encodeWithBlurredGainMap(sdr, encoded, options.gainMapBlurSigma, gmQ);
+  }
+
+  // The encoded range follows targetHdrCapacity, not the content, so judge
+  // the gain map by the headroom its samples actually apply.
+  if (options.omitTrivialGainMap) {
+    DecodedPixels px = decodePixels(encoded);
+    if (open_ultrahdr::peakGainLog2(px.gainMapView(), px.params) < kMeaningfulHdrThreshold) {
+      if (gainMapEmbedded) *gainMapEmbedded = false;
+      return sdr;
+    }
+  }
+  return encoded;
+}
+
+val encodeUltraHdr(const val& sdrBuffer, const val& hdrBuffer,
+                   const UltraHdrEncodeOptions& options) {
+  return u8VectorToVal(encodeToBytes(u8FromVal(sdrBuffer), f32FromVal(hdrBuffer), options));
+}
+
+// Low-memory counterpart of encodeUltraHdr for large images. HDR rows are
+// pulled from `readHdrRows(y, rows)` a strip at a time and folded straight
+// into the gain map, so neither the float HDR image nor the half-float copy
+// libultrahdr's encoder takes is ever held whole. The gain map is always
+// computed in this library, as encodeUltraHdr does for a custom
+// blackPointOffset or gainMapDownsample (BoxAverage when none is set), so it
+// needs BT.709 HDR and its output is byte-identical to encodeUltraHdr's with
+// such options. The SDR is used as given: flattenExisting, forceBaselineBase,
+// oddDimensionPolicy, gainMapBlurSigma and omitTrivialGainMap do not apply,
+// and nor does metadataMaxPercentile, which would need the HDR twice.
+val encodeUltraHdrStrips(const val& sdrBuffer, const val& readHdrRows, int stripRows,
+                         const UltraHdrEncodeOptions& options) {
+  std::vector<uint8_t> sdr = u8FromVal(sdrBuffer);
+  if (sdr.empty()) throw std::runtime_error("Empty SDR buffer");
+  if (stripRows < 1) throw std::runtime_error("Strip rows must be positive");
+  if (is_uhdr_image(sdr.data(), static_cast<int>(sdr.size())) == 1) {
+    throw std::runtime_error("SDR base already contains a gain map");
+  }
+  open_ultrahdr::JpegDims dims = open_ultrahdr::parseJpegDimensions(sdr.data(), sdr.size());
+  if (!dims.ok) throw std::runtime_error("SDR buffer is not a valid JPEG");
+  if (dims.precision != 8) {
+    throw std::runtime_error("SDR JPEG has " + std::to_string(dims.precision) +
+                             "-bit samples; only 8-bit JPEGs can be the SDR base");
+  }
+  checkPixelDecodable(sdr.data(), sdr.size(), "SDR");
+  checkGainRangeOptions(options);
+  applyOrientationOption(sdr, options);
+  requireBt709Hdr(options, "Strip encoding");
+  float luma[3];
+  bool customLuma = lumaFromVal(options.lumaCoefficients, luma);
+  open_ultrahdr::GainMapDownsample downsample =
+      options.gainMapDownsample != 0 ? toGainMapDownsample(options.gainMapDownsample)
+                                     : open_ultrahdr::GainMapDownsample::BoxAverage;
+  int gmScale = effectiveGainMapScale(options, dims);
+  int gmQ = std::clamp(options.gainMapQuality, 0, 100);
+  float maxBoostLinear = maxContentBoost(options);
+
+  // Decode the SDR as decoders will see it by wrapping it with a flat
+  // placeholder gain map, which is then replaced.
+  open_ultrahdr::Dimensions gm =
+      open_ultrahdr::Dimensions{dims.width, dims.height}.scaled(static_cast<uint32_t>(gmScale));
+  std::vector<uint8_t> flat(gm.grayLength(), 0);
+  std::vector<uint8_t> placeholder =
+      open_ultrahdr::encodeJpeg8({flat.data(), gm.width, gm.height, gm.width, 1}, gmQ, {});
+  std::vector<uint8_t> wrapped = assembleUltraHdr(
+      sdr, placeholder,
+      libUhdrFromParams(computedGainMapParams(options.blackPointOffset, maxBoostLinear)));
+  DecodedPixels px = decodePixels(wrapped);
+  std::vector<uint8_t>().swap(wrapped);
+
+  std::vector<float> strip;
+  auto readHdr = [&](uint32_t y, uint32_t rows) {
+    strip = f32FromVal(readHdrRows(y, rows));
+    size_t expected = open_ultrahdr::Dimensions{px.width, rows}.rgbLength();
+    if (strip.size() != expected) {
+      throw std::runtime_error("HDR strip at row " + std::to_string(y) + " has " +
+                               std::to_string(strip.size()) + " floats, expected " +
+                               std::to_string(expected));
+    }
+    return static_cast<const float*>(strip.data());
+  };
+  return u8VectorToVal(encodeWithComputedGainMap(sdr, px, readHdr, static_cast<uint32_t>(stripRows),
+                                                 options.blackPointOffset, downsample,
+                                                 maxBoostLinear, gmScale, gmQ,
+                                                 customLuma ? luma : nullptr, 0.0f));
+}
+
+// Encodes like encodeUltraHdr and reports how much of the quantized gain map
+// saturated, so pipelines can flag a poorly chosen gain range.
+UltraHdrEncodeResult encodeUltraHdrWithStats(const val& sdrBuffer, const val& hdrBuffer,
+                                             const UltraHdrEncodeOptions& options) {
+  UltraHdrEncodeResult out;
+  std::vector<uint8_t> encoded =
+      encodeToBytes(u8FromVal(sdrBuffer), f32FromVal(hdrBuffer), options, &out.gainMapEmbedded);
+  open_ultrahdr::GainMapClipStats clip;
+  if (out.gainMapEmbedded) {
+    DecodedPixels px = decodePixels(encoded);
+    clip = open_ultrahdr::computeClipStats(px.gainMapView());
+  }
+
+  open_ultrahdr::JpegDims dims = open_ultrahdr::parseJpegDimensions(encoded.data(), encoded.size());
+  out.width = static_cast<int>(dims.width);
+  out.height = static_cast<int>(dims.height);
+  out.data = u8VectorToVal(encoded);
+  out.stats.clippedLow = makeFloat3Array(clip.low);
+  out.stats.clippedHigh = makeFloat3Array(clip.high);
+  return out;
+}
+
+// Pairs a caller-graded SDR with a gain map computed afresh against the HDR
+// target. Nothing assumes the SDR is a tone map of the HDR: the gain map just
+// records the per-pixel ratio between them, so any grade reconstructs, except
+// that pixels where the SDR is brighter than the target keep the SDR value
+// (the gain range starts at 1, as for encodeUltraHdr). `width` and `height`
+// describe the HDR buffer and must match the SDR's stored pixels: like
+// encodeUltraHdr, this ignores the SDR's Exif orientation, which the output
+// keeps so viewers turn both layers together.
+val recomputeGainMapForSdr(const val& sdrBuffer, const val& hdrBuffer, int width, int height,
+                           const UltraHdrEncodeOptions& options) {
+  std::vector<uint8_t> sdr = u8FromVal(sdrBuffer);
+  open_ultrahdr::JpegDims dims = open_ultrahdr::parseJpegDimensions(sdr.data(), sdr.size());
+  if (!dims.ok) throw std::runtime_error("SDR buffer is not a valid JPEG");
+  uint16_t orientation = open_ultrahdr::readJpegOrientation(sdr.data(), sdr.size());
+  if (orientation >= 5 && dims.width != dims.height &&
+      static_cast<uint32_t>(width) == dims.height && static_cast<uint32_t>(height) == dims.width) {
+    throw std::runtime_error("HDR target is " + dimsToString(width, height) +
+                             ", the SDR's display orientation (Exif orientation " +
+                             std::to_string(orientation) + "); pass it in the SDR's stored " +
+                             dimsToString(dims.width, dims.height) + " pixel layout instead");
+  }
+  if (static_cast<uint32_t>(width) != dims.width || static_cast<uint32_t>(height) != dims.height) {
+    throw std::runtime_error("HDR target is " + dimsToString(width, height) + " but the SDR is " +
+                             dimsToString(dims.width, dims.height));
+  }
+  return u8VectorToVal(encodeToBytes(std::move(sdr), f32FromVal(hdrBuffer), options));
+}
+
+// Encodes like encodeUltraHdr at the highest gain map quality, up to
+// options.gainMapQuality, whose output is at most `maxBytes`. Quality is
+// bisected for kMaxTargetSizeSteps steps between the requested quality and 1,
+// so the result may sit a few quality steps below the best that fits. The SDR
+// and every other option are kept, so a budget the SDR alone exceeds cannot
+// be met and throws.
+val encodeUltraHdrToTargetSize(const val& sdrBuffer, const val& hdrBuffer, double maxBytes,
+                               const UltraHdrEncodeOptions& options) {
+  if (!(maxBytes > 0)) throw std::runtime_error("Target size must be positive");
+  std::vector<uint8_t> sdr = u8FromVal(sdrBuffer);
+  std::vector<float> hdr = f32FromVal(hdrBuffer);
+  UltraHdrEncodeOptions o = options;
+  auto encodeAt = [&](int quality) {
+    o.gainMapQuality = quality;
+    return encodeToBytes(sdr, hdr, o);
+  };
+  auto fits = [&](const std::vector<uint8_t>& encoded) {
+    return static_cast<double>(encoded.size()) <= maxBytes;
+  };
+
+  int hi = std::clamp(options.gainMapQuality, 1, 100);
+  std::vector<uint8_t> best = encodeAt(hi);
+  if (fits(best)) return u8VectorToVal(best);
+  int lo = 1;
+  best = encodeAt(lo);
+  if (!fits(best)) {
+    throw std::runtime_error("Smallest encode is " + std::to_string(best.size()) +
+                             " bytes, over the target of " +
+                             std::to_string(static_cast<uint64_t>(maxBytes)) + " bytes");
+  }
+  // `lo` fits and `hi` does not.
+  for (int step = 0; step < kMaxTargetSizeSteps && hi - lo > 1; ++step) {
+    int mid = lo + (hi - lo) / 2;
+    std::vector<uint8_t> encoded = encodeAt(mid);
+    if (fits(encoded)) {
+      lo = mid;
+      best = std::move(encoded);
+    } else {
+      hi = mid;
+    }
+  }
+  return u8VectorToVal(best);
+}
+
+// With `applyOrientation`, both layers are put through the same Exif
+// transform so the gain map stays aligned with the SDR it scales.
+UltraHdrPixels decodeToPixels(const val& buffer, bool applyOrientation) {
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  DecodedPixels px = decodePixels(data);
+  UltraHdrPixels out;
+  out.orientation = px.orientation;
+  out.gainMapChannels = static_cast<int>(px.gainMapChannels);
+  if (applyOrientation && px.orientation != 1) {
+    open_ultrahdr::Image8 sdr = open_ultrahdr::orientImage8(px.sdrView(), px.orientation);
+    open_ultrahdr::Image8 gainMap = open_ultrahdr::orientImage8(px.gainMapView(), px.orientation);
+    out.sdr = u8VectorToVal(sdr.data);
+    out.width = static_cast<int>(sdr.width);
+    out.height = static_cast<int>(sdr.height);
+    out.gainMap = u8VectorToVal(gainMap.data);
+    out.gainMapWidth = static_cast<int>(gainMap.width);
+    out.gainMapHeight = static_cast<int>(gainMap.height);
+    return out;
+  }
+  out.sdr = u8VectorToVal(px.sdr);
+  out.width = static_cast<int>(px.width);
+  out.height = static_cast<int>(px.height);
+  out.gainMap = u8VectorToVal(px.gainMap);
+  out.gainMapWidth = static_cast<int>(px.gainMapWidth);
+  out.gainMapHeight = static_cast<int>(px.gainMapHeight);
+  return out;
+}
+
+ReconstructedHdrImage applyGainMap(const val& buffer, float displayHdrCapacity, int edgeMode,
+                                   float maxLinear) {
+  open_ultrahdr::GainMapEdgeMode edge = toEdgeMode(edgeMode);
+  checkMaxLinear(maxLinear);
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  DecodedPixels px = decodePixels(data);
+  std::vector<float> hdr = open_ultrahdr::applyGainMap(px.sdrView(), px.gainMapView(), px.params,
+                                                       displayHdrCapacity, edge, maxLinear);
+  ReconstructedHdrImage out;
+  out.data = f32ArrayToVal(hdr.data(), hdr.size());
+  out.width = static_cast<int>(px.width);
+  out.height = static_cast<int>(px.height);
+  return out;
+}
+
+// One reconstruction per entry of the JS array `capacities`, as an array of
+// ReconstructedHdrImage objects, decoding the file and its gain once.
+val applyGainMapMulti(const val& buffer, const val& capacities, int edgeMode, float maxLinear) {
+  open_ultrahdr::GainMapEdgeMode edge = toEdgeMode(edgeMode);
+  checkMaxLinear(maxLinear);
+  if (!capacities.isArray()) throw std::runtime_error("Capacities must be an array");
+  std::vector<float> levels(capacities["length"].as<unsigned>());
+  for (size_t i = 0; i < levels.size(); ++i) levels[i] = capacities[i].as<float>();
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  DecodedPixels px = decodePixels(data);
+  std::vector<std::vector<float>> hdr = open_ultrahdr::applyGainMapMulti(
+      px.sdrView(), px.gainMapView(), px.params, levels, edge, maxLinear);
+  val out = val::array();
+  for (size_t i = 0; i < hdr.size(); ++i) {
+    val image = val::object();
+    image.set("data", f32ArrayToVal(hdr[i].data(), hdr[i].size()));
+    image.set("width", static_cast<int>(px.width));
+    image.set("height", static_cast<int>(px.height));
+    out.set(static_cast<unsigned>(i), image);
+  }
+  return out;
+}
+
+// Reconstructs the region [x, x + width) x [y, y + height) of the full image;
+// the gain map is still sampled at global coordinates so tiles line up.
+ReconstructedHdrImage applyGainMapRegion(const val& buffer, int x, int y, int width, int height,
+                                         float displayHdrCapacity, int edgeMode,
+                                         float maxLinear) {
+  if (x < 0 || y < 0 || width <= 0 || height <= 0) {
+    throw std::runtime_error("Invalid region " + dimsToString(width, height) + " at " +
+                             std::to_string(x) + "," + std::to_string(y));
+  }
+  open_ultrahdr::GainMapEdgeMode edge = toEdgeMode(edgeMode);
+  checkMaxLinear(maxLinear);
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  DecodedPixels px = decodePixels(data);
+  open_ultrahdr::PixelRect region{static_cast<uint32_t>(x), static_cast<uint32_t>(y),
+                                  static_cast<uint32_t>(width), static_cast<uint32_t>(height)};
+  std::vector<float> hdr = open_ultrahdr::applyGainMapRegion(
+      px.sdrView(), px.gainMapView(), px.params, region, displayHdrCapacity, edge, maxLinear);
+  ReconstructedHdrImage out;
+  out.data = f32ArrayToVal(hdr.data(), hdr.size());
+  out.width = width;
+  out.height = height;
+  return out;
+}
+
+// Streams the reconstruction to `onRow(y, Float32Array)` one row at a time.
+// Each row is a fresh Float32Array the callback may retain.
+void applyGainMapRows(const val& buffer, float displayHdrCapacity, int edgeMode, float maxLinear,
+                      const val& onRow) {
+  open_ultrahdr::GainMapEdgeMode edge = toEdgeMode(edgeMode);
+  checkMaxLinear(maxLinear);
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  DecodedPixels px = decodePixels(data);
+  open_ultrahdr::applyGainMapRows(
+      px.sdrView(), px.gainMapView(), px.params, displayHdrCapacity,
+      [&](uint32_t y, const float* row, size_t length) { onRow(y, f32ArrayToVal(row, length)); },
+      edge, maxLinear);
+}
+
+// Gallery-preview path: the gain map is applied only at thumbnail resolution,
+// with nearest sampling, and the result is tone mapped to a plain sRGB JPEG
+// whose longer side is `maxDim` (images are never upscaled). libultrahdr only
+// decodes at full size, so it just reads the metadata here; the primary is
+// decoded with libjpeg's scaled IDCT to the smallest size still at least
+// `maxDim`, and renderThumbnail decimates the rest of the way.
+val decodeUltraHdrThumbnail(const val& buffer, int maxDim, float displayHdrCapacity) {
+  if (maxDim <= 0) throw std::runtime_error("Thumbnail size must be positive");
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  if (data.empty()) throw std::runtime_error("Empty buffer");
+  checkPixelDecodable(data.data(), data.size(), "Primary");
+  isolateLocatedGainMap(data);
+  open_ultrahdr::ComponentLayout layout;
+  if (!open_ultrahdr::locateComponents(data.data(), data.size(), layout)) {
+    throw std::runtime_error("No gain map found");
+  }
+  const uint8_t* gainMapJpeg = data.data() + layout.gainMap.offset;
+  checkPixelDecodable(gainMapJpeg, layout.gainMap.length, "Gain map");
+
+  open_ultrahdr::GainMapParams params;
+  {
+    DecoderHandle dec;
+    uhdr_compressed_image_t img = compressedImageFor(data);
+    throwOnError(uhdr_dec_set_image(dec.get(), &img), "uhdr_dec_set_image");
+    throwOnError(uhdr_dec_probe(dec.get()), "uhdr_dec_probe");
+    uhdr_gainmap_metadata_t* meta = uhdr_dec_get_gainmap_metadata(dec.get());
+    if (!meta) throw std::runtime_error("Failed to get gain map metadata");
+    // Untagged bases are sRGB, as in decodePixels.
+    open_ultrahdr::Gamut baseGamut = open_ultrahdr::Gamut::Bt709;
+    iccProfileGamut(open_ultrahdr::extractIccProfile(data.data(), data.size()), baseGamut);
+    params = decodeParams(data, *meta, baseGamut);
+  }
+
+  open_ultrahdr::JpegDims dims = open_ultrahdr::parseJpegDimensions(data.data(), data.size());
+  if (!dims.ok) throw std::runtime_error("Invalid JPEG: no frame header");
+  uint32_t longest = std::max(dims.width, dims.height);
+  uint32_t target = std::min(static_cast<uint32_t>(maxDim), longest);
+  auto scaled = [&](uint32_t side) {
+    uint64_t v = (static_cast<uint64_t>(side) * target + longest / 2) / longest;
+    return static_cast<uint32_t>(std::max<uint64_t>(v, 1));
+  };
+  uint32_t width = dims.width >= dims.height ? target : scaled(dims.width);
+  uint32_t height = dims.height > dims.width ? target : scaled(dims.height);
+
+  open_ultrahdr::Image8 sdr = open_ultrahdr::decodeJpeg8Scaled(
+      data.data(), layout.primary.length, target);
+  open_ultrahdr::Image8 gainMap = open_ultrahdr::decodeJpeg8(
+      gainMapJpeg, layout.gainMap.length, /*keepGrayscale=*/true);
+  std::vector<uint8_t> rgb = open_ultrahdr::renderThumbnail(sdr.view(), gainMap.view(), params,
+                                                            displayHdrCapacity, width, height);
+  // Tone mapping keeps the primaries of the base, so its ICC profile applies.
+  std::vector<open_ultrahdr::JpegMarker> markers =
+      open_ultrahdr::iccProfileMarkers(open_ultrahdr::extractIccProfile(data.data(), data.size()));
+  return u8VectorToVal(open_ultrahdr::encodeJpeg8({rgb.data(), width, height, width, 3},
+                                                  kDefaultBaseQuality, markers));
+}
+
+// `lumaCoefficients` is a JS array of three luminance weights, or null for
+// BT.709's.
+HdrLuminanceImage reconstructNits(const val& buffer, float sdrWhiteNits,
+                                  float displayHdrCapacity, int edgeMode,
+                                  const val& lumaCoefficients) {
+  if (!(sdrWhiteNits > 0.0f)) throw std::runtime_error("SDR white must be a positive nits value");
+  open_ultrahdr::GainMapEdgeMode edge = toEdgeMode(edgeMode);
+  float luma[3];
+  if (!lumaFromVal(lumaCoefficients, luma)) {
+    std::copy(open_ultrahdr::kBt709Luma, open_ultrahdr::kBt709Luma + 3, luma);
+  }
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  DecodedPixels px = decodePixels(data);
+  std::vector<float> nits = open_ultrahdr::reconstructNits(
+      px.sdrView(), px.gainMapView(), px.params, displayHdrCapacity, sdrWhiteNits, edge, luma);
+  HdrLuminanceImage out;
+  out.data = f32ArrayToVal(nits.data(), nits.size());
+  out.width = static_cast<int>(px.width);
+  out.height = static_cast<int>(px.height);
+  return out;
+}
+
+ContentLightLevel computeMaxCllMaxFall(const val& nits, int width, int height) {
+  std::vector<float> values = f32FromVal(nits);
+  open_ultrahdr::Dimensions dims{static_cast<uint32_t>(std::max(width, 0)),
+                                 static_cast<uint32_t>(std::max(height, 0))};
+  if (width <= 0 || height <= 0 || values.size() != dims.pixelCount()) {
+    throw std::runtime_error("Luminance buffer size does not match " +
+                             dimsToString(width, height));
+  }
+  open_ultrahdr::ContentLightLevel level =
+      open_ultrahdr::computeContentLightLevel(values.data(), values.size());
+  ContentLightLevel out;
+  out.maxCll = level.maxCll;
+  out.maxFall = level.maxFall;
+  return out;
+}
+
+// Reconstructs HDR from `buffer` at `displayHdrCapacity` and compares it with
+// `referenceHdr`, linear RGB of the same size (SDR white = 1.0).
+ReconstructionErrorMetrics reconstructionError(const val& buffer, const val& referenceHdr,
+                                               float displayHdrCapacity, int edgeMode) {
+  open_ultrahdr::GainMapEdgeMode edge = toEdgeMode(edgeMode);
+  std::vector<float> reference = f32FromVal(referenceHdr);
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  DecodedPixels px = decodePixels(data);
+  size_t pixels = px.dims().pixelCount();
+  if (reference.size() != px.dims().rgbLength()) {
+    throw std::runtime_error("Reference HDR buffer size does not match " +
+                             dimsToString(px.width, px.height));
+  }
+  std::vector<float> hdr = open_ultrahdr::applyGainMap(px.sdrView(), px.gainMapView(), px.params,
+                                                       displayHdrCapacity, edge);
+  open_ultrahdr::ReconstructionError error =
+      open_ultrahdr::computeReconstructionError(hdr.data(), reference.data(), pixels);
+  ReconstructionErrorMetrics out;
+  out.rmse = makeFloat3Array(error.rmse);
+  out.maxError = makeFloat3Array(error.maxError);
+  out.psnr = makeFloat3Array(error.psnr);
+  return out;
+}
+
+// SSIM between the SDR base and the file's own HDR reconstruction tone
+// mapped back to SDR; needs no reference image.
+float sdrReconstructionSimilarity(const val& buffer) {
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  DecodedPixels px = decodePixels(data);
+  return open_ultrahdr::sdrReconstructionSimilarity(px.sdrView(), px.gainMapView(), px.params);
+}
+
+float estimateGainMapGamma(const val& encodedValues, const val& ratios, float gainMapMin,
+                           float gainMapMax) {
+  std::vector<uint8_t> encoded = u8FromVal(encodedValues);
+  std::vector<float> gains = f32FromVal(ratios);
+  if (encoded.size() != gains.size()) {
+    throw std::runtime_error("Got " + std::to_string(encoded.size()) + " encoded values but " +
+                             std::to_string(gains.size()) + " gain ratios");
+  }
+  if (!(gainMapMax > gainMapMin)) {
+    throw std::runtime_error("gainMapMax must exceed gainMapMin");
+  }
+  return open_ultrahdr::estimateGainMapGamma(encoded.data(), gains.data(), encoded.size(),
+                                             gainMapMin, gainMapMax);
+}
+
+GainMapValues decodeGainValues(const val& buffer) {
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  DecodedPixels px = decodePixels(data);
+  std::vector<float> ratios = open_ultrahdr::decodeGainRatios(px.gainMapView(), px.params);
+  GainMapValues out;
+  out.data = f32ArrayToVal(ratios.data(), ratios.size());
+  out.width = static_cast<int>(px.gainMapWidth);
+  out.height = static_cast<int>(px.gainMapHeight);
+  out.channels = px.gainMapChannels == 1 ? 1 : 3;
+  return out;
+}
+
+std::vector<uint8_t> copyMemBlock(const uhdr_mem_block_t* block) {
+  if (!block || !block->data || block->data_sz == 0) return {};
+  const uint8_t* p = static_cast<const uint8_t*>(block->data);
+  return std::vector<uint8_t>(p, p + block->data_sz);
+}
+
+// For files whose primary was rewritten by a tool that left the MPF index
+// pointing at the old gain map position.
+val repairUltraHdr(const val& buffer) {
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  if (data.empty()) throw std::runtime_error("Empty buffer");
+  std::vector<uint8_t> repaired = open_ultrahdr::repairMpfOffsets(data.data(), data.size());
+  if (repaired.empty()) {
+    throw std::runtime_error("No MPF index and gain map codestream to repair");
+  }
+  return u8VectorToVal(repaired);
+}
+
+// Re-encodes only the gain map at `quality`, keeping its dimensions and
+// metadata, and rebuilds the container around the primary codestream as it
+// was. Shrinks a file without another generation of loss on the SDR base.
+val recompressGainMap(const val& buffer, int quality) {
+  if (quality < 1 || quality > 100) {
+    throw std::runtime_error("Gain map quality must be between 1 and 100");
+  }
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  if (data.empty()) throw std::runtime_error("Empty buffer");
+  open_ultrahdr::ComponentLayout layout;
+  if (!open_ultrahdr::locateComponents(data.data(), data.size(), layout)) {
+    throw std::runtime_error("No gain map found");
+  }
+  std::vector<uint8_t> sdr =
+      open_ultrahdr::stripGainMapSignalling(data.data(), layout.primary.length);
+  if (sdr.empty()) throw std::runtime_error("Failed to read base image");
+
+  uhdr_gainmap_metadata_t meta{};
+  {
+    DecoderHandle dec;
+    uhdr_compressed_image_t img = compressedImageFor(data);
+    throwOnError(uhdr_dec_set_image(dec.get(), &img), "uhdr_dec_set_image");
+    throwOnError(uhdr_dec_probe(dec.get()), "uhdr_dec_probe");
+    uhdr_gainmap_metadata_t* m = uhdr_dec_get_gainmap_metadata(dec.get());
+    if (!m) throw std::runtime_error("Failed to get gain map metadata");
+    meta = *m;
+  }
+  applyXmpChannels(data, meta);
+  DecodedPixels px = decodePixels(data);
+  std::vector<uint8_t> gainMapJpeg = open_ultrahdr::encodeJpeg8(px.gainMapView(), quality, {});
+  return u8VectorToVal(assembleUltraHdr(sdr, gainMapJpeg, meta));
+}
+
+// Rotates both layers clockwise and rebuilds the container. When the primary
+// and gain map are MCU aligned their DCT blocks are rearranged losslessly;
+// otherwise both are decoded, rotated and re-encoded at the default
+// qualities so the layers stay consistent. Gain map metadata, ICC and Exif
+// are carried over, with the Exif orientation adjusted for mirrored images.
+val rotateUltraHdr(const val& buffer, int degrees) {
+  if (degrees != 90 && degrees != 180 && degrees != 270) {
+    throw std::runtime_error("Rotation must be 90, 180 or 270 degrees");
+  }
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  if (data.empty()) throw std::runtime_error("Empty buffer");
+
+  std::vector<uint8_t> base, gainMap, exif;
+  uhdr_gainmap_metadata_t meta{};
+  {
+    DecoderHandle dec;
+    uhdr_compressed_image_t img = compressedImageFor(data);
+    throwOnError(uhdr_dec_set_image(dec.get(), &img), "uhdr_dec_set_image");
+    throwOnError(uhdr_dec_probe(dec.get()), "uhdr_dec_probe");
+    base = copyMemBlock(uhdr_dec_get_base_image(dec.get()));
+    gainMap = copyMemBlock(uhdr_dec_get_gainmap_image(dec.get()));
+    exif = copyMemBlock(uhdr_dec_get_exif(dec.get()));
+    uhdr_gainmap_metadata_t* m = uhdr_dec_get_gainmap_metadata(dec.get());
+    if (base.empty()) throw std::runtime_error("Failed to get base image");
+    if (gainMap.empty()) throw std::runtime_error("Failed to get gain map image");
+    if (!m) throw std::runtime_error("Failed to get gain map metadata");
+    meta = *m;
+  }
+
+  // Re-attach the primary's ICC profile (all of its APP2 chunks), otherwise a
+  // wide-gamut base would come out untagged and be shown as sRGB.
+  std::vector<open_ultrahdr::JpegMarker> baseMarkers = open_ultrahdr::iccProfileMarkers(
+      open_ultrahdr::extractIccProfile(data.data(), data.size()));
+  if (!exif.empty()) {
+    uint16_t orientation = open_ultrahdr::readExifOrientation(exif.data(), exif.size());
+    open_ultrahdr::writeExifOrientation(exif.data(), exif.size(),
+                                        open_ultrahdr::rotateExifOrientation(orientation, degrees));
+  }
+
+  std::vector<uint8_t> rotatedBase, rotatedGainMap;
+  if (open_ultrahdr::canRotateJpegLossless(base.data(), base.size()) &&
+      open_ultrahdr::canRotateJpegLossless(gainMap.data(), gainMap.size())) {
+    rotatedBase = open_ultrahdr::rotateJpegLossless(base.data(), base.size(), degrees, baseMarkers);
+    rotatedGainMap = open_ultrahdr::rotateJpegLossless(gainMap.data(), gainMap.size(), degrees, {});
+  } else {
+    DecodedPixels px = decodePixels(data);
+    open_ultrahdr::JfifDensity density;
+    bool hasDensity = open_ultrahdr::readJfifDensity(data.data(), data.size(), density);
+    if (degrees % 180 != 0) std::swap(density.x, density.y);
+    rotatedBase = open_ultrahdr::encodeJpeg8(
+        open_ultrahdr::rotateImage8(px.sdrView(), degrees).view(), kDefaultBaseQuality,
+        baseMarkers, hasDensity ? &density : nullptr);
+    rotatedGainMap = open_ultrahdr::encodeJpeg8(
+        open_ultrahdr::rotateImage8(px.gainMapView(), degrees).view(), kDefaultGainMapQuality, {});
+  }
+
+  EncoderHandle enc;
+  uhdr_compressed_image_t baseImg = compressedImageFor(rotatedBase);
+  uhdr_compressed_image_t gainMapImg = compressedImageFor(rotatedGainMap);
+  throwOnError(uhdr_enc_set_compressed_image(enc.get(), &baseImg, UHDR_BASE_IMG),
+               "uhdr_enc_set_compressed_image");
+  throwOnError(uhdr_enc_set_gainmap_image(enc.get(), &gainMapImg, &meta),
+               "uhdr_enc_set_gainmap_image");
+  if (!exif.empty()) {
+    uhdr_mem_block_t exifBlock{exif.data(), exif.size(), exif.size()};
+    throwOnError(uhdr_enc_set_exif_data(enc.get(), &exifBlock), "uhdr_enc_set_exif_data");
+  }
+  throwOnError(uhdr_encode(enc.get()), "uhdr_encode");
+
   uhdr_compressed_image_t* out = uhdr_get_encoded_stream(enc.get());
   if (!out || !out->data || out->data_sz == 0) {
     throw std::runtime_error("uhdr_encode produced no output");
@@ -533,6 +2430,31 @@ val encodeUltraHdr(const val& sdrBuffer, const val& hdrBuffer,
   return u8VectorToVal(outBytes);
 }
 
+// Optional features of this build, so apps can degrade gracefully instead of
+// probing by trial. Keys are stable; a feature this build lacks reads false.
+val capabilities() {
+  val out = val::object();
+#ifdef OPEN_ULTRAHDR_MOZJPEG
+  out.set("mozjpeg", true);
+#ifdef OPEN_ULTRAHDR_MOZJPEG_PROGRESSIVE
+  out.set("progressiveJpeg", true);
+#else
+  out.set("progressiveJpeg", false);
+#endif
+#else
+  out.set("mozjpeg", false);
+  out.set("progressiveJpeg", false);
+#endif
+  // RGB gain maps are decoded and applied per channel, and written by the
+  // blackPointOffset and gainMapDownsample encode paths.
+  out.set("perChannelGainMap", true);
+  // Reconstruction returns linear floats or nits; there is no PQ encoder.
+  out.set("pqOutput", false);
+  // ISO 21496-1 binary metadata is read alongside the hdrgm XMP.
+  out.set("binaryMetadata", true);
+  return out;
+}
+
 // =============================================================================
 // Encode-options factories
 // =============================================================================
@@ -545,6 +2467,15 @@ UltraHdrEncodeOptions createDefaultOptions() {
   o.includeIsoMetadata = true;
   o.includeUltrahdrV1 = true;
   o.gainMapScale = 1;
+  o.hdrGamut = UHDR_CG_BT_709;
+  o.flattenExisting = false;
+  o.blackPointOffset = kDefaultBlackPointOffset;
+  o.forceBaselineBase = false;
+  o.gainMapBlurSigma = 0.0f;
+  o.gainMapDownsample = 0;
+  o.oddDimensionPolicy = 0;
+  o.orientation = 0;
+  o.lumaCoefficients = val::null();
   return o;
 }
 
@@ -576,6 +2507,28 @@ GainMapMetadata createDefaultMetadata() {
   return m;
 }
 
+// createDefaultMetadata with the capacity and gain range set to the headroom
+// that lifts SDR white (`sdrWhiteNits`) to `peakNits`: log2(peak / white).
+GainMapMetadata forPeakNits(float peakNits, float sdrWhiteNits) {
+  if (!(sdrWhiteNits > 0.0f) || !std::isfinite(sdrWhiteNits)) {
+    throw std::runtime_error("Invalid SDR white: " + std::to_string(sdrWhiteNits) +
+                             " nits (must be positive)");
+  }
+  if (!(peakNits > sdrWhiteNits) || !std::isfinite(peakNits)) {
+    throw std::runtime_error("Invalid peak: " + std::to_string(peakNits) +
+                             " nits (must exceed SDR white)");
+  }
+  float stops = std::log2(peakNits / sdrWhiteNits);
+  if (stops > kMaxHdrCapacity) {
+    throw std::runtime_error("Peak of " + std::to_string(peakNits) + " nits is more than " +
+                             std::to_string(kMaxHdrCapacity) + " stops above SDR white");
+  }
+  GainMapMetadata m = createDefaultMetadata();
+  m.gainMapMax = makeFloat3Array(stops, stops, stops);
+  m.hdrCapacityMax = stops;
+  return m;
+}
+
 // =============================================================================
 // Metadata helpers
 // =============================================================================
@@ -592,6 +2545,14 @@ bool validateMetadata(const GainMapMetadata& m) {
     if (mx.v[i] < mn.v[i]) return false;
   }
   if (m.hdrCapacityMax < m.hdrCapacityMin) return false;
+  if (m.hdrCapacityMax > kMaxHdrCapacity) return false;
+  if (!m.applyColorSpace.isUndefined()) {
+    if (!m.applyColorSpace.isNumber()) return false;
+    double gamut = m.applyColorSpace.as<double>();
+    if (gamut != UHDR_CG_BT_709 && gamut != UHDR_CG_DISPLAY_P3 && gamut != UHDR_CG_BT_2100) {
+      return false;
+    }
+  }
   return true;
 }
 
@@ -606,6 +2567,369 @@ bool isMeaningfulHdr(const GainMapMetadata& m) {
   return estimateHdrHeadroom(m) >= kMeaningfulHdrThreshold;
 }
 
+// The reconstruction parameters `m` describes; throws on malformed arrays.
+open_ultrahdr::GainMapParams paramsFromMetadata(const GainMapMetadata& m) {
+  open_ultrahdr::GainMapParams p;
+  Float3 mn = readFloat3(m.gainMapMin);
+  Float3 mx = readFloat3(m.gainMapMax);
+  Float3 gamma = readFloat3(m.gamma);
+  Float3 offSdr = readFloat3(m.offsetSdr);
+  Float3 offHdr = readFloat3(m.offsetHdr);
+  if (!mn.ok || !mx.ok || !gamma.ok || !offSdr.ok || !offHdr.ok) {
+    throw std::runtime_error("Metadata arrays must have 3 entries");
+  }
+  for (int c = 0; c < 3; ++c) {
+    p.gainMapMin[c] = mn.v[c];
+    p.gainMapMax[c] = mx.v[c];
+    p.gamma[c] = gamma.v[c];
+    p.offsetSdr[c] = offSdr.v[c];
+    p.offsetHdr[c] = offHdr.v[c];
+  }
+  p.hdrCapacityMin = m.hdrCapacityMin;
+  p.hdrCapacityMax = m.hdrCapacityMax;
+  return p;
+}
+
+// Sortable 0-100 HDR strength of the UltraHDR file `data` with parameters
+// `p`, counting gain map pixels boosted by at least kMeaningfulHdrThreshold
+// as covered.
+int strengthScore(const std::vector<uint8_t>& data, const open_ultrahdr::GainMapParams& p) {
+  open_ultrahdr::Image8 gainMap = decodeGainMapOnly(data);
+  float coverage = open_ultrahdr::gainCoverage(gainMap.view(), p, kMeaningfulHdrThreshold);
+  return open_ultrahdr::hdrStrengthScore(p, coverage);
+}
+
+// strengthScore of a file under its own metadata. Files without a gain map
+// score 0.
+int hdrStrengthScore(const val& buffer) {
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  if (data.empty() || is_uhdr_image(data.data(), static_cast<int>(data.size())) != 1) return 0;
+  return strengthScore(data, paramsFromMetadata(readMetadata(data)));
+}
+
+// probeUltraHdr's dimensions and layout with the capacity range from the full
+// metadata and hdrStrengthScore. The version is the file's hdrgm:Version when
+// it has hdrgm XMP, so an unsupported one shows up in the index.
+UltraHdrIndexRecord indexUltraHdr(const val& buffer) {
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  UltraHdrProbeResult probe = probeBytes(data);
+  if (!probe.isValid) throw std::runtime_error("Not an UltraHDR image");
+  GainMapMetadata m = readMetadata(data);
+
+  UltraHdrIndexRecord out;
+  out.width = probe.width;
+  out.height = probe.height;
+  out.gainMapWidth = probe.gainMapWidth;
+  out.gainMapHeight = probe.gainMapHeight;
+  out.hdrCapacityMin = m.hdrCapacityMin;
+  out.hdrCapacityMax = m.hdrCapacityMax;
+  out.version = m.version;
+  std::string xmp = open_ultrahdr::findHdrgmXmp(data.data(), data.size());
+  std::vector<std::string> versions;
+  if (open_ultrahdr::xmpValues(xmp, "hdrgm:Version", versions) && versions.size() == 1) {
+    out.version = versions[0];
+  }
+  out.layout = probe.layout;
+  out.hdrStrength = strengthScore(data, paramsFromMetadata(m));
+  return out;
+}
+
+val supportedVersions() { return stringVectorToVal(open_ultrahdr::supportedHdrgmVersions()); }
+
+// Advisory findings that validateMetadata deliberately accepts. An unknown
+// version is decoded as 1.0 for forward compatibility, but is more often a
+// typo ("1.O") than a newer file. An hdrCapacityMax far from the peak gain
+// (the largest gainMapMax, or the deepest gainMapMin when the base is HDR)
+// reconstructs oddly: below it, displays that are told to apply the full gain
+// cannot show it; above it, the full gain is held back from displays that
+// could.
+val metadataWarnings(const GainMapMetadata& m) {
+  std::vector<std::string> warnings;
+  if (!open_ultrahdr::isSupportedHdrgmVersion(m.version)) {
+    warnings.push_back("Unknown metadata version \"" + m.version + "\"; supported: " +
+                       open_ultrahdr::supportedHdrgmVersions().front());
+  }
+  Float3 mn = readFloat3(m.gainMapMin);
+  Float3 mx = readFloat3(m.gainMapMax);
+  if (mn.ok && mx.ok) {
+    float peak = m.baseRenditionIsHdr ? -std::min({mn.v[0], mn.v[1], mn.v[2]})
+                                      : std::max({mx.v[0], mx.v[1], mx.v[2]});
+    if (std::fabs(m.hdrCapacityMax - peak) > kCapacityGainToleranceStops) {
+      warnings.push_back("hdrCapacityMax " + std::to_string(m.hdrCapacityMax) +
+                         " is inconsistent with a peak gain of " + std::to_string(peak) +
+                         " stops");
+    }
+  }
+  return stringVectorToVal(warnings);
+}
+
+// Numeric fields match within `tolerance` (absolute, in the log2/linear units
+// each field is stored in); the version string, base rendition and apply
+// colour space must match exactly. Malformed per-channel arrays never compare
+// equal.
+bool metadataApproxEqual(const GainMapMetadata& a, const GainMapMetadata& b, float tolerance) {
+  if (!(tolerance >= 0.0f)) throw std::runtime_error("Tolerance must be non-negative");
+  if (a.version != b.version || a.baseRenditionIsHdr != b.baseRenditionIsHdr) return false;
+  if (!a.applyColorSpace.strictlyEquals(b.applyColorSpace)) return false;
+  auto near = [&](float x, float y) { return std::fabs(x - y) <= tolerance; };
+  const val GainMapMetadata::*arrays[] = {&GainMapMetadata::gainMapMin, &GainMapMetadata::gainMapMax,
+                                          &GainMapMetadata::gamma, &GainMapMetadata::offsetSdr,
+                                          &GainMapMetadata::offsetHdr};
+  for (auto field : arrays) {
+    Float3 x = readFloat3(a.*field);
+    Float3 y = readFloat3(b.*field);
+    if (!x.ok || !y.ok) return false;
+    for (int i = 0; i < 3; ++i) {
+      if (!near(x.v[i], y.v[i])) return false;
+    }
+  }
+  return near(a.hdrCapacityMin, b.hdrCapacityMin) && near(a.hdrCapacityMax, b.hdrCapacityMax);
+}
+
+open_ultrahdr::XmpFloatArrayStyle toXmpFloatArrayStyle(int style) {
+  switch (style) {
+    case static_cast<int>(open_ultrahdr::XmpFloatArrayStyle::CommaSeparated):
+      return open_ultrahdr::XmpFloatArrayStyle::CommaSeparated;
+    case static_cast<int>(open_ultrahdr::XmpFloatArrayStyle::RdfSeq):
+      return open_ultrahdr::XmpFloatArrayStyle::RdfSeq;
+    default:
+      throw std::runtime_error("Invalid XMP float array style: " + std::to_string(style));
+  }
+}
+
+std::string gainMapXmp(const GainMapMetadata& m, int style) {
+  open_ultrahdr::XmpGainMapMetadata meta;
+  meta.params = paramsFromMetadata(m);
+  meta.baseRenditionIsHdr = m.baseRenditionIsHdr;
+  return open_ultrahdr::encodeGainMapXmp(meta, toXmpFloatArrayStyle(style));
+}
+
+// Channel `channel`'s decode curve as a `size`-entry Float32Array of linear
+// gain ratios, for clients that reconstruct on the GPU.
+val exportGainDecodeLut(const GainMapMetadata& m, int channel, int size) {
+  if (size < 2) throw std::runtime_error("Lookup table needs at least 2 entries");
+  std::vector<float> lut = open_ultrahdr::gainDecodeLut(paramsFromMetadata(m), channel,
+                                                        static_cast<size_t>(size));
+  return f32ArrayToVal(lut.data(), lut.size());
+}
+
+// The HDR capacity, in stops, the gain map's content actually reaches: the
+// largest log2 gain any of its samples decodes to under `metadata`, or under
+// the file's own metadata when that is null or undefined. Compared with
+// hdrCapacityMax, it shows whether synthesized or suspect metadata matches
+// the gain map it describes.
+float estimateCapacityFromGainMap(const val& buffer, const val& metadata) {
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  GainMapMetadata m;
+  if (!metadata.isNull() && !metadata.isUndefined()) {
+    m = metadata.as<GainMapMetadata>();
+    if (!validateMetadata(m)) throw std::runtime_error("Invalid gain map metadata");
+  } else {
+    m = readMetadata(data);
+  }
+  open_ultrahdr::Image8 gainMap = decodeGainMapOnly(data);
+  return open_ultrahdr::peakGainLog2(gainMap.view(), paramsFromMetadata(m));
+}
+
+GainMapMetadata parseGainMapXmp(const std::string& xmp) {
+  open_ultrahdr::XmpGainMapMetadata meta;
+  std::vector<std::string> errors;
+  if (!open_ultrahdr::decodeGainMapXmp(xmp, meta, &errors)) {
+    std::string msg = errors[0];
+    for (size_t i = 1; i < errors.size(); ++i) msg += "; " + errors[i];
+    throw std::runtime_error(msg);
+  }
+  return fromParams(meta.params, meta.baseRenditionIsHdr);
+}
+
+// Metadata as JSON, for sidecar files and other tools. Per-channel values
+// stay arrays; applyColorSpace is omitted when unset.
+std::string metadataToJson(const GainMapMetadata& m) {
+  return val::global("JSON").call<std::string>("stringify", val(m));
+}
+
+// Inverse of metadataToJson. The fields are type-checked before conversion
+// and the result must pass validateMetadata; malformed JSON throws JSON.parse's
+// SyntaxError.
+GainMapMetadata metadataFromJson(const std::string& json) {
+  val parsed = val::global("JSON").call<val>("parse", json);
+  if (parsed.isNull() || parsed.isArray() || parsed.typeOf().as<std::string>() != "object") {
+    throw std::runtime_error("Metadata JSON must be an object");
+  }
+  if (!parsed["version"].isString()) {
+    throw std::runtime_error("Metadata JSON version must be a string");
+  }
+  if (!parsed["baseRenditionIsHdr"].isTrue() && !parsed["baseRenditionIsHdr"].isFalse()) {
+    throw std::runtime_error("Metadata JSON baseRenditionIsHdr must be a boolean");
+  }
+  for (const char* name : {"hdrCapacityMin", "hdrCapacityMax"}) {
+    if (!parsed[name].isNumber()) {
+      throw std::runtime_error(std::string("Metadata JSON ") + name + " must be a number");
+    }
+  }
+  for (const char* name : {"gainMapMin", "gainMapMax", "gamma", "offsetSdr", "offsetHdr"}) {
+    val arr = parsed[name];
+    bool ok = arr.isArray() && arr["length"].as<unsigned>() == 3;
+    for (unsigned i = 0; ok && i < 3; ++i) ok = arr[i].isNumber();
+    if (!ok) {
+      throw std::runtime_error(std::string("Metadata JSON ") + name + " must hold 3 numbers");
+    }
+  }
+  GainMapMetadata m = parsed.as<GainMapMetadata>();
+  if (!validateMetadata(m)) throw std::runtime_error("Metadata JSON is not valid metadata");
+  return m;
+}
+
+// Copies the gain map codestream out as a plain JPEG that describes itself:
+// its own hdrgm/ISO segments are dropped and the parent's combined metadata
+// is written back as a single hdrgm XMP packet, its per-channel values in
+// `style` (an XmpFloatArrayStyle).
+val extractGainMapStandalone(const val& buffer, int style) {
+  open_ultrahdr::XmpFloatArrayStyle xmpStyle = toXmpFloatArrayStyle(style);
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  if (data.empty()) throw std::runtime_error("Empty buffer");
+  open_ultrahdr::ComponentLayout layout;
+  if (!open_ultrahdr::locateComponents(data.data(), data.size(), layout)) {
+    throw std::runtime_error("No gain map found");
+  }
+  std::vector<uint8_t> gainMap = open_ultrahdr::stripGainMapSignalling(
+      data.data() + layout.gainMap.offset, layout.gainMap.length);
+  if (gainMap.empty()) throw std::runtime_error("Failed to read gain map");
+
+  open_ultrahdr::XmpGainMapMetadata meta;
+  GainMapMetadata m = getMetadata(buffer);
+  meta.params = paramsFromMetadata(m);
+  meta.baseRenditionIsHdr = m.baseRenditionIsHdr;
+  std::string xmp = open_ultrahdr::encodeGainMapXmp(meta, xmpStyle);
+  std::vector<uint8_t> out = open_ultrahdr::insertXmpSegment(gainMap, xmp);
+  if (out.empty()) throw std::runtime_error("Failed to write gain map XMP");
+  return u8VectorToVal(out);
+}
+
+// Splits a file into the codestreams and metadata encodeFromComponents takes
+// back. Both JPEGs are copied without their gain map signalling (XMP, MPF,
+// ISO 21496-1), which assembling writes afresh; their scans are untouched.
+UltraHdrComponents splitUltraHdr(const val& buffer) {
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  if (data.empty()) throw std::runtime_error("Empty buffer");
+  open_ultrahdr::ComponentLayout layout;
+  if (!open_ultrahdr::locateComponents(data.data(), data.size(), layout)) {
+    throw std::runtime_error("No gain map found");
+  }
+  std::vector<uint8_t> primary =
+      open_ultrahdr::stripGainMapSignalling(data.data(), layout.primary.length);
+  if (primary.empty()) throw std::runtime_error("Failed to read base image");
+  std::vector<uint8_t> gainMap = open_ultrahdr::stripGainMapSignalling(
+      data.data() + layout.gainMap.offset, layout.gainMap.length);
+  if (gainMap.empty()) throw std::runtime_error("Failed to read gain map");
+
+  UltraHdrComponents out;
+  out.primaryJpeg = u8VectorToVal(primary);
+  out.gainMapJpeg = u8VectorToVal(gainMap);
+  out.metadataJson = metadataToJson(getMetadata(buffer));
+  return out;
+}
+
+// libultrahdr metadata for `m`, the inverse of fromLibUhdr. libultrahdr
+// writes only SDR-base files that apply the gain in the base's colour space,
+// so metadata asking for anything else is rejected rather than dropped.
+uhdr_gainmap_metadata_t toLibUhdr(const GainMapMetadata& m) {
+  if (!validateMetadata(m)) throw std::runtime_error("Invalid gain map metadata");
+  if (m.baseRenditionIsHdr) throw std::runtime_error("HDR-base metadata cannot be written");
+  if (!m.applyColorSpace.isUndefined()) {
+    throw std::runtime_error("applyColorSpace cannot be written");
+  }
+  return libUhdrFromParams(paramsFromMetadata(m));
+}
+
+// Joins an SDR JPEG and a gain map JPEG, e.g. from splitUltraHdr, into an
+// UltraHDR file described by `m`. Neither codestream is re-encoded; `xmpMode`
+// (a GainMapXmpMode) decides whether the gain map's own metadata segments are
+// kept.
+val encodeFromComponents(const val& primaryBuffer, const val& gainMapBuffer,
+                         const GainMapMetadata& m, int xmpMode) {
+  GainMapXmpMode mode = toGainMapXmpMode(xmpMode);
+  std::vector<uint8_t> primary = u8FromVal(primaryBuffer);
+  std::vector<uint8_t> gainMap = u8FromVal(gainMapBuffer);
+  if (primary.empty()) throw std::runtime_error("Empty primary buffer");
+  if (gainMap.empty()) throw std::runtime_error("Empty gain map buffer");
+  if (mode != GainMapXmpMode::Keep) {
+    gainMap = open_ultrahdr::stripGainMapSignalling(gainMap.data(), gainMap.size(),
+                                                    mode == GainMapXmpMode::Strip);
+    if (gainMap.empty()) throw std::runtime_error("Failed to read gain map");
+  }
+  return u8VectorToVal(assembleUltraHdr(primary, gainMap, toLibUhdr(m)));
+}
+
+// Decodes like decodeUltraHdr but throws on any deviation from the spec
+// instead of recovering: structural violations are checked on the raw bytes
+// first, then decoder warnings and the combined metadata are treated as fatal.
+UltraHdrDecodeResult decodeUltraHdrStrict(const val& buffer) {
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  if (data.empty()) throw std::runtime_error("Empty buffer");
+
+  std::vector<std::string> violations =
+      open_ultrahdr::findConformanceViolations(data.data(), data.size());
+  if (!violations.empty()) {
+    std::string msg = "Non-conformant UltraHDR: " + violations[0];
+    for (size_t i = 1; i < violations.size(); ++i) msg += "; " + violations[i];
+    throw std::runtime_error(msg);
+  }
+
+  UltraHdrDecodeResult out = decodeUltraHdr(buffer);
+  if (out.warnings["length"].as<unsigned>() > 0) {
+    throw std::runtime_error("Non-conformant UltraHDR: " + out.warnings[0].as<std::string>());
+  }
+  if (!validateMetadata(out.metadata)) {
+    throw std::runtime_error("Non-conformant UltraHDR: gain map metadata is out of range");
+  }
+  return out;
+}
+
+// Items are plain objects rather than value_objects so rules can be added
+// without touching the bindings. `compatible` is false if any item failed.
+val checkCompatibility(const val& buffer, int profile) {
+  std::vector<uint8_t> data = u8FromVal(buffer);
+  if (data.empty()) throw std::runtime_error("Empty buffer");
+  std::vector<open_ultrahdr::CompatItem> items =
+      open_ultrahdr::checkCompatibility(data.data(), data.size(), toCompatProfile(profile));
+
+  bool compatible = true;
+  val list = val::array();
+  for (size_t i = 0; i < items.size(); ++i) {
+    const char* status = "pass";
+    if (items[i].status == open_ultrahdr::CompatStatus::Warn) status = "warn";
+    if (items[i].status == open_ultrahdr::CompatStatus::Fail) {
+      status = "fail";
+      compatible = false;
+    }
+    val item = val::object();
+    item.set("rule", items[i].rule);
+    item.set("status", std::string(status));
+    item.set("message", items[i].message);
+    list.set(static_cast<unsigned>(i), item);
+  }
+  val out = val::object();
+  out.set("profile", profile);
+  out.set("compatible", compatible);
+  out.set("items", list);
+  return out;
+}
+
+// =============================================================================
+// Transfer functions
+// =============================================================================
+
+// Applies `fn` to every element of a JS Float32Array, writing results back
+// into the caller's array.
+void transformInPlace(const val& values, float (*fn)(float)) {
+  std::vector<float> data = f32FromVal(values);
+  if (data.empty()) return;
+  for (float& v : data) v = fn(v);
+  val view{typed_memory_view(data.size(), data.data())};
+  values.call<void>("set", view);
+}
+
 }  // namespace
 
 // Translates std::exception to a JS Error so callers see a real message.
@@ -613,6 +2937,9 @@ template <typename Fn>
 auto translateErr(Fn&& fn) -> decltype(fn()) {
   try {
     return fn();
+  } catch (const DecodeBackendUnsupported& e) {
+    openUltraHdrThrowNamedJsError("DecodeBackendUnsupportedError", e.what());
+    throw;  // unreachable
   } catch (const std::exception& e) {
     openUltraHdrThrowJsError(e.what());
     throw;  // unreachable
@@ -629,7 +2956,19 @@ EMSCRIPTEN_BINDINGS(open_ultrahdr) {
       .field("targetHdrCapacity", &UltraHdrEncodeOptions::targetHdrCapacity)
       .field("includeIsoMetadata", &UltraHdrEncodeOptions::includeIsoMetadata)
       .field("includeUltrahdrV1", &UltraHdrEncodeOptions::includeUltrahdrV1)
-      .field("gainMapScale", &UltraHdrEncodeOptions::gainMapScale);
+      .field("gainMapScale", &UltraHdrEncodeOptions::gainMapScale)
+      .field("hdrGamut", &UltraHdrEncodeOptions::hdrGamut)
+      .field("flattenExisting", &UltraHdrEncodeOptions::flattenExisting)
+      .field("omitTrivialGainMap", &UltraHdrEncodeOptions::omitTrivialGainMap)
+      .field("absoluteMaxGain", &UltraHdrEncodeOptions::absoluteMaxGain)
+      .field("metadataMaxPercentile", &UltraHdrEncodeOptions::metadataMaxPercentile)
+      .field("blackPointOffset", &UltraHdrEncodeOptions::blackPointOffset)
+      .field("forceBaselineBase", &UltraHdrEncodeOptions::forceBaselineBase)
+      .field("gainMapBlurSigma", &UltraHdrEncodeOptions::gainMapBlurSigma)
+      .field("gainMapDownsample", &UltraHdrEncodeOptions::gainMapDownsample)
+      .field("oddDimensionPolicy", &UltraHdrEncodeOptions::oddDimensionPolicy)
+      .field("orientation", &UltraHdrEncodeOptions::orientation)
+      .field("lumaCoefficients", &UltraHdrEncodeOptions::lumaCoefficients);
 
   value_object<GainMapMetadata>("GainMapMetadata")
       .field("version", &GainMapMetadata::version)
@@ -640,7 +2979,8 @@ EMSCRIPTEN_BINDINGS(open_ultrahdr) {
       .field("offsetSdr", &GainMapMetadata::offsetSdr)
       .field("offsetHdr", &GainMapMetadata::offsetHdr)
       .field("hdrCapacityMin", &GainMapMetadata::hdrCapacityMin)
-      .field("hdrCapacityMax", &GainMapMetadata::hdrCapacityMax);
+      .field("hdrCapacityMax", &GainMapMetadata::hdrCapacityMax)
+      .field("applyColorSpace", &GainMapMetadata::applyColorSpace);
 
   value_object<UltraHdrProbeResult>("UltraHdrProbeResult")
       .field("isValid", &UltraHdrProbeResult::isValid)
@@ -652,7 +2992,21 @@ EMSCRIPTEN_BINDINGS(open_ultrahdr) {
       .field("gainMapWidth", &UltraHdrProbeResult::gainMapWidth)
       .field("gainMapHeight", &UltraHdrProbeResult::gainMapHeight)
       .field("hdrCapacity", &UltraHdrProbeResult::hdrCapacity)
-      .field("metadataVersion", &UltraHdrProbeResult::metadataVersion);
+      .field("metadataVersion", &UltraHdrProbeResult::metadataVersion)
+      .field("primaryIsProgressive", &UltraHdrProbeResult::primaryIsProgressive)
+      .field("warnings", &UltraHdrProbeResult::warnings)
+      .field("layout", &UltraHdrProbeResult::layout);
+
+  value_object<UltraHdrIndexRecord>("UltraHdrIndexRecord")
+      .field("width", &UltraHdrIndexRecord::width)
+      .field("height", &UltraHdrIndexRecord::height)
+      .field("gainMapWidth", &UltraHdrIndexRecord::gainMapWidth)
+      .field("gainMapHeight", &UltraHdrIndexRecord::gainMapHeight)
+      .field("hdrCapacityMin", &UltraHdrIndexRecord::hdrCapacityMin)
+      .field("hdrCapacityMax", &UltraHdrIndexRecord::hdrCapacityMax)
+      .field("version", &UltraHdrIndexRecord::version)
+      .field("layout", &UltraHdrIndexRecord::layout)
+      .field("hdrStrength", &UltraHdrIndexRecord::hdrStrength);
 
   value_object<UltraHdrDecodeResult>("UltraHdrDecodeResult")
       .field("sdrImage", &UltraHdrDecodeResult::sdrImage)
@@ -661,7 +3015,65 @@ EMSCRIPTEN_BINDINGS(open_ultrahdr) {
       .field("width", &UltraHdrDecodeResult::width)
       .field("height", &UltraHdrDecodeResult::height)
       .field("gainMapWidth", &UltraHdrDecodeResult::gainMapWidth)
-      .field("gainMapHeight", &UltraHdrDecodeResult::gainMapHeight);
+      .field("gainMapHeight", &UltraHdrDecodeResult::gainMapHeight)
+      .field("primaryIsProgressive", &UltraHdrDecodeResult::primaryIsProgressive)
+      .field("warnings", &UltraHdrDecodeResult::warnings);
+
+  value_object<UltraHdrEncodeStats>("UltraHdrEncodeStats")
+      .field("clippedLow", &UltraHdrEncodeStats::clippedLow)
+      .field("clippedHigh", &UltraHdrEncodeStats::clippedHigh);
+
+  value_object<UltraHdrEncodeResult>("UltraHdrEncodeResult")
+      .field("data", &UltraHdrEncodeResult::data)
+      .field("stats", &UltraHdrEncodeResult::stats)
+      .field("gainMapEmbedded", &UltraHdrEncodeResult::gainMapEmbedded)
+      .field("width", &UltraHdrEncodeResult::width)
+      .field("height", &UltraHdrEncodeResult::height);
+
+  value_object<UltraHdrPixels>("UltraHdrPixels")
+      .field("sdr", &UltraHdrPixels::sdr)
+      .field("width", &UltraHdrPixels::width)
+      .field("height", &UltraHdrPixels::height)
+      .field("gainMap", &UltraHdrPixels::gainMap)
+      .field("gainMapWidth", &UltraHdrPixels::gainMapWidth)
+      .field("gainMapHeight", &UltraHdrPixels::gainMapHeight)
+      .field("gainMapChannels", &UltraHdrPixels::gainMapChannels)
+      .field("orientation", &UltraHdrPixels::orientation);
+
+  value_object<ReconstructedHdrImage>("ReconstructedHdrImage")
+      .field("data", &ReconstructedHdrImage::data)
+      .field("width", &ReconstructedHdrImage::width)
+      .field("height", &ReconstructedHdrImage::height);
+
+  value_object<HdrLuminanceImage>("HdrLuminanceImage")
+      .field("data", &HdrLuminanceImage::data)
+      .field("width", &HdrLuminanceImage::width)
+      .field("height", &HdrLuminanceImage::height);
+
+  value_object<ContentLightLevel>("ContentLightLevel")
+      .field("maxCll", &ContentLightLevel::maxCll)
+      .field("maxFall", &ContentLightLevel::maxFall);
+
+  value_object<ReconstructionErrorMetrics>("ReconstructionErrorMetrics")
+      .field("rmse", &ReconstructionErrorMetrics::rmse)
+      .field("maxError", &ReconstructionErrorMetrics::maxError)
+      .field("psnr", &ReconstructionErrorMetrics::psnr);
+
+  value_object<GainMapValues>("GainMapValues")
+      .field("data", &GainMapValues::data)
+      .field("width", &GainMapValues::width)
+      .field("height", &GainMapValues::height)
+      .field("channels", &GainMapValues::channels);
+
+  value_object<UltraHdrComponents>("UltraHdrComponents")
+      .field("primaryJpeg", &UltraHdrComponents::primaryJpeg)
+      .field("gainMapJpeg", &UltraHdrComponents::gainMapJpeg)
+      .field("metadataJson", &UltraHdrComponents::metadataJson);
+
+  class_<UltraHdrDecodeHandle>("UltraHdrDecodeHandle")
+      .function("getSdrView", &UltraHdrDecodeHandle::getSdrView)
+      .function("getGainMapView", &UltraHdrDecodeHandle::getGainMapView)
+      .function("copyOut", &UltraHdrDecodeHandle::copyOut);
 
   function("isUltraHdr", optional_override([](const val& b) {
             return translateErr([&] { return isUltraHdr(b); });
@@ -672,21 +3084,155 @@ EMSCRIPTEN_BINDINGS(open_ultrahdr) {
   function("decodeUltraHdr", optional_override([](const val& b) {
             return translateErr([&] { return decodeUltraHdr(b); });
           }));
+  function("decodeUltraHdrHandle", optional_override([](const val& b) {
+            return translateErr([&] { return UltraHdrDecodeHandle(b); });
+          }));
+  function("decodeUltraHdrStrict", optional_override([](const val& b) {
+            return translateErr([&] { return decodeUltraHdrStrict(b); });
+          }));
+  function("gainMapDimensionsMatch", optional_override([](const val& gm, int w, int h) {
+            return translateErr([&] { return gainMapDimensionsMatch(gm, w, h); });
+          }));
+  function("checkCompatibility", optional_override([](const val& b, int profile) {
+            return translateErr([&] { return checkCompatibility(b, profile); });
+          }));
   function("encodeUltraHdr",
            optional_override([](const val& sdr, const val& hdr,
                                 const UltraHdrEncodeOptions& o) {
              return translateErr([&] { return encodeUltraHdr(sdr, hdr, o); });
            }));
+  function("encodeUltraHdrWithStats",
+           optional_override([](const val& sdr, const val& hdr,
+                                const UltraHdrEncodeOptions& o) {
+             return translateErr([&] { return encodeUltraHdrWithStats(sdr, hdr, o); });
+           }));
+  function("encodeUltraHdrStrips",
+           optional_override([](const val& sdr, const val& readHdrRows, int stripRows,
+                                const UltraHdrEncodeOptions& o) {
+             return translateErr(
+                 [&] { return encodeUltraHdrStrips(sdr, readHdrRows, stripRows, o); });
+           }));
+  function("recomputeGainMapForSdr",
+           optional_override([](const val& sdr, const val& hdr, int width, int height,
+                                const UltraHdrEncodeOptions& o) {
+             return translateErr(
+                 [&] { return recomputeGainMapForSdr(sdr, hdr, width, height, o); });
+           }));
+  function("encodeUltraHdrToTargetSize",
+           optional_override([](const val& sdr, const val& hdr, double maxBytes,
+                                const UltraHdrEncodeOptions& o) {
+             return translateErr(
+                 [&] { return encodeUltraHdrToTargetSize(sdr, hdr, maxBytes, o); });
+           }));
+  function("locateComponents", optional_override([](const val& b) {
+            return translateErr([&] { return locateComponents(b); });
+          }));
+  function("getJfifDensity", optional_override([](const val& b) {
+            return translateErr([&] { return getJfifDensity(b); });
+          }));
+  function("getQuantizationTables", optional_override([](const val& b) {
+            return translateErr([&] { return getQuantizationTables(b); });
+          }));
+  function("listAuxiliaryImages", optional_override([](const val& b) {
+            return translateErr([&] { return listAuxiliaryImages(b); });
+          }));
+  function("extractAuxiliaryImage",
+           optional_override([](const val& b, const std::string& semantic) {
+             return translateErr([&] { return extractAuxiliaryImage(b, semantic); });
+           }));
   function("extractSdrBase", optional_override([](const val& b) {
             return translateErr([&] { return extractSdrBase(b); });
           }));
+  function("extractGainMapStandalone", optional_override([](const val& b, int style) {
+            return translateErr([&] { return extractGainMapStandalone(b, style); });
+          }));
+  function("splitUltraHdr", optional_override([](const val& b) {
+            return translateErr([&] { return splitUltraHdr(b); });
+          }));
+  function("encodeFromComponents", optional_override([](const val& primary, const val& gainMap,
+                                                        const GainMapMetadata& m, int xmpMode) {
+             return translateErr(
+                 [&] { return encodeFromComponents(primary, gainMap, m, xmpMode); });
+           }));
   function("getMetadata", optional_override([](const val& b) {
             return translateErr([&] { return getMetadata(b); });
           }));
+  function("decodeToPixels", optional_override([](const val& b, bool applyOrientation) {
+            return translateErr([&] { return decodeToPixels(b, applyOrientation); });
+          }));
+  function("applyGainMap",
+           optional_override([](const val& b, float capacity, int edgeMode, float maxLinear) {
+             return translateErr([&] { return applyGainMap(b, capacity, edgeMode, maxLinear); });
+           }));
+  function("applyGainMapRegion",
+           optional_override([](const val& b, int x, int y, int w, int h, float capacity,
+                                int edgeMode, float maxLinear) {
+             return translateErr([&] {
+               return applyGainMapRegion(b, x, y, w, h, capacity, edgeMode, maxLinear);
+             });
+           }));
+  function("applyGainMapMulti",
+           optional_override([](const val& b, const val& capacities, int edgeMode,
+                                float maxLinear) {
+             return translateErr(
+                 [&] { return applyGainMapMulti(b, capacities, edgeMode, maxLinear); });
+           }));
+  function("applyGainMapRows",
+           optional_override([](const val& b, float capacity, int edgeMode, float maxLinear,
+                                const val& onRow) {
+             translateErr([&] { applyGainMapRows(b, capacity, edgeMode, maxLinear, onRow); });
+           }));
+  function("decodeUltraHdrThumbnail",
+           optional_override([](const val& b, int maxDim, float capacity) {
+             return translateErr([&] { return decodeUltraHdrThumbnail(b, maxDim, capacity); });
+           }));
+  function("reconstructNits",
+           optional_override([](const val& b, float sdrWhite, float capacity, int edgeMode,
+                                const val& luma) {
+             return translateErr(
+                 [&] { return reconstructNits(b, sdrWhite, capacity, edgeMode, luma); });
+           }));
+  function("computeMaxCllMaxFall", optional_override([](const val& nits, int w, int h) {
+            return translateErr([&] { return computeMaxCllMaxFall(nits, w, h); });
+          }));
+  function("reconstructionError",
+           optional_override([](const val& b, const val& ref, float capacity, int edgeMode) {
+             return translateErr([&] { return reconstructionError(b, ref, capacity, edgeMode); });
+           }));
+  function("sdrReconstructionSimilarity", optional_override([](const val& b) {
+             return translateErr([&] { return sdrReconstructionSimilarity(b); });
+           }));
+  function("estimateGainMapGamma",
+           optional_override([](const val& encoded, const val& ratios, float min, float max) {
+             return translateErr([&] { return estimateGainMapGamma(encoded, ratios, min, max); });
+           }));
+  function("decodeGainValues", optional_override([](const val& b) {
+            return translateErr([&] { return decodeGainValues(b); });
+          }));
+  function("exportGainDecodeLut",
+           optional_override([](const GainMapMetadata& m, int channel, int size) {
+             return translateErr([&] { return exportGainDecodeLut(m, channel, size); });
+           }));
+  function("repairUltraHdr", optional_override([](const val& b) {
+            return translateErr([&] { return repairUltraHdr(b); });
+          }));
+  function("recompressGainMap", optional_override([](const val& b, int quality) {
+            return translateErr([&] { return recompressGainMap(b, quality); });
+          }));
+  function("rotateUltraHdr", optional_override([](const val& b, int degrees) {
+            return translateErr([&] { return rotateUltraHdr(b, degrees); });
+          }));
+  function("jpegEncoderBackend", optional_override([] {
+            return std::string(open_ultrahdr::jpegEncoderBackend());
+          }));
+  function("capabilities", &capabilities);
   function("createDefaultOptions", &createDefaultOptions);
   function("createHighQualityOptions", &createHighQualityOptions);
   function("createSmallSizeOptions", &createSmallSizeOptions);
   function("createDefaultMetadata", &createDefaultMetadata);
+  function("forPeakNits", optional_override([](float peakNits, float sdrWhiteNits) {
+            return translateErr([&] { return forPeakNits(peakNits, sdrWhiteNits); });
+          }));
   function("validateMetadata", optional_override([](const GainMapMetadata& m) {
             return translateErr([&] { return validateMetadata(m); });
           }));
@@ -696,4 +3242,58 @@ EMSCRIPTEN_BINDINGS(open_ultrahdr) {
   function("isMeaningfulHdr", optional_override([](const GainMapMetadata& m) {
             return translateErr([&] { return isMeaningfulHdr(m); });
           }));
+  function("hdrStrengthScore", optional_override([](const val& b) {
+            return translateErr([&] { return hdrStrengthScore(b); });
+          }));
+  function("estimateCapacityFromGainMap", optional_override([](const val& b, const val& m) {
+            return translateErr([&] { return estimateCapacityFromGainMap(b, m); });
+          }));
+  function("indexUltraHdr", optional_override([](const val& b) {
+            return translateErr([&] { return indexUltraHdr(b); });
+          }));
+  function("supportedVersions", &supportedVersions);
+  function("metadataWarnings", optional_override([](const GainMapMetadata& m) {
+            return translateErr([&] { return metadataWarnings(m); });
+          }));
+  function("metadataApproxEqual",
+           optional_override([](const GainMapMetadata& a, const GainMapMetadata& b, float tol) {
+             return translateErr([&] { return metadataApproxEqual(a, b, tol); });
+           }));
+  function("gainMapXmp", optional_override([](const GainMapMetadata& m, int style) {
+            return translateErr([&] { return gainMapXmp(m, style); });
+          }));
+  function("parseGainMapXmp", optional_override([](const std::string& xmp) {
+            return translateErr([&] { return parseGainMapXmp(xmp); });
+          }));
+  function("metadataToJson", optional_override([](const GainMapMetadata& m) {
+            return translateErr([&] { return metadataToJson(m); });
+          }));
+  function("metadataFromJson", optional_override([](const std::string& json) {
+            return translateErr([&] { return metadataFromJson(json); });
+          }));
+
+  function("srgbToLinear", &open_ultrahdr::srgbToLinear);
+  function("linearToSrgb", &open_ultrahdr::linearToSrgb);
+  function("pqToNits", &open_ultrahdr::pqToNits);
+  function("nitsToPq", &open_ultrahdr::nitsToPq);
+  function("hlgToLinear", &open_ultrahdr::hlgToLinear);
+  function("linearToHlg", &open_ultrahdr::linearToHlg);
+  function("srgbToLinearInPlace", optional_override([](const val& v) {
+            translateErr([&] { transformInPlace(v, &open_ultrahdr::srgbToLinear); });
+          }));
+  function("linearToSrgbInPlace", optional_override([](const val& v) {
+            translateErr([&] { transformInPlace(v, &open_ultrahdr::linearToSrgb); });
+          }));
+  function("pqToNitsInPlace", optional_override([](const val& v) {
+            translateErr([&] { transformInPlace(v, &open_ultrahdr::pqToNits); });
+          }));
+  function("nitsToPqInPlace", optional_override([](const val& v) {
+            translateErr([&] { transformInPlace(v, &open_ultrahdr::nitsToPq); });
+          }));
+  function("hlgToLinearInPlace", optional_override([](const val& v) {
+            translateErr([&] { transformInPlace(v, &open_ultrahdr::hlgToLinear); });
+          }));
+  function("linearToHlgInPlace", optional_override([](const val& v) {
+            translateErr([&] { transformInPlace(v, &open_ultrahdr::linearToHlg); });
+          }));
 }
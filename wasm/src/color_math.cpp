@@ -0,0 +1,102 @@
+#include "color_math.h"
+
+#include <algorithm>
+#include <cmath>
+
+namespace open_ultrahdr {
+
+namespace {
+
+// SMPTE ST 2084 constants.
+constexpr float kPqM1 = 2610.0f / 16384.0f;
+constexpr float kPqM2 = 2523.0f / 4096.0f * 128.0f;
+constexpr float kPqC1 = 3424.0f / 4096.0f;
+constexpr float kPqC2 = 2413.0f / 4096.0f * 32.0f;
+constexpr float kPqC3 = 2392.0f / 4096.0f * 32.0f;
+
+// ARIB STD-B67 constants.
+constexpr float kHlgA = 0.17883277f;
+constexpr float kHlgB = 0.28466892f;
+constexpr float kHlgC = 0.55991073f;
+
+// Linear RGB to CIE XYZ (D65), row-major.
+constexpr double kRgbToXyz[3][9] = {
+    {0.4124564, 0.3575761, 0.1804375, 0.2126729, 0.7151522, 0.0721750, 0.0193339, 0.1191920,
+     0.9503041},
+    {0.4865709, 0.2656677, 0.1982173, 0.2289746, 0.6917385, 0.0792869, 0.0000000, 0.0451134,
+     1.0439444},
+    {0.6369580, 0.1446169, 0.1688810, 0.2627002, 0.6779981, 0.0593017, 0.0000000, 0.0280727,
+     1.0609851},
+};
+
+void invert3x3(const double (&a)[9], double (&out)[9]) {
+  double c0 = a[4] * a[8] - a[5] * a[7];
+  double c1 = a[5] * a[6] - a[3] * a[8];
+  double c2 = a[3] * a[7] - a[4] * a[6];
+  double det = a[0] * c0 + a[1] * c1 + a[2] * c2;
+  out[0] = c0 / det;
+  out[1] = (a[2] * a[7] - a[1] * a[8]) / det;
+  out[2] = (a[1] * a[5] - a[2] * a[4]) / det;
+  out[3] = c1 / det;
+  out[4] = (a[0] * a[8] - a[2] * a[6]) / det;
+  out[5] = (a[2] * a[3] - a[0] * a[5]) / det;
+  out[6] = c2 / det;
+  out[7] = (a[1] * a[6] - a[0] * a[7]) / det;
+  out[8] = (a[0] * a[4] - a[1] * a[3]) / det;
+}
+
+}  // namespace
+
+float srgbToLinear(float v) {
+  if (v <= 0.04045f) return v / 12.92f;
+  return std::pow((v + 0.055f) / 1.055f, 2.4f);
+}
+
+float linearToSrgb(float v) {
+  if (v <= 0.0031308f) return v * 12.92f;
+  return 1.055f * std::pow(v, 1.0f / 2.4f) - 0.055f;
+}
+
+float pqToNits(float v) {
+  float e = std::pow(std::clamp(v, 0.0f, 1.0f), 1.0f / kPqM2);
+  float num = std::max(e - kPqC1, 0.0f);
+  float den = kPqC2 - kPqC3 * e;
+  return std::pow(num / den, 1.0f / kPqM1) * kPqMaxNits;
+}
+
+float nitsToPq(float nits) {
+  float y = std::clamp(nits / kPqMaxNits, 0.0f, 1.0f);
+  float ym = std::pow(y, kPqM1);
+  return std::pow((kPqC1 + kPqC2 * ym) / (1.0f + kPqC3 * ym), kPqM2);
+}
+
+float hlgToLinear(float v) {
+  v = std::clamp(v, 0.0f, 1.0f);
+  if (v <= 0.5f) return v * v / 3.0f;
+  return (std::exp((v - kHlgC) / kHlgA) + kHlgB) / 12.0f;
+}
+
+float linearToHlg(float v) {
+  v = std::clamp(v, 0.0f, 1.0f);
+  if (v <= 1.0f / 12.0f) return std::sqrt(3.0f * v);
+  return kHlgA * std::log(12.0f * v - kHlgB) + kHlgC;
+}
+
+float luminance(float r, float g, float b, const float (&coeffs)[3]) {
+  return coeffs[0] * r + coeffs[1] * g + coeffs[2] * b;
+}
+
+void gamutConversion(Gamut from, Gamut to, float (&m)[9]) {
+  const double(&src)[9] = kRgbToXyz[static_cast<int>(from)];
+  double dst[9];
+  invert3x3(kRgbToXyz[static_cast<int>(to)], dst);
+  for (int r = 0; r < 3; ++r) {
+    for (int c = 0; c < 3; ++c) {
+      double v = 0.0;
+      for (int k = 0; k < 3; ++k) v += dst[r * 3 + k] * src[k * 3 + c];
+      m[r * 3 + c] = static_cast<float>(v);
+    }
+  }
+}
+
+}  // namespace open_ultrahdr
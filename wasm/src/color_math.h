@@ -0,0 +1,38 @@
+#pragma once
+
+// Transfer functions. Depends only on <cmath>, allocates nothing and never
+// throws, so it can be built standalone (see OPEN_ULTRAHDR_MATH_ONLY in
+// CMakeLists.txt).
+
+namespace open_ultrahdr {
+
+// Peak luminance of the PQ (SMPTE ST 2084) signal range, in nits.
+constexpr float kPqMaxNits = 10000.0f;
+
+// sRGB transfer: non-linear signal [0, 1] <-> linear light [0, 1].
+float srgbToLinear(float v);
+float linearToSrgb(float v);
+
+// PQ (SMPTE ST 2084): code value [0, 1] <-> absolute luminance in nits.
+// Luminance is clamped to [0, kPqMaxNits] before encoding.
+float pqToNits(float v);
+float nitsToPq(float nits);
+
+// HLG (ARIB STD-B67 / BT.2100): signal [0, 1] <-> scene-linear light [0, 1].
+float hlgToLinear(float v);
+float linearToHlg(float v);
+
+// Luminance of linear RGB as a weighted sum. kBt709Luma holds the BT.709 /
+// sRGB weights (the Y row of its RGB -> XYZ matrix); custom weights such as
+// equal thirds are taken as given and need not sum to 1.
+constexpr float kBt709Luma[3] = {0.2126f, 0.7152f, 0.0722f};
+float luminance(float r, float g, float b, const float (&coeffs)[3]);
+
+// RGB primaries, all with a D65 white point. Values match ColorGamut on the
+// JS side and uhdr_color_gamut_t.
+enum class Gamut { Bt709 = 0, DisplayP3 = 1, Bt2100 = 2 };
+
+// Row-major 3x3 matrix taking linear RGB in `from` primaries to `to`.
+void gamutConversion(Gamut from, Gamut to, float (&m)[9]);
+
+}  // namespace open_ultrahdr
@@ -0,0 +1,307 @@
+#include "conformance.h"
+
+#include "gain_math.h"
+#include "iso_metadata.h"
+#include "jpeg_meta.h"
+#include "xmp_metadata.h"
+
+namespace open_ultrahdr {
+
+namespace {
+
+constexpr char kIsoSignature[] = "urn:iso:std:iso:ts:21496:-1";  // + NUL
+constexpr char kVersion[] = "1.0";
+
+std::string dimsToString(uint32_t w, uint32_t h) {
+  return std::to_string(w) + "x" + std::to_string(h);
+}
+
+bool hasIsoSegment(const uint8_t* data, size_t size, JpegSegment& found) {
+  JpegSegmentReader reader(data, size);
+  for (JpegSegment seg; reader.next(seg);) {
+    if (isIsoGainMapSegment(data, seg)) {
+      found = seg;
+      return true;
+    }
+  }
+  return false;
+}
+
+void checkRanges(const GainMapParams& p, const std::string& source,
+                 std::vector<std::string>& out) {
+  for (int c = 0; c < 3; ++c) {
+    if (!(p.gamma[c] > 0.0f)) {
+      out.push_back(source + " gamma must be positive");
+      break;
+    }
+  }
+  for (int c = 0; c < 3; ++c) {
+    if (!(p.offsetSdr[c] >= 0.0f) || !(p.offsetHdr[c] >= 0.0f)) {
+      out.push_back(source + " offsets must be non-negative");
+      break;
+    }
+  }
+  for (int c = 0; c < 3; ++c) {
+    if (p.gainMapMin[c] > p.gainMapMax[c]) {
+      out.push_back(source + " gain map min exceeds max");
+      break;
+    }
+  }
+  if (p.hdrCapacityMin < 0.0f || p.hdrCapacityMin > p.hdrCapacityMax) {
+    out.push_back(source + " HDR capacity range is invalid");
+  }
+}
+
+void checkVersion(const std::string& xmp, const std::string& source,
+                  std::vector<std::string>& out) {
+  std::vector<std::string> values;
+  if (!xmpValues(xmp, "hdrgm:Version", values)) {
+    out.push_back(source + " is missing hdrgm:Version");
+  } else if (values.size() != 1 || !isSupportedHdrgmVersion(values[0])) {
+    out.push_back(source + " hdrgm:Version is \"" + (values.empty() ? "" : values[0]) +
+                  "\", expected \"" + kVersion + "\"");
+  }
+}
+
+void checkGainMapXmp(const std::string& xmp, std::vector<std::string>& out) {
+  const std::string source = "gain map XMP";
+  checkVersion(xmp, source, out);
+  XmpGainMapMetadata meta;
+  if (decodeGainMapXmp(xmp, meta, &out)) checkRanges(meta.params, source, out);
+}
+
+void checkGainMapIso(const uint8_t* data, const JpegSegment& seg,
+                     std::vector<std::string>& out) {
+  const std::string source = "gain map ISO 21496-1 metadata";
+  size_t skip = sizeof(kIsoSignature);
+  IsoGainMapMetadata iso;
+  if (!decodeIsoBinary(data + seg.payloadOffset + skip, seg.payloadLength - skip, iso)) {
+    out.push_back(source + " does not decode");
+    return;
+  }
+  checkRanges(iso.params, source, out);
+}
+
+void checkGeometry(const uint8_t* primary, size_t primarySize, const uint8_t* gainMap,
+                   size_t gainMapSize, std::vector<std::string>& out) {
+  JpegDims image = parseJpegDimensions(primary, primarySize);
+  JpegDims map = parseJpegDimensions(gainMap, gainMapSize);
+  if (!image.ok || !map.ok || map.width == 0 || map.height == 0) {
+    out.push_back("primary or gain map JPEG has no readable SOF marker");
+    return;
+  }
+  uint32_t ratio = image.width / map.width;
+  bool uniform = image.width % map.width == 0 && image.height % map.height == 0 &&
+                 ratio == image.height / map.height;
+  if (!uniform || (ratio != 1 && ratio != 2 && ratio != 4)) {
+    out.push_back("gain map " + dimsToString(map.width, map.height) +
+                  " is not a 1x, 2x or 4x downscale of the " +
+                  dimsToString(image.width, image.height) + " primary");
+  }
+}
+
+// Follows the primary's MPF index to the gain map, recording each way the
+// index falls short. Returns the gain map's size, or 0 if it is unreachable.
+size_t followMpf(const uint8_t* data, size_t size, const uint8_t*& gainMap,
+                 std::vector<std::string>& out) {
+  // The gain map must be reachable through MPF rather than by scanning.
+  std::vector<MpfImage> images;
+  bool found = false;
+  JpegSegmentReader reader(data, size);
+  for (JpegSegment seg; reader.next(seg);) {
+    if (isMpfSegment(data, seg)) {
+      found = true;
+      images = readMpfImages(data, seg);
+      if (images.empty()) out.push_back("MPF segment is malformed");
+      break;
+    }
+  }
+  if (!found) out.push_back("primary image has no MPF segment");
+  if (images.empty()) return 0;
+  if (images.size() < 2) {
+    out.push_back("MPF segment does not list a gain map image");
+    return 0;
+  }
+  // The primary rendition is the first codestream, whichever rendition the
+  // metadata declares as the base; only it may carry the primary type code.
+  if (images[0].type() != kMpfPrimaryImageType) {
+    out.push_back("MPF does not mark the first image as the primary");
+  }
+  for (size_t i = 1; i < images.size(); ++i) {
+    if (images[i].type() == kMpfPrimaryImageType) {
+      out.push_back("MPF marks image " + std::to_string(i + 1) + " as a second primary");
+    }
+  }
+  const MpfImage& gm = images[1];
+  if (gm.offset >= size || size - gm.offset < 2 || data[gm.offset] != 0xFF ||
+      data[gm.offset + 1] != 0xD8) {
+    out.push_back("MPF gain map offset " + std::to_string(gm.offset) +
+                  " does not point at a JPEG");
+    return 0;
+  }
+  gainMap = data + gm.offset;
+  size_t gainMapSize = findJpegEnd(gainMap, size - gm.offset);
+  if (gainMapSize != gm.size) {
+    out.push_back("MPF gain map size " + std::to_string(gm.size) + " does not match the " +
+                  std::to_string(gainMapSize) + "-byte JPEG at its offset");
+  }
+  return gainMapSize;
+}
+
+// How much a profile cares about a rule: not at all, enough to warn, or
+// enough that its readers reject or mis-render the file.
+enum class Need { Skip, Prefer, Require };
+
+struct ProfileRules {
+  Need mpf;
+  Need xmp;
+  Need iso;
+  Need directory;
+  Need evenDimensions;
+  Need singleChannel;
+  Need geometry;
+};
+
+// Indexed by CompatProfile.
+constexpr ProfileRules kProfiles[] = {
+    // Android
+    {Need::Require, Need::Require, Need::Prefer, Need::Prefer, Need::Require, Need::Require,
+     Need::Require},
+    // Adobe
+    {Need::Require, Need::Require, Need::Prefer, Need::Skip, Need::Skip, Need::Skip,
+     Need::Prefer},
+    // Apple
+    {Need::Require, Need::Prefer, Need::Require, Need::Skip, Need::Skip, Need::Skip,
+     Need::Prefer},
+    // IsoStrict
+    {Need::Require, Need::Skip, Need::Require, Need::Skip, Need::Skip, Need::Skip, Need::Skip},
+};
+
+// Appends the outcome of `rule` and clears `problems` for the next rule.
+void addRule(std::vector<CompatItem>& items, const char* rule, Need need,
+             std::vector<std::string>& problems) {
+  if (need != Need::Skip) {
+    CompatItem item;
+    item.rule = rule;
+    if (!problems.empty()) {
+      item.status = need == Need::Require ? CompatStatus::Fail : CompatStatus::Warn;
+      item.message = problems[0];
+      for (size_t i = 1; i < problems.size(); ++i) item.message += "; " + problems[i];
+    }
+    items.push_back(item);
+  }
+  problems.clear();
+}
+
+}  // namespace
+
+std::vector<std::string> findConformanceViolations(const uint8_t* data, size_t size) {
+  std::vector<std::string> out;
+  size_t primaryEnd = findJpegEnd(data, size);
+  if (primaryEnd == 0) {
+    out.push_back("primary image is not a complete JPEG");
+    return out;
+  }
+
+  const uint8_t* gainMap = nullptr;
+  size_t gainMapSize = followMpf(data, size, gainMap, out);
+  if (gainMapSize == 0) return out;
+
+  JpegSegment iso;
+  std::string primaryXmp = findHdrgmXmp(data, primaryEnd);
+  if (!primaryXmp.empty()) {
+    checkVersion(primaryXmp, "primary XMP", out);
+  } else if (!hasIsoSegment(data, primaryEnd, iso)) {
+    out.push_back("primary image has no hdrgm XMP or ISO 21496-1 signalling");
+  }
+
+  std::string gainMapXmp = findHdrgmXmp(gainMap, gainMapSize);
+  bool gainMapIso = hasIsoSegment(gainMap, gainMapSize, iso);
+  if (!gainMapXmp.empty()) checkGainMapXmp(gainMapXmp, out);
+  if (gainMapIso) checkGainMapIso(gainMap, iso, out);
+  if (gainMapXmp.empty() && !gainMapIso) {
+    out.push_back("gain map has no hdrgm XMP or ISO 21496-1 metadata");
+  }
+
+  checkGeometry(data, primaryEnd, gainMap, gainMapSize, out);
+  return out;
+}
+
+std::vector<CompatItem> checkCompatibility(const uint8_t* data, size_t size,
+                                           CompatProfile profile) {
+  const ProfileRules& rules = kProfiles[static_cast<int>(profile)];
+  std::vector<CompatItem> items;
+  size_t primaryEnd = findJpegEnd(data, size);
+  ComponentLayout layout;
+  if (primaryEnd == 0 || !locateComponents(data, size, layout)) {
+    items.push_back({"gain-map", CompatStatus::Fail, "no primary and gain map JPEG pair found"});
+    return items;
+  }
+  const uint8_t* gainMap = data + layout.gainMap.offset;
+  size_t gainMapSize = layout.gainMap.length;
+  std::vector<std::string> problems;
+
+  const uint8_t* mpfGainMap = nullptr;
+  followMpf(data, size, mpfGainMap, problems);
+  addRule(items, "mpf", rules.mpf, problems);
+
+  // The other rules read the gain map wherever the file's own directory
+  // puts it, so a broken MPF index is reported once rather than everywhere.
+  std::string primaryXmp = findHdrgmXmp(data, primaryEnd);
+  std::string gainMapXmp = findHdrgmXmp(gainMap, gainMapSize);
+  if (primaryXmp.empty()) {
+    problems.push_back("primary image has no hdrgm XMP");
+  } else {
+    checkVersion(primaryXmp, "primary XMP", problems);
+  }
+  if (gainMapXmp.empty()) {
+    problems.push_back("gain map has no hdrgm XMP");
+  } else {
+    checkGainMapXmp(gainMapXmp, problems);
+  }
+  addRule(items, "xmp", rules.xmp, problems);
+
+  JpegSegment iso;
+  if (!hasIsoSegment(data, primaryEnd, iso)) {
+    problems.push_back("primary image has no ISO 21496-1 version segment");
+  }
+  if (hasIsoSegment(gainMap, gainMapSize, iso)) {
+    checkGainMapIso(gainMap, iso, problems);
+  } else {
+    problems.push_back("gain map has no ISO 21496-1 metadata");
+  }
+  addRule(items, "iso-metadata", rules.iso, problems);
+
+  if (primaryXmp.empty() || readContainerItems(primaryXmp.data(), primaryXmp.size()).empty()) {
+    problems.push_back("primary XMP has no GContainer directory");
+  }
+  addRule(items, "container-directory", rules.directory, problems);
+
+  JpegDims image = parseJpegDimensions(data, primaryEnd);
+  JpegDims map = parseJpegDimensions(gainMap, gainMapSize);
+  if (!image.ok) {
+    problems.push_back("primary JPEG has no readable SOF marker");
+  } else if (image.hSampling == 0 || image.vSampling == 0) {
+    problems.push_back("primary SOF lists no components");
+  } else if (image.width % image.hSampling != 0 || image.height % image.vSampling != 0) {
+    // Only chroma subsampling needs whole chroma samples; 4:4:4 may be odd.
+    problems.push_back("primary " + dimsToString(image.width, image.height) +
+                       " is not a multiple of its " +
+                       dimsToString(image.hSampling, image.vSampling) + " chroma subsampling");
+  }
+  addRule(items, "even-dimensions", rules.evenDimensions, problems);
+
+  if (!map.ok) {
+    problems.push_back("gain map JPEG has no readable SOF marker");
+  } else if (map.components != 1) {
+    problems.push_back("gain map has " + std::to_string(map.components) +
+                       " colour components, expected 1");
+  }
+  addRule(items, "gain-map-channels", rules.singleChannel, problems);
+
+  checkGeometry(data, primaryEnd, gainMap, gainMapSize, problems);
+  addRule(items, "gain-map-scale", rules.geometry, problems);
+  return items;
+}
+
+}  // namespace open_ultrahdr
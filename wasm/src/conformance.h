@@ -0,0 +1,46 @@
+#pragma once
+
+#include <cstddef>
+#include <cstdint>
+#include <string>
+#include <vector>
+
+namespace open_ultrahdr {
+
+// Structural conformance checks for an UltraHDR JPEG, run on the raw bytes
+// before any lenient decoding:
+//   - the primary's MPF segment flags only the first image as the primary and
+//     lists a gain map whose offset lands on a JPEG of exactly the listed size;
+//   - every hdrgm XMP block carries its required fields, with Version "1.0";
+//   - XMP and ISO 21496-1 metadata values are within spec ranges;
+//   - the gain map is a uniform 1x, 2x or 4x downscale of the primary.
+// Returns one human-readable message per violation; empty means conformant.
+std::vector<std::string> findConformanceViolations(const uint8_t* data, size_t size);
+
+// Ecosystems whose readers differ in what they require of an UltraHDR file.
+enum class CompatProfile { Android = 0, Adobe = 1, Apple = 2, IsoStrict = 3 };
+
+enum class CompatStatus { Pass, Warn, Fail };
+
+// Outcome of one rule of a profile. `rule` is a stable identifier such as
+// "mpf" or "even-dimensions"; `message` explains a warning or failure.
+struct CompatItem {
+  std::string rule;
+  CompatStatus status = CompatStatus::Pass;
+  std::string message;
+};
+
+// Runs the structural checks a profile's readers depend on:
+//   - Android: hdrgm v1 XMP, MPF, dimensions that are whole multiples of the
+//     primary's chroma subsampling (even for 4:2:0, anything for 4:4:4), a
+//     single-channel gain map at a 1x, 2x or 4x downscale; ISO metadata and a
+//     GContainer directory are recommended;
+//   - Adobe: hdrgm v1 XMP and MPF; ISO metadata is recommended;
+//   - Apple: ISO 21496-1 metadata and MPF; hdrgm XMP is recommended;
+//   - IsoStrict: ISO 21496-1 metadata on both images and MPF, with any XMP
+//     ignored.
+// Rules a profile does not care about are omitted from the result.
+std::vector<CompatItem> checkCompatibility(const uint8_t* data, size_t size,
+                                           CompatProfile profile);
+
+}  // namespace open_ultrahdr
@@ -0,0 +1,46 @@
+#include "gain_math.h"
+
+#include <algorithm>
+#include <cmath>
+
+namespace open_ultrahdr {
+
+float gainMapWeight(const GainMapParams& p, float displayHdrCapacity) {
+  if (p.hdrCapacityMax <= p.hdrCapacityMin) {
+    return displayHdrCapacity >= p.hdrCapacityMax ? 1.0f : 0.0f;
+  }
+  float w = (displayHdrCapacity - p.hdrCapacityMin) / (p.hdrCapacityMax - p.hdrCapacityMin);
+  return std::clamp(w, 0.0f, 1.0f);
+}
+
+float decodeGainLog2(const GainMapParams& p, int c, float encoded) {
+  float v = std::clamp(encoded, 0.0f, 1.0f);
+  if (p.gamma[c] != 1.0f) v = std::pow(v, 1.0f / p.gamma[c]);
+  return p.gainMapMin[c] + (p.gainMapMax[c] - p.gainMapMin[c]) * v;
+}
+
+float applyGain(const GainMapParams& p, int c, float sdrLinear, float log2Gain, float weight) {
+  if (weight <= 0.0f) return sdrLinear;
+  float hdr = (sdrLinear + p.offsetSdr[c]) * std::exp2(log2Gain * weight) - p.offsetHdr[c];
+  return std::max(hdr, 0.0f);
+}
+
+void applyGainRgb(const GainMapParams& p, const float (&sdrLinear)[3], const float (&log2Gain)[3],
+                  float weight, float (&hdr)[3]) {
+  if (p.applyInBaseSpace) {
+    for (int c = 0; c < 3; ++c) hdr[c] = applyGain(p, c, sdrLinear[c], log2Gain[c], weight);
+    return;
+  }
+  float applied[3];
+  for (int c = 0; c < 3; ++c) {
+    const float* row = p.toApplySpace + c * 3;
+    float v = row[0] * sdrLinear[0] + row[1] * sdrLinear[1] + row[2] * sdrLinear[2];
+    applied[c] = applyGain(p, c, v, log2Gain[c], weight);
+  }
+  for (int c = 0; c < 3; ++c) {
+    const float* row = p.fromApplySpace + c * 3;
+    hdr[c] = std::max(row[0] * applied[0] + row[1] * applied[1] + row[2] * applied[2], 0.0f);
+  }
+}
+
+}  // namespace open_ultrahdr
@@ -0,0 +1,47 @@
+#pragma once
+
+// Scalar gain map math (ISO 21496-1). Same constraints as color_math.h:
+// <cmath> only, no allocation, no exceptions.
+
+namespace open_ultrahdr {
+
+// Gain map metadata in the form the reconstruction math consumes. Gains and
+// capacities are in log2 stops, matching the JS-facing GainMapMetadata.
+struct GainMapParams {
+  float gainMapMin[3] = {0.0f, 0.0f, 0.0f};
+  float gainMapMax[3] = {0.0f, 0.0f, 0.0f};
+  float gamma[3] = {1.0f, 1.0f, 1.0f};
+  float offsetSdr[3] = {1.0f / 64.0f, 1.0f / 64.0f, 1.0f / 64.0f};
+  float offsetHdr[3] = {1.0f / 64.0f, 1.0f / 64.0f, 1.0f / 64.0f};
+  float hdrCapacityMin = 0.0f;
+  float hdrCapacityMax = 0.0f;
+  // ISO 21496-1 may apply the gain in another colour space than the base's
+  // (use_base_colour_space = 0). When it does, linear base RGB is taken into
+  // it by toApplySpace and the result brought back by fromApplySpace.
+  bool applyInBaseSpace = true;
+  float toApplySpace[9] = {1.0f, 0.0f, 0.0f, 0.0f, 1.0f, 0.0f, 0.0f, 0.0f, 1.0f};
+  float fromApplySpace[9] = {1.0f, 0.0f, 0.0f, 0.0f, 1.0f, 0.0f, 0.0f, 0.0f, 1.0f};
+};
+
+// Blend weight in [0, 1] for a display with the given headroom (log2 stops):
+// 0 at or below hdrCapacityMin (SDR output), 1 at or above hdrCapacityMax.
+float gainMapWeight(const GainMapParams& p, float displayHdrCapacity);
+
+// Decodes an encoded gain map value in [0, 1] to a log2 gain for channel `c`.
+float decodeGainLog2(const GainMapParams& p, int c, float encoded);
+
+// Applies a log2 gain to a linear SDR value for channel `c`. `weight` scales
+// the gain in the log domain, i.e. it interpolates the applied linear gain
+// towards 1.0 rather than interpolating the gain range endpoints towards 0.
+// Weight 0 therefore reproduces the SDR exactly even when the gain range is
+// negative (HDR darker than SDR), and weight 1 applies the full gain.
+// This is the reconstruction of both ISO 21496-1 and Adobe's hdrgm Gain Map
+// Specification 1.0, so XMP-only and ISO-described files share one path.
+float applyGain(const GainMapParams& p, int c, float sdrLinear, float log2Gain, float weight);
+
+// applyGain for all three channels of a linear base RGB pixel, in the colour
+// space the gain applies in. `hdr` is in the base's colour space.
+void applyGainRgb(const GainMapParams& p, const float (&sdrLinear)[3], const float (&log2Gain)[3],
+                  float weight, float (&hdr)[3]);
+
+}  // namespace open_ultrahdr
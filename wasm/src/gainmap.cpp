@@ -0,0 +1,698 @@
+#include "gainmap.h"
+
+#include <algorithm>
+#include <array>
+#include <cmath>
+#include <limits>
+#include <stdexcept>
+#include <string>
+
+#include "color_math.h"
+
+namespace open_ultrahdr {
+
+namespace {
+
+// hdrStrengthScore saturates its headroom and capacity terms at this many
+// stops, and its breadth term at this fraction of boosted pixels.
+constexpr float kStrengthFullStops = 3.0f;
+constexpr float kStrengthFullCoverage = 0.5f;
+
+const std::array<float, 256>& srgbDecodeLut() {
+  static const std::array<float, 256> lut = [] {
+    std::array<float, 256> out{};
+    for (int i = 0; i < 256; ++i) out[i] = srgbToLinear(static_cast<float>(i) / 255.0f);
+    return out;
+  }();
+  return lut;
+}
+
+inline const uint8_t* pixelAt(const ImageView8& img, uint32_t x, uint32_t y) {
+  return img.data + (static_cast<size_t>(y) * img.stride + x) * img.channels;
+}
+
+// Normalized [0, 1] value of channel `c`; grayscale images broadcast their
+// single channel.
+inline float channelValue(const ImageView8& img, uint32_t x, uint32_t y, int c) {
+  return pixelAt(img, x, y)[img.channels == 1 ? 0 : c] / 255.0f;
+}
+
+// Maps a continuous coordinate into [0, size - 1] according to `mode`.
+float extendCoord(float v, uint32_t size, GainMapEdgeMode mode) {
+  const float last = static_cast<float>(size - 1);
+  if (mode == GainMapEdgeMode::Mirror) {
+    if (v < 0.0f) v = -v;
+    if (v > last) v = 2.0f * last - v;
+  }
+  return std::clamp(v, 0.0f, last);
+}
+
+// Bilinear sample at continuous gain map coordinates, extended past the edges
+// according to `mode`.
+float sampleBilinear(const ImageView8& gm, float fx, float fy, int c, GainMapEdgeMode mode) {
+  fx = extendCoord(fx, gm.width, mode);
+  fy = extendCoord(fy, gm.height, mode);
+  uint32_t x0 = static_cast<uint32_t>(fx);
+  uint32_t y0 = static_cast<uint32_t>(fy);
+  uint32_t x1 = std::min(x0 + 1, gm.width - 1);
+  uint32_t y1 = std::min(y0 + 1, gm.height - 1);
+  float tx = fx - static_cast<float>(x0);
+  float ty = fy - static_cast<float>(y0);
+  float top = channelValue(gm, x0, y0, c) * (1.0f - tx) + channelValue(gm, x1, y0, c) * tx;
+  float bottom = channelValue(gm, x0, y1, c) * (1.0f - tx) + channelValue(gm, x1, y1, c) * tx;
+  return top * (1.0f - ty) + bottom * ty;
+}
+
+void checkImage(const ImageView8& img, const char* name) {
+  if (!img.data || img.width == 0 || img.height == 0) {
+    throw std::runtime_error(std::string(name) + " image is empty");
+  }
+  if (img.channels != 1 && img.channels != 3 && img.channels != 4) {
+    throw std::runtime_error(std::string(name) + " image has unsupported channel count");
+  }
+  if (img.stride < img.width) {
+    throw std::runtime_error(std::string(name) + " image stride is smaller than its width");
+  }
+}
+
+// Reconstructs the rows of `region`, passing each to `sink` with its row index
+// relative to the region. Gain map coordinates are derived from the full SDR
+// size, so any region lines up with a full reconstruction.
+void reconstructRegionRows(const ImageView8& sdr, const ImageView8& gainMap, const GainMapParams& p,
+                           const PixelRect& region, float displayHdrCapacity,
+                           const HdrRowSink& sink, GainMapEdgeMode edgeMode, float maxLinear) {
+  checkImage(sdr, "SDR");
+  checkImage(gainMap, "Gain map");
+  if (sdr.channels == 1) throw std::runtime_error("SDR image must be RGB or RGBA");
+
+  const auto& lut = srgbDecodeLut();
+  const float weight = gainMapWeight(p, displayHdrCapacity);
+  const float scaleX = static_cast<float>(gainMap.width) / static_cast<float>(sdr.width);
+  const float scaleY = static_cast<float>(gainMap.height) / static_cast<float>(sdr.height);
+
+  std::vector<float> row(static_cast<size_t>(region.width) * 3);
+  for (uint32_t ry = 0; ry < region.height; ++ry) {
+    uint32_t y = region.y + ry;
+    float gy = (static_cast<float>(y) + 0.5f) * scaleY - 0.5f;
+    for (uint32_t rx = 0; rx < region.width; ++rx) {
+      uint32_t x = region.x + rx;
+      float gx = (static_cast<float>(x) + 0.5f) * scaleX - 0.5f;
+      const uint8_t* px = pixelAt(sdr, x, y);
+      float sdrLinear[3], gain[3], hdr[3];
+      for (int c = 0; c < 3; ++c) {
+        sdrLinear[c] = lut[px[c]];
+        gain[c] = decodeGainLog2(p, c, sampleBilinear(gainMap, gx, gy, c, edgeMode));
+      }
+      applyGainRgb(p, sdrLinear, gain, weight, hdr);
+      for (float& v : hdr) v = std::min(v, maxLinear);
+      std::copy(hdr, hdr + 3, row.begin() + static_cast<std::ptrdiff_t>(rx) * 3);
+    }
+    sink(ry, row.data(), row.size());
+  }
+}
+
+// Extended Reinhard: compresses linear `v` into SDR range so that the white
+// point whose inverse square is `invWhiteSq` lands exactly on 1.0.
+inline float toneMapExtendedReinhard(float v, float invWhiteSq) {
+  return v * (1.0f + v * invWhiteSq) / (1.0f + v);
+}
+
+// BT.709 weighting of the sRGB-encoded samples of pixel (x, y), in [0, 255].
+inline float encodedLuma(const ImageView8& img, uint32_t x, uint32_t y) {
+  float v = 0.0f;
+  for (int c = 0; c < 3; ++c) v += kBt709Luma[c] * channelValue(img, x, y, c);
+  return v * 255.0f;
+}
+
+}  // namespace
+
+void applyGainMapRows(const ImageView8& sdr, const ImageView8& gainMap, const GainMapParams& p,
+                      float displayHdrCapacity, const HdrRowSink& sink,
+                      GainMapEdgeMode edgeMode, float maxLinear) {
+  reconstructRegionRows(sdr, gainMap, p, {0, 0, sdr.width, sdr.height}, displayHdrCapacity, sink,
+                        edgeMode, maxLinear);
+}
+
+std::vector<float> applyGainMapRegion(const ImageView8& sdr, const ImageView8& gainMap,
+                                      const GainMapParams& p, const PixelRect& region,
+                                      float displayHdrCapacity, GainMapEdgeMode edgeMode,
+                                      float maxLinear) {
+  if (region.width == 0 || region.height == 0) throw std::runtime_error("Region is empty");
+  if (region.x >= sdr.width || region.width > sdr.width - region.x || region.y >= sdr.height ||
+      region.height > sdr.height - region.y) {
+    throw std::runtime_error("Region lies outside the image");
+  }
+  std::vector<float> out(Dimensions{region.width, region.height}.rgbLength());
+  reconstructRegionRows(
+      sdr, gainMap, p, region, displayHdrCapacity,
+      [&](uint32_t y, const float* row, size_t length) {
+        std::copy(row, row + length, out.begin() + static_cast<size_t>(y) * length);
+      },
+      edgeMode, maxLinear);
+  return out;
+}
+
+std::vector<float> applyGainMap(const ImageView8& sdr, const ImageView8& gainMap,
+                                const GainMapParams& p, float displayHdrCapacity,
+                                GainMapEdgeMode edgeMode, float maxLinear) {
+  std::vector<float> out(Dimensions{sdr.width, sdr.height}.rgbLength());
+  applyGainMapRows(
+      sdr, gainMap, p, displayHdrCapacity,
+      [&](uint32_t y, const float* row, size_t length) {
+        std::copy(row, row + length, out.begin() + static_cast<size_t>(y) * length);
+      },
+      edgeMode, maxLinear);
+  return out;
+}
+
+std::vector<std::vector<float>> applyGainMapMulti(const ImageView8& sdr,
+                                                  const ImageView8& gainMap,
+                                                  const GainMapParams& p,
+                                                  const std::vector<float>& capacities,
+                                                  GainMapEdgeMode edgeMode, float maxLinear) {
+  checkImage(sdr, "SDR");
+  checkImage(gainMap, "Gain map");
+  if (sdr.channels == 1) throw std::runtime_error("SDR image must be RGB or RGBA");
+
+  const auto& lut = srgbDecodeLut();
+  std::vector<float> weights(capacities.size());
+  for (size_t i = 0; i < capacities.size(); ++i) weights[i] = gainMapWeight(p, capacities[i]);
+  const float scaleX = static_cast<float>(gainMap.width) / static_cast<float>(sdr.width);
+  const float scaleY = static_cast<float>(gainMap.height) / static_cast<float>(sdr.height);
+
+  size_t count = Dimensions{sdr.width, sdr.height}.rgbLength();
+  std::vector<std::vector<float>> out(capacities.size(), std::vector<float>(count));
+  for (uint32_t y = 0; y < sdr.height; ++y) {
+    float gy = (static_cast<float>(y) + 0.5f) * scaleY - 0.5f;
+    for (uint32_t x = 0; x < sdr.width; ++x) {
+      float gx = (static_cast<float>(x) + 0.5f) * scaleX - 0.5f;
+      const uint8_t* px = pixelAt(sdr, x, y);
+      size_t at = (static_cast<size_t>(y) * sdr.width + x) * 3;
+      float sdrLinear[3], gain[3], hdr[3];
+      for (int c = 0; c < 3; ++c) {
+        sdrLinear[c] = lut[px[c]];
+        gain[c] = decodeGainLog2(p, c, sampleBilinear(gainMap, gx, gy, c, edgeMode));
+      }
+      for (size_t i = 0; i < weights.size(); ++i) {
+        applyGainRgb(p, sdrLinear, gain, weights[i], hdr);
+        for (float& v : hdr) v = std::min(v, maxLinear);
+        std::copy(hdr, hdr + 3, out[i].begin() + static_cast<std::ptrdiff_t>(at));
+      }
+    }
+  }
+  return out;
+}
+
+std::vector<float> reconstructNits(const ImageView8& sdr, const ImageView8& gainMap,
+                                   const GainMapParams& p, float displayHdrCapacity,
+                                   float sdrWhiteNits, GainMapEdgeMode edgeMode,
+                                   const float (&luma)[3]) {
+  std::vector<float> out(Dimensions{sdr.width, sdr.height}.grayLength());
+  applyGainMapRows(
+      sdr, gainMap, p, displayHdrCapacity,
+      [&](uint32_t y, const float* row, size_t length) {
+        float* dst = out.data() + static_cast<size_t>(y) * sdr.width;
+        for (size_t i = 0; i < length / 3; ++i) {
+          const float* rgb = row + i * 3;
+          dst[i] = luminance(rgb[0], rgb[1], rgb[2], luma) * sdrWhiteNits;
+        }
+      },
+      edgeMode);
+  return out;
+}
+
+std::vector<uint8_t> renderThumbnail(const ImageView8& sdr, const ImageView8& gainMap,
+                                     const GainMapParams& p, float displayHdrCapacity,
+                                     uint32_t width, uint32_t height) {
+  checkImage(sdr, "SDR");
+  checkImage(gainMap, "Gain map");
+  if (sdr.channels == 1) throw std::runtime_error("SDR image must be RGB or RGBA");
+  if (width == 0 || height == 0) throw std::runtime_error("Thumbnail size must be non-zero");
+
+  const auto& lut = srgbDecodeLut();
+  const float weight = gainMapWeight(p, displayHdrCapacity);
+  const float headroom = std::clamp(displayHdrCapacity, 0.0f, std::max(p.hdrCapacityMax, 0.0f));
+  const float white = std::exp2(headroom);
+  const float invWhiteSq = 1.0f / (white * white);
+
+  std::vector<uint8_t> out(Dimensions{width, height}.rgbLength());
+  for (uint32_t y = 0; y < height; ++y) {
+    float v = (static_cast<float>(y) + 0.5f) / static_cast<float>(height);
+    uint32_t sy = std::min(static_cast<uint32_t>(v * sdr.height), sdr.height - 1);
+    uint32_t gy = std::min(static_cast<uint32_t>(v * gainMap.height), gainMap.height - 1);
+    for (uint32_t x = 0; x < width; ++x) {
+      float u = (static_cast<float>(x) + 0.5f) / static_cast<float>(width);
+      uint32_t sx = std::min(static_cast<uint32_t>(u * sdr.width), sdr.width - 1);
+      uint32_t gx = std::min(static_cast<uint32_t>(u * gainMap.width), gainMap.width - 1);
+      const uint8_t* px = pixelAt(sdr, sx, sy);
+      uint8_t* dst = out.data() + (static_cast<size_t>(y) * width + x) * 3;
+      float sdrLinear[3], gain[3], hdr[3];
+      for (int c = 0; c < 3; ++c) {
+        sdrLinear[c] = lut[px[c]];
+        gain[c] = decodeGainLog2(p, c, channelValue(gainMap, gx, gy, c));
+      }
+      applyGainRgb(p, sdrLinear, gain, weight, hdr);
+      for (int c = 0; c < 3; ++c) {
+        float mapped = toneMapExtendedReinhard(hdr[c], invWhiteSq);
+        float encoded = linearToSrgb(std::clamp(mapped, 0.0f, 1.0f));
+        dst[c] = static_cast<uint8_t>(std::lround(encoded * 255.0f));
+      }
+    }
+  }
+  return out;
+}
+
+float lumaSsim(const ImageView8& a, const ImageView8& b) {
+  checkImage(a, "First");
+  checkImage(b, "Second");
+  if (a.width != b.width || a.height != b.height) {
+    throw std::runtime_error("Images to compare differ in size");
+  }
+  constexpr uint32_t kWindow = 8;
+  constexpr uint32_t kStep = 4;
+  constexpr double kC1 = (0.01 * 255.0) * (0.01 * 255.0);
+  constexpr double kC2 = (0.03 * 255.0) * (0.03 * 255.0);
+  const uint32_t winW = std::min(kWindow, a.width);
+  const uint32_t winH = std::min(kWindow, a.height);
+
+  double total = 0.0;
+  size_t windows = 0;
+  for (uint32_t wy = 0; wy + winH <= a.height; wy += kStep) {
+    for (uint32_t wx = 0; wx + winW <= a.width; wx += kStep) {
+      double sa = 0.0, sb = 0.0, saa = 0.0, sbb = 0.0, sab = 0.0;
+      for (uint32_t y = wy; y < wy + winH; ++y) {
+        for (uint32_t x = wx; x < wx + winW; ++x) {
+          double va = encodedLuma(a, x, y);
+          double vb = encodedLuma(b, x, y);
+          sa += va;
+          sb += vb;
+          saa += va * va;
+          sbb += vb * vb;
+          sab += va * vb;
+        }
+      }
+      const double n = static_cast<double>(winW) * winH;
+      double ma = sa / n, mb = sb / n;
+      double varA = saa / n - ma * ma;
+      double varB = sbb / n - mb * mb;
+      double cov = sab / n - ma * mb;
+      total += (2.0 * ma * mb + kC1) * (2.0 * cov + kC2) /
+               ((ma * ma + mb * mb + kC1) * (varA + varB + kC2));
+      ++windows;
+    }
+  }
+  return static_cast<float>(total / static_cast<double>(windows));
+}
+
+float sdrReconstructionSimilarity(const ImageView8& sdr, const ImageView8& gainMap,
+                                  const GainMapParams& p) {
+  if (sdr.channels == 1) throw std::runtime_error("SDR image must be RGB or RGBA");
+  const float headroom = std::max(p.hdrCapacityMax, 0.0f);
+  std::vector<float> hdr = applyGainMap(sdr, gainMap, p, headroom);
+  const float white = std::exp2(headroom);
+  const float invWhiteSq = 1.0f / (white * white);
+
+  std::vector<uint8_t> rendered(hdr.size());
+  for (size_t i = 0; i < hdr.size(); ++i) {
+    float mapped = std::clamp(toneMapExtendedReinhard(hdr[i], invWhiteSq), 0.0f, 1.0f);
+    rendered[i] = static_cast<uint8_t>(std::lround(linearToSrgb(mapped) * 255.0f));
+  }
+  return lumaSsim(sdr, {rendered.data(), sdr.width, sdr.height, sdr.width, 3});
+}
+
+size_t Dimensions::samples(uint32_t channels) const {
+  const size_t limit = std::numeric_limits<size_t>::max();
+  bool fits = (height == 0 || width <= limit / height) &&
+              (channels == 0 || static_cast<size_t>(width) * height <= limit / channels);
+  if (!fits) {
+    throw std::runtime_error("Image " + std::to_string(width) + "x" + std::to_string(height) +
+                             " is too large");
+  }
+  return static_cast<size_t>(width) * height * channels;
+}
+
+Dimensions Dimensions::scaled(uint32_t scale) const {
+  if (scale == 0) throw std::runtime_error("Gain map scale must be positive");
+  return {width / scale + (width % scale != 0), height / scale + (height % scale != 0)};
+}
+
+ContentLightLevel computeContentLightLevel(const float* nits, size_t count) {
+  ContentLightLevel out;
+  if (count == 0) return out;
+  double sum = 0.0;
+  for (size_t i = 0; i < count; ++i) {
+    float v = std::max(nits[i], 0.0f);
+    out.maxCll = std::max(out.maxCll, v);
+    sum += v;
+  }
+  out.maxFall = static_cast<float>(sum / static_cast<double>(count));
+  return out;
+}
+
+ReconstructionError computeReconstructionError(const float* image, const float* reference,
+                                               size_t pixels) {
+  ReconstructionError out;
+  if (pixels == 0) return out;
+  double sumSq[3] = {0.0, 0.0, 0.0};
+  float peak = 1.0f;
+  for (size_t i = 0; i < pixels; ++i) {
+    for (int c = 0; c < 3; ++c) {
+      float ref = reference[i * 3 + c];
+      float diff = std::fabs(image[i * 3 + c] - ref);
+      sumSq[c] += static_cast<double>(diff) * diff;
+      out.maxError[c] = std::max(out.maxError[c], diff);
+      peak = std::max(peak, ref);
+    }
+  }
+  for (int c = 0; c < 3; ++c) {
+    double rmse = std::sqrt(sumSq[c] / static_cast<double>(pixels));
+    out.rmse[c] = static_cast<float>(rmse);
+    out.psnr[c] = rmse > 0.0 ? static_cast<float>(20.0 * std::log10(peak / rmse))
+                             : std::numeric_limits<float>::infinity();
+  }
+  return out;
+}
+
+GainMapStripBuilder::GainMapStripBuilder(uint32_t width, uint32_t height, const GainMapParams& p,
+                                         uint32_t scale, GainMapDownsample downsample,
+                                         const float* luma)
+    : width_(width), height_(height), scale_(scale), downsample_(downsample), params_(p) {
+  if (luma) {
+    useLuma_ = true;
+    std::copy(luma, luma + 3, luma_);
+  }
+  if (width == 0 || height == 0) throw std::runtime_error("SDR image is empty");
+  Dimensions gainMap = Dimensions{width, height}.scaled(scale);
+  gainMapWidth_ = gainMap.width;
+  blocks_.resize(static_cast<size_t>(gainMapWidth_) * channels());
+  out_.resize(gainMap.samples(channels()));
+  resetBlocks();
+}
+
+void GainMapStripBuilder::resetBlocks() {
+  double init = downsample_ == GainMapDownsample::MaxGain
+                    ? -std::numeric_limits<double>::infinity()
+                    : 0.0;
+  std::fill(blocks_.begin(), blocks_.end(), init);
+}
+
+void GainMapStripBuilder::addRows(const ImageView8& sdr, const float* hdr) {
+  checkImage(sdr, "SDR");
+  if (sdr.width != width_) throw std::runtime_error("SDR strip width does not match the image");
+  if (sdr.height > height_ - nextRow_) {
+    throw std::runtime_error("SDR strip runs past the bottom of the image");
+  }
+  const auto& lut = srgbDecodeLut();
+  const uint32_t channels = this->channels();
+  for (uint32_t row = 0; row < sdr.height; ++row, ++nextRow_) {
+    uint32_t gy = nextRow_ / scale_;
+    uint32_t y0 = gy * scale_;
+    uint32_t y1 = std::min(height_, y0 + scale_);
+    bool centerRow = nextRow_ == (y0 + y1 - 1) / 2;
+    for (uint32_t gx = 0; gx < gainMapWidth_; ++gx) {
+      uint32_t x0 = gx * scale_;
+      uint32_t x1 = std::min(width_, x0 + scale_);
+      for (uint32_t c = 0; c < channels; ++c) {
+        auto sdrAt = [&](uint32_t x, uint32_t k) {
+          return lut[pixelAt(sdr, x, row)[sdr.channels == 1 ? 0 : k]];
+        };
+        auto hdrAt = [&](uint32_t x, uint32_t k) {
+          return std::max(hdr[(static_cast<size_t>(row) * width_ + x) * 3 + k], 0.0f);
+        };
+        auto pixelGain = [&](uint32_t x) {
+          float s, h;
+          if (useLuma_) {
+            s = luminance(sdrAt(x, 0), sdrAt(x, 1), sdrAt(x, 2), luma_);
+            h = luminance(hdrAt(x, 0), hdrAt(x, 1), hdrAt(x, 2), luma_);
+          } else {
+            s = sdrAt(x, c);
+            h = hdrAt(x, c);
+          }
+          return std::log2((h + params_.offsetHdr[c]) / (s + params_.offsetSdr[c]));
+        };
+        double& block = blocks_[static_cast<size_t>(gx) * channels + c];
+        if (downsample_ == GainMapDownsample::CenterSample) {
+          if (centerRow) block = pixelGain((x0 + x1 - 1) / 2);
+        } else if (downsample_ == GainMapDownsample::MaxGain) {
+          for (uint32_t x = x0; x < x1; ++x) block = std::max<double>(block, pixelGain(x));
+        } else {
+          for (uint32_t x = x0; x < x1; ++x) block += pixelGain(x);
+        }
+      }
+    }
+    if (nextRow_ + 1 == y1) encodeBlockRow(gy, y1 - y0);
+  }
+}
+
+void GainMapStripBuilder::encodeBlockRow(uint32_t gy, uint32_t blockHeight) {
+  const uint32_t channels = this->channels();
+  for (uint32_t gx = 0; gx < gainMapWidth_; ++gx) {
+    uint32_t blockWidth = std::min(width_, gx * scale_ + scale_) - gx * scale_;
+    for (uint32_t c = 0; c < channels; ++c) {
+      double block = blocks_[static_cast<size_t>(gx) * channels + c];
+      float log2Gain = downsample_ == GainMapDownsample::BoxAverage
+                           ? static_cast<float>(block / (blockWidth * blockHeight))
+                           : static_cast<float>(block);
+      float range = params_.gainMapMax[c] - params_.gainMapMin[c];
+      float normalized =
+          range > 0.0f ? std::clamp((log2Gain - params_.gainMapMin[c]) / range, 0.0f, 1.0f)
+                       : 0.0f;
+      // decodeGainLog2 undoes this with pow(encoded, 1 / gamma).
+      float encoded = std::pow(normalized, params_.gamma[c]);
+      out_[(static_cast<size_t>(gy) * gainMapWidth_ + gx) * channels + c] =
+          static_cast<uint8_t>(std::lround(encoded * 255.0f));
+    }
+  }
+  resetBlocks();
+}
+
+std::vector<uint8_t> GainMapStripBuilder::finish() {
+  if (nextRow_ != height_) {
+    throw std::runtime_error("Gain map needs " + std::to_string(height_) + " rows, got " +
+                             std::to_string(nextRow_));
+  }
+  return std::move(out_);
+}
+
+std::vector<uint8_t> computeGainMap(const ImageView8& sdr, const float* hdr,
+                                    const GainMapParams& p, uint32_t scale,
+                                    GainMapDownsample downsample, const float* luma) {
+  checkImage(sdr, "SDR");
+  GainMapStripBuilder builder(sdr.width, sdr.height, p, scale, downsample, luma);
+  builder.addRows(sdr, hdr);
+  return builder.finish();
+}
+
+std::vector<uint8_t> blurGainMap(const ImageView8& gainMap, float sigma) {
+  checkImage(gainMap, "Gain map");
+  uint32_t width = gainMap.width;
+  uint32_t height = gainMap.height;
+  uint32_t channels = gainMap.channels;
+  std::vector<uint8_t> out(Dimensions{width, height}.samples(channels));
+  for (uint32_t y = 0; y < height; ++y) {
+    const uint8_t* row = pixelAt(gainMap, 0, y);
+    std::copy(row, row + static_cast<size_t>(width) * channels,
+              out.begin() + static_cast<size_t>(y) * width * channels);
+  }
+  if (!(sigma > 0.0f)) return out;
+
+  int radius = std::max(1, static_cast<int>(std::ceil(3.0f * sigma)));
+  std::vector<float> kernel(2 * radius + 1);
+  float total = 0.0f;
+  for (int k = -radius; k <= radius; ++k) {
+    kernel[k + radius] = std::exp(-0.5f * (k * k) / (sigma * sigma));
+    total += kernel[k + radius];
+  }
+  for (float& w : kernel) w /= total;
+
+  // Horizontal pass into floats, then vertical pass back to bytes, so the
+  // intermediate result is not quantized.
+  std::vector<float> rows(out.size());
+  int maxX = static_cast<int>(width) - 1;
+  int maxY = static_cast<int>(height) - 1;
+  for (uint32_t y = 0; y < height; ++y) {
+    const uint8_t* src = out.data() + static_cast<size_t>(y) * width * channels;
+    float* dst = rows.data() + static_cast<size_t>(y) * width * channels;
+    for (int x = 0; x <= maxX; ++x) {
+      for (uint32_t c = 0; c < channels; ++c) {
+        float sum = 0.0f;
+        for (int k = -radius; k <= radius; ++k) {
+          int sx = std::clamp(x + k, 0, maxX);
+          sum += kernel[k + radius] * src[static_cast<size_t>(sx) * channels + c];
+        }
+        dst[static_cast<size_t>(x) * channels + c] = sum;
+      }
+    }
+  }
+  size_t rowLength = static_cast<size_t>(width) * channels;
+  for (int y = 0; y <= maxY; ++y) {
+    for (size_t i = 0; i < rowLength; ++i) {
+      float sum = 0.0f;
+      for (int k = -radius; k <= radius; ++k) {
+        int sy = std::clamp(y + k, 0, maxY);
+        sum += kernel[k + radius] * rows[static_cast<size_t>(sy) * rowLength + i];
+      }
+      out[static_cast<size_t>(y) * rowLength + i] =
+          static_cast<uint8_t>(std::clamp(std::lround(sum), 0L, 255L));
+    }
+  }
+  return out;
+}
+
+GainMapClipStats computeClipStats(const ImageView8& gainMap) {
+  checkImage(gainMap, "Gain map");
+  size_t counts[2][3] = {{0, 0, 0}, {0, 0, 0}};
+  int channels = gainMap.channels == 1 ? 1 : 3;
+  for (uint32_t y = 0; y < gainMap.height; ++y) {
+    for (uint32_t x = 0; x < gainMap.width; ++x) {
+      const uint8_t* px = pixelAt(gainMap, x, y);
+      for (int c = 0; c < channels; ++c) {
+        if (px[c] == 0) ++counts[0][c];
+        if (px[c] == 255) ++counts[1][c];
+      }
+    }
+  }
+  GainMapClipStats out;
+  float total = static_cast<float>(gainMap.width) * static_cast<float>(gainMap.height);
+  for (int c = 0; c < 3; ++c) {
+    int src = channels == 1 ? 0 : c;
+    out.low[c] = static_cast<float>(counts[0][src]) / total;
+    out.high[c] = static_cast<float>(counts[1][src]) / total;
+  }
+  return out;
+}
+
+float gainPercentileLog2(const ImageView8& sdr, const float* hdr, float offset, float maxLog2,
+                         float percentile, const float* luma) {
+  checkImage(sdr, "SDR");
+  if (!(percentile > 0.0f) || percentile > 100.0f) {
+    throw std::runtime_error("Percentile must be in (0, 100]");
+  }
+  if (!(maxLog2 > 0.0f)) return 0.0f;
+  float weights[3] = {0.0f, 0.0f, 0.0f};
+  if (luma) std::copy(luma, luma + 3, weights);
+  const auto& lut = srgbDecodeLut();
+  std::vector<uint64_t> bins(kGainHistogramBins, 0);
+  uint64_t total = 0;
+  float peak = 0.0f;
+  auto add = [&](float s, float h) {
+    float gain = std::clamp(std::log2((h + offset) / (s + offset)), 0.0f, maxLog2);
+    size_t bin = static_cast<size_t>(gain / maxLog2 * kGainHistogramBins);
+    ++bins[std::min(bin, kGainHistogramBins - 1)];
+    ++total;
+    peak = std::max(peak, gain);
+  };
+  for (uint32_t y = 0; y < sdr.height; ++y) {
+    for (uint32_t x = 0; x < sdr.width; ++x) {
+      const uint8_t* px = pixelAt(sdr, x, y);
+      const float* hp = hdr + (static_cast<size_t>(y) * sdr.width + x) * 3;
+      float s[3], h[3];
+      for (int k = 0; k < 3; ++k) {
+        s[k] = lut[px[sdr.channels == 1 ? 0 : k]];
+        h[k] = std::max(hp[k], 0.0f);
+      }
+      if (luma) {
+        add(luminance(s[0], s[1], s[2], weights), luminance(h[0], h[1], h[2], weights));
+      } else {
+        for (int k = 0; k < 3; ++k) add(s[k], h[k]);
+      }
+    }
+  }
+  uint64_t needed = static_cast<uint64_t>(std::ceil(total * (percentile / 100.0)));
+  uint64_t seen = 0;
+  for (size_t i = 0; i < kGainHistogramBins; ++i) {
+    seen += bins[i];
+    if (seen >= needed) {
+      return std::min(peak, static_cast<float>(i + 1) * maxLog2 / kGainHistogramBins);
+    }
+  }
+  return peak;
+}
+
+std::vector<float> decodeGainRatios(const ImageView8& gainMap, const GainMapParams& p) {
+  checkImage(gainMap, "Gain map");
+  int channels = gainMap.channels == 1 ? 1 : 3;
+  std::vector<float> out;
+  out.reserve(Dimensions{gainMap.width, gainMap.height}.samples(channels));
+  for (uint32_t y = 0; y < gainMap.height; ++y) {
+    for (uint32_t x = 0; x < gainMap.width; ++x) {
+      for (int c = 0; c < channels; ++c) {
+        out.push_back(std::exp2(decodeGainLog2(p, c, channelValue(gainMap, x, y, c))));
+      }
+    }
+  }
+  return out;
+}
+
+std::vector<float> gainDecodeLut(const GainMapParams& p, int c, size_t size) {
+  if (c < 0 || c > 2) throw std::runtime_error("Channel must be 0, 1 or 2");
+  if (size < 2) throw std::runtime_error("Lookup table needs at least 2 entries");
+  std::vector<float> out(size);
+  float last = static_cast<float>(size - 1);
+  for (size_t i = 0; i < size; ++i) {
+    out[i] = std::exp2(decodeGainLog2(p, c, static_cast<float>(i) / last));
+  }
+  return out;
+}
+
+float peakGainLog2(const ImageView8& gainMap, const GainMapParams& p) {
+  checkImage(gainMap, "Gain map");
+  int channels = gainMap.channels == 1 ? 1 : 3;
+  float peak = 0.0f;
+  for (uint32_t y = 0; y < gainMap.height; ++y) {
+    for (uint32_t x = 0; x < gainMap.width; ++x) {
+      for (int c = 0; c < channels; ++c) {
+        peak = std::max(peak, decodeGainLog2(p, c, channelValue(gainMap, x, y, c)));
+      }
+    }
+  }
+  return peak;
+}
+
+float gainCoverage(const ImageView8& gainMap, const GainMapParams& p, float minLog2) {
+  checkImage(gainMap, "Gain map");
+  if (gainMap.width == 0 || gainMap.height == 0) return 0.0f;
+  int channels = gainMap.channels == 1 ? 1 : 3;
+  size_t boosted = 0;
+  for (uint32_t y = 0; y < gainMap.height; ++y) {
+    for (uint32_t x = 0; x < gainMap.width; ++x) {
+      for (int c = 0; c < channels; ++c) {
+        if (decodeGainLog2(p, c, channelValue(gainMap, x, y, c)) >= minLog2) {
+          ++boosted;
+          break;
+        }
+      }
+    }
+  }
+  return static_cast<float>(boosted) / (static_cast<float>(gainMap.width) * gainMap.height);
+}
+
+uint8_t hdrStrengthScore(const GainMapParams& p, float coverage) {
+  float headroom = std::max({p.gainMapMax[0], p.gainMapMax[1], p.gainMapMax[2], 0.0f});
+  float range = std::max(p.hdrCapacityMax - p.hdrCapacityMin, 0.0f);
+  float magnitude = (2.0f * std::min(headroom / kStrengthFullStops, 1.0f) +
+                     std::min(range / kStrengthFullStops, 1.0f)) /
+                    3.0f;
+  float breadth = std::sqrt(std::clamp(coverage / kStrengthFullCoverage, 0.0f, 1.0f));
+  return static_cast<uint8_t>(std::lround(100.0f * magnitude * breadth));
+}
+
+float estimateGainMapGamma(const uint8_t* encoded, const float* ratios, size_t count,
+                           float gainMapMin, float gainMapMax) {
+  if (!(gainMapMax > gainMapMin)) return 1.0f;
+  double numerator = 0.0;
+  double denominator = 0.0;
+  for (size_t i = 0; i < count; ++i) {
+    if (encoded[i] == 0 || encoded[i] == 255 || !(ratios[i] > 0.0f)) continue;
+    double normalized = (std::log2(ratios[i]) - gainMapMin) / (gainMapMax - gainMapMin);
+    if (!(normalized > 0.0) || normalized >= 1.0) continue;
+    double x = std::log(normalized);
+    double y = std::log(encoded[i] / 255.0);
+    numerator += x * y;
+    denominator += x * x;
+  }
+  if (denominator <= 0.0) return 1.0f;
+  return static_cast<float>(numerator / denominator);
+}
+
+}  // namespace open_ultrahdr
@@ -0,0 +1,284 @@
+#pragma once
+
+#include <cstddef>
+#include <cstdint>
+#include <functional>
+#include <limits>
+#include <vector>
+
+#include "color_math.h"
+#include "gain_math.h"
+
+namespace open_ultrahdr {
+
+// Non-owning view of an 8-bit image. `channels` is 1 (grayscale), 3 (RGB) or
+// 4 (RGBA, alpha ignored); `stride` is in pixels.
+struct ImageView8 {
+  const uint8_t* data = nullptr;
+  uint32_t width = 0;
+  uint32_t height = 0;
+  uint32_t stride = 0;
+  uint32_t channels = 0;
+};
+
+// Image size in pixels. Sample counts are overflow-checked and throw
+// std::runtime_error instead of wrapping: size_t is 32 bits in wasm, where
+// 65536 x 65536 pixels already do not fit.
+struct Dimensions {
+  uint32_t width = 0;
+  uint32_t height = 0;
+
+  size_t pixelCount() const { return samples(1); }
+  size_t rgbLength() const { return samples(3); }
+  size_t grayLength() const { return samples(1); }
+  // pixelCount() * channels.
+  size_t samples(uint32_t channels) const;
+
+  // Size of a gain map with one sample per `scale` x `scale` block, each side
+  // rounded up. Throws for a zero scale.
+  Dimensions scaled(uint32_t scale) const;
+};
+
+// How gain map samples beyond the outermost texel centers are extended.
+// Clamp repeats the edge texel; Mirror reflects about it (edge texel not
+// repeated), matching encoders that downsample with symmetric extension.
+enum class GainMapEdgeMode { Clamp = 0, Mirror = 1 };
+
+// How computeGainMap reduces each scale x scale block of pixel gains to one
+// gain map sample. CenterSample takes the pixel at the block's center, which
+// aliases on detail finer than the block; BoxAverage averages the log2 gains;
+// MaxGain keeps the largest log2 gain so small highlights are not dimmed.
+// 0 is left for the JS encode option that keeps libultrahdr's own gain map.
+enum class GainMapDownsample { CenterSample = 1, BoxAverage = 2, MaxGain = 3 };
+
+// Axis-aligned rectangle of pixels, in full-image coordinates.
+struct PixelRect {
+  uint32_t x = 0;
+  uint32_t y = 0;
+  uint32_t width = 0;
+  uint32_t height = 0;
+};
+
+// Reconstruction ceiling meaning none: linear values are only floored at 0.
+constexpr float kUnboundedLinear = std::numeric_limits<float>::infinity();
+
+// Receives one reconstructed row of linear RGB (3 floats per pixel, SDR white
+// = 1.0). The row buffer is reused and is only valid for the duration of the
+// call.
+using HdrRowSink = std::function<void(uint32_t y, const float* row, size_t length)>;
+
+// Reconstructs linear HDR from an sRGB-encoded SDR base and its gain map, one
+// row at a time, without allocating the full HDR buffer. Values above
+// `maxLinear` are clamped to it, so later PQ or 16-bit quantization cannot
+// overflow; the apply variants below take the same ceiling.
+//
+// The gain map is sampled bilinearly at each pixel's center mapped into it,
+// whatever the size ratio. A gain map larger than the SDR, which the spec
+// does not provide for but malformed files carry, is accepted rather than
+// downsampled first: at 2x each pixel averages the 2x2 texels it covers, and
+// at larger ratios the texels nearest its center.
+void applyGainMapRows(const ImageView8& sdr, const ImageView8& gainMap, const GainMapParams& p,
+                      float displayHdrCapacity, const HdrRowSink& sink,
+                      GainMapEdgeMode edgeMode = GainMapEdgeMode::Clamp,
+                      float maxLinear = kUnboundedLinear);
+
+// Bulk variant of applyGainMapRows returning width * height * 3 floats.
+std::vector<float> applyGainMap(const ImageView8& sdr, const ImageView8& gainMap,
+                                const GainMapParams& p, float displayHdrCapacity,
+                                GainMapEdgeMode edgeMode = GainMapEdgeMode::Clamp,
+                                float maxLinear = kUnboundedLinear);
+
+// Reconstructs like applyGainMap at each of `capacities` in one pass. Each
+// SDR sample is linearized and each gain sampled and decoded once; only the
+// weighted blend is repeated per capacity. Element i equals applyGainMap at
+// capacities[i].
+std::vector<std::vector<float>> applyGainMapMulti(
+    const ImageView8& sdr, const ImageView8& gainMap, const GainMapParams& p,
+    const std::vector<float>& capacities, GainMapEdgeMode edgeMode = GainMapEdgeMode::Clamp,
+    float maxLinear = kUnboundedLinear);
+
+// Reconstructs only the pixels inside `region`, for viewers that render one
+// tile of a large image at a time. `sdr` is the full SDR image; the gain map
+// is sampled at the same global coordinates as a full reconstruction, so the
+// result equals the matching slice of applyGainMap. Returns region.width *
+// region.height * 3 floats; throws if the region is empty or out of bounds.
+std::vector<float> applyGainMapRegion(const ImageView8& sdr, const ImageView8& gainMap,
+                                      const GainMapParams& p, const PixelRect& region,
+                                      float displayHdrCapacity,
+                                      GainMapEdgeMode edgeMode = GainMapEdgeMode::Clamp,
+                                      float maxLinear = kUnboundedLinear);
+
+// Reconstructs like applyGainMap and returns one luminance value per pixel
+// in nits, with SDR diffuse white (linear 1.0) at `sdrWhiteNits`. Luminance
+// weights the RGB by `luma`, BT.709's unless given.
+std::vector<float> reconstructNits(const ImageView8& sdr, const ImageView8& gainMap,
+                                   const GainMapParams& p, float displayHdrCapacity,
+                                   float sdrWhiteNits,
+                                   GainMapEdgeMode edgeMode = GainMapEdgeMode::Clamp,
+                                   const float (&luma)[3] = kBt709Luma);
+
+// Renders a `width` x `height` preview for a display with the given headroom:
+// SDR pixels are decimated and the gain map sampled nearest-neighbour (no
+// filtering), then the reconstructed highlights are compressed back into SDR
+// range with an extended Reinhard curve whose white point is the applied
+// headroom. Returns width * height * 3 sRGB-encoded bytes.
+std::vector<uint8_t> renderThumbnail(const ImageView8& sdr, const ImageView8& gainMap,
+                                     const GainMapParams& p, float displayHdrCapacity,
+                                     uint32_t width, uint32_t height);
+
+// Mean SSIM of the BT.709 luma of two RGB images of the same size, over 8x8
+// windows stepped by 4 (a side shorter than 8 is covered by one window).
+// 1.0 means identical structure; unrelated content scores near 0.
+float lumaSsim(const ImageView8& a, const ImageView8& b);
+
+// Self-contained sanity metric: reconstructs at the full hdrCapacityMax,
+// tone maps back into SDR range as renderThumbnail does and returns lumaSsim
+// against the SDR base. A gain map that does not belong to its base, or is
+// corrupt, scores far lower than a well-formed one.
+float sdrReconstructionSimilarity(const ImageView8& sdr, const ImageView8& gainMap,
+                                  const GainMapParams& p);
+
+// HDR10 content light level (CTA-861.3), in nits.
+struct ContentLightLevel {
+  float maxCll = 0.0f;   // Brightest pixel.
+  float maxFall = 0.0f;  // Frame-average light level.
+};
+
+// Computes MaxCLL/MaxFALL from per-pixel luminance in nits, as produced by
+// reconstructNits. Negative values count as 0.
+ContentLightLevel computeContentLightLevel(const float* nits, size_t count);
+
+// Per-channel (RGB) error of a linear HDR image against a reference.
+struct ReconstructionError {
+  float rmse[3] = {0.0f, 0.0f, 0.0f};
+  float maxError[3] = {0.0f, 0.0f, 0.0f};  // Largest absolute difference.
+  // 20 log10(peak / rmse), where peak is the brightest reference sample but
+  // at least SDR white (1.0); infinite when the channel matches exactly.
+  float psnr[3] = {0.0f, 0.0f, 0.0f};
+};
+
+// Compares `pixels` RGB pixels of `image` with `reference` (3 floats each).
+ReconstructionError computeReconstructionError(const float* image, const float* reference,
+                                               size_t pixels);
+
+// Computes an RGB gain map from an sRGB-encoded SDR image and linear HDR (3
+// floats per pixel, SDR white = 1.0, same primaries as the SDR), using the
+// offsets, log2 gain range and gamma of channel c in `p`. Each `scale` x
+// `scale` block is reduced to one sample by `downsample`; returns
+// ceil(width / scale) * ceil(height / scale) * 3 bytes.
+//
+// With `luma` set the gain map is single-channel instead (one byte per
+// sample): each pixel's gain is the ratio of HDR to SDR luminance under those
+// weights, taken against channel 0 of `p`.
+std::vector<uint8_t> computeGainMap(
+    const ImageView8& sdr, const float* hdr, const GainMapParams& p, uint32_t scale,
+    GainMapDownsample downsample = GainMapDownsample::BoxAverage, const float* luma = nullptr);
+
+// Bins gainPercentileLog2 spreads its [0, maxLog2] range over.
+constexpr size_t kGainHistogramBins = 1024;
+
+// The log2 gain at `percentile` (in (0, 100]) of the per-pixel gains
+// computeGainMap derives from `sdr` and `hdr` with `offset` on both sides:
+// every channel counts, or with `luma` each pixel's luminance gain once.
+// Gains are histogrammed over [0, maxLog2] stops, clamping those outside it,
+// and the result is the upper edge of the bin the percentile falls in, capped
+// at the largest gain seen. So 100 gives the true maximum, and 99.9 ignores
+// the brightest 0.1% of samples, such as a few hot pixels. Every pixel is
+// visited: there is no sampling stride that could alias with stripes or
+// other regular structure and miss features between the sampled columns.
+float gainPercentileLog2(const ImageView8& sdr, const float* hdr, float offset, float maxLog2,
+                         float percentile, const float* luma = nullptr);
+
+// Builds the same gain map as computeGainMap from SDR and HDR supplied a strip
+// of rows at a time, top to bottom, so neither has to be held whole. Only one
+// row of gain map blocks is accumulated; each finished block is quantized
+// straight into the output.
+class GainMapStripBuilder {
+ public:
+  GainMapStripBuilder(uint32_t width, uint32_t height, const GainMapParams& p, uint32_t scale,
+                      GainMapDownsample downsample = GainMapDownsample::BoxAverage,
+                      const float* luma = nullptr);
+
+  // 1 for a luminance gain map, otherwise 3.
+  uint32_t channels() const { return useLuma_ ? 1 : 3; }
+
+  // Adds the next `sdr.height` rows: `sdr` views just the strip and `hdr`
+  // holds its sdr.width * sdr.height * 3 floats. Throws if the strip is not
+  // as wide as the image or runs past its bottom.
+  void addRows(const ImageView8& sdr, const float* hdr);
+
+  // Returns the gain map, laid out as computeGainMap's. Throws unless every
+  // row has been added.
+  std::vector<uint8_t> finish();
+
+ private:
+  void resetBlocks();
+  void encodeBlockRow(uint32_t gy, uint32_t blockHeight);
+
+  uint32_t width_;
+  uint32_t height_;
+  uint32_t scale_;
+  GainMapDownsample downsample_;
+  GainMapParams params_;
+  bool useLuma_ = false;  // Single-channel gain map from luma_-weighted luminance.
+  float luma_[3] = {0.0f, 0.0f, 0.0f};
+  uint32_t gainMapWidth_ = 0;
+  uint32_t nextRow_ = 0;
+  std::vector<double> blocks_;  // Per block and channel: sum, max or center gain.
+  std::vector<uint8_t> out_;
+};
+
+// Applies a separable Gaussian blur of standard deviation `sigma` (in gain map
+// pixels) to every channel of an 8-bit gain map, clamping at the edges. The
+// kernel spans 3 sigma each way and is normalized, so flat regions and the
+// overall mean are kept. Returns width * height * channels packed bytes; a
+// non-positive sigma returns an unblurred copy.
+std::vector<uint8_t> blurGainMap(const ImageView8& gainMap, float sigma);
+
+// Per-channel fraction [0, 1] of gain map samples at the quantization limits.
+struct GainMapClipStats {
+  float low[3] = {0.0f, 0.0f, 0.0f};
+  float high[3] = {0.0f, 0.0f, 0.0f};
+};
+
+// Measures saturation of an 8-bit gain map. Grayscale maps report their single
+// channel for all three entries.
+GainMapClipStats computeClipStats(const ImageView8& gainMap);
+
+// Decodes every gain map sample to the linear gain ratio it applies at full
+// weight, 2^decodeGainLog2. Grayscale maps yield one float per pixel, colour
+// maps three (RGB, alpha dropped).
+std::vector<float> decodeGainRatios(const ImageView8& gainMap, const GainMapParams& p);
+
+// Samples the decode curve of channel `c` at `size` evenly spaced encoded
+// values from 0 to 1 inclusive: entry i is 2^decodeGainLog2(p, c, i / (size -
+// 1)), so a 1D texture lookup reproduces decodeGainRatios. `size` must be at
+// least 2.
+std::vector<float> gainDecodeLut(const GainMapParams& p, int c, size_t size);
+
+// Largest log2 gain any sample of `gainMap` applies under `p`, i.e. the
+// headroom the content actually uses rather than the range it was encoded
+// with. Never negative.
+float peakGainLog2(const ImageView8& gainMap, const GainMapParams& p);
+
+// Fraction [0, 1] of gain map pixels that brighten some channel by at least
+// `minLog2` stops under `p`.
+float gainCoverage(const ImageView8& gainMap, const GainMapParams& p, float minLog2);
+
+// Sortable 0-100 estimate of how HDR an image looks. Headroom (the largest
+// gainMapMax) and the HDR capacity range, each saturating at 3 stops, set the
+// magnitude, weighted 2:1; it is scaled by the square root of `coverage`
+// (from gainCoverage) relative to half the frame. So a wide gain range used
+// only by a small highlight scores below a smaller one applied broadly, and
+// a gain map that boosts nothing scores 0.
+uint8_t hdrStrengthScore(const GainMapParams& p, float coverage);
+
+// Recovers the gamma a gain map was encoded with from sampled pairs of encoded
+// bytes and the linear gain ratios they stand for, given the map's log2 gain
+// range. Least-squares fit of log(encoded) = gamma * log(normalized gain);
+// samples at either end of the range carry no gamma information and are
+// skipped. Returns 1.0 when no sample is usable.
+float estimateGainMapGamma(const uint8_t* encoded, const float* ratios, size_t count,
+                           float gainMapMin, float gainMapMax);
+
+}  // namespace open_ultrahdr
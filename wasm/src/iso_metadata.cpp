@@ -0,0 +1,232 @@
+#include "iso_metadata.h"
+
+#include <cstring>
+#include <map>
+#include <string>
+#include <utility>
+#include <vector>
+
+#include "jpeg_meta.h"
+
+namespace open_ultrahdr {
+
+namespace {
+
+constexpr char kIsoUrn[] = "urn:iso:std:iso:ts:21496:-1";
+
+constexpr uint8_t kMultiChannelFlag = 0x80;
+constexpr uint8_t kUseBaseColorSpaceFlag = 0x40;
+constexpr uint8_t kBackwardDirectionFlag = 0x04;
+constexpr uint8_t kCommonDenominatorFlag = 0x08;
+
+constexpr uint32_t fourCc(const char (&s)[5]) {
+  return (static_cast<uint32_t>(s[0]) << 24) | (static_cast<uint32_t>(s[1]) << 16) |
+         (static_cast<uint32_t>(s[2]) << 8) | static_cast<uint32_t>(s[3]);
+}
+constexpr uint32_t kJumbBox = fourCc("jumb");
+constexpr uint32_t kJumdBox = fourCc("jumd");
+constexpr uint8_t kJumdLabelToggle = 0x02;
+
+// Bounds-checked big-endian reader.
+class Reader {
+ public:
+  Reader(const uint8_t* data, size_t size) : data_(data), size_(size) {}
+
+  bool ok() const { return ok_; }
+  size_t pos() const { return pos_; }
+
+  uint8_t u8() { return static_cast<uint8_t>(read(1)); }
+  uint16_t u16() { return static_cast<uint16_t>(read(2)); }
+  uint32_t u32() { return static_cast<uint32_t>(read(4)); }
+  int32_t s32() { return static_cast<int32_t>(u32()); }
+  uint64_t u64() { return read(8); }
+
+ private:
+  uint64_t read(size_t n) {
+    if (!ok_ || size_ - pos_ < n) {
+      ok_ = false;
+      return 0;
+    }
+    uint64_t v = 0;
+    for (size_t i = 0; i < n; ++i) v = (v << 8) | data_[pos_++];
+    return v;
+  }
+
+  const uint8_t* data_;
+  size_t size_;
+  size_t pos_ = 0;
+  bool ok_ = true;
+};
+
+// Reads a signed or unsigned fraction, rejecting a zero denominator.
+bool fraction(int64_t numerator, uint32_t denominator, float& out) {
+  if (denominator == 0) return false;
+  out = static_cast<float>(static_cast<double>(numerator) / denominator);
+  return true;
+}
+
+struct Box {
+  uint32_t type = 0;
+  size_t offset = 0;  // Start of the box header.
+  size_t payloadOffset = 0;
+  size_t end = 0;  // One past the last payload byte.
+};
+
+bool readBox(const uint8_t* data, size_t size, size_t at, Box& box) {
+  Reader r(data + at, size - at);
+  uint64_t length = r.u32();
+  box.type = r.u32();
+  if (length == 1) {
+    length = r.u64();
+  } else if (length == 0) {
+    length = size - at;  // Box extends to the end of the enclosing data.
+  }
+  if (!r.ok() || length < r.pos() || length > size - at) return false;
+  box.offset = at;
+  box.payloadOffset = at + r.pos();
+  box.end = at + static_cast<size_t>(length);
+  return true;
+}
+
+// Size of a box header: LBox and TBox, plus XLBox when LBox is 1.
+size_t boxHeaderSize(const std::vector<uint8_t>& bytes) {
+  bool extended = bytes.size() >= 4 && bytes[0] == 0 && bytes[1] == 0 && bytes[2] == 0 &&
+                  bytes[3] == 1;
+  return extended ? 16 : 8;
+}
+
+// Label of a JUMBF description box, or empty if it has none.
+std::string jumdLabel(const uint8_t* data, const Box& jumd) {
+  // Type UUID (16 bytes), then the toggles byte.
+  size_t p = jumd.payloadOffset + 16;
+  if (p >= jumd.end || !(data[p] & kJumdLabelToggle)) return {};
+  ++p;
+  const char* label = reinterpret_cast<const char*>(data + p);
+  const void* nul = std::memchr(label, 0, jumd.end - p);
+  if (!nul) return {};
+  return std::string(label, static_cast<const char*>(nul) - label);
+}
+
+// Searches a list of sibling boxes, descending into superboxes.
+bool findInBoxes(const uint8_t* data, size_t begin, size_t end, IsoGainMapMetadata& out) {
+  Box box;
+  for (size_t at = begin; at < end && readBox(data, end, at, box); at = box.end) {
+    if (box.type != kJumbBox) continue;
+    Box jumd;
+    if (!readBox(data, box.end, box.payloadOffset, jumd) || jumd.type != kJumdBox) continue;
+    if (jumdLabel(data, jumd) == kIsoUrn) {
+      Box content;
+      if (readBox(data, box.end, jumd.end, content) &&
+          decodeIsoBinary(data + content.payloadOffset, content.end - content.payloadOffset,
+                          out)) {
+        return true;
+      }
+      continue;
+    }
+    if (findInBoxes(data, jumd.end, box.end, out)) return true;
+  }
+  return false;
+}
+
+}  // namespace
+
+bool decodeIsoBinary(const uint8_t* data, size_t size, IsoGainMapMetadata& out) {
+  Reader r(data, size);
+  uint16_t minimumVersion = r.u16();
+  r.u16();  // Writer version; informational only.
+  uint8_t flags = r.u8();
+  if (!r.ok() || minimumVersion != 0) return false;
+
+  const int channels = (flags & kMultiChannelFlag) ? 3 : 1;
+  IsoGainMapMetadata m;
+  m.useBaseColorSpace = (flags & kUseBaseColorSpaceFlag) != 0;
+  m.baseRenditionIsHdr = (flags & kBackwardDirectionFlag) != 0;
+  GainMapParams& p = m.params;
+
+  bool ok = true;
+  if (flags & kCommonDenominatorFlag) {
+    uint32_t d = r.u32();
+    ok &= fraction(r.u32(), d, p.hdrCapacityMin);
+    ok &= fraction(r.u32(), d, p.hdrCapacityMax);
+    for (int c = 0; c < channels; ++c) {
+      ok &= fraction(r.s32(), d, p.gainMapMin[c]);
+      ok &= fraction(r.s32(), d, p.gainMapMax[c]);
+      ok &= fraction(r.u32(), d, p.gamma[c]);
+      ok &= fraction(r.s32(), d, p.offsetSdr[c]);
+      ok &= fraction(r.s32(), d, p.offsetHdr[c]);
+    }
+  } else {
+    int64_t n = r.u32();
+    ok &= fraction(n, r.u32(), p.hdrCapacityMin);
+    n = r.u32();
+    ok &= fraction(n, r.u32(), p.hdrCapacityMax);
+    for (int c = 0; c < channels; ++c) {
+      n = r.s32();
+      ok &= fraction(n, r.u32(), p.gainMapMin[c]);
+      n = r.s32();
+      ok &= fraction(n, r.u32(), p.gainMapMax[c]);
+      n = r.u32();
+      ok &= fraction(n, r.u32(), p.gamma[c]);
+      n = r.s32();
+      ok &= fraction(n, r.u32(), p.offsetSdr[c]);
+      n = r.s32();
+      ok &= fraction(n, r.u32(), p.offsetHdr[c]);
+    }
+  }
+  if (!ok || !r.ok()) return false;
+  // The block stores the base's headroom first. With the backward direction
+  // flag the base is the HDR rendition, so that is the upper end of the range.
+  if (m.baseRenditionIsHdr) std::swap(p.hdrCapacityMin, p.hdrCapacityMax);
+
+  for (int c = channels; c < 3; ++c) {
+    p.gainMapMin[c] = p.gainMapMin[0];
+    p.gainMapMax[c] = p.gainMapMax[0];
+    p.gamma[c] = p.gamma[0];
+    p.offsetSdr[c] = p.offsetSdr[0];
+    p.offsetHdr[c] = p.offsetHdr[0];
+  }
+  out = m;
+  return true;
+}
+
+bool findJumbfGainMapMetadata(const uint8_t* data, size_t size, IsoGainMapMetadata& out) {
+  // Each APP11 segment starts with CI "JP" (2 bytes), box instance En (2) and
+  // packet sequence Z (4). Continuation packets repeat the superbox header,
+  // which is dropped when stitching them back together.
+  std::map<uint16_t, std::map<uint32_t, std::vector<uint8_t>>> packets;
+  for (const JpegSegment& seg : findJumbfSegments(data, size)) {
+    if (seg.payloadLength < 8) continue;
+    const uint8_t* p = data + seg.payloadOffset;
+    uint16_t instance = static_cast<uint16_t>((p[2] << 8) | p[3]);
+    uint32_t sequence = (static_cast<uint32_t>(p[4]) << 24) | (p[5] << 16) | (p[6] << 8) | p[7];
+    packets[instance][sequence].assign(p + 8, p + seg.payloadLength);
+  }
+
+  for (auto& entry : packets) {
+    std::vector<uint8_t> boxes;
+    for (auto& packet : entry.second) {
+      const std::vector<uint8_t>& bytes = packet.second;
+      if (boxes.empty()) {
+        boxes = bytes;
+        continue;
+      }
+      size_t header = boxHeaderSize(bytes);
+      if (bytes.size() < header) break;
+      boxes.insert(boxes.end(), bytes.begin() + static_cast<std::ptrdiff_t>(header), bytes.end());
+    }
+    if (findInBoxes(boxes.data(), 0, boxes.size(), out)) return true;
+  }
+  return false;
+}
+
+bool findIsoSegmentMetadata(const uint8_t* data, size_t size, IsoGainMapMetadata& out) {
+  JpegSegmentReader reader(data, size);
+  for (JpegSegment seg; reader.next(seg);) {
+    if (!isIsoGainMapSegment(data, seg)) continue;
+    size_t skip = sizeof(kIsoUrn);
+    return decodeIsoBinary(data + seg.payloadOffset + skip, seg.payloadLength - skip, out);
+  }
+  return false;
+}
+
+}  // namespace open_ultrahdr
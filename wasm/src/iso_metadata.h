@@ -0,0 +1,38 @@
+#pragma once
+
+#include <cstddef>
+#include <cstdint>
+
+#include "gain_math.h"
+
+namespace open_ultrahdr {
+
+// ISO 21496-1 gain map metadata decoded from its binary form.
+struct IsoGainMapMetadata {
+  GainMapParams params;  // Single-channel metadata is broadcast to RGB.
+  bool baseRenditionIsHdr = false;
+  bool useBaseColorSpace = true;
+};
+
+// Decodes an ISO 21496-1 binary metadata block (what follows the URN in an
+// APP2 segment, or the content of a JUMBF box). Returns false for malformed
+// input or an unsupported minimum version. The backward direction flag sets
+// baseRenditionIsHdr, and the headrooms are then read as HDR base first, so
+// hdrCapacityMin is always the SDR end.
+//
+// The block has no fields for the gain map's dimensions or codec, and it is
+// written by libultrahdr, so neither is carried here; adding private fields
+// would break other readers. decodeUltraHdrStrict instead checks the gain
+// map's own SOF against the primary (see findConformanceViolations).
+bool decodeIsoBinary(const uint8_t* data, size_t size, IsoGainMapMetadata& out);
+
+// Reassembles the JUMBF boxes carried in APP11 segments of `data` and decodes
+// the first superbox labelled with the ISO 21496-1 URN. Returns false if there
+// is none or it does not decode.
+bool findJumbfGainMapMetadata(const uint8_t* data, size_t size, IsoGainMapMetadata& out);
+
+// Decodes the metadata of the first ISO 21496-1 APP2 segment of the JPEG at
+// the start of `data`. Returns false if there is none or it does not decode.
+bool findIsoSegmentMetadata(const uint8_t* data, size_t size, IsoGainMapMetadata& out);
+
+}  // namespace open_ultrahdr
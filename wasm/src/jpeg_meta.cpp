@@ -1,9 +1,35 @@
 #include "jpeg_meta.h"
 
+#include <algorithm>
+#include <cstdio>
+#include <cstring>
+#include <limits>
+#include <string>
+
+#include "md5.h"
+
 namespace open_ultrahdr {
 
 namespace {
 
+constexpr char kXmpSignature[] = "http://ns.adobe.com/xap/1.0/";  // + NUL
+constexpr char kXmpExtensionSignature[] = "http://ns.adobe.com/xmp/extension/";  // + NUL
+constexpr char kMpfSignature[] = "MPF";                           // + NUL
+constexpr char kIsoSignature[] = "urn:iso:std:iso:ts:21496:-1";   // + NUL
+constexpr char kExifSignature[] = "Exif\0";                       // + NUL
+constexpr char kJfifSignature[] = "JFIF";                          // + NUL
+// JFIF APP0 payload up to the densities: identifier, version, units, X, Y.
+constexpr size_t kJfifDensityEnd = sizeof(kJfifSignature) + 2 + 1 + 2 + 2;
+constexpr uint16_t kOrientationTag = 0x0112;
+constexpr uint16_t kTiffShort = 3;
+constexpr uint16_t kMpEntryTag = 0xB002;
+constexpr size_t kMpEntrySize = 16;
+// Caps on counts read from untrusted MPF data. A real MP Index IFD has a
+// handful of tags, and UltraHDR needs two images; these leave generous room
+// for other multi-picture files while rejecting absurd claims up front.
+constexpr uint16_t kMaxMpfIfdEntries = 64;
+constexpr size_t kMaxMpfImages = 16;
+
 // All SOFn markers (excluding DHT=0xC4, JPG=0xC8, DAC=0xCC) carry frame-header
 // payload starting with precision (1 byte), height (2 bytes BE), width (2 bytes BE).
 inline bool isSofMarker(uint8_t marker) {
@@ -11,6 +37,222 @@ inline bool isSofMarker(uint8_t marker) {
   return marker != 0xC4 && marker != 0xC8 && marker != 0xCC;
 }
 
+inline bool isProgressiveSofMarker(uint8_t marker) {
+  return marker == 0xC2 || marker == 0xC6 || marker == 0xCA || marker == 0xCE;
+}
+
+// Sets out.components and the largest sampling factors across the component
+// entries of the SOF payload `sof` (precision byte first, `length` bytes).
+// Those set the MCU size; a component entry cut short is not counted.
+void readSofComponents(const uint8_t* sof, size_t length, JpegDims& out) {
+  if (length < 6) return;
+  out.components = sof[5];
+  for (size_t k = 0; k < out.components && 6 + 3 * (k + 1) <= length; ++k) {
+    uint8_t sampling = sof[7 + 3 * k];
+    out.hSampling = std::max<uint8_t>(out.hSampling, sampling >> 4);
+    out.vSampling = std::max<uint8_t>(out.vSampling, sampling & 0x0F);
+  }
+}
+
+inline bool isStandaloneMarker(uint8_t marker) {
+  return marker == 0xD8 || marker == 0xD9 || marker == 0x01 || (marker >= 0xD0 && marker <= 0xD7);
+}
+
+// True if the segment payload starts with `sig` including its NUL terminator.
+bool payloadStartsWith(const uint8_t* data, const JpegSegment& seg, const char* sig,
+                       size_t sigLen) {
+  if (seg.payloadLength < sigLen + 1) return false;
+  return std::memcmp(data + seg.payloadOffset, sig, sigLen + 1) == 0;
+}
+
+bool payloadContains(const uint8_t* data, const JpegSegment& seg, const char* needle) {
+  size_t n = std::strlen(needle);
+  if (seg.payloadLength < n) return false;
+  const uint8_t* p = data + seg.payloadOffset;
+  for (size_t i = 0; i + n <= seg.payloadLength; ++i) {
+    if (std::memcmp(p + i, needle, n) == 0) return true;
+  }
+  return false;
+}
+
+// Advances over entropy-coded data starting at `i`, returning the offset of
+// the next non-RST marker (its 0xFF byte), or `size` if none.
+size_t skipEntropyData(const uint8_t* data, size_t size, size_t i) {
+  while (i + 1 < size) {
+    if (data[i] != 0xFF) {
+      ++i;
+      continue;
+    }
+    uint8_t next = data[i + 1];
+    if (next == 0x00 || (next >= 0xD0 && next <= 0xD7)) {
+      i += 2;  // Stuffed byte or restart marker.
+    } else if (next == 0xFF) {
+      ++i;  // Fill byte.
+    } else {
+      return i;
+    }
+  }
+  return size;
+}
+
+// Offset of the next plausible marker at or after `from`: an 0xFF followed by
+// EOI or by a marker whose length field fits in the buffer. Returns `size` if
+// there is none.
+size_t resyncToMarker(const uint8_t* data, size_t size, size_t from) {
+  for (size_t i = from; i + 1 < size; ++i) {
+    if (data[i] != 0xFF) continue;
+    uint8_t marker = data[i + 1];
+    if (marker == 0xD9) return i;
+    if (marker < 0xC0 || marker == 0xFF || isStandaloneMarker(marker)) continue;
+    if (i + 4 > size) continue;
+    uint16_t segLen = (static_cast<uint16_t>(data[i + 2]) << 8) | data[i + 3];
+    if (segLen >= 2 && i + 2 + segLen <= size) return i;
+  }
+  return size;
+}
+
+std::string markerAt(uint8_t marker, size_t offset) {
+  char hex[8];
+  std::snprintf(hex, sizeof(hex), "0x%02X", marker);
+  return std::string("Marker ") + hex + " at offset " + std::to_string(offset);
+}
+
+// Byte-order aware reads over a TIFF block.
+struct TiffReader {
+  const uint8_t* data;
+  size_t size;
+  bool littleEndian;
+
+  bool has(size_t offset, size_t n) const { return offset <= size && n <= size - offset; }
+  uint16_t u16(size_t o) const {
+    return littleEndian ? static_cast<uint16_t>(data[o] | (data[o + 1] << 8))
+                        : static_cast<uint16_t>((data[o] << 8) | data[o + 1]);
+  }
+  uint32_t u32(size_t o) const {
+    return littleEndian ? (static_cast<uint32_t>(u16(o + 2)) << 16) | u16(o)
+                        : (static_cast<uint32_t>(u16(o)) << 16) | u16(o + 2);
+  }
+};
+
+// Locates the value field of IFD0's Orientation entry. Returns 0 if absent.
+size_t findOrientationValue(const uint8_t* exif, size_t size, bool& littleEndian) {
+  size_t sigLen = sizeof(kExifSignature);
+  if (size >= sigLen && std::memcmp(exif, kExifSignature, sigLen) == 0) {
+    exif += sigLen;
+    size -= sigLen;
+  } else {
+    sigLen = 0;
+  }
+  if (size < 8) return 0;
+  if (exif[0] == 'I' && exif[1] == 'I') {
+    littleEndian = true;
+  } else if (exif[0] == 'M' && exif[1] == 'M') {
+    littleEndian = false;
+  } else {
+    return 0;
+  }
+  TiffReader tiff{exif, size, littleEndian};
+  if (tiff.u16(2) != 42) return 0;
+  size_t ifd = tiff.u32(4);
+  if (!tiff.has(ifd, 2)) return 0;
+  uint16_t count = tiff.u16(ifd);
+  for (uint16_t e = 0; e < count; ++e) {
+    size_t entry = ifd + 2 + static_cast<size_t>(e) * 12;
+    if (!tiff.has(entry, 12)) return 0;
+    if (tiff.u16(entry) != kOrientationTag) continue;
+    if (tiff.u16(entry + 2) != kTiffShort || tiff.u32(entry + 4) != 1) return 0;
+    return sigLen + entry + 8;
+  }
+  return 0;
+}
+
+// Value of the attribute `name` (e.g. "Item:Length") within the text of one
+// XML start tag. Returns false if absent.
+bool xmlAttribute(const std::string& tag, const char* name, std::string& value) {
+  std::string key = std::string(name) + "=";
+  size_t at = 0;
+  while ((at = tag.find(key, at)) != std::string::npos) {
+    // Reject matches that are the tail of a longer name.
+    bool boundary = at > 0 && (tag[at - 1] == ' ' || tag[at - 1] == '\t' ||
+                               tag[at - 1] == '\n' || tag[at - 1] == '\r');
+    size_t q = at + key.size();
+    if (boundary && q < tag.size() && (tag[q] == '"' || tag[q] == '\'')) {
+      size_t close = tag.find(tag[q], q + 1);
+      if (close == std::string::npos) return false;
+      value = tag.substr(q + 1, close - q - 1);
+      return true;
+    }
+    at += key.size();
+  }
+  return false;
+}
+
+bool parseByteCount(const std::string& text, size_t& out) {
+  if (text.empty() || text.size() > 9) return false;
+  out = 0;
+  for (char c : text) {
+    if (c < '0' || c > '9') return false;
+    out = out * 10 + static_cast<size_t>(c - '0');
+  }
+  return true;
+}
+
+// A gain map candidate must parse as far as a frame header. An SOI alone is
+// not enough: if the primary's entropy data holds an FF D9 that is not really
+// its EOI, the bytes after that point are still the primary's scan.
+bool isPlausibleJpeg(const uint8_t* data, size_t size) {
+  JpegDims dims = parseJpegDimensions(data, size);
+  return dims.ok && dims.width > 0 && dims.height > 0;
+}
+
+// Gain map range described by the GContainer directory of the XMP packet
+// `xmp`, for a primary ending at `primaryEnd`. Length is 0 when the directory
+// is absent, inconsistent or points outside the file.
+ByteRange gainMapFromContainer(const uint8_t* data, size_t size, size_t primaryEnd,
+                               const ByteRange& xmp) {
+  std::vector<ContainerItem> items =
+      readContainerItems(reinterpret_cast<const char*>(data + xmp.offset), xmp.length);
+  if (items.empty() || items[0].semantic != "Primary") return {};
+  size_t offset = primaryEnd + items[0].padding;
+  for (size_t i = 1; i < items.size(); ++i) {
+    const ContainerItem& item = items[i];
+    if (item.semantic == "GainMap") {
+      if (item.length < 4 || offset > size || item.length > size - offset) return {};
+      if (!isPlausibleJpeg(data + offset, item.length)) return {};
+      return {offset, item.length};
+    }
+    // Items before the gain map need a length to be skipped.
+    if (item.length == 0) return {};
+    offset += item.length + item.padding;
+    if (offset > size) return {};
+  }
+  return {};
+}
+
+// The first codestream at or after `from`, skipping any padding before its
+// SOI. Length is 0 if there is none.
+ByteRange nextCodestream(const uint8_t* data, size_t size, size_t from) {
+  size_t start = from;
+  while (start + 1 < size && !(data[start] == 0xFF && data[start + 1] == 0xD8)) ++start;
+  size_t length = start < size ? findJpegEnd(data + start, size - start) : 0;
+  return {start, length};
+}
+
+// Appends an APP1 segment holding `header` (NUL included) and `body`.
+void appendApp1(std::vector<uint8_t>& out, const char* header, size_t headerSize,
+                const uint8_t* body, size_t bodySize) {
+  size_t length = 2 + headerSize + bodySize;
+  out.insert(out.end(), {0xFF, 0xE1});
+  out.push_back(static_cast<uint8_t>(length >> 8));
+  out.push_back(static_cast<uint8_t>(length & 0xFF));
+  out.insert(out.end(), header, header + headerSize);
+  out.insert(out.end(), body, body + bodySize);
+}
+
+void appendU32(std::vector<uint8_t>& out, uint32_t v) {
+  for (int shift = 24; shift >= 0; shift -= 8) out.push_back(static_cast<uint8_t>(v >> shift));
+}
+
 }  // namespace
 
 JpegDims parseJpegDimensions(const uint8_t* data, size_t size) {
@@ -45,8 +287,12 @@ JpegDims parseJpegDimensions(const uint8_t* data, size_t size) {
       uint16_t h = (static_cast<uint16_t>(data[i + 3]) << 8) | data[i + 4];
       uint16_t w = (static_cast<uint16_t>(data[i + 5]) << 8) | data[i + 6];
       out.ok = true;
+      out.marker = marker;
       out.width = w;
       out.height = h;
+      out.precision = data[i + 2];
+      out.progressive = isProgressiveSofMarker(marker);
+      readSofComponents(data + i + 2, segLen - 2, out);
       return out;
     }
 
@@ -55,4 +301,627 @@ JpegDims parseJpegDimensions(const uint8_t* data, size_t size) {
   return out;
 }
 
+JpegSegmentReader::JpegSegmentReader(const uint8_t* data, size_t size,
+                                     std::vector<std::string>* errors, bool throughScans)
+    : data_(data), size_(size), errors_(errors), throughScans_(throughScans) {
+  done_ = !data || size < 4 || data[0] != 0xFF || data[1] != 0xD8;  // SOI
+}
+
+bool JpegSegmentReader::next(JpegSegment& seg) {
+  while (!done_ && pos_ + 1 < size_) {
+    size_t& i = pos_;
+    if (inScan_) {
+      // Lands on the 0xFF of the marker that ended the scan, which is then
+      // read like any other.
+      i = skipEntropyData(data_, size_, i);
+      inScan_ = false;
+      continue;
+    }
+    if (data_[i] != 0xFF) {
+      if (!errors_) break;
+      errors_->push_back("Expected a marker at offset " + std::to_string(i));
+      i = resyncToMarker(data_, size_, i);
+      continue;
+    }
+    size_t start = i;
+    while (i < size_ && data_[i] == 0xFF) ++i;
+    if (i >= size_) break;
+    uint8_t marker = data_[i++];
+    if (marker == 0xD9) {
+      end_ = i;
+      break;
+    }
+    if (isStandaloneMarker(marker)) continue;
+
+    if (i + 2 > size_) {
+      if (errors_) errors_->push_back(markerAt(marker, start) + " is truncated");
+      break;
+    }
+    uint16_t segLen = (static_cast<uint16_t>(data_[i]) << 8) | data_[i + 1];
+    if (segLen < 2 || i + segLen > size_) {
+      if (!errors_) break;
+      errors_->push_back(markerAt(marker, start) + " has invalid length " +
+                         std::to_string(segLen));
+      i = resyncToMarker(data_, size_, i);
+      continue;
+    }
+
+    seg.marker = marker;
+    seg.offset = start;
+    seg.length = (i - start) + segLen;
+    seg.payloadOffset = i + 2;
+    seg.payloadLength = segLen - 2u;
+    // SOS: entropy-coded data follows.
+    if (marker == 0xDA) {
+      inScan_ = throughScans_;
+      done_ = !throughScans_;
+    }
+    i += segLen;
+    return true;
+  }
+  done_ = true;
+  return false;
+}
+
+std::vector<JpegSegment> parseJpegSegments(const uint8_t* data, size_t size) {
+  std::vector<JpegSegment> out;
+  JpegSegmentReader reader(data, size);
+  for (JpegSegment seg; reader.next(seg);) out.push_back(seg);
+  return out;
+}
+
+std::vector<JpegSegment> parseJpegSegmentsRecover(const uint8_t* data, size_t size,
+                                                  std::vector<std::string>& errors) {
+  std::vector<JpegSegment> out;
+  JpegSegmentReader reader(data, size, &errors);
+  for (JpegSegment seg; reader.next(seg);) out.push_back(seg);
+  return out;
+}
+
+JpegDims dimensionsFromSegments(const uint8_t* data, const std::vector<JpegSegment>& segments) {
+  JpegDims out;
+  for (const JpegSegment& seg : segments) {
+    if (!isSofMarker(seg.marker)) continue;
+    // Precision (1 byte), height (2 bytes BE), width (2 bytes BE).
+    if (seg.payloadLength < 5) return out;
+    const uint8_t* p = data + seg.payloadOffset;
+    out.ok = true;
+    out.marker = seg.marker;
+    out.precision = p[0];
+    out.progressive = isProgressiveSofMarker(seg.marker);
+    readSofComponents(p, seg.payloadLength, out);
+    out.height = (static_cast<uint16_t>(p[1]) << 8) | p[2];
+    out.width = (static_cast<uint16_t>(p[3]) << 8) | p[4];
+    return out;
+  }
+  return out;
+}
+
+size_t findJpegEnd(const uint8_t* data, size_t size) {
+  JpegSegmentReader reader(data, size, nullptr, /*throughScans=*/true);
+  // The segments themselves are not needed; walking them to EOI is what sets
+  // reader.end().
+  JpegSegment seg;
+  while (reader.next(seg)) {
+  }
+  return reader.end();
+}
+
+bool hasScanData(const uint8_t* data, size_t size, const JpegSegment& sos) {
+  size_t at = sos.offset + sos.length;
+  if (at >= size) return false;
+  if (data[at] != 0xFF) return true;
+  if (at + 1 >= size) return false;
+  uint8_t next = data[at + 1];
+  return next == 0x00 || (next >= 0xD0 && next <= 0xD7);
+}
+
+bool isXmpSegment(const uint8_t* data, const JpegSegment& seg) {
+  return seg.marker == 0xE1 &&
+         payloadStartsWith(data, seg, kXmpSignature, sizeof(kXmpSignature) - 1);
+}
+
+bool isExifSegment(const uint8_t* data, const JpegSegment& seg) {
+  return seg.marker == 0xE1 &&
+         payloadStartsWith(data, seg, kExifSignature, sizeof(kExifSignature) - 1);
+}
+
+bool isGainMapXmpSegment(const uint8_t* data, const JpegSegment& seg) {
+  return isXmpSegment(data, seg) &&
+         (payloadContains(data, seg, "hdrgm") || payloadContains(data, seg, "GContainer"));
+}
+
+bool isMpfSegment(const uint8_t* data, const JpegSegment& seg) {
+  return seg.marker == 0xE2 &&
+         payloadStartsWith(data, seg, kMpfSignature, sizeof(kMpfSignature) - 1);
+}
+
+bool isIsoGainMapSegment(const uint8_t* data, const JpegSegment& seg) {
+  return seg.marker == 0xE2 &&
+         payloadStartsWith(data, seg, kIsoSignature, sizeof(kIsoSignature) - 1);
+}
+
+bool isIccSegment(const uint8_t* data, const JpegSegment& seg) {
+  return seg.marker == 0xE2 && seg.payloadLength >= kIccChunkHeaderSize &&
+         payloadStartsWith(data, seg, kIccSignature, sizeof(kIccSignature) - 1);
+}
+
+std::vector<uint8_t> extractIccProfile(const uint8_t* data, size_t size) {
+  std::vector<const JpegSegment*> chunks;
+  std::vector<JpegSegment> segments = parseJpegSegments(data, size);
+  size_t count = 0;
+  for (const JpegSegment& seg : segments) {
+    if (!isIccSegment(data, seg)) continue;
+    const uint8_t* header = data + seg.payloadOffset + sizeof(kIccSignature);
+    size_t seq = header[0];
+    size_t total = header[1];
+    if (total == 0 || seq == 0 || seq > total || (count != 0 && total != count)) return {};
+    if (count == 0) {
+      count = total;
+      chunks.assign(count, nullptr);
+    }
+    if (chunks[seq - 1]) return {};
+    chunks[seq - 1] = &seg;
+  }
+
+  std::vector<uint8_t> out;
+  for (const JpegSegment* seg : chunks) {
+    if (!seg) return {};
+    const uint8_t* begin = data + seg->payloadOffset + kIccChunkHeaderSize;
+    out.insert(out.end(), begin, begin + seg->payloadLength - kIccChunkHeaderSize);
+  }
+  return out;
+}
+
+bool readJfifDensity(const uint8_t* data, size_t size, JfifDensity& out) {
+  JpegSegmentReader reader(data, size);
+  for (JpegSegment seg; reader.next(seg);) {
+    if (seg.marker != 0xE0 || seg.payloadLength < kJfifDensityEnd ||
+        !payloadStartsWith(data, seg, kJfifSignature, sizeof(kJfifSignature) - 1)) {
+      continue;
+    }
+    const uint8_t* p = data + seg.payloadOffset + sizeof(kJfifSignature) + 2;
+    out.units = p[0];
+    out.x = static_cast<uint16_t>((p[1] << 8) | p[2]);
+    out.y = static_cast<uint16_t>((p[3] << 8) | p[4]);
+    return true;
+  }
+  return false;
+}
+
+std::vector<QuantizationTable> readQuantizationTables(const uint8_t* data, size_t size) {
+  std::vector<QuantizationTable> tables;
+  JpegSegmentReader reader(data, size);
+  for (JpegSegment seg; reader.next(seg);) {
+    if (seg.marker != 0xDB) continue;
+    const uint8_t* p = data + seg.payloadOffset;
+    const uint8_t* end = p + seg.payloadLength;
+    while (p < end) {
+      QuantizationTable table;
+      table.precision = (p[0] >> 4) ? 16 : 8;
+      table.id = p[0] & 0x0F;
+      size_t bytes = table.precision / 8;
+      if (static_cast<size_t>(end - p - 1) < 64 * bytes) return tables;
+      ++p;
+      for (uint16_t& v : table.values) {
+        v = bytes == 2 ? static_cast<uint16_t>((p[0] << 8) | p[1]) : p[0];
+        p += bytes;
+      }
+      tables.push_back(table);
+    }
+  }
+  return tables;
+}
+
+bool isJumbfSegment(const uint8_t* data, const JpegSegment& seg) {
+  return seg.marker == 0xEB && seg.payloadLength >= 2 && data[seg.payloadOffset] == 'J' &&
+         data[seg.payloadOffset + 1] == 'P';
+}
+
+std::vector<JpegSegment> findJumbfSegments(const uint8_t* data, size_t size) {
+  std::vector<JpegSegment> out;
+  size_t start = 0;
+  while (start + 4 <= size && data[start] == 0xFF && data[start + 1] == 0xD8) {
+    for (JpegSegment seg : parseJpegSegments(data + start, size - start)) {
+      if (!isJumbfSegment(data + start, seg)) continue;
+      seg.offset += start;
+      seg.payloadOffset += start;
+      out.push_back(seg);
+    }
+    size_t end = findJpegEnd(data + start, size - start);
+    if (end == 0) break;
+    // The next codestream may follow after padding.
+    start += end;
+    while (start + 1 < size && !(data[start] == 0xFF && data[start + 1] == 0xD8)) ++start;
+  }
+  return out;
+}
+
+std::vector<uint8_t> stripGainMapSignalling(const uint8_t* data, size_t size, bool allXmp) {
+  std::vector<uint8_t> out;
+  size_t end = findJpegEnd(data, size);
+  std::vector<JpegSegment> segments = parseJpegSegments(data, size);
+  if (end == 0 || segments.empty() || segments.back().marker != 0xDA) return out;
+  if (!hasScanData(data, end, segments.back())) return out;
+
+  out.reserve(end);
+  out.push_back(0xFF);
+  out.push_back(0xD8);
+  for (const JpegSegment& seg : segments) {
+    if (seg.marker == 0xDA) break;
+    if (isGainMapXmpSegment(data, seg) || isMpfSegment(data, seg) || isIsoGainMapSegment(data, seg)) continue;
+    if (allXmp && isXmpSegment(data, seg)) continue;
+    out.insert(out.end(), data + seg.offset, data + seg.offset + seg.length);
+  }
+  // SOS header, entropy-coded data and everything up to the primary's EOI.
+  out.insert(out.end(), data + segments.back().offset, data + end);
+  return out;
+}
+
+std::vector<uint8_t> insertXmpSegment(const std::vector<uint8_t>& jpeg, const std::string& xmp) {
+  std::vector<uint8_t> out;
+  if (jpeg.size() < 2 || jpeg[0] != 0xFF || jpeg[1] != 0xD8 ||
+      xmp.size() > std::numeric_limits<uint32_t>::max()) {
+    return out;
+  }
+
+  size_t at = 2;
+  JpegSegmentReader reader(jpeg.data(), jpeg.size());
+  for (JpegSegment seg; reader.next(seg) && seg.offset == at;) {
+    if (seg.marker != 0xE0 && !isExifSegment(jpeg.data(), seg)) break;
+    at = seg.offset + seg.length;
+  }
+
+  out.insert(out.end(), jpeg.begin(), jpeg.begin() + static_cast<std::ptrdiff_t>(at));
+  const uint8_t* packet = reinterpret_cast<const uint8_t*>(xmp.data());
+  if (2 + sizeof(kXmpSignature) + xmp.size() <= 0xFFFF) {
+    appendApp1(out, kXmpSignature, sizeof(kXmpSignature), packet, xmp.size());
+  } else {
+    // Extended XMP (XMP Specification Part 3, 1.1.3.1): the whole packet is
+    // the extended portion, split across extension segments that each carry
+    // its MD5 as a GUID, its full length and the chunk's offset. The standard
+    // packet only points at it.
+    std::string guid = md5Hex(packet, xmp.size());
+    std::string standard =
+        "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n"
+        "  <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n"
+        "    <rdf:Description rdf:about=\"\"\n"
+        "        xmlns:xmpNote=\"http://ns.adobe.com/xmp/note/\"\n"
+        "        xmpNote:HasExtendedXMP=\"" + guid + "\"/>\n"
+        "  </rdf:RDF>\n"
+        "</x:xmpmeta>\n";
+    appendApp1(out, kXmpSignature, sizeof(kXmpSignature),
+               reinterpret_cast<const uint8_t*>(standard.data()), standard.size());
+
+    std::vector<uint8_t> header(kXmpExtensionSignature,
+                                kXmpExtensionSignature + sizeof(kXmpExtensionSignature));
+    header.insert(header.end(), guid.begin(), guid.end());
+    appendU32(header, static_cast<uint32_t>(xmp.size()));
+    const size_t maxChunk = 0xFFFF - 2 - header.size() - 4;
+    for (size_t offset = 0; offset < xmp.size(); offset += maxChunk) {
+      std::vector<uint8_t> chunkHeader = header;
+      appendU32(chunkHeader, static_cast<uint32_t>(offset));
+      appendApp1(out, reinterpret_cast<const char*>(chunkHeader.data()), chunkHeader.size(),
+                 packet + offset, std::min(maxChunk, xmp.size() - offset));
+    }
+  }
+  out.insert(out.end(), jpeg.begin() + static_cast<std::ptrdiff_t>(at), jpeg.end());
+  return out;
+}
+
+std::vector<uint8_t> reorderInvertedLayers(const uint8_t* data, size_t size) {
+  std::vector<uint8_t> out;
+  size_t firstEnd = findJpegEnd(data, size);
+  std::vector<JpegSegment> segments = parseJpegSegments(data, firstEnd);
+  if (firstEnd == 0 || segments.empty() || segments.back().marker != 0xDA) return out;
+  if (!hasScanData(data, firstEnd, segments.back())) return out;
+
+  bool inverted = false;
+  for (const JpegSegment& seg : segments) {
+    if (isMpfSegment(data, seg)) {
+      std::vector<MpfImage> images = readMpfImages(data, seg);
+      inverted |= images.size() >= 2 && images[0].type() != kMpfPrimaryImageType &&
+                  images[1].type() == kMpfPrimaryImageType;
+    } else if (isXmpSegment(data, seg)) {
+      std::vector<ContainerItem> items = readContainerItems(
+          reinterpret_cast<const char*>(data + seg.payloadOffset) + sizeof(kXmpSignature),
+          seg.payloadLength - sizeof(kXmpSignature));
+      inverted |= !items.empty() && items[0].semantic == "GainMap";
+    }
+  }
+  if (!inverted) return out;
+  ByteRange sdr = nextCodestream(data, size, firstEnd);
+  if (sdr.length == 0) return out;
+
+  out.reserve(sdr.length + firstEnd);
+  out.insert(out.end(), data + sdr.offset, data + sdr.offset + sdr.length);
+  out.push_back(0xFF);
+  out.push_back(0xD8);
+  for (const JpegSegment& seg : segments) {
+    if (seg.marker == 0xDA) break;
+    if (isMpfSegment(data, seg)) continue;
+    out.insert(out.end(), data + seg.offset, data + seg.offset + seg.length);
+  }
+  out.insert(out.end(), data + segments.back().offset, data + firstEnd);
+  return out;
+}
+
+bool locateComponents(const uint8_t* data, size_t size, ComponentLayout& out) {
+  out = ComponentLayout();
+  size_t primaryEnd = findJpegEnd(data, size);
+  if (primaryEnd == 0) return false;
+  out.primary = {0, primaryEnd};
+
+  std::vector<MpfImage> images;
+  JpegSegmentReader reader(data, primaryEnd);
+  for (JpegSegment seg; reader.next(seg);) {
+    if (!out.hasXmp && isGainMapXmpSegment(data, seg)) {
+      out.hasXmp = true;
+      out.xmp = {seg.payloadOffset + sizeof(kXmpSignature),
+                 seg.payloadLength - sizeof(kXmpSignature)};
+    } else if (!out.hasMpf && isMpfSegment(data, seg)) {
+      out.hasMpf = true;
+      out.mpf = {seg.payloadOffset + sizeof(kMpfSignature),
+                 seg.payloadLength - sizeof(kMpfSignature)};
+      images = readMpfImages(data, seg);
+    }
+  }
+
+  if (out.hasXmp) {
+    out.gainMap = gainMapFromContainer(data, size, primaryEnd, out.xmp);
+    if (out.gainMap.length != 0) return true;
+  }
+  if (images.size() >= 2) {
+    const MpfImage& gm = images[1];
+    if (gm.offset < size && gm.size <= size - gm.offset &&
+        isPlausibleJpeg(data + gm.offset, gm.size)) {
+      out.gainMap = {gm.offset, gm.size};
+      return true;
+    }
+  }
+  // No usable MPF index: the gain map is the next codestream.
+  out.gainMap = nextCodestream(data, size, primaryEnd);
+  return out.gainMap.length != 0 &&
+         isPlausibleJpeg(data + out.gainMap.offset, out.gainMap.length);
+}
+
+std::vector<uint8_t> isolateGainMapLayers(const uint8_t* data, size_t size) {
+  std::vector<uint8_t> out;
+  ComponentLayout layout;
+  if (!locateComponents(data, size, layout)) return out;
+  if (nextCodestream(data, size, layout.primary.length).offset == layout.gainMap.offset) return out;
+
+  const uint8_t* gainMap = data + layout.gainMap.offset;
+  out.reserve(layout.primary.length + layout.gainMap.length);
+  out.insert(out.end(), data, data + layout.primary.length);
+  out.insert(out.end(), gainMap, gainMap + layout.gainMap.length);
+  return out;
+}
+
+std::vector<AuxiliaryImage> listAuxiliaryImages(const uint8_t* data, size_t size) {
+  std::vector<AuxiliaryImage> out;
+  size_t primaryEnd = findJpegEnd(data, size);
+  if (primaryEnd == 0) return out;
+  std::vector<ContainerItem> items;
+  JpegSegmentReader reader(data, primaryEnd);
+  for (JpegSegment seg; items.empty() && reader.next(seg);) {
+    if (!isGainMapXmpSegment(data, seg)) continue;
+    items = readContainerItems(reinterpret_cast<const char*>(data + seg.payloadOffset),
+                               seg.payloadLength);
+  }
+  if (items.empty() || items[0].semantic != "Primary") return out;
+
+  out.push_back({items[0].semantic, items[0].mime, {0, primaryEnd}});
+  size_t offset = primaryEnd + items[0].padding;
+  for (size_t i = 1; i < items.size(); ++i) {
+    const ContainerItem& item = items[i];
+    if (item.length == 0 || offset > size || item.length > size - offset) break;
+    out.push_back({item.semantic, item.mime, {offset, item.length}});
+    offset += item.length + item.padding;
+  }
+  return out;
+}
+
+std::vector<ContainerItem> readContainerItems(const char* xmp, size_t size) {
+  std::vector<ContainerItem> items;
+  std::string text(xmp, size);
+  const std::string open = "<Container:Item";
+  size_t at = 0;
+  while ((at = text.find(open, at)) != std::string::npos) {
+    size_t end = text.find('>', at);
+    if (end == std::string::npos) return {};
+    std::string tag = text.substr(at, end - at);
+    at = end;
+
+    ContainerItem item;
+    std::string value;
+    if (!xmlAttribute(tag, "Item:Semantic", item.semantic)) return {};
+    xmlAttribute(tag, "Item:Mime", item.mime);
+    if (xmlAttribute(tag, "Item:Length", value) && !parseByteCount(value, item.length)) return {};
+    if (xmlAttribute(tag, "Item:Padding", value) && !parseByteCount(value, item.padding)) {
+      return {};
+    }
+    items.push_back(item);
+  }
+  return items;
+}
+
+std::vector<MpfImage> readMpfImages(const uint8_t* data, const JpegSegment& seg) {
+  std::vector<MpfImage> out;
+  if (!isMpfSegment(data, seg)) return out;
+  // Offsets in the MP Entry table are relative to the TIFF header that
+  // follows the "MPF\0" identifier.
+  size_t tiffStart = seg.payloadOffset + sizeof(kMpfSignature);
+  const uint8_t* p = data + tiffStart;
+  size_t size = seg.payloadLength - sizeof(kMpfSignature);
+  if (size < 8) return out;
+  bool littleEndian;
+  if (p[0] == 'I' && p[1] == 'I') {
+    littleEndian = true;
+  } else if (p[0] == 'M' && p[1] == 'M') {
+    littleEndian = false;
+  } else {
+    return out;
+  }
+  TiffReader tiff{p, size, littleEndian};
+  if (tiff.u16(2) != 42) return out;
+  size_t ifd = tiff.u32(4);
+  if (!tiff.has(ifd, 2)) return out;
+  uint16_t count = tiff.u16(ifd);
+  if (count > kMaxMpfIfdEntries) return out;
+  for (uint16_t e = 0; e < count; ++e) {
+    size_t entry = ifd + 2 + static_cast<size_t>(e) * 12;
+    if (!tiff.has(entry, 12)) return out;
+    if (tiff.u16(entry) != kMpEntryTag) continue;
+    uint32_t bytes = tiff.u32(entry + 4);
+    size_t table = tiff.u32(entry + 8);
+    if (bytes % kMpEntrySize != 0 || bytes / kMpEntrySize > kMaxMpfImages) return out;
+    if (!tiff.has(table, bytes)) return out;
+    for (size_t at = table; at < table + bytes; at += kMpEntrySize) {
+      MpfImage img;
+      img.attributes = tiff.u32(at);
+      img.size = tiff.u32(at + 4);
+      uint32_t offset = tiff.u32(at + 8);
+      // size_t is 32 bits in wasm, where this sum could wrap to a small,
+      // plausible-looking offset.
+      if (offset > std::numeric_limits<size_t>::max() - tiffStart) return {};
+      img.offset = offset == 0 ? 0 : tiffStart + offset;
+      img.entryOffset = tiffStart + at;
+      out.push_back(img);
+    }
+    return out;
+  }
+  return out;
+}
+
+bool mpfListsPossibleGainMap(const uint8_t* data, size_t size, const JpegSegment& seg) {
+  std::vector<MpfImage> images = readMpfImages(data, seg);
+  if (images.size() < 2) return false;
+  const MpfImage& second = images[1];
+  uint32_t typeClass = second.type() >> 16;
+  if (typeClass == 0x01 || typeClass == 0x02) return false;
+  if (second.offset != 0 && second.offset < size && second.size <= size - second.offset) {
+    JpegDims gainMap = parseJpegDimensions(data + second.offset, second.size);
+    JpegDims primary = parseJpegDimensions(data, size);
+    if (gainMap.ok && primary.ok &&
+        (gainMap.width > primary.width || gainMap.height > primary.height)) {
+      return false;
+    }
+  }
+  return true;
+}
+
+std::vector<uint8_t> repairMpfOffsets(const uint8_t* data, size_t size) {
+  std::vector<uint8_t> out;
+  size_t primaryEnd = findJpegEnd(data, size);
+  if (primaryEnd == 0) return out;
+  ByteRange gainMap = nextCodestream(data, size, primaryEnd);
+  if (gainMap.length == 0) return out;
+
+  JpegSegmentReader reader(data, primaryEnd);
+  for (JpegSegment seg; reader.next(seg);) {
+    if (!isMpfSegment(data, seg)) continue;
+    std::vector<MpfImage> images = readMpfImages(data, seg);
+    if (images.size() < 2) return out;
+    size_t tiffStart = seg.payloadOffset + sizeof(kMpfSignature);
+    bool littleEndian = data[tiffStart] == 'I';
+    out.assign(data, data + size);
+    auto put32 = [&](size_t at, size_t value) {
+      uint32_t v = static_cast<uint32_t>(value);
+      for (int b = 0; b < 4; ++b) {
+        out[at + (littleEndian ? b : 3 - b)] = static_cast<uint8_t>(v >> (8 * b));
+      }
+    };
+    // The first image's offset is always 0; the others count from the TIFF
+    // header inside the MPF segment.
+    put32(images[0].entryOffset + 4, primaryEnd);
+    put32(images[0].entryOffset + 8, 0);
+    put32(images[1].entryOffset + 4, gainMap.length);
+    put32(images[1].entryOffset + 8, gainMap.offset - tiffStart);
+    return out;
+  }
+  return out;
+}
+
+uint16_t readExifOrientation(const uint8_t* exif, size_t size) {
+  bool littleEndian = false;
+  size_t at = findOrientationValue(exif, size, littleEndian);
+  if (at == 0) return 1;
+  uint16_t v = littleEndian ? static_cast<uint16_t>(exif[at] | (exif[at + 1] << 8))
+                            : static_cast<uint16_t>((exif[at] << 8) | exif[at + 1]);
+  return v >= 1 && v <= 8 ? v : 1;
+}
+
+uint16_t readJpegOrientation(const uint8_t* data, size_t size) {
+  JpegSegmentReader reader(data, size);
+  for (JpegSegment seg; reader.next(seg);) {
+    if (isExifSegment(data, seg)) {
+      return readExifOrientation(data + seg.payloadOffset, seg.payloadLength);
+    }
+  }
+  return 1;
+}
+
+std::vector<uint8_t> setJpegOrientation(const std::vector<uint8_t>& jpeg, uint16_t orientation) {
+  std::vector<uint8_t> out;
+  if (jpeg.size() < 2 || jpeg[0] != 0xFF || jpeg[1] != 0xD8) return out;
+
+  size_t at = 2;
+  JpegSegmentReader reader(jpeg.data(), jpeg.size());
+  for (JpegSegment seg; reader.next(seg);) {
+    if (isExifSegment(jpeg.data(), seg)) {
+      out = jpeg;
+      if (!writeExifOrientation(out.data() + seg.payloadOffset, seg.payloadLength, orientation)) {
+        out.clear();
+      }
+      return out;
+    }
+    if (seg.marker == 0xE0 && seg.offset == at) at = seg.offset + seg.length;
+  }
+  if (orientation == 1) return jpeg;
+
+  const uint8_t hi = static_cast<uint8_t>(orientation >> 8);
+  const uint8_t lo = static_cast<uint8_t>(orientation & 0xFF);
+  const uint8_t exif[] = {
+      0xFF, 0xE1, 0x00, 0x22, 'E', 'x', 'i', 'f', 0x00, 0x00,
+      // Big-endian TIFF header with IFD0 at offset 8.
+      'M', 'M', 0x00, 0x2A, 0x00, 0x00, 0x00, 0x08,
+      // One entry: Orientation, SHORT, count 1, value left-aligned.
+      0x00, 0x01, 0x01, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, hi, lo, 0x00, 0x00,
+      // No IFD1.
+      0x00, 0x00, 0x00, 0x00};
+  out.reserve(jpeg.size() + sizeof(exif));
+  out.insert(out.end(), jpeg.begin(), jpeg.begin() + static_cast<std::ptrdiff_t>(at));
+  out.insert(out.end(), exif, exif + sizeof(exif));
+  out.insert(out.end(), jpeg.begin() + static_cast<std::ptrdiff_t>(at), jpeg.end());
+  return out;
+}
+
+bool writeExifOrientation(uint8_t* exif, size_t size, uint16_t orientation) {
+  bool littleEndian = false;
+  size_t at = findOrientationValue(exif, size, littleEndian);
+  if (at == 0) return false;
+  exif[at] = static_cast<uint8_t>(littleEndian ? orientation & 0xFF : orientation >> 8);
+  exif[at + 1] = static_cast<uint8_t>(littleEndian ? orientation >> 8 : orientation & 0xFF);
+  return true;
+}
+
+uint16_t rotateExifOrientation(uint16_t orientation, int degrees) {
+  if (degrees % 180 == 0) return orientation;
+  // A quarter turn conjugates each mirror into its perpendicular counterpart:
+  // horizontal <-> vertical flip, transpose <-> transverse.
+  switch (orientation) {
+    case 2:
+      return 4;
+    case 4:
+      return 2;
+    case 5:
+      return 7;
+    case 7:
+      return 5;
+    default:
+      return orientation;
+  }
+}
+
 }  // namespace open_ultrahdr
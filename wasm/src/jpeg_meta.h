@@ -2,17 +2,307 @@
 
 #include <cstdint>
 #include <cstddef>
+#include <string>
+#include <vector>
 
 namespace open_ultrahdr {
 
 struct JpegDims {
   bool ok = false;
+  uint8_t marker = 0;  // SOFn marker code, 0xC0-0xCF.
   uint32_t width = 0;
   uint32_t height = 0;
+  uint8_t precision = 8;  // Bits per sample from the SOF header: 8, 12 or 16.
+  bool progressive = false;  // Progressive SOFn (SOF2, or SOF6/10/14).
+  uint8_t components = 0;    // Colour components in the frame: 1 (grey) or 3.
+  // Largest sampling factors across all components, which set the MCU size:
+  // 1 or 2 for the usual 4:4:4, 4:2:2 and 4:2:0 layouts; 0 if the SOF lists
+  // no components.
+  uint8_t hSampling = 0;
+  uint8_t vSampling = 0;
 };
 
 // Parses JPEG segments to find the first SOF marker and extract image dimensions.
 // Returns ok=false for non-JPEG input or if no SOF marker is found.
 JpegDims parseJpegDimensions(const uint8_t* data, size_t size);
 
+// A marker segment located within a JPEG buffer. Offsets index into the
+// buffer that was parsed; nothing is copied.
+struct JpegSegment {
+  uint8_t marker = 0;
+  size_t offset = 0;         // Offset of the 0xFF that starts the marker.
+  size_t length = 0;         // Total length including marker and length field.
+  size_t payloadOffset = 0;  // Offset of the payload (after the length field).
+  size_t payloadLength = 0;
+};
+
+// Walks the marker segments of the first JPEG in `data` one at a time, from
+// the segment after SOI up to and including the first SOS, without building a
+// list. For lookups that stop at the first segment they want:
+//
+//   JpegSegmentReader reader(data, size);
+//   for (JpegSegment seg; reader.next(seg);) { ... }
+//
+// With `errors` null the walk stops at the first malformed segment; otherwise
+// each problem is appended to `errors` and the walk resumes at the next
+// plausible marker.
+//
+// With `throughScans` set the walk does not stop at the first SOS: the
+// entropy-coded data after each SOS is skipped up to the marker that ends it,
+// and that marker (DHT, DNL, a further SOS, ...) is returned next, through to
+// EOI.
+class JpegSegmentReader {
+ public:
+  JpegSegmentReader(const uint8_t* data, size_t size,
+                    std::vector<std::string>* errors = nullptr, bool throughScans = false);
+
+  // Stores the next segment in `seg`. Returns false once the walk has ended.
+  bool next(JpegSegment& seg);
+
+  // Offset one past EOI once the walk has reached it, else 0.
+  size_t end() const { return end_; }
+
+ private:
+  const uint8_t* data_;
+  size_t size_;
+  std::vector<std::string>* errors_;
+  bool throughScans_;
+  size_t pos_ = 2;  // After SOI.
+  size_t end_ = 0;
+  bool inScan_ = false;
+  bool done_ = false;
+};
+
+// Lists the marker segments of the first JPEG in `data`, from the segment
+// after SOI up to and including the first SOS. Stops early (returning what was
+// found) on malformed structure.
+std::vector<JpegSegment> parseJpegSegments(const uint8_t* data, size_t size);
+
+// Like parseJpegSegments, but a segment with an impossible length or stray
+// bytes between segments does not end the walk: the problem is appended to
+// `errors` and parsing resumes at the next plausible marker.
+//
+// Only probeUltraHdr recovers this way. Decoding goes through libultrahdr,
+// which stops at such a segment, and locateComponents and findJpegEnd stop
+// there too, so a file with one still fails to decode or locate.
+std::vector<JpegSegment> parseJpegSegmentsRecover(const uint8_t* data, size_t size,
+                                                  std::vector<std::string>& errors);
+
+// Dimensions from the first SOF segment in `segments`.
+JpegDims dimensionsFromSegments(const uint8_t* data, const std::vector<JpegSegment>& segments);
+
+// Returns the offset one past the EOI marker of the first JPEG in `data`,
+// walking entropy-coded data and any further scans. Returns 0 if no EOI is
+// found.
+size_t findJpegEnd(const uint8_t* data, size_t size);
+
+// True if entropy-coded data follows the SOS segment `sos` before the next
+// marker (a stuffed 0xFF00 or an RSTn counts as data). An SOS directly
+// followed by EOI is structurally a JPEG but not a decodable one, and some
+// decoders hang on it.
+bool hasScanData(const uint8_t* data, size_t size, const JpegSegment& sos);
+
+// Segment classification helpers.
+bool isXmpSegment(const uint8_t* data, const JpegSegment& seg);
+// APP1 segment carrying Exif ("Exif\0\0"), often with an IFD1 thumbnail that
+// takes it close to the 64 KB segment limit.
+bool isExifSegment(const uint8_t* data, const JpegSegment& seg);
+// XMP segment carrying hdrgm gain map or GContainer signalling.
+bool isGainMapXmpSegment(const uint8_t* data, const JpegSegment& seg);
+bool isMpfSegment(const uint8_t* data, const JpegSegment& seg);
+bool isIsoGainMapSegment(const uint8_t* data, const JpegSegment& seg);
+// APP2 segment carrying a chunk of an ICC profile: "ICC_PROFILE\0".
+bool isIccSegment(const uint8_t* data, const JpegSegment& seg);
+// APP11 segment carrying a JUMBF (ISO 19566-5) box: common identifier "JP".
+bool isJumbfSegment(const uint8_t* data, const JpegSegment& seg);
+
+// Lists the JUMBF segments of every JPEG codestream in `data` (the primary,
+// then any following image such as the gain map). Offsets index into `data`.
+std::vector<JpegSegment> findJumbfSegments(const uint8_t* data, size_t size);
+
+// MP type code (low 24 attribute bits) of a Baseline MP Primary Image.
+constexpr uint32_t kMpfPrimaryImageType = 0x030000;
+
+// One image listed in an MPF (CIPA DC-007) MP Index IFD. `offset` is
+// converted to an offset into the parsed buffer; the first image is at 0.
+struct MpfImage {
+  uint32_t attributes = 0;
+  uint32_t size = 0;
+  size_t offset = 0;
+  size_t entryOffset = 0;  // Where this image's MP Entry sits in the buffer.
+
+  uint32_t type() const { return attributes & 0xFFFFFF; }
+};
+
+// Reads the MP Entry table of the MPF segment `seg` of the JPEG at the start
+// of `data`. Returns an empty list if the segment is malformed.
+std::vector<MpfImage> readMpfImages(const uint8_t* data, const JpegSegment& seg);
+
+// True when the MPF segment `seg` of the JPEG at the start of `data` (`size`
+// bytes in all) lists a second image that could be a gain map. MPF alone is
+// also how stereo pairs, panoramas and bursts are packaged, so a second image
+// typed as a large thumbnail or multi-frame image (MP type classes 0x01 and
+// 0x02) does not count, nor does one whose frame, when present in `data`, is
+// larger than the primary's.
+bool mpfListsPossibleGainMap(const uint8_t* data, size_t size, const JpegSegment& seg);
+
+// Returns a copy of `data` whose MP Entry table lists the primary and the
+// gain map where they really are: the primary's SOI to EOI, then the next
+// codestream after it. For files whose primary was rewritten without
+// updating MPF. Returns an empty vector if there is no MPF index with at
+// least two entries or no gain map codestream follows the primary.
+std::vector<uint8_t> repairMpfOffsets(const uint8_t* data, size_t size);
+
+struct ByteRange {
+  size_t offset = 0;
+  size_t length = 0;
+};
+
+// One media item listed in a GContainer XMP directory (Container:Item).
+// Lengths and padding are in bytes; both default to 0 when not given. `mime`
+// is Item:Mime, empty when not given.
+struct ContainerItem {
+  std::string semantic;
+  std::string mime;
+  size_t length = 0;
+  size_t padding = 0;
+};
+
+// Reads the Container:Item entries of an XMP packet in directory order.
+// Returns an empty list if there is no directory or an item has no
+// Item:Semantic or an unparseable Item:Length/Item:Padding.
+std::vector<ContainerItem> readContainerItems(const char* xmp, size_t size);
+
+// Where each UltraHDR component sits within the file, so callers can slice
+// the original buffer instead of copying. `xmp` is the primary's gain map XMP
+// packet and `mpf` its MPF data, both after their namespace identifiers.
+struct ComponentLayout {
+  ByteRange primary;
+  ByteRange gainMap;
+  bool hasXmp = false;
+  ByteRange xmp;
+  bool hasMpf = false;
+  ByteRange mpf;
+};
+
+// Locates the primary JPEG (SOI to EOI) and the gain map JPEG. The gain map
+// is found, in order of preference, from the GContainer directory in the XMP
+// (primary length plus the lengths and padding of the items before it), the
+// MPF index, or as the next codestream after the primary. A candidate that
+// does not parse up to a frame header is skipped in favour of the next
+// source. Returns false if either JPEG cannot be found.
+bool locateComponents(const uint8_t* data, size_t size, ComponentLayout& out);
+
+// A GContainer item located within the file: Primary, GainMap, or another
+// semantic such as ISO 21496-1's Alternate (a fully rendered HDR image).
+struct AuxiliaryImage {
+  std::string semantic;
+  std::string mime;
+  ByteRange range;
+};
+
+// Lists the items of the primary's GContainer directory with their byte
+// ranges: the primary from SOI to EOI, then each later item at the primary's
+// end plus the lengths and padding of the items before it. The list stops at
+// an item without a length or one that runs past the end of `data`. Empty if
+// there is no directory or it does not start with the Primary item.
+std::vector<AuxiliaryImage> listAuxiliaryImages(const uint8_t* data, size_t size);
+
+// Some encoders store the gain map as the first codestream and the SDR base
+// second, flagging only the second image as the Baseline MP Primary in MPF or
+// listing Item:Semantic="GainMap" first in the container XMP. For such files
+// this returns the SDR codestream followed by the gain map codestream (minus
+// its now meaningless MPF segment); otherwise it returns an empty vector.
+// The gain map's entropy-coded data is copied verbatim, so it is also empty
+// if that codestream has none (see hasScanData).
+std::vector<uint8_t> reorderInvertedLayers(const uint8_t* data, size_t size);
+
+// Decoders such as libultrahdr take the codestream after the primary as the
+// gain map. When locateComponents finds the gain map elsewhere, e.g. past a
+// depth map listed before it in the GContainer directory, this returns the
+// primary followed by that gain map, both copied verbatim; otherwise it
+// returns an empty vector.
+std::vector<uint8_t> isolateGainMapLayers(const uint8_t* data, size_t size);
+
+// Returns a copy of the first JPEG in `data` with its gain map signalling
+// removed: hdrgm/GContainer XMP, MPF and ISO 21496-1 segments are dropped and
+// anything after the primary's EOI is discarded. The scans are copied
+// verbatim; returns an empty vector if `data` is not a parseable JPEG or its
+// first scan has no entropy-coded data (see hasScanData), rather than
+// writing a header-only file. With `allXmp` every XMP segment is dropped,
+// not only the gain map ones.
+std::vector<uint8_t> stripGainMapSignalling(const uint8_t* data, size_t size,
+                                            bool allXmp = false);
+
+// Returns a copy of `jpeg` with `xmp` in an APP1 XMP segment after its SOI
+// and any leading APP0 (JFIF) and Exif segments, which readers expect first.
+// Those are copied whole whatever their size; the packet gets a segment of its
+// own. A packet too large for one segment is written as Extended XMP: a
+// standard packet holding only xmpNote:HasExtendedXMP, followed by extension
+// segments carrying the whole packet under its MD5. Readers that ignore
+// Extended XMP then see none of its properties, so keep packets that must be
+// read everywhere, such as hdrgm metadata, under the limit. Returns an empty
+// vector if `jpeg` does not start with an SOI.
+std::vector<uint8_t> insertXmpSegment(const std::vector<uint8_t>& jpeg, const std::string& xmp);
+
+// Identifier that starts every ICC APP2 chunk, followed by a 1-based sequence
+// number and the chunk count (ICC.1 Annex B.4).
+constexpr char kIccSignature[] = "ICC_PROFILE";  // + NUL
+constexpr size_t kIccChunkHeaderSize = sizeof(kIccSignature) + 2;
+
+// Reassembles the ICC profile carried in the APP2 chunks of the first JPEG in
+// `data`, in sequence-number order. Returns an empty vector when there is no
+// profile or its chunks are missing, duplicated or inconsistently numbered.
+std::vector<uint8_t> extractIccProfile(const uint8_t* data, size_t size);
+
+// Pixel density from a JFIF APP0 header. `units` is 0 when x:y only gives
+// the pixel aspect ratio, 1 for dots per inch and 2 for dots per cm.
+struct JfifDensity {
+  uint8_t units = 0;
+  uint16_t x = 1;
+  uint16_t y = 1;
+};
+
+// Reads the density of the first JFIF APP0 segment of the first JPEG in
+// `data`. Returns false when there is none.
+bool readJfifDensity(const uint8_t* data, size_t size, JfifDensity& out);
+
+// One quantization table of a DQT segment. `precision` is 8 or 16 bits (Pq 0
+// or 1); `values` are in the zigzag order they are stored in.
+struct QuantizationTable {
+  uint8_t id = 0;
+  uint8_t precision = 8;
+  uint16_t values[64] = {};
+};
+
+// Reads the quantization tables defined before the first scan of the first
+// JPEG in `data`, in file order. A DQT segment may carry several tables, and
+// tables with 16-bit precision store each value as two big-endian bytes.
+// Stops at a truncated table, returning those before it.
+std::vector<QuantizationTable> readQuantizationTables(const uint8_t* data, size_t size);
+
+// Exif Orientation (TIFF tag 0x0112). `exif` is an APP1 payload, with or
+// without its "Exif\0\0" identifier. readExifOrientation returns 1 (top-left)
+// when the tag is absent or the block is malformed; writeExifOrientation
+// patches the tag in place and returns false if there is none to patch.
+uint16_t readExifOrientation(const uint8_t* exif, size_t size);
+bool writeExifOrientation(uint8_t* exif, size_t size, uint16_t orientation);
+
+// Exif orientation of the first JPEG in `data`, from its first Exif APP1
+// segment; 1 when there is none.
+uint16_t readJpegOrientation(const uint8_t* data, size_t size);
+
+// Returns a copy of `jpeg` tagged with Exif `orientation` (1-8). The first
+// Exif segment's Orientation entry is patched in place; a JPEG without Exif
+// gets a minimal segment (a TIFF header and an IFD0 holding only that entry)
+// after its SOI and any APP0, unless `orientation` is 1, which untagged files
+// already mean. Returns an empty vector if `jpeg` does not start with an SOI
+// or its Exif has no Orientation entry to patch.
+std::vector<uint8_t> setJpegOrientation(const std::vector<uint8_t>& jpeg, uint16_t orientation);
+
+// Orientation to store alongside pixels that were rotated `degrees` clockwise
+// so that viewers show the original display orientation rotated by the same
+// amount. Only the mirrored orientations change, and only for 90/270.
+uint16_t rotateExifOrientation(uint16_t orientation, int degrees);
+
 }  // namespace open_ultrahdr
@@ -0,0 +1,585 @@
+#include "jpeg_rotate.h"
+
+#include <algorithm>
+#include <csetjmp>
+#include <cstdio>
+#include <cstdlib>
+#include <cstring>
+#include <stdexcept>
+#include <string>
+#include <utility>
+
+extern "C" {
+#include <jpeglib.h>
+}
+
+#if defined(OPEN_ULTRAHDR_MOZJPEG) && !defined(JPEG_C_PARAM_SUPPORTED)
+#error "OPEN_ULTRAHDR_MOZJPEG is set but jpeglib.h is not mozjpeg's"
+#endif
+
+#include "jpeg_meta.h"
+
+namespace open_ultrahdr {
+
+namespace {
+
+constexpr int kCoefsPerBlock = DCTSIZE2;
+
+// libjpeg reports fatal errors through error_exit, which must not return.
+// Unwinding C++ exceptions through libjpeg's C frames is not safe, so jump
+// back to the caller and throw from there.
+struct ErrorManager {
+  jpeg_error_mgr pub;
+  std::jmp_buf jump;
+};
+
+void exitOnError(j_common_ptr cinfo) {
+  std::longjmp(reinterpret_cast<ErrorManager*>(cinfo->err)->jump, 1);
+}
+
+std::string errorMessage(j_common_ptr cinfo) {
+  char buf[JMSG_LENGTH_MAX];
+  (*cinfo->err->format_message)(cinfo, buf);
+  return buf;
+}
+
+void checkDegrees(int degrees) {
+  if (degrees != 90 && degrees != 180 && degrees != 270) {
+    throw std::runtime_error("Rotation must be 90, 180 or 270 degrees");
+  }
+}
+
+void writeMarkers(j_compress_ptr cinfo, const std::vector<JpegMarker>& markers) {
+  for (const JpegMarker& m : markers) {
+    jpeg_write_marker(cinfo, m.marker, m.payload.data(),
+                      static_cast<unsigned int>(m.payload.size()));
+  }
+}
+
+// Has `src` keep every APPn and COM marker; call before jpeg_read_header.
+void saveMarkers(j_decompress_ptr src) {
+  jpeg_save_markers(src, JPEG_COM, 0xFFFF);
+  for (int m = 0; m < 16; ++m) jpeg_save_markers(src, JPEG_APP0 + m, 0xFFFF);
+}
+
+// Writes the markers saveMarkers kept from `src` into `dst`.
+void copySavedMarkers(j_decompress_ptr src, j_compress_ptr dst) {
+  for (jpeg_saved_marker_ptr m = src->marker_list; m; m = m->next) {
+    // The encoder writes its own JFIF and Adobe headers when they apply.
+    bool jfif = m->marker == JPEG_APP0 && m->data_length >= 5 &&
+                std::memcmp(m->data, "JFIF", 5) == 0;
+    bool adobe = m->marker == JPEG_APP0 + 14 && m->data_length >= 5 &&
+                 std::memcmp(m->data, "Adobe", 5) == 0;
+    if ((jfif && dst->write_JFIF_header) || (adobe && dst->write_Adobe_marker)) continue;
+    jpeg_write_marker(dst, m->marker, m->data, m->data_length);
+  }
+}
+
+// Rotates one 8x8 block of DCT coefficients. A quarter turn is a transpose
+// followed by a flip; flipping negates the odd frequencies along that axis.
+void rotateBlock(const JCOEF* in, JCOEF* out, int degrees) {
+  for (int v = 0; v < DCTSIZE; ++v) {
+    for (int u = 0; u < DCTSIZE; ++u) {
+      JCOEF c;
+      bool negate;
+      if (degrees == 180) {
+        c = in[v * DCTSIZE + u];
+        negate = ((u + v) & 1) != 0;
+      } else {
+        c = in[u * DCTSIZE + v];
+        negate = ((degrees == 90 ? u : v) & 1) != 0;
+      }
+      out[v * DCTSIZE + u] = negate ? static_cast<JCOEF>(-c) : c;
+    }
+  }
+}
+
+void transposeQuantTable(JQUANT_TBL* tbl) {
+  for (int v = 0; v < DCTSIZE; ++v) {
+    for (int u = v + 1; u < DCTSIZE; ++u) {
+      std::swap(tbl->quantval[v * DCTSIZE + u], tbl->quantval[u * DCTSIZE + v]);
+    }
+  }
+}
+
+// Shared by decodeJpeg8 and decodeJpeg8Scaled; `minLongSide` 0 decodes at
+// full size.
+Image8 decodeJpeg8Impl(const uint8_t* data, size_t size, bool keepGrayscale,
+                       uint32_t minLongSide) {
+  jpeg_decompress_struct cinfo{};
+  ErrorManager err;
+  Image8 out;
+  cinfo.err = jpeg_std_error(&err.pub);
+  err.pub.error_exit = exitOnError;
+  if (setjmp(err.jump)) {
+    std::string msg = errorMessage(reinterpret_cast<j_common_ptr>(&cinfo));
+    jpeg_destroy_decompress(&cinfo);
+    throw std::runtime_error("JPEG decoding failed: " + msg);
+  }
+
+  jpeg_create_decompress(&cinfo);
+  jpeg_mem_src(&cinfo, const_cast<unsigned char*>(data), static_cast<unsigned long>(size));
+  jpeg_read_header(&cinfo, TRUE);
+  bool gray = keepGrayscale && cinfo.num_components == 1;
+  cinfo.out_color_space = gray ? JCS_GRAYSCALE : JCS_RGB;
+  if (minLongSide > 0) {
+    uint32_t longest = std::max(cinfo.image_width, cinfo.image_height);
+    for (unsigned denom : {8u, 4u, 2u}) {
+      if ((longest + denom - 1) / denom >= minLongSide) {
+        cinfo.scale_num = 1;
+        cinfo.scale_denom = denom;
+        break;
+      }
+    }
+  }
+  jpeg_start_decompress(&cinfo);
+  out.width = cinfo.output_width;
+  out.height = cinfo.output_height;
+  out.channels = gray ? 1 : 3;
+  out.data.resize(static_cast<size_t>(out.width) * out.height * out.channels);
+  while (cinfo.output_scanline < cinfo.output_height) {
+    JSAMPROW rowPtr =
+        out.data.data() + static_cast<size_t>(cinfo.output_scanline) * out.width * out.channels;
+    jpeg_read_scanlines(&cinfo, &rowPtr, 1);
+  }
+  jpeg_finish_decompress(&cinfo);
+  jpeg_destroy_decompress(&cinfo);
+  return out;
+}
+
+}  // namespace
+
+std::vector<JpegMarker> iccProfileMarkers(const std::vector<uint8_t>& profile) {
+  // A marker payload is at most 65533 bytes, including the chunk header.
+  constexpr size_t kMaxChunkData = 65533 - kIccChunkHeaderSize;
+  std::vector<JpegMarker> out;
+  size_t count = (profile.size() + kMaxChunkData - 1) / kMaxChunkData;
+  if (count > 255) throw std::runtime_error("ICC profile is too large to embed in a JPEG");
+  for (size_t i = 0; i < count; ++i) {
+    size_t begin = i * kMaxChunkData;
+    size_t end = std::min(begin + kMaxChunkData, profile.size());
+    JpegMarker m;
+    m.marker = 0xE2;
+    m.payload.assign(kIccSignature, kIccSignature + sizeof(kIccSignature));
+    m.payload.push_back(static_cast<uint8_t>(i + 1));
+    m.payload.push_back(static_cast<uint8_t>(count));
+    m.payload.insert(m.payload.end(), profile.begin() + begin, profile.begin() + end);
+    out.push_back(std::move(m));
+  }
+  return out;
+}
+
+Image8 rotateImage8(const ImageView8& src, int degrees) {
+  checkDegrees(degrees);
+  if (!src.data || src.width == 0 || src.height == 0 || src.channels == 0) {
+    throw std::runtime_error("Cannot rotate an empty image");
+  }
+  Image8 out;
+  bool swap = degrees != 180;
+  out.width = swap ? src.height : src.width;
+  out.height = swap ? src.width : src.height;
+  out.channels = src.channels;
+  out.data.resize(static_cast<size_t>(out.width) * out.height * out.channels);
+
+  for (uint32_t y = 0; y < src.height; ++y) {
+    for (uint32_t x = 0; x < src.width; ++x) {
+      uint32_t dx, dy;
+      if (degrees == 90) {
+        dx = src.height - 1 - y;
+        dy = x;
+      } else if (degrees == 180) {
+        dx = src.width - 1 - x;
+        dy = src.height - 1 - y;
+      } else {
+        dx = y;
+        dy = src.width - 1 - x;
+      }
+      std::memcpy(out.data.data() + (static_cast<size_t>(dy) * out.width + dx) * out.channels,
+                  src.data + (static_cast<size_t>(y) * src.stride + x) * src.channels,
+                  src.channels);
+    }
+  }
+  return out;
+}
+
+Image8 orientImage8(const ImageView8& src, uint16_t orientation) {
+  if (orientation < 1 || orientation > 8) {
+    throw std::runtime_error("Invalid Exif orientation: " + std::to_string(orientation));
+  }
+  if (!src.data || src.width == 0 || src.height == 0 || src.channels == 0) {
+    throw std::runtime_error("Cannot orient an empty image");
+  }
+  Image8 out;
+  bool swap = orientation >= 5;
+  out.width = swap ? src.height : src.width;
+  out.height = swap ? src.width : src.height;
+  out.channels = src.channels;
+  out.data.resize(static_cast<size_t>(out.width) * out.height * out.channels);
+
+  uint32_t lastX = src.width - 1;
+  uint32_t lastY = src.height - 1;
+  for (uint32_t y = 0; y < src.height; ++y) {
+    for (uint32_t x = 0; x < src.width; ++x) {
+      uint32_t dx, dy;
+      switch (orientation) {
+        case 2:  // Mirrored horizontally.
+          dx = lastX - x;
+          dy = y;
+          break;
+        case 3:  // Rotated 180.
+          dx = lastX - x;
+          dy = lastY - y;
+          break;
+        case 4:  // Mirrored vertically.
+          dx = x;
+          dy = lastY - y;
+          break;
+        case 5:  // Transposed.
+          dx = y;
+          dy = x;
+          break;
+        case 6:  // Needs a 90 degree clockwise turn.
+          dx = lastY - y;
+          dy = x;
+          break;
+        case 7:  // Transversed.
+          dx = lastY - y;
+          dy = lastX - x;
+          break;
+        case 8:  // Needs a 90 degree counter-clockwise turn.
+          dx = y;
+          dy = lastX - x;
+          break;
+        default:
+          dx = x;
+          dy = y;
+          break;
+      }
+      std::memcpy(out.data.data() + (static_cast<size_t>(dy) * out.width + dx) * out.channels,
+                  src.data + (static_cast<size_t>(y) * src.stride + x) * src.channels,
+                  src.channels);
+    }
+  }
+  return out;
+}
+
+bool canRotateJpegLossless(const uint8_t* data, size_t size) {
+  jpeg_decompress_struct cinfo{};
+  ErrorManager err;
+  cinfo.err = jpeg_std_error(&err.pub);
+  err.pub.error_exit = exitOnError;
+  if (setjmp(err.jump)) {
+    jpeg_destroy_decompress(&cinfo);
+    return false;
+  }
+
+  jpeg_create_decompress(&cinfo);
+  jpeg_mem_src(&cinfo, const_cast<unsigned char*>(data), static_cast<unsigned long>(size));
+  jpeg_read_header(&cinfo, TRUE);
+  int maxH = 1;
+  int maxV = 1;
+  for (int ci = 0; ci < cinfo.num_components; ++ci) {
+    maxH = std::max(maxH, cinfo.comp_info[ci].h_samp_factor);
+    maxV = std::max(maxV, cinfo.comp_info[ci].v_samp_factor);
+  }
+  bool aligned = cinfo.image_width % (DCTSIZE * maxH) == 0 &&
+                 cinfo.image_height % (DCTSIZE * maxV) == 0;
+  jpeg_destroy_decompress(&cinfo);
+  return aligned;
+}
+
+std::vector<uint8_t> rotateJpegLossless(const uint8_t* data, size_t size, int degrees,
+                                        const std::vector<JpegMarker>& markers) {
+  checkDegrees(degrees);
+  if (!canRotateJpegLossless(data, size)) {
+    throw std::runtime_error("JPEG dimensions are not a multiple of the MCU size");
+  }
+
+  // Everything that outlives a longjmp is declared before setjmp.
+  jpeg_decompress_struct src{};
+  jpeg_compress_struct dst{};
+  ErrorManager err;
+  unsigned char* outBuf = nullptr;
+  unsigned long outSize = 0;
+  std::vector<JCOEF> grid;
+  std::vector<uint8_t> out;
+  src.err = jpeg_std_error(&err.pub);
+  dst.err = &err.pub;
+  err.pub.error_exit = exitOnError;
+  if (setjmp(err.jump)) {
+    std::string msg = errorMessage(reinterpret_cast<j_common_ptr>(&src));
+    jpeg_destroy_compress(&dst);
+    jpeg_destroy_decompress(&src);
+    std::free(outBuf);
+    throw std::runtime_error("Lossless JPEG rotation failed: " + msg);
+  }
+
+  jpeg_create_decompress(&src);
+  jpeg_create_compress(&dst);
+  jpeg_mem_src(&src, const_cast<unsigned char*>(data), static_cast<unsigned long>(size));
+  jpeg_read_header(&src, TRUE);
+
+  // Destination coefficient arrays must be requested before
+  // jpeg_read_coefficients realizes the source's virtual arrays. The encoder
+  // reads them one iMCU row (v_samp_factor block rows) at a time.
+  const bool swap = degrees != 180;
+  jvirt_barray_ptr dstCoefs[MAX_COMPONENTS];
+  for (int ci = 0; ci < src.num_components; ++ci) {
+    const jpeg_component_info& comp = src.comp_info[ci];
+    dstCoefs[ci] = (*src.mem->request_virt_barray)(
+        reinterpret_cast<j_common_ptr>(&src), JPOOL_IMAGE, FALSE,
+        swap ? comp.height_in_blocks : comp.width_in_blocks,
+        swap ? comp.width_in_blocks : comp.height_in_blocks,
+        static_cast<JDIMENSION>(swap ? comp.h_samp_factor : comp.v_samp_factor));
+  }
+  jvirt_barray_ptr* srcCoefs = jpeg_read_coefficients(&src);
+
+  for (int ci = 0; ci < src.num_components; ++ci) {
+    const JDIMENSION srcW = src.comp_info[ci].width_in_blocks;
+    const JDIMENSION srcH = src.comp_info[ci].height_in_blocks;
+    grid.resize(static_cast<size_t>(srcW) * srcH * kCoefsPerBlock);
+    for (JDIMENSION by = 0; by < srcH; ++by) {
+      JBLOCKARRAY row = (*src.mem->access_virt_barray)(reinterpret_cast<j_common_ptr>(&src),
+                                                         srcCoefs[ci], by, 1, FALSE);
+      std::memcpy(grid.data() + static_cast<size_t>(by) * srcW * kCoefsPerBlock, row[0],
+                  static_cast<size_t>(srcW) * sizeof(JBLOCK));
+    }
+
+    const JDIMENSION dstW = swap ? srcH : srcW;
+    const JDIMENSION dstH = swap ? srcW : srcH;
+    for (JDIMENSION dy = 0; dy < dstH; ++dy) {
+      JBLOCKARRAY row = (*src.mem->access_virt_barray)(reinterpret_cast<j_common_ptr>(&src),
+                                                         dstCoefs[ci], dy, 1, TRUE);
+      for (JDIMENSION dx = 0; dx < dstW; ++dx) {
+        JDIMENSION sx, sy;
+        if (degrees == 90) {
+          sx = dy;
+          sy = srcH - 1 - dx;
+        } else if (degrees == 180) {
+          sx = srcW - 1 - dx;
+          sy = srcH - 1 - dy;
+        } else {
+          sx = srcW - 1 - dy;
+          sy = dx;
+        }
+        rotateBlock(grid.data() + (static_cast<size_t>(sy) * srcW + sx) * kCoefsPerBlock,
+                    row[0][dx], degrees);
+      }
+    }
+  }
+
+  jpeg_copy_critical_parameters(&src, &dst);
+  if (swap) {
+    std::swap(dst.image_width, dst.image_height);
+    std::swap(dst.X_density, dst.Y_density);
+    for (int ci = 0; ci < dst.num_components; ++ci) {
+      std::swap(dst.comp_info[ci].h_samp_factor, dst.comp_info[ci].v_samp_factor);
+    }
+    for (JQUANT_TBL* tbl : dst.quant_tbl_ptrs) {
+      if (tbl) transposeQuantTable(tbl);
+    }
+  }
+  jpeg_mem_dest(&dst, &outBuf, &outSize);
+  jpeg_write_coefficients(&dst, dstCoefs);
+  writeMarkers(&dst, markers);
+  // The destination arrays live in the source's pool, so finish writing first.
+  jpeg_finish_compress(&dst);
+  jpeg_finish_decompress(&src);
+
+  out.assign(outBuf, outBuf + outSize);
+  jpeg_destroy_compress(&dst);
+  jpeg_destroy_decompress(&src);
+  std::free(outBuf);
+  return out;
+}
+
+std::vector<uint8_t> cropJpegLossless(const uint8_t* data, size_t size, uint32_t width,
+                                      uint32_t height) {
+  jpeg_decompress_struct src{};
+  jpeg_compress_struct dst{};
+  ErrorManager err;
+  unsigned char* outBuf = nullptr;
+  unsigned long outSize = 0;
+  std::vector<uint8_t> out;
+  src.err = jpeg_std_error(&err.pub);
+  dst.err = &err.pub;
+  err.pub.error_exit = exitOnError;
+  if (setjmp(err.jump)) {
+    std::string msg = errorMessage(reinterpret_cast<j_common_ptr>(&src));
+    jpeg_destroy_compress(&dst);
+    jpeg_destroy_decompress(&src);
+    std::free(outBuf);
+    throw std::runtime_error("Lossless JPEG crop failed: " + msg);
+  }
+
+  jpeg_create_decompress(&src);
+  jpeg_create_compress(&dst);
+  jpeg_mem_src(&src, const_cast<unsigned char*>(data), static_cast<unsigned long>(size));
+  saveMarkers(&src);
+  jpeg_read_header(&src, TRUE);
+  if (width == 0 || height == 0 || width > src.image_width || height > src.image_height) {
+    std::string msg = "cannot crop " + std::to_string(src.image_width) + "x" +
+                      std::to_string(src.image_height) + " to " + std::to_string(width) + "x" +
+                      std::to_string(height);
+    jpeg_destroy_compress(&dst);
+    jpeg_destroy_decompress(&src);
+    throw std::runtime_error("Lossless JPEG crop failed: " + msg);
+  }
+  jvirt_barray_ptr* coefs = jpeg_read_coefficients(&src);
+
+  // The crop keeps the top-left corner, so every kept block sits where it
+  // was: the encoder reads the source arrays as they are, stopping at the
+  // smaller block counts of the new size. The coefficients are unchanged, but
+  // the entropy-coded data is not: optimal Huffman tables are built for the
+  // kept blocks instead of carrying over the source's.
+  jpeg_copy_critical_parameters(&src, &dst);
+  dst.image_width = width;
+  dst.image_height = height;
+  dst.optimize_coding = TRUE;
+  dst.scan_info = nullptr;
+  dst.num_scans = 0;
+#ifdef OPEN_ULTRAHDR_MOZJPEG
+  jpeg_c_set_bool_param(&dst, JBOOLEAN_OPTIMIZE_SCANS, FALSE);
+#endif
+  jpeg_mem_dest(&dst, &outBuf, &outSize);
+  jpeg_write_coefficients(&dst, coefs);
+  copySavedMarkers(&src, &dst);
+  jpeg_finish_compress(&dst);
+  jpeg_finish_decompress(&src);
+
+  out.assign(outBuf, outBuf + outSize);
+  jpeg_destroy_compress(&dst);
+  jpeg_destroy_decompress(&src);
+  std::free(outBuf);
+  return out;
+}
+
+std::vector<uint8_t> transcodeJpegBaseline(const uint8_t* data, size_t size) {
+  jpeg_decompress_struct src{};
+  jpeg_compress_struct dst{};
+  ErrorManager err;
+  unsigned char* outBuf = nullptr;
+  unsigned long outSize = 0;
+  std::vector<uint8_t> out;
+  src.err = jpeg_std_error(&err.pub);
+  dst.err = &err.pub;
+  err.pub.error_exit = exitOnError;
+  if (setjmp(err.jump)) {
+    std::string msg = errorMessage(reinterpret_cast<j_common_ptr>(&src));
+    jpeg_destroy_compress(&dst);
+    jpeg_destroy_decompress(&src);
+    std::free(outBuf);
+    throw std::runtime_error("Baseline JPEG transcode failed: " + msg);
+  }
+
+  jpeg_create_decompress(&src);
+  jpeg_create_compress(&dst);
+  jpeg_mem_src(&src, const_cast<unsigned char*>(data), static_cast<unsigned long>(size));
+  saveMarkers(&src);
+  jpeg_read_header(&src, TRUE);
+  jvirt_barray_ptr* coefs = jpeg_read_coefficients(&src);
+
+  jpeg_copy_critical_parameters(&src, &dst);
+  // A null scan script means one sequential scan per component set.
+  dst.scan_info = nullptr;
+  dst.num_scans = 0;
+#ifdef OPEN_ULTRAHDR_MOZJPEG
+  jpeg_c_set_bool_param(&dst, JBOOLEAN_OPTIMIZE_SCANS, FALSE);
+#endif
+  jpeg_mem_dest(&dst, &outBuf, &outSize);
+  jpeg_write_coefficients(&dst, coefs);
+  copySavedMarkers(&src, &dst);
+  jpeg_finish_compress(&dst);
+  jpeg_finish_decompress(&src);
+
+  out.assign(outBuf, outBuf + outSize);
+  jpeg_destroy_compress(&dst);
+  jpeg_destroy_decompress(&src);
+  std::free(outBuf);
+  return out;
+}
+
+Image8 decodeJpeg8(const uint8_t* data, size_t size, bool keepGrayscale) {
+  return decodeJpeg8Impl(data, size, keepGrayscale, 0);
+}
+
+Image8 decodeJpeg8Scaled(const uint8_t* data, size_t size, uint32_t minLongSide) {
+  return decodeJpeg8Impl(data, size, false, minLongSide);
+}
+
+std::vector<uint8_t> encodeJpeg8(const ImageView8& img, int quality,
+                                 const std::vector<JpegMarker>& markers,
+                                 const JfifDensity* density) {
+  if (!img.data || img.width == 0 || img.height == 0) {
+    throw std::runtime_error("Cannot encode an empty image");
+  }
+  if (img.channels != 1 && img.channels != 3 && img.channels != 4) {
+    throw std::runtime_error("Cannot encode an image with " + std::to_string(img.channels) +
+                             " channels");
+  }
+  const int outChannels = img.channels == 1 ? 1 : 3;
+
+  jpeg_compress_struct cinfo{};
+  ErrorManager err;
+  unsigned char* outBuf = nullptr;
+  unsigned long outSize = 0;
+  std::vector<uint8_t> row(static_cast<size_t>(img.width) * outChannels);
+  std::vector<uint8_t> out;
+  cinfo.err = jpeg_std_error(&err.pub);
+  err.pub.error_exit = exitOnError;
+  if (setjmp(err.jump)) {
+    std::string msg = errorMessage(reinterpret_cast<j_common_ptr>(&cinfo));
+    jpeg_destroy_compress(&cinfo);
+    std::free(outBuf);
+    throw std::runtime_error("JPEG encoding failed: " + msg);
+  }
+
+  jpeg_create_compress(&cinfo);
+  jpeg_mem_dest(&cinfo, &outBuf, &outSize);
+  cinfo.image_width = img.width;
+  cinfo.image_height = img.height;
+  cinfo.input_components = outChannels;
+  cinfo.in_color_space = outChannels == 1 ? JCS_GRAYSCALE : JCS_RGB;
+  jpeg_set_defaults(&cinfo);
+#if defined(OPEN_ULTRAHDR_MOZJPEG) && !defined(OPEN_ULTRAHDR_MOZJPEG_PROGRESSIVE)
+  // mozjpeg's default profile selects a progressive scan script.
+  jpeg_c_set_bool_param(&cinfo, JBOOLEAN_OPTIMIZE_SCANS, FALSE);
+  cinfo.scan_info = nullptr;
+  cinfo.num_scans = 0;
+#endif
+  jpeg_set_quality(&cinfo, quality, TRUE);
+  if (density) {
+    cinfo.density_unit = density->units;
+    cinfo.X_density = density->x;
+    cinfo.Y_density = density->y;
+  }
+  jpeg_start_compress(&cinfo, TRUE);
+  writeMarkers(&cinfo, markers);
+  while (cinfo.next_scanline < cinfo.image_height) {
+    const uint8_t* src = img.data + static_cast<size_t>(cinfo.next_scanline) * img.stride *
+                                        img.channels;
+    for (uint32_t x = 0; x < img.width; ++x) {
+      for (int c = 0; c < outChannels; ++c) {
+        row[static_cast<size_t>(x) * outChannels + c] = src[x * img.channels + c];
+      }
+    }
+    JSAMPROW rowPtr = row.data();
+    jpeg_write_scanlines(&cinfo, &rowPtr, 1);
+  }
+  jpeg_finish_compress(&cinfo);
+
+  out.assign(outBuf, outBuf + outSize);
+  jpeg_destroy_compress(&cinfo);
+  std::free(outBuf);
+  return out;
+}
+
+const char* jpegEncoderBackend() {
+#ifdef OPEN_ULTRAHDR_MOZJPEG
+  return "mozjpeg";
+#else
+  return "libjpeg";
+#endif
+}
+
+}  // namespace open_ultrahdr
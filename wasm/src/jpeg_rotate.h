@@ -0,0 +1,86 @@
+#pragma once
+
+#include <cstddef>
+#include <cstdint>
+#include <vector>
+
+#include "gainmap.h"
+#include "jpeg_meta.h"
+
+namespace open_ultrahdr {
+
+// Tightly packed 8-bit image that owns its pixels.
+struct Image8 {
+  std::vector<uint8_t> data;
+  uint32_t width = 0;
+  uint32_t height = 0;
+  uint32_t channels = 0;
+
+  ImageView8 view() const { return {data.data(), width, height, width, channels}; }
+};
+
+// A marker segment to emit right after the frame header, e.g. an ICC APP2.
+struct JpegMarker {
+  int marker = 0;
+  std::vector<uint8_t> payload;
+};
+
+// Splits an ICC profile into the APP2 markers that carry it in a JPEG, each
+// prefixed with the ICC_PROFILE chunk header. Empty for an empty profile.
+std::vector<JpegMarker> iccProfileMarkers(const std::vector<uint8_t>& profile);
+
+// Rotates pixels clockwise. `degrees` must be 90, 180 or 270.
+Image8 rotateImage8(const ImageView8& src, int degrees);
+
+// Transforms pixels stored with Exif `orientation` (1-8) into display
+// orientation. Orientations 5-8 swap width and height; 1 returns a copy.
+Image8 orientImage8(const ImageView8& src, uint16_t orientation);
+
+// True when both JPEG dimensions are whole multiples of the MCU size, so the
+// DCT blocks can be rearranged without trimming or re-quantizing edges.
+bool canRotateJpegLossless(const uint8_t* data, size_t size);
+
+// Rotates a JPEG clockwise in the DCT domain; the entropy-decoded
+// coefficients are rearranged, never re-quantized. Only `markers` are carried
+// into the output, plus the JFIF density (swapped for a quarter turn). Throws
+// if the image is not MCU aligned.
+std::vector<uint8_t> rotateJpegLossless(const uint8_t* data, size_t size, int degrees,
+                                        const std::vector<JpegMarker>& markers);
+
+// Crops a JPEG to its top-left `width` x `height` in the DCT domain. Kept
+// blocks are copied unchanged and the rest dropped, so the retained pixels
+// decode as before; a partial block at the new edge keeps its full
+// coefficients. APPn and COM markers, such as Exif, XMP and ICC, are carried
+// over as they are. Throws if the crop is empty or larger than the image.
+std::vector<uint8_t> cropJpegLossless(const uint8_t* data, size_t size, uint32_t width,
+                                      uint32_t height);
+
+// Rewrites a JPEG as baseline sequential without re-quantizing: the DCT
+// coefficients are copied as they are and only the scan structure changes.
+// APPn and COM markers are carried over.
+std::vector<uint8_t> transcodeJpegBaseline(const uint8_t* data, size_t size);
+
+// Decodes a JPEG to tightly packed 8-bit RGB. Grayscale sources are expanded
+// unless `keepGrayscale` is set, when they decode to one channel.
+Image8 decodeJpeg8(const uint8_t* data, size_t size, bool keepGrayscale = false);
+
+// Decodes a JPEG to RGB like decodeJpeg8, reduced by libjpeg's scaled IDCT:
+// the largest of 1/8, 1/4 or 1/2 that keeps the longer side at least
+// `minLongSide`, or full size when none does. Much cheaper than decoding at
+// full size and then decimating.
+Image8 decodeJpeg8Scaled(const uint8_t* data, size_t size, uint32_t minLongSide);
+
+// Encodes a grayscale, RGB or RGBA (alpha dropped) image as a JPEG. Baseline
+// with the libjpeg port; with mozjpeg, trellis-quantized and, unless built
+// without OPEN_ULTRAHDR_MOZJPEG_PROGRESSIVE, progressive. The JFIF header
+// carries `density` when given, else libjpeg's 1:1 aspect ratio. Grayscale
+// is written full range, as JFIF specifies and gain maps need: samples 0 and
+// 255 decode as 0 and 255, not the studio-swing 16 and 235.
+std::vector<uint8_t> encodeJpeg8(const ImageView8& img, int quality,
+                                 const std::vector<JpegMarker>& markers,
+                                 const JfifDensity* density = nullptr);
+
+// Library JPEGs are encoded with: "mozjpeg" or "libjpeg".
+const char* jpegEncoderBackend();
+
+}  // namespace open_ultrahdr
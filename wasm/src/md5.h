@@ -0,0 +1,14 @@
+#pragma once
+
+#include <cstddef>
+#include <cstdint>
+#include <string>
+
+namespace open_ultrahdr {
+
+// MD5 (RFC 1321) digest of `data` as 32 uppercase hex digits, the form
+// Extended XMP uses as the GUID that ties its chunks to the standard packet.
+// Not for anything security related.
+std::string md5Hex(const uint8_t* data, size_t size);
+
+}  // namespace open_ultrahdr
@@ -0,0 +1,250 @@
+#include "xmp_metadata.h"
+
+#include <algorithm>
+#include <cctype>
+#include <cmath>
+#include <cstdio>
+#include <cstdlib>
+
+#include "jpeg_meta.h"
+
+namespace open_ultrahdr {
+
+namespace {
+
+constexpr char kXmpSignature[] = "http://ns.adobe.com/xap/1.0/";  // + NUL
+
+std::string trim(const std::string& s) {
+  size_t begin = s.find_first_not_of(" \t\r\n");
+  if (begin == std::string::npos) return {};
+  return s.substr(begin, s.find_last_not_of(" \t\r\n") - begin + 1);
+}
+
+bool isDigits(const std::string& s, size_t from = 0) {
+  if (from >= s.size()) return false;
+  return std::all_of(s.begin() + from, s.end(), [](char c) { return c >= '0' && c <= '9'; });
+}
+
+// XMP booleans are "True" or "False", but some writers use other case, 1/0
+// or yes/on. Anything else, including an empty value, is false.
+bool parseXmpBool(const std::string& text) {
+  std::string lower = trim(text);
+  std::transform(lower.begin(), lower.end(), lower.begin(),
+                 [](unsigned char c) { return static_cast<char>(std::tolower(c)); });
+  return lower == "true" || lower == "1" || lower == "yes" || lower == "on";
+}
+
+// Writers running under a European locale format numbers with a decimal
+// comma. A single comma between digits, as in an rdf:li value "0,5", is read
+// as a decimal point.
+bool parseNumber(const std::string& text, float& out) {
+  if (text.empty()) return false;
+  std::string number = text;
+  size_t comma = number.find(',');
+  if (comma != std::string::npos && number.find(',', comma + 1) == std::string::npos &&
+      comma > 0 && std::isdigit(static_cast<unsigned char>(number[comma - 1])) &&
+      comma + 1 < number.size() && std::isdigit(static_cast<unsigned char>(number[comma + 1]))) {
+    number[comma] = '.';
+  }
+  char* end = nullptr;
+  double v = std::strtod(number.c_str(), &end);
+  if (end != number.c_str() + number.size() || !std::isfinite(v)) return false;
+  out = static_cast<float>(v);
+  return true;
+}
+
+// Comma-separated attribute values split a decimal comma in two: "0,5" reads
+// as "0" and "5". When there are neither one nor `channels` values but they
+// pair up as integer and fraction digits, rejoins each pair ("0.5"). A count
+// that already fits is always kept as a list, so "1,5,2" on a per-channel
+// property is three values, never 1.5 and 2.
+void joinDecimalCommas(std::vector<std::string>& values, size_t channels) {
+  if (values.size() == 1 || values.size() == channels) return;
+  if (values.size() != 2 && values.size() != 2 * channels) return;
+  std::vector<std::string> joined;
+  for (size_t i = 0; i < values.size(); i += 2) {
+    const std::string& whole = values[i];
+    size_t sign = !whole.empty() && (whole[0] == '-' || whole[0] == '+') ? 1 : 0;
+    if (!isDigits(whole, sign) || !isDigits(values[i + 1])) return;
+    joined.push_back(whole + "." + values[i + 1]);
+  }
+  values = joined;
+}
+
+// Nine significant digits, enough for any float to parse back unchanged.
+std::string formatNumber(float v) {
+  char buf[32];
+  std::snprintf(buf, sizeof(buf), "%.9g", v);
+  return buf;
+}
+
+// One per-channel property, either as an attribute (appended to `attrs`) or,
+// for differing channels in RdfSeq style, as an element (appended to `elems`).
+void writeChannels(const char* name, const float (&v)[3], XmpFloatArrayStyle style,
+                   std::string& attrs, std::string& elems) {
+  if (v[0] == v[1] && v[1] == v[2]) {
+    attrs += std::string("\n        ") + name + "=\"" + formatNumber(v[0]) + "\"";
+  } else if (style == XmpFloatArrayStyle::CommaSeparated) {
+    attrs += std::string("\n        ") + name + "=\"" + formatNumber(v[0]) + ", " +
+             formatNumber(v[1]) + ", " + formatNumber(v[2]) + "\"";
+  } else {
+    elems += std::string("      <") + name + ">\n        <rdf:Seq>\n";
+    for (float c : v) elems += "          <rdf:li>" + formatNumber(c) + "</rdf:li>\n";
+    elems += std::string("        </rdf:Seq>\n      </") + name + ">\n";
+  }
+}
+
+}  // namespace
+
+const std::vector<std::string>& supportedHdrgmVersions() {
+  static const std::vector<std::string> versions = {"1.0"};
+  return versions;
+}
+
+bool isSupportedHdrgmVersion(const std::string& version) {
+  const std::vector<std::string>& versions = supportedHdrgmVersions();
+  return std::find(versions.begin(), versions.end(), version) != versions.end();
+}
+
+std::string findHdrgmXmp(const uint8_t* data, size_t size) {
+  JpegSegmentReader reader(data, size);
+  for (JpegSegment seg; reader.next(seg);) {
+    if (!isXmpSegment(data, seg)) continue;
+    std::string text(reinterpret_cast<const char*>(data + seg.payloadOffset) +
+                         sizeof(kXmpSignature),
+                     seg.payloadLength - sizeof(kXmpSignature));
+    if (text.find("hdrgm:") != std::string::npos) return text;
+  }
+  return {};
+}
+
+bool xmpValues(const std::string& xmp, const std::string& name, std::vector<std::string>& values) {
+  values.clear();
+  std::string attr = name + "=";
+  size_t at = xmp.find(attr);
+  if (at != std::string::npos && at + attr.size() < xmp.size()) {
+    char quote = xmp[at + attr.size()];
+    if (quote == '"' || quote == '\'') {
+      size_t begin = at + attr.size() + 1;
+      size_t end = xmp.find(quote, begin);
+      if (end == std::string::npos) return false;
+      size_t comma;
+      while ((comma = xmp.find(',', begin)) < end) {
+        values.push_back(trim(xmp.substr(begin, comma - begin)));
+        begin = comma + 1;
+      }
+      values.push_back(trim(xmp.substr(begin, end - begin)));
+      return true;
+    }
+  }
+
+  std::string open = "<" + name + ">";
+  size_t begin = xmp.find(open);
+  if (begin == std::string::npos) return false;
+  begin += open.size();
+  size_t end = xmp.find("</" + name + ">", begin);
+  if (end == std::string::npos) return false;
+  std::string body = xmp.substr(begin, end - begin);
+  size_t li = body.find("<rdf:li");
+  if (li == std::string::npos) {
+    values.push_back(trim(body));
+    return true;
+  }
+  while (li != std::string::npos) {
+    size_t start = body.find('>', li);
+    if (start == std::string::npos) break;
+    size_t stop = body.find("</rdf:li>", start);
+    if (stop == std::string::npos) break;
+    values.push_back(trim(body.substr(start + 1, stop - start - 1)));
+    li = body.find("<rdf:li", stop);
+  }
+  return true;
+}
+
+bool readXmpChannels(const std::string& xmp, const std::string& name, float (&out)[3]) {
+  std::vector<std::string> values;
+  if (!xmpValues(xmp, name, values)) return false;
+  joinDecimalCommas(values, 3);
+  if (values.size() != 3) return false;
+  float parsed[3];
+  for (int c = 0; c < 3; ++c) {
+    if (!parseNumber(values[c], parsed[c])) return false;
+  }
+  for (int c = 0; c < 3; ++c) out[c] = parsed[c];
+  return true;
+}
+
+bool decodeGainMapXmp(const std::string& xmp, XmpGainMapMetadata& out,
+                      std::vector<std::string>* errors) {
+  const std::string source = "gain map XMP";
+  out = XmpGainMapMetadata();
+  GainMapParams& p = out.params;
+  struct Field {
+    const char* name;
+    float* values;
+    int channels;
+    bool required;
+  };
+  const Field fields[] = {
+      {"hdrgm:GainMapMin", p.gainMapMin, 3, false},
+      {"hdrgm:GainMapMax", p.gainMapMax, 3, true},
+      {"hdrgm:Gamma", p.gamma, 3, false},
+      {"hdrgm:OffsetSDR", p.offsetSdr, 3, false},
+      {"hdrgm:OffsetHDR", p.offsetHdr, 3, false},
+      {"hdrgm:HDRCapacityMin", &p.hdrCapacityMin, 1, false},
+      {"hdrgm:HDRCapacityMax", &p.hdrCapacityMax, 1, true},
+  };
+  std::vector<std::string> problems;
+  std::vector<std::string> values;
+  for (const Field& f : fields) {
+    if (!xmpValues(xmp, f.name, values)) {
+      if (f.required) problems.push_back(source + " is missing " + f.name);
+      continue;
+    }
+    joinDecimalCommas(values, f.channels);
+    if (values.size() != 1 && static_cast<int>(values.size()) != f.channels) {
+      problems.push_back(source + " " + f.name + " has " + std::to_string(values.size()) +
+                         " values");
+      continue;
+    }
+    for (int c = 0; c < f.channels; ++c) {
+      const std::string& text = values[values.size() == 1 ? 0 : c];
+      if (!parseNumber(text, f.values[c])) {
+        problems.push_back(source + " " + f.name + " \"" + text + "\" is not a number");
+        break;
+      }
+    }
+  }
+  if (xmpValues(xmp, "hdrgm:BaseRenditionIsHDR", values) && values.size() == 1) {
+    out.baseRenditionIsHdr = parseXmpBool(values[0]);
+  }
+  if (errors) errors->insert(errors->end(), problems.begin(), problems.end());
+  return problems.empty();
+}
+
+std::string encodeGainMapXmp(const XmpGainMapMetadata& meta, XmpFloatArrayStyle style) {
+  const GainMapParams& p = meta.params;
+  std::string attrs;
+  std::string elems;
+  attrs += "\n        hdrgm:Version=\"1.0\"";
+  writeChannels("hdrgm:GainMapMin", p.gainMapMin, style, attrs, elems);
+  writeChannels("hdrgm:GainMapMax", p.gainMapMax, style, attrs, elems);
+  writeChannels("hdrgm:Gamma", p.gamma, style, attrs, elems);
+  writeChannels("hdrgm:OffsetSDR", p.offsetSdr, style, attrs, elems);
+  writeChannels("hdrgm:OffsetHDR", p.offsetHdr, style, attrs, elems);
+  attrs += "\n        hdrgm:HDRCapacityMin=\"" + formatNumber(p.hdrCapacityMin) + "\"";
+  attrs += "\n        hdrgm:HDRCapacityMax=\"" + formatNumber(p.hdrCapacityMax) + "\"";
+  attrs += std::string("\n        hdrgm:BaseRenditionIsHDR=\"") +
+           (meta.baseRenditionIsHdr ? "True" : "False") + "\"";
+
+  return "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n"
+         "  <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n"
+         "    <rdf:Description rdf:about=\"\"\n"
+         "        xmlns:hdrgm=\"http://ns.adobe.com/hdr-gain-map/1.0/\"" +
+         attrs + ">\n" + elems +
+         "    </rdf:Description>\n"
+         "  </rdf:RDF>\n"
+         "</x:xmpmeta>\n";
+}
+
+}  // namespace open_ultrahdr
@@ -0,0 +1,70 @@
+#pragma once
+
+#include <cstddef>
+#include <cstdint>
+#include <string>
+#include <vector>
+
+#include "gain_math.h"
+
+namespace open_ultrahdr {
+
+// hdrgm gain map metadata read from, or written to, XMP packet text.
+struct XmpGainMapMetadata {
+  GainMapParams params;  // Single-valued properties are broadcast to RGB.
+  bool baseRenditionIsHdr = false;
+};
+
+// How encodeGainMapXmp writes a per-channel property whose channels differ.
+// Properties with one value for all channels are always a scalar attribute.
+//   - CommaSeparated: an attribute, hdrgm:Gamma="1, 1.2, 1";
+//   - RdfSeq: an element holding an rdf:Seq of three rdf:li values, the
+//     canonical form strict ISO 21496-1 consumers expect.
+enum class XmpFloatArrayStyle { CommaSeparated = 0, RdfSeq = 1 };
+
+// hdrgm:Version values this library understands. Files declaring another
+// version are still read, since later versions are expected to stay
+// compatible, but callers can flag them.
+const std::vector<std::string>& supportedHdrgmVersions();
+bool isSupportedHdrgmVersion(const std::string& version);
+
+// Text of the first XMP segment in `data` that uses the hdrgm namespace, or
+// empty.
+std::string findHdrgmXmp(const uint8_t* data, size_t size);
+
+// Values of XMP property `name`, written either as an attribute
+// (hdrgm:Gamma="1", or comma-separated per-channel values "1, 1.2, 1") or as
+// an element holding a value or an rdf:Seq of per-channel rdf:li values.
+// Values are trimmed of surrounding whitespace. Returns false if the property
+// is absent.
+bool xmpValues(const std::string& xmp, const std::string& name, std::vector<std::string>& values);
+
+// Reads hdrgm XMP property `name` (e.g. "hdrgm:Gamma") into `out` when it
+// lists one value per channel. Returns false if it is absent, single-valued
+// or not numeric, leaving `out` unchanged. Numbers may use exponents (1e-2)
+// or, from mis-localized writers, a decimal comma (0,5); see decodeGainMapXmp.
+bool readXmpChannels(const std::string& xmp, const std::string& name, float (&out)[3]);
+
+// Reads the hdrgm properties of a gain map XMP packet into `out`; unlisted
+// optional properties keep their spec defaults. hdrgm:Version is not checked.
+// Each problem (a missing required property, a wrong value count, a value
+// that is not a number) is appended to `errors` when given. Returns false if
+// there was any.
+//
+// Numbers may use exponents (1e-2) or a decimal comma (0,5) from writers
+// running under a European locale. In a comma-separated attribute, commas
+// are list separators whenever that gives one value or one per channel;
+// otherwise, if the pieces pair up as integer and fraction digits, each pair
+// is one decimal-comma number. So Gamma="1,5" is 1.5 on all channels,
+// Gamma="1,5, 2,5, 3,5" is 1.5, 2.5 and 3.5, but Gamma="1,5,2" is 1, 5 and 2.
+// BaseRenditionIsHDR is true for "True" in any case, "1", "yes" or "on",
+// ignoring surrounding whitespace, and false otherwise.
+bool decodeGainMapXmp(const std::string& xmp, XmpGainMapMetadata& out,
+                      std::vector<std::string>* errors = nullptr);
+
+// Writes an hdrgm Version 1.0 XMP packet (without the APP1 namespace
+// signature) describing a gain map image. Values round-trip through
+// decodeGainMapXmp exactly.
+std::string encodeGainMapXmp(const XmpGainMapMetadata& meta, XmpFloatArrayStyle style);
+
+}  // namespace open_ultrahdr
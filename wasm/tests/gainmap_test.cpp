@@ -0,0 +1,120 @@
+// Host-compiled checks for the gain map math, for the parts the JS tests
+// cannot reach through the bindings. Build with
+// -DOPEN_ULTRAHDR_NATIVE_TESTS=ON and run with ctest.
+
+#include <cmath>
+#include <cstdint>
+#include <cstdio>
+#include <limits>
+#include <stdexcept>
+#include <vector>
+
+#include "color_math.h"
+#include "gain_math.h"
+#include "gainmap.h"
+
+namespace {
+
+int failures = 0;
+
+void expectNear(float actual, float expected, float tolerance, const char* what) {
+  if (std::fabs(actual - expected) > tolerance) {
+    std::printf("FAIL %s: expected %f, got %f\n", what, expected, actual);
+    ++failures;
+  }
+}
+
+// Every channel's gain is recovered through computeGainMap and
+// decodeGainLog2 when the gamma is not 1, in either direction.
+void gammaRoundTrip() {
+  constexpr uint32_t kWidth = 16;
+  const float gains[kWidth] = {0.0f,  0.2f,  0.4f,  0.6f,  0.8f,  1.0f,  1.25f, 1.5f,
+                               1.75f, 2.0f,  2.25f, 2.5f,  2.75f, 2.9f,  2.95f, 3.0f};
+  open_ultrahdr::GainMapParams p;
+  const float gamma[3] = {2.0f, 0.5f, 1.5f};
+  for (int c = 0; c < 3; ++c) {
+    p.gainMapMin[c] = 0.0f;
+    p.gainMapMax[c] = 3.0f;
+    p.gamma[c] = gamma[c];
+  }
+
+  std::vector<uint8_t> sdr(kWidth * 3, 128);
+  std::vector<float> hdr(kWidth * 3);
+  float sdrLinear = open_ultrahdr::srgbToLinear(128.0f / 255.0f);
+  for (uint32_t x = 0; x < kWidth; ++x) {
+    for (int c = 0; c < 3; ++c) {
+      hdr[x * 3 + c] = (sdrLinear + p.offsetSdr[c]) * std::exp2(gains[x]) - p.offsetHdr[c];
+    }
+  }
+
+  std::vector<uint8_t> gainMap =
+      open_ultrahdr::computeGainMap({sdr.data(), kWidth, 1, kWidth, 3}, hdr.data(), p, 1);
+  for (uint32_t x = 0; x < kWidth; ++x) {
+    for (int c = 0; c < 3; ++c) {
+      float decoded = open_ultrahdr::decodeGainLog2(p, c, gainMap[x * 3 + c] / 255.0f);
+      // Well over one 8-bit step for these gammas, well under a wrong gamma.
+      expectNear(decoded, gains[x], 0.1f, "gamma round trip");
+    }
+  }
+}
+
+template <typename F>
+void expectThrows(F&& f, const char* what) {
+  try {
+    f();
+  } catch (const std::runtime_error&) {
+    return;
+  }
+  std::printf("FAIL %s: expected a throw\n", what);
+  ++failures;
+}
+
+void expectDimensions(open_ultrahdr::Dimensions actual, uint32_t width, uint32_t height,
+                      const char* what) {
+  if (actual.width != width || actual.height != height) {
+    std::printf("FAIL %s: expected %ux%u, got %ux%u\n", what, width, height, actual.width,
+                actual.height);
+    ++failures;
+  }
+}
+
+// Gain map sides round up, so a partial block still gets a sample, and the
+// rounding holds at the top of the uint32_t range where width + scale - 1
+// would wrap.
+void scaledRoundsUp() {
+  expectDimensions(open_ultrahdr::Dimensions{32, 16}.scaled(4), 8, 4, "exact scale");
+  expectDimensions(open_ultrahdr::Dimensions{33, 17}.scaled(4), 9, 5, "partial block");
+  expectDimensions(open_ultrahdr::Dimensions{1, 1}.scaled(4), 1, 1, "smaller than a block");
+  const uint32_t kMax = std::numeric_limits<uint32_t>::max();
+  expectDimensions(open_ultrahdr::Dimensions{kMax, kMax}.scaled(2), 0x80000000u, 0x80000000u,
+                   "largest sides");
+  expectThrows([] { open_ultrahdr::Dimensions{16, 16}.scaled(0); }, "zero scale");
+}
+
+// samples() throws rather than wrapping. The widest uint32_t sides fit one
+// channel in a 64-bit size_t but not three; in wasm's 32-bit size_t the
+// product of the sides already overflows.
+void samplesRejectOverflow() {
+  if (open_ultrahdr::Dimensions{16, 8}.samples(3) != 384) {
+    std::printf("FAIL samples: expected 384\n");
+    ++failures;
+  }
+  const uint32_t kMax = std::numeric_limits<uint32_t>::max();
+  const open_ultrahdr::Dimensions widest{kMax, kMax};
+  if (sizeof(size_t) > sizeof(uint32_t) &&
+      widest.samples(1) != static_cast<size_t>(kMax) * kMax) {
+    std::printf("FAIL samples: widest single channel\n");
+    ++failures;
+  }
+  expectThrows([&] { widest.samples(3); }, "samples overflow");
+}
+
+}  // namespace
+
+int main() {
+  gammaRoundTrip();
+  scaledRoundsUp();
+  samplesRejectOverflow();
+  if (failures == 0) std::printf("All gain map tests passed\n");
+  return failures == 0 ? 0 : 1;
+}
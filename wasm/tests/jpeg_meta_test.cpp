@@ -0,0 +1,217 @@
+// Host-compiled checks for the JPEG segment walker, on hand-built streams the
+// JS fixtures cannot produce. Build with -DOPEN_ULTRAHDR_NATIVE_TESTS=ON and
+// run with ctest.
+
+#include <cstdio>
+#include <cstring>
+#include <initializer_list>
+#include <string>
+#include <vector>
+
+#include "jpeg_meta.h"
+
+namespace {
+
+int failures = 0;
+
+void expectEqual(size_t actual, size_t expected, const char* what) {
+  if (actual != expected) {
+    std::printf("FAIL %s: expected %zu, got %zu\n", what, expected, actual);
+    ++failures;
+  }
+}
+
+void expectEqual(const std::string& actual, const std::string& expected, const char* what) {
+  if (actual != expected) {
+    std::printf("FAIL %s: expected \"%s\", got \"%s\"\n", what, expected.c_str(),
+                actual.c_str());
+    ++failures;
+  }
+}
+
+uint32_t readU32(const uint8_t* p) {
+  return (static_cast<uint32_t>(p[0]) << 24) | (p[1] << 16) | (p[2] << 8) | p[3];
+}
+
+// Two scans of a progressive-style stream, with a DNL and a DHT between them:
+// SOI, SOF2, SOS, data, DNL, DHT, SOS, data, EOI. The entropy data holds a
+// stuffed 0xFF00 and an RST0, neither of which ends a scan.
+std::vector<uint8_t> multiScanStream(size_t& secondSos) {
+  std::vector<uint8_t> out = {0xFF, 0xD8};
+  auto append = [&](std::initializer_list<uint8_t> bytes) {
+    out.insert(out.end(), bytes.begin(), bytes.end());
+  };
+  append({0xFF, 0xC2, 0x00, 0x0B, 0x08, 0x00, 0x10, 0x00, 0x10, 0x01, 0x01, 0x11, 0x00});
+  append({0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00});
+  append({0x12, 0x34, 0xFF, 0x00, 0x56, 0xFF, 0xD0, 0x78});
+  append({0xFF, 0xDC, 0x00, 0x04, 0x00, 0x10});
+  append({0xFF, 0xC4, 0x00, 0x05, 0x00, 0x01, 0x02});
+  secondSos = out.size();
+  append({0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x01, 0x3F, 0x00});
+  append({0x9A, 0xBC});
+  append({0xFF, 0xD9});
+  return out;
+}
+
+// Walking through scans resumes on the marker that ends each scan, so the
+// second SOS is read as a segment rather than ending the walk.
+void resumesAtSecondScan() {
+  size_t secondSos = 0;
+  std::vector<uint8_t> data = multiScanStream(secondSos);
+  open_ultrahdr::JpegSegmentReader reader(data.data(), data.size(), nullptr,
+                                          /*throughScans=*/true);
+  std::vector<open_ultrahdr::JpegSegment> segments;
+  for (open_ultrahdr::JpegSegment seg; reader.next(seg);) segments.push_back(seg);
+
+  const uint8_t expected[] = {0xC2, 0xDA, 0xDC, 0xC4, 0xDA};
+  expectEqual(segments.size(), sizeof(expected), "segments through scans");
+  for (size_t i = 0; i < segments.size() && i < sizeof(expected); ++i) {
+    expectEqual(segments[i].marker, expected[i], "marker through scans");
+  }
+  if (segments.size() == sizeof(expected)) {
+    expectEqual(segments[4].offset, secondSos, "second SOS offset");
+  }
+  expectEqual(reader.end(), data.size(), "end after EOI");
+}
+
+// Without throughScans the walk stops at the first SOS, as header readers
+// expect, and never reaches EOI.
+void stopsAtFirstScan() {
+  size_t secondSos = 0;
+  std::vector<uint8_t> data = multiScanStream(secondSos);
+  open_ultrahdr::JpegSegmentReader reader(data.data(), data.size());
+  size_t count = 0;
+  for (open_ultrahdr::JpegSegment seg; reader.next(seg);) ++count;
+  expectEqual(count, 2, "segments up to the first scan");
+  expectEqual(reader.end(), 0, "end without scans");
+}
+
+// findJpegEnd stops at the first EOI even when a second codestream follows,
+// and reports 0 for a stream cut off inside a scan.
+void findsEndOfFirstCodestream() {
+  size_t secondSos = 0;
+  std::vector<uint8_t> data = multiScanStream(secondSos);
+  size_t size = data.size();
+  expectEqual(open_ultrahdr::findJpegEnd(data.data(), size), size, "end of one codestream");
+
+  std::vector<uint8_t> twice = data;
+  twice.insert(twice.end(), data.begin(), data.end());
+  expectEqual(open_ultrahdr::findJpegEnd(twice.data(), twice.size()), size,
+              "end of the first of two codestreams");
+
+  expectEqual(open_ultrahdr::findJpegEnd(data.data(), secondSos + 12), 0, "truncated scan");
+}
+
+// Sampling factors are the largest across all components, not the first
+// component's: here luma is 1x1 while a chroma component is 2x1 and another
+// 1x2, an unusual but valid layout with a 16x16 MCU.
+void readsLargestSamplingFactors() {
+  const std::vector<uint8_t> data = {
+      0xFF, 0xD8, 0xFF, 0xC0, 0x00, 0x11, 0x08, 0x00, 0x10, 0x00, 0x10, 0x03,
+      0x01, 0x11, 0x00, 0x02, 0x21, 0x01, 0x03, 0x12, 0x01, 0xFF, 0xD9,
+  };
+  open_ultrahdr::JpegDims dims = open_ultrahdr::parseJpegDimensions(data.data(), data.size());
+  expectEqual(dims.components, 3, "components");
+  expectEqual(dims.hSampling, 2, "horizontal sampling");
+  expectEqual(dims.vSampling, 2, "vertical sampling");
+
+  open_ultrahdr::JpegDims fromSegments = open_ultrahdr::dimensionsFromSegments(
+      data.data(), open_ultrahdr::parseJpegSegments(data.data(), data.size()));
+  expectEqual(fromSegments.hSampling, 2, "horizontal sampling from segments");
+  expectEqual(fromSegments.vSampling, 2, "vertical sampling from segments");
+}
+
+// A packet too large for one APP1 segment becomes Extended XMP: a standard
+// packet naming the MD5 of the whole packet, then extension chunks under that
+// GUID that reassemble to it.
+void splitsExtendedXmp() {
+  const std::string packet = "<x:xmpmeta>" + std::string(100000, 'a') + "</x:xmpmeta>";
+  const std::string guid = "EB8A71522E0EAF8760A99E30222FE611";  // MD5 of `packet`.
+  const std::vector<uint8_t> jpeg = {0xFF, 0xD8, 0xFF, 0xD9};
+  std::vector<uint8_t> out = open_ultrahdr::insertXmpSegment(jpeg, packet);
+
+  const char kStandard[] = "http://ns.adobe.com/xap/1.0/";
+  const char kExtension[] = "http://ns.adobe.com/xmp/extension/";
+  std::string standard;
+  std::string extended(packet.size(), '\0');
+  size_t extendedBytes = 0;
+  size_t at = 2;
+  while (at + 4 <= out.size() && out[at] == 0xFF && out[at + 1] == 0xE1) {
+    size_t length = (out[at + 2] << 8) | out[at + 3];
+    const uint8_t* payload = out.data() + at + 4;
+    size_t payloadSize = length - 2;
+    if (std::memcmp(payload, kStandard, sizeof(kStandard)) == 0) {
+      standard.assign(reinterpret_cast<const char*>(payload) + sizeof(kStandard),
+                      payloadSize - sizeof(kStandard));
+    } else if (std::memcmp(payload, kExtension, sizeof(kExtension)) == 0) {
+      const uint8_t* p = payload + sizeof(kExtension);
+      expectEqual(std::string(reinterpret_cast<const char*>(p), 32), guid, "chunk GUID");
+      expectEqual(readU32(p + 32), packet.size(), "extended length");
+      size_t offset = readU32(p + 36);
+      size_t chunk = payloadSize - sizeof(kExtension) - 40;
+      if (offset + chunk <= extended.size()) {
+        std::memcpy(&extended[offset], p + 40, chunk);
+      }
+      extendedBytes += chunk;
+    }
+    at += 2 + length;
+  }
+
+  expectEqual(standard.find("xmpNote:HasExtendedXMP=\"" + guid + "\"") != std::string::npos, 1,
+              "standard packet names the GUID");
+  expectEqual(extendedBytes, packet.size(), "extended bytes");
+  expectEqual(extended == packet, 1, "reassembled packet");
+  expectEqual(out.size() - at, 2, "EOI after the XMP segments");
+}
+
+// A baseline frame header only, `width` x 16 grey: enough for the walkers to
+// treat it as a codestream.
+std::vector<uint8_t> frameOnly(uint8_t width) {
+  return {0xFF, 0xD8, 0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x00, 0x10, 0x00,
+          width, 0x01, 0x01, 0x11, 0x00, 0xFF, 0xD9};
+}
+
+// With a depth map listed before the gain map in the GContainer directory,
+// the gain map is moved up to follow the primary; with the gain map already
+// next, nothing is rebuilt.
+void isolatesLocatedGainMap() {
+  const std::vector<uint8_t> depth = frameOnly(8);
+  const std::vector<uint8_t> gainMap = frameOnly(4);
+  const std::string xmp =
+      "<x:xmpmeta><rdf:Description hdrgm:Version=\"1.0\"><Container:Directory><rdf:Seq>"
+      "<rdf:li><Container:Item Item:Semantic=\"Primary\" Item:Mime=\"image/jpeg\"/></rdf:li>"
+      "<rdf:li><Container:Item Item:Semantic=\"Depth\" Item:Mime=\"image/jpeg\" Item:Length=\"" +
+      std::to_string(depth.size()) +
+      "\"/></rdf:li>"
+      "<rdf:li><Container:Item Item:Semantic=\"GainMap\" Item:Mime=\"image/jpeg\" "
+      "Item:Length=\"" +
+      std::to_string(gainMap.size()) + "\"/></rdf:li></rdf:Seq></Container:Directory>"
+      "</rdf:Description></x:xmpmeta>";
+  const std::vector<uint8_t> primary = open_ultrahdr::insertXmpSegment(frameOnly(16), xmp);
+
+  std::vector<uint8_t> file = primary;
+  file.insert(file.end(), depth.begin(), depth.end());
+  file.insert(file.end(), gainMap.begin(), gainMap.end());
+  std::vector<uint8_t> expected = primary;
+  expected.insert(expected.end(), gainMap.begin(), gainMap.end());
+  expectEqual(open_ultrahdr::isolateGainMapLayers(file.data(), file.size()) == expected, 1,
+              "primary followed by the located gain map");
+
+  std::vector<uint8_t> adjacent = open_ultrahdr::insertXmpSegment(frameOnly(16), "hdrgm");
+  adjacent.insert(adjacent.end(), gainMap.begin(), gainMap.end());
+  expectEqual(open_ultrahdr::isolateGainMapLayers(adjacent.data(), adjacent.size()).size(), 0,
+              "gain map already next");
+}
+
+}  // namespace
+
+int main() {
+  resumesAtSecondScan();
+  stopsAtFirstScan();
+  findsEndOfFirstCodestream();
+  readsLargestSamplingFactors();
+  splitsExtendedXmp();
+  isolatesLocatedGainMap();
+  if (failures == 0) std::printf("All JPEG segment tests passed\n");
+  return failures == 0 ? 0 : 1;
+}